@@ -1,6 +1,6 @@
 mod types;
 
-use crate::types::{MetaCommand, PrepareResult, Statement, VirtualMachine};
+use crate::types::{ExecuteResult, ExecutionFailure, MetaCommand, PrepareResult, VirtualMachine};
 use anyhow::{ Result};
 use std::io::{self, Write};
 
@@ -17,21 +17,49 @@ fn main() -> Result<()> {
             .expect("Failed to read user input");
         let input = input.trim();
 
-        if input.starts_with('.') {
+        if let Some(meta_command) = input.strip_prefix('.') {
             // Execute meta-command by taking the substring after '.'
-            MetaCommand::from_str(&input[1..])?.execute();
+            if let Err(err) =
+                MetaCommand::from_str(meta_command).and_then(|command| command.execute(&mut vm))
+            {
+                println!("Error: {}", err);
+            }
         } else {
-            match Statement::prepare(input) {
+            match vm.prepare(input) {
                 PrepareResult::UnrecognizedStatement => {
                     println!("Unrecognized command: {}", input)
                 }
                 PrepareResult::SyntaxError(err) => {
                     println!("Syntax error: {}", err)
                 }
-                PrepareResult::Success(statement) => {
-                    let result = vm.execute(statement);
-                    println!("{:?}", result);
-                }
+                PrepareResult::Success(statement) => match vm.execute(*statement) {
+                    Ok(ExecuteResult::Success(rows)) => {
+                        for row in rows {
+                            println!("{:?}", row);
+                        }
+                    }
+                    Ok(ExecuteResult::ResultSet(result_set)) => println!("{}", result_set),
+                    Ok(ExecuteResult::IntegrityCheck(problems)) if problems.is_empty() => {
+                        println!("ok")
+                    }
+                    Ok(ExecuteResult::IntegrityCheck(problems)) => {
+                        for problem in problems {
+                            println!("{}", problem);
+                        }
+                    }
+                    Ok(ExecuteResult::CompressionStats(stats)) => println!("{}", stats),
+                    Ok(ExecuteResult::ExecutionFailure(ExecutionFailure::UniqueViolation {
+                        constraint,
+                        value,
+                    })) => println!("Error: UNIQUE constraint {} failed for {}", constraint, value),
+                    Ok(ExecuteResult::ExecutionFailure(ExecutionFailure::CheckViolation {
+                        constraint,
+                    })) => println!("Error: CHECK constraint {} failed", constraint),
+                    Ok(ExecuteResult::ExecutionFailure(ExecutionFailure::ForeignKeyViolation {
+                        constraint,
+                    })) => println!("Error: FOREIGN KEY constraint {} failed", constraint),
+                    result => println!("{:?}", result),
+                },
             }
         }
     }
@@ -0,0 +1,570 @@
+use crate::types::expression::Scope;
+use crate::types::{Expression, Schema, Tuple, Value};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+impl AggregateFunction {
+    /// Looks up an aggregate function by its case-insensitive name.
+    pub fn from_name(name: &str) -> Option<AggregateFunction> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(AggregateFunction::Count),
+            "sum" => Some(AggregateFunction::Sum),
+            "avg" => Some(AggregateFunction::Avg),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateFunction::Count => write!(f, "count"),
+            AggregateFunction::Sum => write!(f, "sum"),
+            AggregateFunction::Avg => write!(f, "avg"),
+            AggregateFunction::Min => write!(f, "min"),
+            AggregateFunction::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// Running state of one aggregate function over the rows of a group. NULL arguments are
+/// skipped, and SUM, AVG, MIN and MAX of no values are NULL.
+#[derive(Clone, Debug)]
+pub enum Accumulator {
+    Count(i64),
+    Sum(Option<Value>),
+    Avg { sum: f64, count: i64 },
+    Min(Option<Value>),
+    Max(Option<Value>),
+}
+impl Accumulator {
+    pub fn new(function: AggregateFunction) -> Accumulator {
+        match function {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(None),
+            AggregateFunction::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggregateFunction::Min => Accumulator::Min(None),
+            AggregateFunction::Max => Accumulator::Max(None),
+        }
+    }
+
+    /// Adds the argument of one row, `None` for `COUNT(*)`.
+    pub fn update(&mut self, value: Option<Value>) -> Result<()> {
+        let value = match value {
+            None => Value::Integer(1),
+            Some(Value::Null) => return Ok(()),
+            Some(value) => value,
+        };
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => {
+                *sum = Some(match (sum.take(), value) {
+                    (None, value @ (Value::Integer(_) | Value::Real(_))) => value,
+                    (Some(Value::Integer(sum)), Value::Integer(value)) => Value::Integer(
+                        sum.checked_add(value)
+                            .ok_or_else(|| anyhow!("Integer overflow"))?,
+                    ),
+                    (Some(sum), value) => match (sum.as_f64(), value.as_f64()) {
+                        (Some(sum), Some(value)) => Value::Real(sum + value),
+                        _ => return Err(anyhow!("Cannot apply sum to {}", value.type_name())),
+                    },
+                    (None, value) => {
+                        return Err(anyhow!("Cannot apply sum to {}", value.type_name()))
+                    }
+                });
+            }
+            Accumulator::Avg { sum, count } => {
+                *sum += value
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("Cannot apply avg to {}", value.type_name()))?;
+                *count += 1;
+            }
+            Accumulator::Min(min) => {
+                if min.as_ref().is_none_or(|min| value.compare(min).is_lt()) {
+                    *min = Some(value);
+                }
+            }
+            Accumulator::Max(max) => {
+                if max.as_ref().is_none_or(|max| value.compare(max).is_gt()) {
+                    *max = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Value {
+        match self {
+            Accumulator::Count(count) => Value::Integer(count),
+            Accumulator::Avg { count: 0, .. } => Value::Null,
+            Accumulator::Avg { sum, count } => Value::Real(sum / count as f64),
+            Accumulator::Sum(value) | Accumulator::Min(value) | Accumulator::Max(value) => {
+                value.unwrap_or(Value::Null)
+            }
+        }
+    }
+
+    fn memory_size(&self) -> usize {
+        match self {
+            Accumulator::Sum(Some(value))
+            | Accumulator::Min(Some(value))
+            | Accumulator::Max(Some(value)) => value.memory_size(),
+            _ => size_of::<Accumulator>(),
+        }
+    }
+}
+
+/// One group of a grouped query: its first row, which supplies the values of columns that
+/// are not aggregated, and the values of the aggregates in the order they were requested.
+#[derive(Debug)]
+pub struct Group {
+    pub row: Option<Vec<Value>>,
+    pub values: Vec<Value>,
+}
+impl Group {
+    /// `schema` describes the rows that were grouped.
+    pub fn scope<'a>(&'a self, schema: &'a Schema, aggregates: &'a [Expression]) -> GroupScope<'a> {
+        GroupScope {
+            group: self,
+            schema,
+            aggregates,
+        }
+    }
+}
+
+/// Evaluates expressions over a group, resolving aggregate calls to their computed values.
+pub struct GroupScope<'a> {
+    group: &'a Group,
+    schema: &'a Schema,
+    aggregates: &'a [Expression],
+}
+impl Scope for GroupScope<'_> {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<Value> {
+        let i = self.schema.resolve(table, name).ok()?;
+        match &self.group.row {
+            Some(row) => row.get(i).cloned(),
+            None => Some(Value::Null),
+        }
+    }
+
+    fn aggregate(&self, expression: &Expression) -> Option<Value> {
+        let i = self.aggregates.iter().position(|a| a == expression)?;
+        self.group.values.get(i).cloned()
+    }
+}
+
+struct Partition {
+    writer: BufWriter<File>,
+    rows: usize,
+}
+
+/// Groups rows by the values of the GROUP BY expressions in a hash table and accumulates
+/// the aggregates of each group.
+///
+/// Once the groups exceed the memory budget, rows of groups that are already in memory are
+/// still accumulated, but rows starting a new group are written to one of
+/// `Self::PARTITIONS` temporary files chosen by the hash of their group key. Every group
+/// lives in exactly one partition, so each partition is aggregated on its own afterwards,
+/// spilling again with a differently seeded hash if it is still too large.
+pub struct HashAggregator {
+    schema: Schema,
+    group_by: Vec<Expression>,
+    aggregates: Vec<Expression>,
+    memory_budget: usize,
+    level: u64,
+    index: HashMap<Vec<u8>, usize>,
+    groups: Vec<(Vec<Value>, Vec<Accumulator>)>,
+    memory_used: usize,
+    partitions: Vec<Partition>,
+}
+impl HashAggregator {
+    const PARTITIONS: usize = 16;
+
+    /// `schema` describes the rows to group and `aggregates` holds the aggregate calls whose
+    /// values each group reports, see `Expression::collect_aggregates`.
+    pub fn new(
+        schema: Schema,
+        group_by: Vec<Expression>,
+        aggregates: Vec<Expression>,
+        memory_budget: usize,
+    ) -> HashAggregator {
+        Self::with_level(schema, group_by, aggregates, memory_budget, 0)
+    }
+
+    fn with_level(
+        schema: Schema,
+        group_by: Vec<Expression>,
+        aggregates: Vec<Expression>,
+        memory_budget: usize,
+        level: u64,
+    ) -> HashAggregator {
+        HashAggregator {
+            schema,
+            group_by,
+            aggregates,
+            memory_budget,
+            level,
+            index: HashMap::new(),
+            groups: Vec::new(),
+            memory_used: 0,
+            partitions: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, values: &[Value]) -> Result<()> {
+        let row = &Tuple {
+            schema: &self.schema,
+            values,
+        };
+        let mut key = Vec::new();
+        for expression in &self.group_by {
+            expression.evaluate(row)?.serialize(&mut key);
+        }
+        let group = match self.index.get(&key) {
+            Some(&group) => group,
+            None if !self.partitions.is_empty() => return self.spill(&key, values),
+            None => {
+                let accumulators = self.new_accumulators()?;
+                self.memory_used += key.len();
+                self.memory_used += values.iter().map(Value::memory_size).sum::<usize>();
+                self.memory_used += accumulators
+                    .iter()
+                    .map(Accumulator::memory_size)
+                    .sum::<usize>();
+                self.index.insert(key, self.groups.len());
+                self.groups.push((values.to_vec(), accumulators));
+                if self.memory_used > self.memory_budget {
+                    self.partitions = (0..Self::PARTITIONS)
+                        .map(|_| {
+                            Ok(Partition {
+                                writer: BufWriter::new(tempfile::tempfile()?),
+                                rows: 0,
+                            })
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                self.groups.len() - 1
+            }
+        };
+        let row = &Tuple {
+            schema: &self.schema,
+            values,
+        };
+        let accumulators = &mut self.groups[group].1;
+        for (aggregate, accumulator) in self.aggregates.iter().zip(accumulators) {
+            let Expression::Aggregate { argument, .. } = aggregate else {
+                unreachable!("Checked when the group was created")
+            };
+            let value = match argument {
+                Some(argument) => Some(argument.evaluate(row)?),
+                None => None,
+            };
+            accumulator.update(value)?;
+        }
+        Ok(())
+    }
+
+    fn new_accumulators(&self) -> Result<Vec<Accumulator>> {
+        self.aggregates
+            .iter()
+            .map(|aggregate| match aggregate {
+                Expression::Aggregate { function, .. } => Ok(Accumulator::new(*function)),
+                _ => Err(anyhow!("Not an aggregate: {}", aggregate)),
+            })
+            .collect()
+    }
+
+    // Every spilled row has the same number of values, the columns of the schema.
+    fn spill(&mut self, key: &[u8], values: &[Value]) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        self.level.hash(&mut hasher);
+        key.hash(&mut hasher);
+        let partition = &mut self.partitions[hasher.finish() as usize % Self::PARTITIONS];
+        let mut buf = Vec::new();
+        for value in values {
+            value.serialize(&mut buf);
+        }
+        partition.writer.write_all(&buf)?;
+        partition.rows += 1;
+        Ok(())
+    }
+
+    /// Returns the groups, those held in memory first and then those of each partition. A
+    /// query without GROUP BY has exactly one group, even over no rows.
+    pub fn finish(mut self) -> Result<Groups> {
+        if self.group_by.is_empty() && self.groups.is_empty() {
+            let group = Group {
+                row: None,
+                values: self
+                    .new_accumulators()?
+                    .into_iter()
+                    .map(Accumulator::finish)
+                    .collect(),
+            };
+            return Ok(Groups {
+                schema: self.schema,
+                group_by: self.group_by,
+                aggregates: self.aggregates,
+                memory_budget: self.memory_budget,
+                current: vec![group].into_iter(),
+                pending: Vec::new(),
+            });
+        }
+        let mut pending = Vec::new();
+        for partition in self.partitions.drain(..) {
+            if partition.rows > 0 {
+                let mut file = partition.writer.into_inner()?;
+                file.seek(SeekFrom::Start(0))?;
+                pending.push((BufReader::new(file), partition.rows, self.level + 1));
+            }
+        }
+        let groups: Vec<Group> = self
+            .groups
+            .into_iter()
+            .map(|(row, accumulators)| Group {
+                row: Some(row),
+                values: accumulators.into_iter().map(Accumulator::finish).collect(),
+            })
+            .collect();
+        Ok(Groups {
+            schema: self.schema,
+            group_by: self.group_by,
+            aggregates: self.aggregates,
+            memory_budget: self.memory_budget,
+            current: groups.into_iter(),
+            pending,
+        })
+    }
+}
+
+/// Iterator over the groups of a finished `HashAggregator`, aggregating spilled partitions
+/// one at a time as it reaches them.
+pub struct Groups {
+    schema: Schema,
+    group_by: Vec<Expression>,
+    aggregates: Vec<Expression>,
+    memory_budget: usize,
+    current: std::vec::IntoIter<Group>,
+    pending: Vec<(BufReader<File>, usize, u64)>,
+}
+impl Groups {
+    fn aggregate_partition(&mut self) -> Result<()> {
+        let Some((mut reader, rows, level)) = self.pending.pop() else {
+            return Ok(());
+        };
+        let mut aggregator = HashAggregator::with_level(
+            self.schema.clone(),
+            self.group_by.clone(),
+            self.aggregates.clone(),
+            self.memory_budget,
+            level,
+        );
+        for _ in 0..rows {
+            let values = (0..self.schema.columns.len())
+                .map(|_| Value::deserialize(&mut reader))
+                .collect::<Result<Vec<_>>>()?;
+            aggregator.push(&values)?;
+        }
+        let groups = aggregator.finish()?;
+        self.current = groups.current;
+        self.pending.extend(groups.pending);
+        Ok(())
+    }
+}
+impl Iterator for Groups {
+    type Item = Result<Group>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(group) = self.current.next() {
+                return Some(Ok(group));
+            }
+            if self.pending.is_empty() {
+                return None;
+            }
+            if let Err(err) = self.aggregate_partition() {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Parser, Row, TableDefinition};
+    use heapless::String as HeapLessString;
+
+    fn row(id: i32, username: &str) -> Vec<Value> {
+        Row {
+            id,
+            username: HeapLessString::try_from(username).unwrap(),
+            email: HeapLessString::try_from("user@example.com").unwrap(),
+        }
+        .values()
+    }
+
+    fn new_aggregator(group_by: &str, aggregates: &str, budget: usize) -> HashAggregator {
+        let group_by = if group_by.is_empty() {
+            Vec::new()
+        } else {
+            expressions(group_by)
+        };
+        HashAggregator::new(
+            TableDefinition::default().schema("users"),
+            group_by,
+            expressions(aggregates),
+            budget,
+        )
+    }
+
+    fn expressions(input: &str) -> Vec<Expression> {
+        vec![Parser::new(input).unwrap().parse_expression().unwrap()]
+    }
+
+    fn aggregate(
+        group_by: &str,
+        aggregates: &str,
+        rows: &[Vec<Value>],
+        budget: usize,
+    ) -> Vec<Vec<Value>> {
+        let group_by = if group_by.is_empty() {
+            Vec::new()
+        } else {
+            expressions(group_by)
+        };
+        let select = Parser::new(&format!("select {}", aggregates))
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        let mut aggregates = Vec::new();
+        for column in &select.columns {
+            if let crate::types::ResultColumn::Expression { expression, .. } = column {
+                expression.collect_aggregates(&mut aggregates);
+            }
+        }
+        let mut aggregator = HashAggregator::new(
+            TableDefinition::default().schema("users"),
+            group_by,
+            aggregates,
+            budget,
+        );
+        for row in rows {
+            aggregator.push(row).unwrap();
+        }
+        let mut groups: Vec<Vec<Value>> = aggregator
+            .finish()
+            .unwrap()
+            .map(|group| {
+                let group = group.unwrap();
+                let mut values = vec![group.row.map_or(Value::Null, |row| row[0].clone())];
+                values.extend(group.values);
+                values
+            })
+            .collect();
+        groups.sort_by(|a, b| a[0].compare(&b[0]));
+        groups
+    }
+
+    #[test]
+    fn test_aggregate_functions() {
+        let rows = [
+            row(1, "alice"),
+            row(2, "bob"),
+            row(3, "alice"),
+            row(4, "bob"),
+        ];
+        assert_eq!(
+            aggregate(
+                "",
+                "count(*), sum(id), avg(id), min(username), max(id)",
+                &rows,
+                1 << 20
+            ),
+            vec![vec![
+                Value::Integer(1),
+                Value::Integer(4),
+                Value::Integer(10),
+                Value::Real(2.5),
+                Value::Text("alice".to_string()),
+                Value::Integer(4),
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_empty_input() {
+        assert_eq!(
+            aggregate(
+                "",
+                "count(*), count(id), sum(id), avg(id), min(id)",
+                &[],
+                1 << 20
+            ),
+            vec![vec![
+                Value::Null,
+                Value::Integer(0),
+                Value::Integer(0),
+                Value::Null,
+                Value::Null,
+                Value::Null,
+            ]]
+        );
+        assert!(aggregate("username", "count(*)", &[], 1 << 20).is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_errors() {
+        let mut aggregator = new_aggregator("", "sum(username)", 1 << 20);
+        let err = aggregator.push(&row(1, "alice")).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot apply sum to TEXT");
+
+        let mut aggregator = new_aggregator("", "sum(id * 4611686018427387904)", 1 << 20);
+        aggregator.push(&row(1, "alice")).unwrap();
+        let err = aggregator.push(&row(1, "alice")).unwrap_err();
+        assert_eq!(err.to_string(), "Integer overflow");
+    }
+
+    #[test]
+    fn test_group_by_spills_partitions() {
+        let rows: Vec<Vec<Value>> = (0..2000).map(|id| row(id, "alice")).collect();
+        let expected: Vec<Vec<Value>> = (0..100)
+            .map(|group| {
+                let ids = (0..2000).filter(|id| id % 100 == group);
+                vec![
+                    Value::Integer(group as i64),
+                    Value::Integer(20),
+                    Value::Integer(ids.sum::<i32>() as i64),
+                ]
+            })
+            .collect();
+        assert_eq!(
+            aggregate("id % 100", "count(*), sum(id)", &rows, 1 << 20),
+            expected
+        );
+
+        // A budget of a few groups forces spilling, and partitions spill again.
+        let mut aggregator = new_aggregator("id % 100", "count(*)", 2048);
+        for row in &rows[..200] {
+            aggregator.push(row).unwrap();
+        }
+        assert!(!aggregator.partitions.is_empty());
+        assert_eq!(
+            aggregate("id % 100", "count(*), sum(id)", &rows, 2048),
+            expected
+        );
+    }
+}
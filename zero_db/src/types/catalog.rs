@@ -0,0 +1,153 @@
+use crate::types::{ColumnStatistics, Statistics, Value};
+use anyhow::{anyhow, Result};
+
+/// What a database stores besides its rows: the statements that define its indexes and
+/// views, and the statistics ANALYZE collected. `Table::open` runs the statements again,
+/// rebuilding the indexes from the rows, so only the definitions are stored, see
+/// `Pager::catalog`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Catalog {
+    pub definitions: Vec<String>,
+    pub statistics: Option<Statistics>,
+}
+impl Catalog {
+    /// The number of definitions followed by each of them, then the statistics if there
+    /// are any, written as values. An empty catalog takes no bytes, like the catalog of a
+    /// file written before it existed.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if *self == Catalog::default() {
+            return buf;
+        }
+        Value::Integer(self.definitions.len() as i64).serialize(&mut buf);
+        for definition in &self.definitions {
+            Value::Text(definition.clone()).serialize(&mut buf);
+        }
+        if let Some(statistics) = &self.statistics {
+            Value::Integer(statistics.rows as i64).serialize(&mut buf);
+            Value::Integer(statistics.columns.len() as i64).serialize(&mut buf);
+            for column in &statistics.columns {
+                Value::Text(column.column.clone()).serialize(&mut buf);
+                Value::Integer(column.distinct as i64).serialize(&mut buf);
+                Value::Integer(column.histogram.len() as i64).serialize(&mut buf);
+                for bound in &column.histogram {
+                    bound.serialize(&mut buf);
+                }
+            }
+        }
+        buf
+    }
+
+    pub fn deserialize(mut bytes: &[u8]) -> Result<Catalog> {
+        let mut catalog = Catalog::default();
+        if bytes.is_empty() {
+            return Ok(catalog);
+        }
+        let count = read_count(&mut bytes)?;
+        for _ in 0..count {
+            match Value::deserialize(&mut bytes)? {
+                Value::Text(definition) => catalog.definitions.push(definition),
+                value => {
+                    return Err(anyhow!(
+                        "Expected a definition in the catalog, got {}",
+                        value
+                    ))
+                }
+            }
+        }
+        if !bytes.is_empty() {
+            catalog.statistics = Some(read_statistics(&mut bytes)?);
+        }
+        if !bytes.is_empty() {
+            return Err(anyhow!(
+                "Unexpected {} bytes after the catalog",
+                bytes.len()
+            ));
+        }
+        Ok(catalog)
+    }
+}
+
+fn read_statistics(bytes: &mut &[u8]) -> Result<Statistics> {
+    let rows = read_count(bytes)?;
+    let mut columns = Vec::new();
+    for _ in 0..read_count(bytes)? {
+        let column = match Value::deserialize(bytes)? {
+            Value::Text(column) => column,
+            value => {
+                return Err(anyhow!(
+                    "Expected a column name in the catalog, got {}",
+                    value
+                ))
+            }
+        };
+        let distinct = read_count(bytes)?;
+        let histogram = (0..read_count(bytes)?)
+            .map(|_| Value::deserialize(bytes))
+            .collect::<Result<_>>()?;
+        columns.push(ColumnStatistics {
+            column,
+            distinct,
+            histogram,
+        });
+    }
+    Ok(Statistics { rows, columns })
+}
+
+fn read_count(bytes: &mut &[u8]) -> Result<usize> {
+    match Value::deserialize(bytes)? {
+        Value::Integer(count) if count >= 0 => Ok(count as usize),
+        value => Err(anyhow!("Expected a count in the catalog, got {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Row;
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        assert!(Catalog::default().serialize().is_empty());
+        assert_eq!(Catalog::deserialize(&[]).unwrap(), Catalog::default());
+
+        let catalog = Catalog {
+            definitions: vec![
+                "CREATE INDEX by_name ON users (username)".to_string(),
+                "CREATE UNIQUE INDEX by_email ON users (email, id)".to_string(),
+            ],
+            statistics: None,
+        };
+        let bytes = catalog.serialize();
+        assert_eq!(Catalog::deserialize(&bytes).unwrap(), catalog);
+
+        let err = Catalog::deserialize(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(err.to_string(), "failed to fill whole buffer");
+        let err = Catalog::deserialize(&[2]).unwrap_err();
+        assert_eq!(err.to_string(), "Expected a count in the catalog, got NULL");
+
+        let rows = vec![
+            vec![
+                Value::Integer(1),
+                Value::Text("alice".to_string()),
+                Value::Text("alice@example.com".to_string()),
+            ],
+            vec![
+                Value::Integer(2),
+                Value::Text("bob".to_string()),
+                Value::Null,
+            ],
+        ];
+        for definitions in [Vec::new(), catalog.definitions] {
+            let catalog = Catalog {
+                definitions,
+                statistics: Some(Statistics::collect(&Row::COLUMNS, &rows)),
+            };
+            let mut bytes = catalog.serialize();
+            assert_eq!(Catalog::deserialize(&bytes).unwrap(), catalog);
+            bytes.push(2);
+            let err = Catalog::deserialize(&bytes).unwrap_err();
+            assert_eq!(err.to_string(), "Unexpected 1 bytes after the catalog");
+        }
+    }
+}
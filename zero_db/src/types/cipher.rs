@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use sha2::Sha256;
+
+/// Authenticated encryption of pages with ChaCha20-Poly1305, keyed by a passphrase.
+pub struct PageCipher {
+    cipher: ChaCha20Poly1305,
+}
+impl PageCipher {
+    pub const SALT_SIZE: usize = 16;
+    pub const NONCE_SIZE: usize = 12;
+    pub const TAG_SIZE: usize = 16;
+    /// Bytes added to every encrypted buffer: the nonce in front and the tag at the end.
+    pub const OVERHEAD: usize = Self::NONCE_SIZE + Self::TAG_SIZE;
+    pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+
+    /// Derives the key from the passphrase with PBKDF2-HMAC-SHA256.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8], kdf_iterations: u32) -> PageCipher {
+        let mut key = Key::default();
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, kdf_iterations, &mut key);
+        PageCipher {
+            cipher: ChaCha20Poly1305::new(&key),
+        }
+    }
+
+    pub fn generate_salt() -> [u8; Self::SALT_SIZE] {
+        let mut salt = [0; Self::SALT_SIZE];
+        rand::rng().fill_bytes(&mut salt);
+        salt
+    }
+
+    /// Encrypts `plaintext` under a fresh random nonce. The associated data is authenticated
+    /// but not stored, so the same value must be passed to `decrypt`.
+    pub fn encrypt(&self, associated_data: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = Nonce::default();
+        rand::rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| anyhow!("Encryption failed"))?;
+
+        let mut encrypted = Vec::with_capacity(plaintext.len() + Self::OVERHEAD);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        Ok(encrypted)
+    }
+
+    pub fn decrypt(&self, associated_data: &[u8], encrypted: &[u8]) -> Result<Vec<u8>> {
+        if encrypted.len() < Self::OVERHEAD {
+            return Err(anyhow!("Encrypted data is truncated"));
+        }
+        let (nonce, ciphertext) = encrypted.split_at(Self::NONCE_SIZE);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| anyhow!("Decryption failed: wrong key or corrupted data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_KDF_ITERATIONS: u32 = 1_000;
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let salt = PageCipher::generate_salt();
+        let cipher = PageCipher::from_passphrase("secret", &salt, TEST_KDF_ITERATIONS);
+
+        let encrypted = cipher.encrypt(b"page 1", b"hello world").unwrap();
+        assert_eq!(encrypted.len(), b"hello world".len() + PageCipher::OVERHEAD);
+        assert_ne!(&encrypted[PageCipher::NONCE_SIZE..][..11], b"hello world");
+        assert_eq!(cipher.decrypt(b"page 1", &encrypted).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key() {
+        let salt = PageCipher::generate_salt();
+        let cipher = PageCipher::from_passphrase("secret", &salt, TEST_KDF_ITERATIONS);
+        let other = PageCipher::from_passphrase("guess", &salt, TEST_KDF_ITERATIONS);
+
+        let encrypted = cipher.encrypt(b"page 1", b"hello world").unwrap();
+        let err = other.decrypt(b"page 1", &encrypted).unwrap_err();
+        assert_eq!(err.to_string(), "Decryption failed: wrong key or corrupted data");
+    }
+
+    #[test]
+    fn test_decrypt_detects_tampering() {
+        let salt = PageCipher::generate_salt();
+        let cipher = PageCipher::from_passphrase("secret", &salt, TEST_KDF_ITERATIONS);
+
+        let mut encrypted = cipher.encrypt(b"page 1", b"hello world").unwrap();
+        // Data moved to another page fails authentication as well as modified data.
+        assert!(cipher.decrypt(b"page 2", &encrypted).is_err());
+        encrypted[PageCipher::NONCE_SIZE] ^= 1;
+        assert!(cipher.decrypt(b"page 1", &encrypted).is_err());
+        assert!(cipher.decrypt(b"page 1", &encrypted[..10]).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// Compression applied to pages before they are written to the database file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+}
+impl Compression {
+    pub fn from_str(input: &str) -> Result<Compression> {
+        match input {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(anyhow!("Unknown compression: {}", input)),
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+        }
+    }
+
+    pub fn from_u32(value: u32) -> Result<Compression> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            _ => Err(anyhow!("Unknown compression id: {}", value)),
+        }
+    }
+
+    pub fn compress(self, bytes: Vec<u8>) -> Vec<u8> {
+        match self {
+            Compression::None => bytes,
+            Compression::Lz4 => lz4_flex::compress(&bytes),
+        }
+    }
+
+    pub fn decompress(self, bytes: &[u8], page_size: usize) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Lz4 => lz4_flex::decompress(bytes, page_size)
+                .map_err(|err| anyhow!("Decompression failed: {}", err)),
+        }
+    }
+}
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+/// Space taken by the pages of a database file compared to their uncompressed size.
+#[derive(Debug, PartialEq)]
+pub struct CompressionStats {
+    pub compression: Compression,
+    pub pages: usize,
+    pub uncompressed_bytes: u64,
+    pub stored_bytes: u64,
+}
+impl CompressionStats {
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 1.0;
+        }
+        self.uncompressed_bytes as f64 / self.stored_bytes as f64
+    }
+}
+impl fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "compression: {}, pages: {}, uncompressed: {} bytes, stored: {} bytes, ratio: {:.2}",
+            self.compression,
+            self.pages,
+            self.uncompressed_bytes,
+            self.stored_bytes,
+            self.ratio()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Compression::from_str("none").unwrap(), Compression::None);
+        assert_eq!(Compression::from_str("lz4").unwrap(), Compression::Lz4);
+        let err = Compression::from_str("zip").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown compression: zip");
+    }
+
+    #[test]
+    fn test_lz4_roundtrip() {
+        let mut bytes = b"alice@example.com".repeat(10);
+        bytes.resize(4096, 0);
+
+        let compressed = Compression::Lz4.compress(bytes.clone());
+        assert!(compressed.len() < bytes.len());
+        assert_eq!(Compression::Lz4.decompress(&compressed, 4096).unwrap(), bytes);
+        assert!(Compression::Lz4.decompress(&[0xff; 8], 4096).is_err());
+    }
+
+    #[test]
+    fn test_stats_display() {
+        let stats = CompressionStats {
+            compression: Compression::Lz4,
+            pages: 2,
+            uncompressed_bytes: 8192,
+            stored_bytes: 1024,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "compression: lz4, pages: 2, uncompressed: 8192 bytes, stored: 1024 bytes, ratio: 8.00"
+        );
+    }
+}
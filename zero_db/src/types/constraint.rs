@@ -0,0 +1,351 @@
+use crate::types::tokenizer::quote_identifier;
+use crate::types::{Expression, Row, Schema, TableDefinition, Tuple, Value};
+use anyhow::{anyhow, Context, Result};
+
+/// A constraint added by `ALTER TABLE users ADD CONSTRAINT name CHECK (expression)`, which
+/// every row of the table must satisfy when it is inserted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckConstraint {
+    pub name: String,
+    pub expression: Expression,
+}
+impl CheckConstraint {
+    /// A constraint whose expression only refers to columns of `table`, without subqueries,
+    /// aggregate or window functions, so that it depends on one row alone.
+    pub fn new(
+        name: &str,
+        expression: Expression,
+        table: &TableDefinition,
+    ) -> Result<CheckConstraint> {
+        if expression.contains_subquery() {
+            return Err(anyhow!("Subqueries are not allowed in CHECK constraints"));
+        }
+        if expression.contains_aggregate() || expression.contains_window() {
+            return Err(anyhow!(
+                "Aggregate and window functions are not allowed in CHECK constraints"
+            ));
+        }
+        expression.check_columns(&[&table.schema(&table.name)])?;
+        Ok(CheckConstraint {
+            name: name.to_string(),
+            expression,
+        })
+    }
+
+    /// The ALTER TABLE statement that adds the constraint to `table` again, as stored in the
+    /// `Catalog`.
+    pub fn definition(&self, table: &TableDefinition) -> String {
+        format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} CHECK ({})",
+            quote_identifier(&table.name),
+            quote_identifier(&self.name),
+            self.expression
+        )
+    }
+
+    /// Whether a row of `table` with `values` satisfies the constraint. As in SQL, only a
+    /// false expression fails it, and NULL does not.
+    pub fn holds(&self, table: &TableDefinition, values: &[Value]) -> Result<bool> {
+        let schema = table.schema(&table.name);
+        let row = Tuple {
+            schema: &schema,
+            values,
+        };
+        Ok(self.expression.evaluate(&row)?.as_bool()?.unwrap_or(true))
+    }
+
+    /// Follows the rename of the table `name` to `new_name`, which the columns of the
+    /// expression can be qualified by.
+    pub fn rename_table(&mut self, name: &str, new_name: &str) {
+        self.expression.rename_columns(&mut |table, _| {
+            if table.as_deref() == Some(name) {
+                *table = Some(new_name.to_string());
+            }
+        });
+    }
+
+    /// Follows the rename of the column `column` of the table to `new_name`.
+    pub fn rename_column(&mut self, column: &str, new_name: &str) {
+        self.expression.rename_columns(&mut |_, name| {
+            if name == column {
+                *name = new_name.to_string();
+            }
+        });
+    }
+}
+
+/// The value set by `ALTER TABLE users ALTER COLUMN column SET DEFAULT expression`, which
+/// an insert leaving out the column gives it, see `Statement::parse_insert_input`. The
+/// columns of an insert are given in order, so only the last ones can be left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnDefault {
+    pub column: String,
+    pub value: Value,
+}
+impl ColumnDefault {
+    /// The default of `column` of `table` to the value of `expression`, which must be a
+    /// constant the column can hold.
+    pub fn new(
+        column: &str,
+        expression: &Expression,
+        table: &TableDefinition,
+    ) -> Result<ColumnDefault> {
+        let position = table.position(column)?;
+        if expression.contains_subquery()
+            || expression.contains_aggregate()
+            || expression.contains_window()
+        {
+            return Err(anyhow!("Default value of {} is not constant", column));
+        }
+        expression
+            .check_columns(&[])
+            .map_err(|_| anyhow!("Default value of {} is not constant", column))?;
+        let schema = Schema::default();
+        let value = expression.evaluate(&Tuple {
+            schema: &schema,
+            values: &[],
+        })?;
+        // The value must fit the column as any inserted value does.
+        let mut values = vec![
+            Value::Integer(0),
+            Value::Text(String::new()),
+            Value::Text(String::new()),
+        ];
+        values[position] = value.clone();
+        Row::from_values(&values)
+            .with_context(|| format!("Invalid default value for {}", column))?;
+        Ok(ColumnDefault {
+            column: column.to_string(),
+            value,
+        })
+    }
+
+    /// The ALTER TABLE statement that sets the default of the column of `table` again, as
+    /// stored in the `Catalog`.
+    pub fn definition(&self, table: &TableDefinition) -> String {
+        format!(
+            "ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {}",
+            quote_identifier(&table.name),
+            quote_identifier(&self.column),
+            Expression::Literal(self.value.clone())
+        )
+    }
+}
+
+/// What deleting a row does to the rows whose foreign key refers to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ForeignKeyAction {
+    /// The delete fails, which is also what `NO ACTION` does without deferred constraints.
+    Restrict,
+    /// The rows referring to it are deleted as well.
+    Cascade,
+}
+impl ForeignKeyAction {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            ForeignKeyAction::Restrict => "RESTRICT",
+            ForeignKeyAction::Cascade => "CASCADE",
+        }
+    }
+}
+
+/// A constraint added by `ALTER TABLE users ADD CONSTRAINT name FOREIGN KEY (column)
+/// REFERENCES users (parent_column) [ON DELETE action]`: the value of `column` in every row
+/// must be the value of `parent_column` in some row, the parent, which a unique index on
+/// `parent_column` finds. A row can be its own parent. The database holds a single table,
+/// so it refers to itself, as in a tree of rows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    pub name: String,
+    pub column: String,
+    pub parent_column: String,
+    pub on_delete: ForeignKeyAction,
+    /// Positions of `column` and `parent_column` in the rows of the table.
+    positions: (usize, usize),
+}
+impl ForeignKey {
+    /// A foreign key from `column` to `parent_column`, which must be columns of `table`.
+    pub fn new(
+        name: &str,
+        column: &str,
+        parent_column: &str,
+        on_delete: ForeignKeyAction,
+        table: &TableDefinition,
+    ) -> Result<ForeignKey> {
+        Ok(ForeignKey {
+            name: name.to_string(),
+            column: column.to_string(),
+            parent_column: parent_column.to_string(),
+            on_delete,
+            positions: (table.position(column)?, table.position(parent_column)?),
+        })
+    }
+
+    /// The ALTER TABLE statement that adds the constraint to `table` again, as stored in the
+    /// `Catalog`.
+    pub fn definition(&self, table: &TableDefinition) -> String {
+        let table = quote_identifier(&table.name);
+        format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({}) ON DELETE {}",
+            table,
+            quote_identifier(&self.name),
+            quote_identifier(&self.column),
+            table,
+            quote_identifier(&self.parent_column),
+            self.on_delete.keyword()
+        )
+    }
+
+    /// The value a row with `values` refers to its parent by.
+    pub fn key(&self, values: &[Value]) -> Value {
+        values[self.positions.0].clone()
+    }
+
+    /// The value the rows referring to a row with `values` have in `column`.
+    pub fn parent_key(&self, values: &[Value]) -> Value {
+        values[self.positions.1].clone()
+    }
+
+    /// Follows the rename of the column `column` of the table to `new_name`.
+    pub fn rename_column(&mut self, column: &str, new_name: &str) {
+        for name in [&mut self.column, &mut self.parent_column] {
+            if name == column {
+                *name = new_name.to_string();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Parser;
+
+    fn check(expression: &str) -> Result<CheckConstraint> {
+        let expression = Parser::new(expression)?.parse_expression()?;
+        CheckConstraint::new("positive id", expression, &TableDefinition::default())
+    }
+
+    #[test]
+    fn test_holds() {
+        let values = |id: Value| {
+            vec![
+                id,
+                Value::Text("alice".to_string()),
+                Value::Text("alice@example.com".to_string()),
+            ]
+        };
+        let table = TableDefinition::default();
+        let constraint = check("id > 0 and email <> ''").unwrap();
+        assert!(constraint
+            .holds(&table, &values(Value::Integer(1)))
+            .unwrap());
+        assert!(!constraint
+            .holds(&table, &values(Value::Integer(0)))
+            .unwrap());
+        assert!(constraint.holds(&table, &values(Value::Null)).unwrap());
+        assert_eq!(
+            constraint.definition(&table),
+            "ALTER TABLE users ADD CONSTRAINT \"positive id\" CHECK ((id > 0) AND (email != ''))"
+        );
+
+        let err = check("username")
+            .unwrap()
+            .holds(&table, &values(Value::Integer(1)));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Expected a boolean condition but got TEXT"
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut constraint = check("users.id > 0 and id < 100").unwrap();
+        constraint.rename_table("users", "people");
+        constraint.rename_column("id", "number");
+        let columns = ["number", "username", "email"].map(String::from);
+        let table = TableDefinition::new("people", &columns).unwrap();
+        assert!(constraint
+            .expression
+            .check_columns(&[&table.schema("people")])
+            .is_ok());
+        assert_eq!(
+            constraint.definition(&table),
+            "ALTER TABLE people ADD CONSTRAINT \"positive id\" CHECK \
+             ((people.number > 0) AND (number < 100))"
+        );
+
+        let mut foreign_key = ForeignKey::new(
+            "boss",
+            "email",
+            "username",
+            ForeignKeyAction::Cascade,
+            &TableDefinition::default(),
+        )
+        .unwrap();
+        foreign_key.rename_column("username", "name");
+        let columns = ["id", "name", "email"].map(String::from);
+        let table = TableDefinition::new("people", &columns).unwrap();
+        assert_eq!(
+            foreign_key.definition(&table),
+            "ALTER TABLE people ADD CONSTRAINT boss FOREIGN KEY (email) REFERENCES people \
+             (name) ON DELETE CASCADE"
+        );
+        let values = ["1", "alice", "bob"].map(|value| Value::Text(value.to_string()));
+        assert_eq!(foreign_key.key(&values), values[2]);
+        assert_eq!(foreign_key.parent_key(&values), values[1]);
+    }
+
+    #[test]
+    fn test_new_errors() {
+        let cases = [
+            ("age > 0", "No such column: age"),
+            ("u.id > 0", "No such column: u.id"),
+            (
+                "id in (select id from users)",
+                "Subqueries are not allowed in CHECK constraints",
+            ),
+            (
+                "count(*) > 0",
+                "Aggregate and window functions are not allowed in CHECK constraints",
+            ),
+        ];
+        for (expression, expected) in cases {
+            let err = check(expression).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn test_column_default() {
+        let default = |column: &str, expression: &str| {
+            let expression = Parser::new(expression)?.parse_expression()?;
+            ColumnDefault::new(column, &expression, &TableDefinition::default())
+        };
+        let email = default("email", "'none@example.com'").unwrap();
+        assert_eq!(email.value, Value::Text("none@example.com".to_string()));
+        assert_eq!(
+            email.definition(&TableDefinition::default()),
+            "ALTER TABLE users ALTER COLUMN email SET DEFAULT 'none@example.com'"
+        );
+        assert_eq!(default("id", "-(2 * 3)").unwrap().value, Value::Integer(-6));
+
+        let long = format!("'{}'", "a".repeat(33));
+        let cases = [
+            ("age", "1", "No such column: age"),
+            ("id", "id + 1", "Default value of id is not constant"),
+            ("id", "(select 1)", "Default value of id is not constant"),
+            ("id", "count(*)", "Default value of id is not constant"),
+            ("id", "'one'", "Invalid default value for id"),
+            (
+                "username",
+                long.as_str(),
+                "Invalid default value for username",
+            ),
+        ];
+        for (column, expression, expected) in cases {
+            let err = default(column, expression).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", expression);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::types::join::RowSource;
+use crate::types::{IndexLookup, Table, Value};
+use anyhow::{anyhow, Result};
+
+/// Walks the rows of a table in storage order, loading each page only when the cursor
+/// reaches it. Stopping early leaves the remaining pages unread. The table is passed to
+/// each call rather than borrowed, so rows can be evaluated against it, as subqueries do,
+/// while the cursor is open.
+#[derive(Default)]
+pub struct Cursor {
+    page_num: usize,
+    row_num: usize,
+}
+impl Cursor {
+    pub fn table_start() -> Cursor {
+        Cursor::default()
+    }
+}
+impl RowSource for Cursor {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        table.check_exists()?;
+        while self.page_num < table.pager.num_pages() {
+            let page = table.pager.get_page(self.page_num)?;
+            if let Some(row) = page.rows.get(self.row_num) {
+                self.row_num += 1;
+                return Ok(Some(row.values()));
+            }
+            self.page_num += 1;
+            self.row_num = 0;
+        }
+        Ok(None)
+    }
+}
+
+/// Walks the rows of a common table expression, as computed by the query that defines it,
+/// see `Table::common_table`.
+pub struct CommonTableCursor {
+    name: String,
+    row_num: usize,
+}
+impl CommonTableCursor {
+    pub fn new(name: &str) -> CommonTableCursor {
+        CommonTableCursor {
+            name: name.to_string(),
+            row_num: 0,
+        }
+    }
+}
+impl RowSource for CommonTableCursor {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        let rows = table
+            .common_table(&self.name)
+            .ok_or_else(|| anyhow!("No rows computed for {}", self.name))?;
+        let row = rows.get(self.row_num).cloned();
+        self.row_num += 1;
+        Ok(row)
+    }
+}
+
+/// Reads the rows an index lookup finds, in storage order. The index is searched when the
+/// first row is asked for, see `Index::lookup`.
+pub struct IndexCursor {
+    lookup: IndexLookup,
+    rows: Option<std::vec::IntoIter<usize>>,
+}
+impl IndexCursor {
+    pub fn new(lookup: IndexLookup) -> IndexCursor {
+        IndexCursor { lookup, rows: None }
+    }
+
+    /// Starts over from the first row, searching the index again.
+    pub fn rewind(&mut self) {
+        self.rows = None;
+    }
+}
+impl RowSource for IndexCursor {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        if self.rows.is_none() {
+            let index = table
+                .indexes
+                .iter()
+                .find(|index| index.name == self.lookup.index)
+                .ok_or_else(|| anyhow!("No such index: {}", self.lookup.index))?;
+            self.rows = Some(index.lookup(&self.lookup).into_iter());
+        }
+        match self.rows.as_mut().and_then(Iterator::next) {
+            Some(row) => table.read_row(row).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Walks the rows of the `zero_stat` system table, which has none until ANALYZE runs, see
+/// `Statistics::table_rows`.
+#[derive(Default)]
+pub struct StatisticsCursor {
+    row_num: usize,
+}
+impl RowSource for StatisticsCursor {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        let row = table.statistics.as_ref().and_then(|statistics| {
+            statistics
+                .table_rows(&table.definition.name)
+                .nth(self.row_num)
+        });
+        self.row_num += 1;
+        Ok(row)
+    }
+}
+
+/// Returns one row without columns, the FROM clause of a query of a WITH clause that has
+/// none.
+#[derive(Default)]
+pub struct SingleRowCursor {
+    done: bool,
+}
+impl RowSource for SingleRowCursor {
+    fn next_row(&mut self, _table: &mut Table) -> Result<Option<Vec<Value>>> {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        Ok(Some(Vec::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Page, Row};
+    use heapless::String as HeapLessString;
+
+    #[test]
+    fn test_walks_every_page() {
+        let mut table = Table::new();
+        let rows_per_page = Page::max_rows_per_page(4096);
+        for id in 0..rows_per_page as i32 * 2 + 1 {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from("alice").unwrap(),
+                email: HeapLessString::try_from("alice@example.com").unwrap(),
+            };
+            if (id as usize).is_multiple_of(rows_per_page) {
+                table.pager.allocate_page();
+            }
+            let page_num = id as usize / rows_per_page;
+            table
+                .pager
+                .get_page_mut(page_num)
+                .unwrap()
+                .add_row(row)
+                .unwrap();
+        }
+        let mut cursor = Cursor::table_start();
+        let mut ids = Vec::new();
+        while let Some(values) = cursor.next_row(&mut table).unwrap() {
+            ids.push(values[0].clone());
+        }
+        let expected: Vec<Value> = (0..rows_per_page as i64 * 2 + 1)
+            .map(Value::Integer)
+            .collect();
+        assert_eq!(ids, expected);
+    }
+}
@@ -1,28 +1,91 @@
 use crate::types::row::Row;
 use crate::types::statement::Statement;
+use crate::types::{
+    CommonTableExpression, Compression, CompressionStats, DatabaseOptions, Expression,
+    ForeignKeyAction, Index, ResultSet, Value, VirtualMachine,
+};
 use anyhow::{anyhow, Result};
+use std::path::Path;
 
 #[derive(Debug, PartialEq)]
 pub enum MetaCommand {
     Exit,
+    Open {
+        path: String,
+        options: DatabaseOptions,
+    },
+    Save {
+        path: String,
+        options: DatabaseOptions,
+    },
 }
 impl MetaCommand {
     pub fn from_str(input: &str) -> Result<MetaCommand> {
-        match input {
-            "exit" => Ok(MetaCommand::Exit),
+        let mut parts = input.split_whitespace();
+        match parts.next() {
+            Some("exit") if parts.next().is_none() => Ok(MetaCommand::Exit),
+            Some("open") => {
+                let (path, options) = Self::parse_database_file("open", parts)?;
+                Ok(MetaCommand::Open { path, options })
+            }
+            Some("save") => {
+                let (path, options) = Self::parse_database_file("save", parts)?;
+                Ok(MetaCommand::Save { path, options })
+            }
             _ => Err(anyhow!("Invalid meta-command")),
         }
     }
-    pub fn execute(self) {
+    // Parses the arguments shared by `.open` and `.save`:
+    // `FILE [--key PASSPHRASE] [--compression none|lz4] [--mmap-size BYTES]`.
+    fn parse_database_file<'a>(
+        command: &str,
+        mut parts: impl Iterator<Item = &'a str>,
+    ) -> Result<(String, DatabaseOptions)> {
+        let path = parts
+            .next()
+            .ok_or_else(|| anyhow!("No database file provided"))?
+            .to_string();
+        let mut options = DatabaseOptions::default();
+        while let Some(option) = parts.next() {
+            match option {
+                "--key" => {
+                    let passphrase = parts
+                        .next()
+                        .ok_or_else(|| anyhow!("Option --key requires a passphrase"))?;
+                    options.key = Some(passphrase.to_string());
+                }
+                "--compression" => {
+                    let compression = parts
+                        .next()
+                        .ok_or_else(|| anyhow!("Option --compression requires a mode"))?;
+                    options.compression = Some(Compression::from_str(compression)?);
+                }
+                "--mmap-size" => {
+                    options.mmap_size = parts
+                        .next()
+                        .and_then(|size| size.parse().ok())
+                        .ok_or_else(|| anyhow!("Option --mmap-size requires a size in bytes"))?;
+                }
+                _ => return Err(anyhow!("Unknown option for .{}: {}", command, option)),
+            }
+        }
+        Ok((path, options))
+    }
+    pub fn execute(self, vm: &mut VirtualMachine) -> Result<()> {
         match self {
-            MetaCommand::Exit => std::process::exit(0),
+            MetaCommand::Exit => {
+                vm.close()?;
+                std::process::exit(0)
+            }
+            MetaCommand::Open { path, options } => vm.open(Path::new(&path), &options),
+            MetaCommand::Save { path, options } => vm.save(Path::new(&path), &options),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum PrepareResult {
-    Success(Statement),
+    Success(Box<Statement>),
     SyntaxError(String),
     UnrecognizedStatement,
 }
@@ -30,19 +93,123 @@ pub enum PrepareResult {
 #[derive(Debug)]
 pub enum ExecutionFailure {
     TableFull,
+    /// A row would repeat the `value` another row has in the columns of the unique index
+    /// `constraint`. Values of several columns are separated by commas.
+    UniqueViolation {
+        constraint: String,
+        value: String,
+    },
+    /// A row would not satisfy the CHECK constraint `constraint`.
+    CheckViolation {
+        constraint: String,
+    },
+    /// A row would refer to a parent row that does not exist through the foreign key
+    /// `constraint`, or a delete would leave a row referring to a deleted one.
+    ForeignKeyViolation {
+        constraint: String,
+    },
+}
+impl ExecutionFailure {
+    /// The failure of a row repeating `key`, the values of the columns of `index`.
+    pub fn unique_violation(index: &Index, key: &[Value]) -> ExecutionFailure {
+        let value: Vec<String> = key.iter().map(Value::to_string).collect();
+        ExecutionFailure::UniqueViolation {
+            constraint: index.name.clone(),
+            value: value.join(", "),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ExecuteResult {
     Success(Vec<Row>),
+    ResultSet(ResultSet),
     ExecutionFailure(ExecutionFailure),
+    IntegrityCheck(Vec<String>),
+    CompressionStats(CompressionStats),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[derive(PartialEq)]
 pub enum StatementType {
     Insert,
     Select,
+    /// Deletes the rows `condition` holds for, or every row without one, see
+    /// `Table::delete`.
+    Delete {
+        condition: Option<Expression>,
+    },
+    IntegrityCheck,
+    CompressionStats,
+    /// Shows the recursion limit of common table expressions, or sets it to the given value.
+    RecursionLimit(Option<usize>),
+    /// Shows whether foreign keys are enforced, or turns their enforcement on or off.
+    ForeignKeys(Option<bool>),
+    /// Collects the statistics the planner estimates row counts from, see `Statistics`.
+    Analyze,
+    /// Creates the table again after DROP TABLE, see `Table::create_table`.
+    CreateTable {
+        name: String,
+        columns: Vec<String>,
+    },
+    /// Removes the table and everything defined on it, see `Table::drop_table`.
+    DropTable {
+        name: String,
+    },
+    /// Gives the table a new name, see `Table::rename_table`.
+    RenameTable {
+        name: String,
+        new_name: String,
+    },
+    /// Gives a column of the table a new name, see `Table::rename_column`.
+    RenameColumn {
+        column: String,
+        new_name: String,
+    },
+    /// Creates an index on the given columns of the table, see `Index`.
+    CreateIndex {
+        name: String,
+        columns: Vec<String>,
+        unique: bool,
+    },
+    /// Removes the named index, see `Table::drop_index`.
+    DropIndex {
+        name: String,
+    },
+    /// Creates a view, kept as the common table expression that queries read it as.
+    CreateView(Box<CommonTableExpression>),
+    /// Removes the named view, see `Table::drop_view`.
+    DropView {
+        name: String,
+    },
+    /// Adds a CHECK constraint on the rows of the table, see `CheckConstraint`.
+    AddConstraint {
+        name: String,
+        check: Expression,
+    },
+    /// Adds a foreign key from `column` to `parent_column`, see `ForeignKey`.
+    AddForeignKey {
+        name: String,
+        column: String,
+        parent_column: String,
+        on_delete: ForeignKeyAction,
+    },
+    /// Removes the named constraint, see `Table::drop_constraint`.
+    DropConstraint {
+        name: String,
+    },
+    /// Sets the value inserts leave out `column` with, or removes it when `default` is
+    /// `None`, see `ColumnDefault`.
+    SetDefault {
+        column: String,
+        default: Option<Expression>,
+    },
+    /// Shows the program a statement of the given type compiles into, or with `query_plan`
+    /// the steps its query runs in.
+    Explain {
+        statement_type: Box<StatementType>,
+        query_plan: bool,
+    },
 }
 
 #[cfg(test)]
@@ -60,4 +227,65 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().to_string(), "Invalid meta-command");
     }
+
+    #[test]
+    pub fn test_meta_command_from_str_open() {
+        assert_eq!(
+            MetaCommand::from_str("open test.db").unwrap(),
+            MetaCommand::Open {
+                path: "test.db".to_string(),
+                options: DatabaseOptions::default()
+            }
+        );
+        assert_eq!(
+            MetaCommand::from_str("open test.db --key secret --compression lz4 --mmap-size 4096")
+                .unwrap(),
+            MetaCommand::Open {
+                path: "test.db".to_string(),
+                options: DatabaseOptions {
+                    key: Some("secret".to_string()),
+                    compression: Some(Compression::Lz4),
+                    mmap_size: 4096,
+                }
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_meta_command_from_str_save() {
+        assert_eq!(
+            MetaCommand::from_str("save backup.db --compression lz4").unwrap(),
+            MetaCommand::Save {
+                path: "backup.db".to_string(),
+                options: DatabaseOptions {
+                    compression: Some(Compression::Lz4),
+                    ..Default::default()
+                }
+            }
+        );
+
+        let err = MetaCommand::from_str("save").unwrap_err();
+        assert_eq!(err.to_string(), "No database file provided");
+
+        let err = MetaCommand::from_str("save backup.db --verbose").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown option for .save: --verbose");
+    }
+
+    #[test]
+    pub fn test_meta_command_from_str_open_invalid() {
+        let err = MetaCommand::from_str("open").unwrap_err();
+        assert_eq!(err.to_string(), "No database file provided");
+
+        let err = MetaCommand::from_str("open test.db --key").unwrap_err();
+        assert_eq!(err.to_string(), "Option --key requires a passphrase");
+
+        let err = MetaCommand::from_str("open test.db --compression zip").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown compression: zip");
+
+        let err = MetaCommand::from_str("open test.db --mmap-size lots").unwrap_err();
+        assert_eq!(err.to_string(), "Option --mmap-size requires a size in bytes");
+
+        let err = MetaCommand::from_str("open test.db --verbose").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown option for .open: --verbose");
+    }
 }
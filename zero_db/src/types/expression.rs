@@ -0,0 +1,865 @@
+use crate::types::tokenizer::quote_identifier;
+use crate::types::{AggregateFunction, Row, Schema, Select, Value, Window, WindowFunction};
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+impl BinaryOperator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOperator::Or => "OR",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Eq => "=",
+            BinaryOperator::NotEq => "!=",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::LtEq => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::GtEq => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+        }
+    }
+
+    /// Whether the operator compares its operands rather than combining them.
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Eq
+                | BinaryOperator::NotEq
+                | BinaryOperator::Lt
+                | BinaryOperator::LtEq
+                | BinaryOperator::Gt
+                | BinaryOperator::GtEq
+        )
+    }
+}
+
+/// The rows a subquery returned, shared between the rows of the enclosing query when the
+/// subquery does not depend on them.
+pub type SubqueryRows = Rc<Vec<Vec<Value>>>;
+
+/// What an expression is evaluated against: a table row, the combined row of a join, or a
+/// group of rows whose aggregate values have been computed.
+pub trait Scope {
+    /// Value of the column `[table.]name`, or `None` if there is no such column.
+    fn column(&self, table: Option<&str>, name: &str) -> Option<Value>;
+
+    /// Value computed for an aggregate expression, or `None` outside of a grouped query.
+    fn aggregate(&self, _expression: &Expression) -> Option<Value> {
+        None
+    }
+
+    /// Rows of `select` run with this scope as its enclosing query, so that its correlated
+    /// columns resolve against the current row.
+    fn subquery(&self, _select: &Select) -> Result<SubqueryRows> {
+        Err(anyhow!("Subqueries are not supported here"))
+    }
+
+    /// Value computed for a window function, or `None` before window functions are computed.
+    fn window(&self, _expression: &Expression) -> Option<Value> {
+        None
+    }
+}
+/// A row on its own has no table alias, so only unqualified names resolve.
+impl Scope for Row {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<Value> {
+        match table {
+            Some(_) => None,
+            None => self.value(name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    /// A column reference, optionally qualified by a table name or alias as in `u.id`.
+    Column {
+        table: Option<String>,
+        name: String,
+    },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    /// An aggregate function call, with no argument for `COUNT(*)`.
+    Aggregate {
+        function: AggregateFunction,
+        argument: Option<Box<Expression>>,
+    },
+    /// A subquery used as a value: the first column of its first row, or NULL when it
+    /// returns no rows.
+    Subquery(Box<Select>),
+    /// `EXISTS (subquery)`, true when the subquery returns at least one row.
+    Exists(Box<Select>),
+    /// `operand [NOT] IN (subquery)`.
+    InSubquery {
+        operand: Box<Expression>,
+        subquery: Box<Select>,
+        negated: bool,
+    },
+    /// `function(argument, ...) OVER (window)`, computed from the rows of the query after
+    /// grouping, see `window::compute`.
+    Window {
+        function: WindowFunction,
+        arguments: Vec<Expression>,
+        window: Box<Window>,
+    },
+}
+impl Expression {
+    /// Returns an error naming the first column that is missing from `schemas` or that
+    /// matches more than one column of a schema. `schemas` holds the schema of the query
+    /// the expression belongs to followed by those of its enclosing queries, innermost
+    /// first, and a column resolves against the first schema that has it.
+    pub fn check_columns(&self, schemas: &[&Schema]) -> Result<()> {
+        match self {
+            Expression::Literal(_) => Ok(()),
+            Expression::Column { table, name } => {
+                for schema in schemas {
+                    if schema.find(table.as_deref(), name)?.is_some() {
+                        return Ok(());
+                    }
+                }
+                Err(anyhow!("No such column: {}", self))
+            }
+            Expression::Unary { operand, .. } => operand.check_columns(schemas),
+            Expression::Binary { left, right, .. } => {
+                left.check_columns(schemas)?;
+                right.check_columns(schemas)
+            }
+            Expression::Aggregate { argument, .. } => match argument {
+                Some(argument) => argument.check_columns(schemas),
+                None => Ok(()),
+            },
+            Expression::Subquery(subquery) => Self::check_subquery(subquery, schemas, true),
+            Expression::Exists(subquery) => Self::check_subquery(subquery, schemas, false),
+            Expression::InSubquery {
+                operand, subquery, ..
+            } => {
+                operand.check_columns(schemas)?;
+                Self::check_subquery(subquery, schemas, true)
+            }
+            Expression::Window { .. } => {
+                for operand in self.window_operands() {
+                    operand.check_columns(schemas)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The arguments, PARTITION BY and ORDER BY expressions of a window function call, and
+    /// nothing for any other expression.
+    pub fn window_operands(&self) -> impl Iterator<Item = &Expression> {
+        let (arguments, window) = match self {
+            Expression::Window {
+                arguments, window, ..
+            } => (&arguments[..], Some(window)),
+            _ => (&[][..], None),
+        };
+        let partition_by = window.into_iter().flat_map(|window| &window.partition_by);
+        let order_by = window.into_iter().flat_map(|window| &window.order_by);
+        arguments
+            .iter()
+            .chain(partition_by)
+            .chain(order_by.map(|term| &term.expression))
+    }
+
+    fn check_subquery(subquery: &Select, schemas: &[&Schema], single_column: bool) -> Result<()> {
+        subquery.check_columns(schemas)?;
+        let num_columns = subquery.column_names().len();
+        if single_column && num_columns != 1 {
+            return Err(anyhow!(
+                "Subquery returns {} columns but must return 1",
+                num_columns
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn contains_subquery(&self) -> bool {
+        match self {
+            Expression::Literal(_) | Expression::Column { .. } => false,
+            Expression::Unary { operand, .. } => operand.contains_subquery(),
+            Expression::Binary { left, right, .. } => {
+                left.contains_subquery() || right.contains_subquery()
+            }
+            Expression::Aggregate { argument, .. } => argument
+                .as_ref()
+                .is_some_and(|argument| argument.contains_subquery()),
+            Expression::Subquery(_) | Expression::Exists(_) | Expression::InSubquery { .. } => true,
+            Expression::Window { .. } => self.window_operands().any(Self::contains_subquery),
+        }
+    }
+
+    /// Appends every aggregate call in the expression to `aggregates`, skipping duplicates.
+    /// Aggregate calls inside a subquery belong to the subquery and are not collected.
+    pub fn collect_aggregates(&self, aggregates: &mut Vec<Expression>) {
+        match self {
+            Expression::Literal(_)
+            | Expression::Column { .. }
+            | Expression::Subquery(_)
+            | Expression::Exists(_) => {}
+            Expression::InSubquery { operand, .. } => operand.collect_aggregates(aggregates),
+            Expression::Unary { operand, .. } => operand.collect_aggregates(aggregates),
+            Expression::Binary { left, right, .. } => {
+                left.collect_aggregates(aggregates);
+                right.collect_aggregates(aggregates);
+            }
+            Expression::Aggregate { .. } => {
+                if !aggregates.contains(self) {
+                    aggregates.push(self.clone());
+                }
+            }
+            // An aggregate window function is not an aggregate of the query, but its
+            // operands can use the aggregates of a grouped query.
+            Expression::Window { .. } => {
+                for operand in self.window_operands() {
+                    operand.collect_aggregates(aggregates);
+                }
+            }
+        }
+    }
+
+    pub fn contains_aggregate(&self) -> bool {
+        let mut aggregates = Vec::new();
+        self.collect_aggregates(&mut aggregates);
+        !aggregates.is_empty()
+    }
+
+    /// Appends every window function call in the expression to `windows`, skipping
+    /// duplicates. Window functions cannot be nested, and those of a subquery belong to it.
+    pub fn collect_windows(&self, windows: &mut Vec<Expression>) {
+        match self {
+            Expression::Literal(_)
+            | Expression::Column { .. }
+            | Expression::Subquery(_)
+            | Expression::Exists(_) => {}
+            Expression::InSubquery { operand, .. } => operand.collect_windows(windows),
+            Expression::Unary { operand, .. } => operand.collect_windows(windows),
+            Expression::Binary { left, right, .. } => {
+                left.collect_windows(windows);
+                right.collect_windows(windows);
+            }
+            Expression::Aggregate { argument, .. } => {
+                if let Some(argument) = argument {
+                    argument.collect_windows(windows);
+                }
+            }
+            Expression::Window { .. } => {
+                if !windows.contains(self) {
+                    windows.push(self.clone());
+                }
+            }
+        }
+    }
+
+    pub fn contains_window(&self) -> bool {
+        let mut windows = Vec::new();
+        self.collect_windows(&mut windows);
+        !windows.is_empty()
+    }
+
+    /// Calls `rename` with the table and name of every column the expression refers to,
+    /// which it can change, as when ALTER TABLE renames the table or a column. The columns
+    /// of subqueries are not visited, as they can refer to other tables.
+    pub fn rename_columns(&mut self, rename: &mut impl FnMut(&mut Option<String>, &mut String)) {
+        match self {
+            Expression::Literal(_) | Expression::Subquery(_) | Expression::Exists(_) => {}
+            Expression::Column { table, name } => rename(table, name),
+            Expression::Unary { operand, .. } | Expression::InSubquery { operand, .. } => {
+                operand.rename_columns(rename)
+            }
+            Expression::Binary { left, right, .. } => {
+                left.rename_columns(rename);
+                right.rename_columns(rename);
+            }
+            Expression::Aggregate { argument, .. } => {
+                if let Some(argument) = argument {
+                    argument.rename_columns(rename);
+                }
+            }
+            Expression::Window {
+                arguments, window, ..
+            } => {
+                let order_by = window.order_by.iter_mut().map(|term| &mut term.expression);
+                for operand in arguments
+                    .iter_mut()
+                    .chain(&mut window.partition_by)
+                    .chain(order_by)
+                {
+                    operand.rename_columns(rename);
+                }
+            }
+        }
+    }
+
+    /// Appends the subqueries of the expression to `subqueries`, but not those nested in
+    /// them, which belong to the subqueries themselves.
+    pub fn collect_subqueries<'a>(&'a self, subqueries: &mut Vec<&'a Select>) {
+        match self {
+            Expression::Literal(_) | Expression::Column { .. } => {}
+            Expression::Unary { operand, .. } => operand.collect_subqueries(subqueries),
+            Expression::Binary { left, right, .. } => {
+                left.collect_subqueries(subqueries);
+                right.collect_subqueries(subqueries);
+            }
+            Expression::Aggregate { argument, .. } => {
+                if let Some(argument) = argument {
+                    argument.collect_subqueries(subqueries);
+                }
+            }
+            Expression::Subquery(subquery) | Expression::Exists(subquery) => {
+                subqueries.push(subquery)
+            }
+            Expression::InSubquery {
+                operand, subquery, ..
+            } => {
+                operand.collect_subqueries(subqueries);
+                subqueries.push(subquery);
+            }
+            Expression::Window { .. } => {
+                for operand in self.window_operands() {
+                    operand.collect_subqueries(subqueries);
+                }
+            }
+        }
+    }
+
+    pub fn evaluate(&self, scope: &dyn Scope) -> Result<Value> {
+        match self {
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::Column { table, name } => scope
+                .column(table.as_deref(), name)
+                .ok_or_else(|| anyhow!("No such column: {}", self)),
+            Expression::Unary { operator, operand } => {
+                Self::apply_unary(*operator, operand.evaluate(scope)?)
+            }
+            // AND and OR only evaluate their right side when it decides the result. An
+            // unknown (NULL) side makes the result NULL unless the other side decides it.
+            Expression::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                let deciding = *operator == BinaryOperator::Or;
+                let left = left.evaluate(scope)?;
+                if left.as_bool()? == Some(deciding) {
+                    return Ok(Value::from_bool(deciding));
+                }
+                Self::apply_binary(*operator, left, right.evaluate(scope)?)
+            }
+            Expression::Binary {
+                operator,
+                left,
+                right,
+            } => Self::apply_binary(*operator, left.evaluate(scope)?, right.evaluate(scope)?),
+            Expression::Aggregate { function, .. } => scope
+                .aggregate(self)
+                .ok_or_else(|| anyhow!("Misuse of aggregate function {}()", function)),
+            Expression::Subquery(subquery) => Ok(scope
+                .subquery(subquery)?
+                .first()
+                .and_then(|row| row.first().cloned())
+                .unwrap_or(Value::Null)),
+            Expression::Exists(subquery) => {
+                Ok(Value::from_bool(!scope.subquery(subquery)?.is_empty()))
+            }
+            Expression::InSubquery {
+                operand,
+                subquery,
+                negated,
+            } => {
+                let value = operand.evaluate(scope)?;
+                let rows = scope.subquery(subquery)?;
+                let found = Self::contains(&value, rows.iter().map(|row| &row[0]));
+                Ok(match found {
+                    Some(found) => Value::from_bool(found != *negated),
+                    None => Value::Null,
+                })
+            }
+            Expression::Window { function, .. } => scope
+                .window(self)
+                .ok_or_else(|| anyhow!("Misuse of window function {}()", function)),
+        }
+    }
+
+    // `value IN (values)`: true if an equal value is found, false if not, and unknown when
+    // the answer depends on a NULL, as `value = NULL` is unknown.
+    fn contains<'a>(value: &Value, values: impl Iterator<Item = &'a Value>) -> Option<bool> {
+        let mut unknown = false;
+        for candidate in values {
+            if *value == Value::Null || *candidate == Value::Null {
+                unknown = true;
+            } else if value.compare(candidate) == Ordering::Equal {
+                return Some(true);
+            }
+        }
+        if unknown {
+            None
+        } else {
+            Some(false)
+        }
+    }
+
+    pub fn apply_unary(operator: UnaryOperator, value: Value) -> Result<Value> {
+        match operator {
+            UnaryOperator::Not => Ok(match value.as_bool()? {
+                Some(value) => Value::from_bool(!value),
+                None => Value::Null,
+            }),
+            UnaryOperator::Negate => match value {
+                Value::Null => Ok(Value::Null),
+                Value::Integer(value) => value
+                    .checked_neg()
+                    .map(Value::Integer)
+                    .ok_or_else(|| anyhow!("Integer overflow")),
+                Value::Real(value) => Ok(Value::Real(-value)),
+                _ => Err(anyhow!("Cannot negate {}", value.type_name())),
+            },
+        }
+    }
+
+    /// Applies an operator to the values of both its sides. Unlike `evaluate`, AND and OR
+    /// see both sides, which decide the result as they would if it were evaluated.
+    pub fn apply_binary(operator: BinaryOperator, left: Value, right: Value) -> Result<Value> {
+        if let BinaryOperator::And | BinaryOperator::Or = operator {
+            let deciding = operator == BinaryOperator::Or;
+            return Ok(match (left.as_bool()?, right.as_bool()?) {
+                (Some(left), _) if left == deciding => Value::from_bool(deciding),
+                (_, Some(right)) if right == deciding => Value::from_bool(deciding),
+                (Some(_), Some(_)) => Value::from_bool(!deciding),
+                _ => Value::Null,
+            });
+        }
+        if left == Value::Null || right == Value::Null {
+            return Ok(Value::Null);
+        }
+        let ordering = left.compare(&right);
+        let result = match operator {
+            BinaryOperator::Eq => ordering == Ordering::Equal,
+            BinaryOperator::NotEq => ordering != Ordering::Equal,
+            BinaryOperator::Lt => ordering == Ordering::Less,
+            BinaryOperator::LtEq => ordering != Ordering::Greater,
+            BinaryOperator::Gt => ordering == Ordering::Greater,
+            BinaryOperator::GtEq => ordering != Ordering::Less,
+            _ => return Self::apply_arithmetic(operator, left, right),
+        };
+        Ok(Value::from_bool(result))
+    }
+
+    // Integers stay integers, and any REAL operand makes the result REAL.
+    fn apply_arithmetic(operator: BinaryOperator, left: Value, right: Value) -> Result<Value> {
+        let type_error = || {
+            anyhow!(
+                "Cannot apply {:?} to {} and {}",
+                operator,
+                left.type_name(),
+                right.type_name()
+            )
+        };
+        if let (Value::Integer(left), Value::Integer(right)) = (&left, &right) {
+            if matches!(operator, BinaryOperator::Divide | BinaryOperator::Modulo) && *right == 0 {
+                return Err(anyhow!("Division by zero"));
+            }
+            let result = match operator {
+                BinaryOperator::Add => left.checked_add(*right),
+                BinaryOperator::Subtract => left.checked_sub(*right),
+                BinaryOperator::Multiply => left.checked_mul(*right),
+                BinaryOperator::Divide => left.checked_div(*right),
+                BinaryOperator::Modulo => left.checked_rem(*right),
+                _ => unreachable!("{:?} is not an arithmetic operator", operator),
+            };
+            return result
+                .map(Value::Integer)
+                .ok_or_else(|| anyhow!("Integer overflow"));
+        }
+        let (Some(left), Some(right)) = (left.as_f64(), right.as_f64()) else {
+            return Err(type_error());
+        };
+        if matches!(operator, BinaryOperator::Divide | BinaryOperator::Modulo) && right == 0.0 {
+            return Err(anyhow!("Division by zero"));
+        }
+        Ok(Value::Real(match operator {
+            BinaryOperator::Add => left + right,
+            BinaryOperator::Subtract => left - right,
+            BinaryOperator::Multiply => left * right,
+            BinaryOperator::Divide => left / right,
+            BinaryOperator::Modulo => left % right,
+            _ => unreachable!("{:?} is not an arithmetic operator", operator),
+        }))
+    }
+}
+
+/// Writes the expression back as SQL, parenthesizing every nested binary or NOT expression.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |expression: &Expression| match expression {
+            Expression::Binary { .. }
+            | Expression::InSubquery { .. }
+            | Expression::Unary {
+                operator: UnaryOperator::Not,
+                ..
+            } => format!("({})", expression),
+            _ => expression.to_string(),
+        };
+        match self {
+            Expression::Literal(Value::Text(value)) => write!(f, "'{}'", value.replace('\'', "''")),
+            Expression::Literal(value) => write!(f, "{}", value),
+            Expression::Column {
+                table: Some(table),
+                name,
+            } => write!(f, "{}.{}", quote_identifier(table), quote_identifier(name)),
+            Expression::Column { table: None, name } => write!(f, "{}", quote_identifier(name)),
+            Expression::Unary {
+                operator: UnaryOperator::Not,
+                operand: inner,
+            } => write!(f, "NOT {}", operand(inner)),
+            Expression::Unary {
+                operator: UnaryOperator::Negate,
+                operand: inner,
+            } => write!(f, "-{}", operand(inner)),
+            Expression::Binary {
+                operator,
+                left,
+                right,
+            } => write!(
+                f,
+                "{} {} {}",
+                operand(left),
+                operator.symbol(),
+                operand(right)
+            ),
+            Expression::Aggregate { function, argument } => match argument {
+                Some(argument) => write!(f, "{}({})", function, argument),
+                None => write!(f, "{}(*)", function),
+            },
+            Expression::Subquery(subquery) => write!(f, "({})", subquery),
+            Expression::Exists(subquery) => write!(f, "EXISTS ({})", subquery),
+            Expression::InSubquery {
+                operand: inner,
+                subquery,
+                negated,
+            } => write!(
+                f,
+                "{} {}IN ({})",
+                operand(inner),
+                if *negated { "NOT " } else { "" },
+                subquery
+            ),
+            Expression::Window {
+                function,
+                arguments,
+                window,
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                match (function, arguments.is_empty()) {
+                    (WindowFunction::Aggregate(AggregateFunction::Count), true) => {
+                        write!(f, "count(*) OVER ({})", window)
+                    }
+                    _ => write!(
+                        f,
+                        "{}({}) OVER ({})",
+                        function,
+                        arguments.join(", "),
+                        window
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TableDefinition;
+    use heapless::String as HeapLessString;
+
+    fn row() -> Row {
+        Row {
+            id: 7,
+            username: HeapLessString::try_from("alice").unwrap(),
+            email: HeapLessString::try_from("alice@example.com").unwrap(),
+        }
+    }
+
+    fn binary(operator: BinaryOperator, left: Expression, right: Expression) -> Expression {
+        Expression::Binary {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn column(name: &str) -> Expression {
+        Expression::Column {
+            table: None,
+            name: name.to_string(),
+        }
+    }
+
+    fn integer(value: i64) -> Expression {
+        Expression::Literal(Value::Integer(value))
+    }
+
+    fn text(value: &str) -> Expression {
+        Expression::Literal(Value::Text(value.to_string()))
+    }
+
+    #[test]
+    fn test_evaluate_columns() {
+        assert_eq!(column("id").evaluate(&row()).unwrap(), Value::Integer(7));
+        assert_eq!(
+            column("email").evaluate(&row()).unwrap(),
+            Value::Text("alice@example.com".to_string())
+        );
+        let err = column("age").evaluate(&row()).unwrap_err();
+        assert_eq!(err.to_string(), "No such column: age");
+    }
+
+    #[test]
+    fn test_evaluate_comparisons() {
+        let cases = [
+            (BinaryOperator::Eq, column("id"), integer(7), 1),
+            (BinaryOperator::NotEq, column("id"), integer(7), 0),
+            (BinaryOperator::Lt, column("id"), integer(8), 1),
+            (BinaryOperator::LtEq, column("id"), integer(7), 1),
+            (BinaryOperator::Gt, column("username"), text("bob"), 0),
+            (BinaryOperator::GtEq, column("username"), text("alice"), 1),
+        ];
+        for (operator, left, right, expected) in cases {
+            let expression = binary(operator, left, right);
+            assert_eq!(
+                expression.evaluate(&row()).unwrap(),
+                Value::Integer(expected),
+                "{:?}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_arithmetic() {
+        let expression = binary(
+            BinaryOperator::Modulo,
+            binary(BinaryOperator::Multiply, column("id"), integer(3)),
+            integer(4),
+        );
+        assert_eq!(expression.evaluate(&row()).unwrap(), Value::Integer(1));
+
+        let negated = Expression::Unary {
+            operator: UnaryOperator::Negate,
+            operand: Box::new(column("id")),
+        };
+        assert_eq!(negated.evaluate(&row()).unwrap(), Value::Integer(-7));
+
+        let err = binary(BinaryOperator::Divide, column("id"), integer(0))
+            .evaluate(&row())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Division by zero");
+
+        let err = binary(BinaryOperator::Add, column("username"), integer(1))
+            .evaluate(&row())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Cannot apply Add to TEXT and INTEGER");
+
+        let err = binary(BinaryOperator::Add, integer(i64::MAX), integer(1))
+            .evaluate(&row())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Integer overflow");
+    }
+
+    #[test]
+    fn test_evaluate_logic() {
+        let id_is_7 = binary(BinaryOperator::Eq, column("id"), integer(7));
+        let is_bob = binary(BinaryOperator::Eq, column("username"), text("bob"));
+        let and = binary(BinaryOperator::And, id_is_7.clone(), is_bob.clone());
+        let or = binary(BinaryOperator::Or, id_is_7.clone(), is_bob.clone());
+        let not = Expression::Unary {
+            operator: UnaryOperator::Not,
+            operand: Box::new(is_bob),
+        };
+        assert_eq!(and.evaluate(&row()).unwrap(), Value::Integer(0));
+        assert_eq!(or.evaluate(&row()).unwrap(), Value::Integer(1));
+        assert_eq!(not.evaluate(&row()).unwrap(), Value::Integer(1));
+
+        // The right side is not evaluated once the left side decides the result.
+        let short_circuit = binary(
+            BinaryOperator::Or,
+            id_is_7,
+            binary(BinaryOperator::Divide, integer(1), integer(0)),
+        );
+        assert_eq!(short_circuit.evaluate(&row()).unwrap(), Value::Integer(1));
+    }
+
+    #[test]
+    fn test_evaluate_null() {
+        let null = || Expression::Literal(Value::Null);
+        let is_true = binary(BinaryOperator::Eq, column("id"), integer(7));
+        let is_false = binary(BinaryOperator::Eq, column("id"), integer(8));
+        let cases = [
+            (binary(BinaryOperator::Eq, null(), null()), Value::Null),
+            (
+                binary(BinaryOperator::Add, column("id"), null()),
+                Value::Null,
+            ),
+            (
+                binary(BinaryOperator::And, null(), is_false.clone()),
+                Value::Integer(0),
+            ),
+            (
+                binary(BinaryOperator::And, is_true.clone(), null()),
+                Value::Null,
+            ),
+            (
+                binary(BinaryOperator::Or, null(), is_true),
+                Value::Integer(1),
+            ),
+            (binary(BinaryOperator::Or, is_false, null()), Value::Null),
+            (
+                Expression::Unary {
+                    operator: UnaryOperator::Not,
+                    operand: Box::new(null()),
+                },
+                Value::Null,
+            ),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(
+                expression.evaluate(&row()).unwrap(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_real() {
+        let half = || Expression::Literal(Value::Real(0.5));
+        let cases = [
+            (
+                binary(BinaryOperator::Add, column("id"), half()),
+                Value::Real(7.5),
+            ),
+            (
+                binary(BinaryOperator::Multiply, half(), integer(4)),
+                Value::Real(2.0),
+            ),
+            (
+                binary(BinaryOperator::Lt, half(), integer(1)),
+                Value::Integer(1),
+            ),
+            (
+                binary(
+                    BinaryOperator::Eq,
+                    integer(2),
+                    Expression::Literal(Value::Real(2.0)),
+                ),
+                Value::Integer(1),
+            ),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(
+                expression.evaluate(&row()).unwrap(),
+                expected,
+                "{}",
+                expression
+            );
+        }
+        let err = binary(BinaryOperator::Divide, half(), integer(0))
+            .evaluate(&row())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Division by zero");
+    }
+
+    #[test]
+    fn test_evaluate_aggregate_outside_group() {
+        let count = Expression::Aggregate {
+            function: AggregateFunction::Count,
+            argument: None,
+        };
+        assert_eq!(count.to_string(), "count(*)");
+        let err = count.evaluate(&row()).unwrap_err();
+        assert_eq!(err.to_string(), "Misuse of aggregate function count()");
+    }
+
+    #[test]
+    fn test_display() {
+        let expression = binary(
+            BinaryOperator::And,
+            Expression::Unary {
+                operator: UnaryOperator::Not,
+                operand: Box::new(binary(
+                    BinaryOperator::Eq,
+                    column("username"),
+                    text("o'neil"),
+                )),
+            },
+            binary(
+                BinaryOperator::LtEq,
+                binary(BinaryOperator::Add, column("id"), integer(1)),
+                integer(10),
+            ),
+        );
+        assert_eq!(
+            expression.to_string(),
+            "(NOT (username = 'o''neil')) AND ((id + 1) <= 10)"
+        );
+    }
+
+    #[test]
+    fn test_check_columns() {
+        let schema = TableDefinition::default().schema("users");
+        let expression = binary(BinaryOperator::Eq, column("id"), column("age"));
+        assert!(column("id").check_columns(&[&schema]).is_ok());
+        let err = expression.check_columns(&[&schema]).unwrap_err();
+        assert_eq!(err.to_string(), "No such column: age");
+
+        let qualified = Expression::Column {
+            table: Some("u".to_string()),
+            name: "id".to_string(),
+        };
+        assert_eq!(qualified.to_string(), "u.id");
+        let err = qualified.check_columns(&[&schema]).unwrap_err();
+        assert_eq!(err.to_string(), "No such column: u.id");
+        assert!(qualified
+            .check_columns(&[&TableDefinition::default().schema("u")])
+            .is_ok());
+        assert!(qualified
+            .check_columns(&[&schema, &TableDefinition::default().schema("u")])
+            .is_ok());
+    }
+}
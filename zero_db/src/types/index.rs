@@ -0,0 +1,377 @@
+use crate::types::expression::BinaryOperator;
+use crate::types::tokenizer::quote_identifier;
+use crate::types::{Expression, TableDefinition, Value};
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Bound;
+
+/// The values of the indexed columns of a row followed by the number of the row, ordered by
+/// the values and then the row number, so that rows sharing values each have an entry.
+#[derive(Clone, Debug)]
+struct Entry {
+    key: Vec<Value>,
+    row: usize,
+}
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&self.key, &other.key).then(self.row.cmp(&other.row))
+    }
+}
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+impl Eq for Entry {}
+
+// Keys compare value by value, and a key sorts before the longer keys it is a prefix of.
+fn compare_keys(a: &[Value], b: &[Value]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a.compare(b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// A secondary index created by `CREATE [UNIQUE] INDEX name ON users(column, ...)`: a B-tree
+/// of the values of its columns in every row, each pointing to the number of its row, see
+/// `Table::read_row`. Rows are appended, and keep their number until a DELETE moves the rows
+/// after them up, which rebuilds the index.
+#[derive(Clone, Debug)]
+pub struct Index {
+    pub name: String,
+    pub columns: Vec<String>,
+    /// Whether no two rows may have the same values in the indexed columns. As in SQL, rows
+    /// with a NULL among them never conflict.
+    pub unique: bool,
+    /// Positions of `columns` in the rows of the table.
+    positions: Vec<usize>,
+    entries: BTreeSet<Entry>,
+}
+impl Index {
+    /// An empty index on `columns`, which must be distinct columns of `table`.
+    pub fn new(
+        name: &str,
+        columns: &[String],
+        unique: bool,
+        table: &TableDefinition,
+    ) -> Result<Index> {
+        let mut positions = Vec::with_capacity(columns.len());
+        for column in columns {
+            let position = table.position(column)?;
+            if positions.contains(&position) {
+                return Err(anyhow!("Column {} is indexed twice by {}", column, name));
+            }
+            positions.push(position);
+        }
+        Ok(Index {
+            name: name.to_string(),
+            columns: columns.to_vec(),
+            unique,
+            positions,
+            entries: BTreeSet::new(),
+        })
+    }
+
+    /// The CREATE INDEX statement that builds the index on `table` again, as stored in the
+    /// `Catalog`.
+    pub fn definition(&self, table: &TableDefinition) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|c| quote_identifier(c)).collect();
+        format!(
+            "CREATE {}INDEX {} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            quote_identifier(&self.name),
+            quote_identifier(&table.name),
+            columns.join(", ")
+        )
+    }
+
+    /// Follows the rename of the column `column` of the table to `new_name`.
+    pub fn rename_column(&mut self, column: &str, new_name: &str) {
+        for name in &mut self.columns {
+            if name == column {
+                *name = new_name.to_string();
+            }
+        }
+    }
+
+    /// Adds the entry of row number `row`, whose values are in the order of the columns.
+    pub fn insert(&mut self, values: &[Value], row: usize) {
+        let key = self.key(values);
+        self.entries.insert(Entry { key, row });
+    }
+
+    /// The indexed values of a row that would break the uniqueness of the index if it were
+    /// inserted, because another row already has them.
+    pub fn conflict(&self, values: &[Value]) -> Option<Vec<Value>> {
+        let key = self.key(values);
+        if !self.unique || key.contains(&Value::Null) {
+            return None;
+        }
+        let start = Entry { key, row: 0 };
+        self.entries
+            .range(&start..)
+            .next()
+            .filter(|entry| compare_keys(&entry.key, &start.key).is_eq())
+            .map(|_| start.key)
+    }
+
+    /// Whether a row has `key` as the values of the indexed columns.
+    pub fn contains(&self, key: &[Value]) -> bool {
+        let start = Entry {
+            key: key.to_vec(),
+            row: 0,
+        };
+        self.entries
+            .range(&start..)
+            .next()
+            .is_some_and(|entry| compare_keys(&entry.key, key).is_eq())
+    }
+
+    /// Removes every entry, before the index is filled again.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn key(&self, values: &[Value]) -> Vec<Value> {
+        self.positions.iter().map(|&i| values[i].clone()).collect()
+    }
+
+    /// Numbers of the rows `lookup` finds, in ascending order: those whose first indexed
+    /// values equal `lookup.equal` and whose next one lies between the bounds.
+    pub fn lookup(&self, lookup: &IndexLookup) -> Vec<usize> {
+        let mut start = lookup.equal.clone();
+        if let Bound::Included(value) | Bound::Excluded(value) = &lookup.lower {
+            start.push(value.clone());
+        }
+        let prefix = lookup.equal.len();
+        let start = Entry { key: start, row: 0 };
+        let mut rows: Vec<usize> = self
+            .entries
+            .range(start..)
+            .take_while(|entry| {
+                compare_keys(&entry.key[..prefix], &lookup.equal).is_eq()
+                    && match (&lookup.upper, entry.key.get(prefix)) {
+                        (Bound::Included(upper), Some(value)) => value.compare(upper).is_le(),
+                        (Bound::Excluded(upper), Some(value)) => value.compare(upper).is_lt(),
+                        _ => true,
+                    }
+            })
+            .filter(|entry| match (&lookup.lower, entry.key.get(prefix)) {
+                (Bound::Excluded(lower), Some(value)) => value.compare(lower).is_gt(),
+                _ => true,
+            })
+            .map(|entry| entry.row)
+            .collect();
+        rows.sort_unstable();
+        rows
+    }
+}
+
+/// The rows of the table to read through an index: those whose first indexed columns equal
+/// `equal` and whose next indexed column lies between `lower` and `upper`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexLookup {
+    pub index: String,
+    /// The indexed columns constrained, the one with bounds last if there are any.
+    pub columns: Vec<String>,
+    pub equal: Vec<Value>,
+    pub lower: Bound<Value>,
+    pub upper: Bound<Value>,
+}
+/// Writes the index and the constraints it looks up, with `?` for the values, as in
+/// `idx (username=? AND id>?)`.
+impl fmt::Display for IndexLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms: Vec<String> = self.columns[..self.equal.len()]
+            .iter()
+            .map(|column| format!("{}=?", column))
+            .collect();
+        if let Some(column) = self.columns.get(self.equal.len()) {
+            let bounds = [
+                (&self.lower, BinaryOperator::GtEq, BinaryOperator::Gt),
+                (&self.upper, BinaryOperator::LtEq, BinaryOperator::Lt),
+            ];
+            for (bound, included, excluded) in bounds {
+                match bound {
+                    Bound::Included(_) => terms.push(format!("{}{}?", column, included.symbol())),
+                    Bound::Excluded(_) => terms.push(format!("{}{}?", column, excluded.symbol())),
+                    Bound::Unbounded => {}
+                }
+            }
+        }
+        write!(f, "{} ({})", self.index, terms.join(" AND "))
+    }
+}
+
+/// The rows of the table on the right of a join that an index finds for each row of the
+/// left side: those whose first indexed columns equal the values of `keys` in that row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexProbe {
+    pub index: String,
+    pub columns: Vec<String>,
+    /// Expressions over the columns of the tables joined before, one for each column.
+    pub keys: Vec<Expression>,
+    /// Whether `columns` are all those of a unique index, which makes each search a key
+    /// lookup finding at most one row.
+    pub unique: bool,
+}
+impl IndexProbe {
+    /// The search for the rows whose indexed columns equal `values`, the values of `keys`
+    /// in a row of the left side.
+    pub fn lookup(&self, values: Vec<Value>) -> IndexLookup {
+        IndexLookup {
+            index: self.index.clone(),
+            columns: self.columns.clone(),
+            equal: values,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+}
+/// Writes the index and the columns it is searched on, as in `idx (id=?)`.
+impl fmt::Display for IndexProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| format!("{}=?", column))
+            .collect();
+        write!(f, "{} ({})", self.index, terms.join(" AND "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(columns: &[&str]) -> Index {
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        let mut index = Index::new("idx", &columns, false, &TableDefinition::default()).unwrap();
+        for (row, (id, username)) in [
+            (3, "bob"),
+            (1, "alice"),
+            (2, "bob"),
+            (4, "alice"),
+            (5, "bob"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let values = [
+                Value::Integer(id),
+                Value::Text(username.to_string()),
+                Value::Text(format!("{}@example.com", username)),
+            ];
+            index.insert(&values, row);
+        }
+        index
+    }
+
+    fn lookup(
+        columns: &[&str],
+        equal: Vec<Value>,
+        lower: Bound<i64>,
+        upper: Bound<i64>,
+    ) -> IndexLookup {
+        IndexLookup {
+            index: "idx".to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            equal,
+            lower: lower.map(Value::Integer),
+            upper: upper.map(Value::Integer),
+        }
+    }
+
+    #[test]
+    fn test_index_lookup() {
+        let index = index(&["username", "id"]);
+        let bob = || vec![Value::Text("bob".to_string())];
+        let all_bob = lookup(&["username"], bob(), Bound::Unbounded, Bound::Unbounded);
+        assert_eq!(index.lookup(&all_bob), vec![0, 2, 4]);
+
+        let columns = ["username", "id"];
+        let cases = [
+            (Bound::Included(3), Bound::Unbounded, vec![0, 4]),
+            (Bound::Excluded(3), Bound::Unbounded, vec![4]),
+            (Bound::Unbounded, Bound::Excluded(3), vec![2]),
+            (Bound::Excluded(2), Bound::Included(5), vec![0, 4]),
+            (Bound::Included(6), Bound::Unbounded, vec![]),
+        ];
+        for (lower, upper, expected) in cases {
+            let lookup = lookup(&columns, bob(), lower, upper);
+            assert_eq!(index.lookup(&lookup), expected, "{}", lookup);
+        }
+
+        let carol = vec![Value::Text("carol".to_string())];
+        let lookup = lookup(&["username"], carol, Bound::Unbounded, Bound::Unbounded);
+        assert!(index.lookup(&lookup).is_empty());
+
+        let by_id = self::index(&["id"]);
+        let lookup = self::lookup(&["id"], vec![], Bound::Excluded(1), Bound::Included(3));
+        assert_eq!(by_id.lookup(&lookup), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_index_lookup_display() {
+        let lookup = lookup(
+            &["username", "id"],
+            vec![Value::Text("bob".to_string())],
+            Bound::Excluded(1),
+            Bound::Included(3),
+        );
+        assert_eq!(lookup.to_string(), "idx (username=? AND id>? AND id<=?)");
+    }
+
+    #[test]
+    fn test_new_index_invalid_columns() {
+        let columns =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|c| c.to_string()).collect() };
+        let err = Index::new(
+            "idx",
+            &columns(&["age"]),
+            false,
+            &TableDefinition::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "No such column: age");
+
+        let err = Index::new(
+            "idx",
+            &columns(&["id", "email", "id"]),
+            true,
+            &TableDefinition::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Column id is indexed twice by idx");
+    }
+
+    #[test]
+    fn test_unique_index_conflict() {
+        let columns = vec!["username".to_string(), "email".to_string()];
+        let mut index = Index::new("idx", &columns, true, &TableDefinition::default()).unwrap();
+        let row = |username: &str, email: Value| {
+            vec![Value::Integer(1), Value::Text(username.to_string()), email]
+        };
+        let alice = || Value::Text("alice@example.com".to_string());
+        index.insert(&row("alice", alice()), 0);
+        index.insert(&row("alice", Value::Null), 1);
+
+        assert_eq!(
+            index.conflict(&row("alice", alice())),
+            Some(vec![Value::Text("alice".to_string()), alice()])
+        );
+        assert_eq!(index.conflict(&row("bob", alice())), None);
+        assert_eq!(index.conflict(&row("alice", Value::Null)), None);
+
+        index.unique = false;
+        assert_eq!(index.conflict(&row("alice", alice())), None);
+    }
+}
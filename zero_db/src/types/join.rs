@@ -0,0 +1,890 @@
+use crate::types::planner::{AccessPath, JoinAlgorithm, JoinKeys, QueryPlan};
+use crate::types::{
+    CommonTableCursor, Cursor, Expression, IndexCursor, JoinOperator, Schema, Select,
+    SingleRowCursor, SortedRows, Sorter, StatisticsCursor, Table, TableReference, TableSource,
+    Tuple, Value,
+};
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+
+/// A source of rows pulled one at a time. The table is passed to every call rather than
+/// borrowed by the source, so that the sources of a join can each scan it.
+pub trait RowSource {
+    /// The next row, or `None` once the source is exhausted.
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>>;
+}
+
+/// Reads the rows of a table named in a FROM clause from the start, through `access`.
+pub fn scan(table: &TableReference, access: &AccessPath) -> Box<dyn RowSource> {
+    match table.source {
+        TableSource::Table(_) => match access {
+            // A probe is searched with the keys of a left row, see `NestedLoopJoin`, and
+            // without one reads every row.
+            AccessPath::FullScan | AccessPath::Probe(_) => Box::new(Cursor::table_start()),
+            AccessPath::Index(lookup) => Box::new(IndexCursor::new(lookup.clone())),
+        },
+        TableSource::CommonTable(_) => Box::new(CommonTableCursor::new(&table.name)),
+        TableSource::Statistics => Box::new(StatisticsCursor::default()),
+        TableSource::SingleRow => Box::new(SingleRowCursor::default()),
+    }
+}
+
+/// Builds the operators that produce the rows of the FROM clause of `select`, joining the
+/// tables in the order and with the algorithms chosen by `plan`. The columns of the rows
+/// are in the order of the FROM clause either way.
+pub fn build(select: &Select, plan: &QueryPlan, memory_budget: usize) -> Box<dyn RowSource> {
+    match &plan.reordered {
+        Some(reordered) => Box::new(ColumnOrder::new(
+            join(reordered, plan, memory_budget),
+            select,
+            reordered,
+        )),
+        None => join(select, plan, memory_budget),
+    }
+}
+
+fn join(select: &Select, plan: &QueryPlan, memory_budget: usize) -> Box<dyn RowSource> {
+    let schemas = select.schemas();
+    let mut source = scan(&select.from, &plan.access[0]);
+    for (i, (join, join_plan)) in select.joins.iter().zip(&plan.joins).enumerate() {
+        let condition = JoinCondition {
+            operator: join.operator,
+            table: join.table.clone(),
+            access: plan.access[i + 1].clone(),
+            constraint: join.constraint.clone(),
+            schema: schemas[i + 1].clone(),
+        };
+        let sides = KeyedSides {
+            keys: join_plan.keys.clone(),
+            left_schema: schemas[i].clone(),
+            right_schema: join.table.schema(),
+        };
+        source = match join_plan.algorithm {
+            JoinAlgorithm::NestedLoop => {
+                Box::new(NestedLoopJoin::new(source, condition, schemas[i].clone()))
+            }
+            JoinAlgorithm::Hash => Box::new(HashJoin::new(source, condition, sides, memory_budget)),
+            JoinAlgorithm::SortMerge => {
+                Box::new(MergeJoin::new(source, condition, sides, memory_budget))
+            }
+        };
+    }
+    source
+}
+
+/// Moves the columns of rows joined in another order than the FROM clause of a select back
+/// to the order of its tables.
+struct ColumnOrder {
+    source: Box<dyn RowSource>,
+    /// For each column of the select, its position in the rows of `source`.
+    columns: Vec<usize>,
+}
+impl ColumnOrder {
+    fn new(source: Box<dyn RowSource>, select: &Select, reordered: &Select) -> ColumnOrder {
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+        for table in tables(reordered) {
+            offsets.insert(table.alias(), offset);
+            offset += table.schema().columns.len();
+        }
+        let columns = tables(select)
+            .flat_map(|table| {
+                let start = offsets[table.alias()];
+                start..start + table.schema().columns.len()
+            })
+            .collect();
+        ColumnOrder { source, columns }
+    }
+}
+impl RowSource for ColumnOrder {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        let Some(values) = self.source.next_row(table)? else {
+            return Ok(None);
+        };
+        Ok(Some(
+            self.columns.iter().map(|&i| values[i].clone()).collect(),
+        ))
+    }
+}
+
+fn tables(select: &Select) -> impl Iterator<Item = &TableReference> {
+    std::iter::once(&select.from).chain(select.joins.iter().map(|join| &join.table))
+}
+
+/// How the rows of both sides of a join combine, shared by every join algorithm.
+struct JoinCondition {
+    operator: JoinOperator,
+    /// The joined table, whose rows form the right side.
+    table: TableReference,
+    access: AccessPath,
+    constraint: Option<Expression>,
+    /// Schema of the combined rows.
+    schema: Schema,
+}
+impl JoinCondition {
+    /// The combined row, if it satisfies the join constraint.
+    fn combine(&self, left: &[Value], right: &[Value]) -> Result<Option<Vec<Value>>> {
+        let mut values = Vec::with_capacity(left.len() + right.len());
+        values.extend_from_slice(left);
+        values.extend_from_slice(right);
+        if let Some(constraint) = &self.constraint {
+            let row = Tuple {
+                schema: &self.schema,
+                values: &values,
+            };
+            if !constraint.evaluate(&row)?.is_truthy()? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(values))
+    }
+
+    /// The row a left row without any match produces: padded with NULLs for a LEFT JOIN,
+    /// and none for other joins.
+    fn unmatched(&self, left: &[Value]) -> Option<Vec<Value>> {
+        if self.operator != JoinOperator::Left {
+            return None;
+        }
+        let mut values = left.to_vec();
+        values.resize(self.schema.columns.len(), Value::Null);
+        Some(values)
+    }
+}
+
+/// The equality keys of a join with the schemas they are evaluated against.
+struct KeyedSides {
+    keys: JoinKeys,
+    left_schema: Schema,
+    right_schema: Schema,
+}
+impl KeyedSides {
+    fn left_key(&self, values: &[Value]) -> Result<Option<Vec<Value>>> {
+        evaluate_key(&self.keys.left, &self.left_schema, values)
+    }
+
+    fn right_key(&self, values: &[Value]) -> Result<Option<Vec<Value>>> {
+        evaluate_key(&self.keys.right, &self.right_schema, values)
+    }
+}
+
+// A key with a NULL value is `None`, as it cannot equal anything.
+fn evaluate_key(
+    expressions: &[Expression],
+    schema: &Schema,
+    values: &[Value],
+) -> Result<Option<Vec<Value>>> {
+    let row = Tuple { schema, values };
+    let mut key = Vec::with_capacity(expressions.len());
+    for expression in expressions {
+        match expression.evaluate(&row)? {
+            Value::Null => return Ok(None),
+            value => key.push(value),
+        }
+    }
+    Ok(Some(key))
+}
+
+fn compare_keys(a: &[Value], b: &[Value]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a.compare(b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+// Keys that compare equal must hash equal, so REAL values with an integral value are
+// serialized as the equal INTEGER.
+fn hash_key(key: &[Value]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for value in key {
+        match value {
+            Value::Real(real) if real.fract() == 0.0 && real.abs() < i64::MAX as f64 => {
+                Value::Integer(*real as i64).serialize(&mut buf)
+            }
+            value => value.serialize(&mut buf),
+        }
+    }
+    buf
+}
+
+/// For each row of the left side, scans the table for rows satisfying the join constraint,
+/// or searches its index for the keys of the row when the access path is a probe.
+pub struct NestedLoopJoin {
+    left: Box<dyn RowSource>,
+    condition: JoinCondition,
+    left_schema: Schema,
+    current: Option<Vec<Value>>,
+    right: Box<dyn RowSource>,
+    matched: bool,
+}
+impl NestedLoopJoin {
+    fn new(left: Box<dyn RowSource>, condition: JoinCondition, left_schema: Schema) -> Self {
+        NestedLoopJoin {
+            left,
+            right: scan(&condition.table, &condition.access),
+            condition,
+            left_schema,
+            current: None,
+            matched: false,
+        }
+    }
+
+    // The rows of the table for the `left` row. Those a probe finds still go through the
+    // join constraint, which rejects a NULL key the index would match.
+    fn right(&self, left: &[Value]) -> Result<Box<dyn RowSource>> {
+        let AccessPath::Probe(probe) = &self.condition.access else {
+            return Ok(scan(&self.condition.table, &self.condition.access));
+        };
+        let row = Tuple {
+            schema: &self.left_schema,
+            values: left,
+        };
+        let values = probe
+            .keys
+            .iter()
+            .map(|key| key.evaluate(&row))
+            .collect::<Result<_>>()?;
+        Ok(Box::new(IndexCursor::new(probe.lookup(values))))
+    }
+}
+impl RowSource for NestedLoopJoin {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        loop {
+            let Some(left) = &self.current else {
+                match self.left.next_row(table)? {
+                    Some(left) => {
+                        self.right = self.right(&left)?;
+                        self.current = Some(left);
+                        self.matched = false;
+                        continue;
+                    }
+                    None => return Ok(None),
+                }
+            };
+            match self.right.next_row(table)? {
+                Some(right) => {
+                    if let Some(row) = self.condition.combine(left, &right)? {
+                        self.matched = true;
+                        return Ok(Some(row));
+                    }
+                }
+                None => {
+                    let left = self.current.take().unwrap_or_default();
+                    if !self.matched {
+                        if let Some(row) = self.condition.unmatched(&left) {
+                            return Ok(Some(row));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows written to an anonymous temporary file, each as its values.
+struct SpillFile {
+    writer: BufWriter<File>,
+    rows: usize,
+}
+impl SpillFile {
+    fn new() -> Result<SpillFile> {
+        Ok(SpillFile {
+            writer: BufWriter::new(tempfile::tempfile()?),
+            rows: 0,
+        })
+    }
+
+    fn write(&mut self, values: &[Value]) -> Result<()> {
+        let mut buf = Vec::new();
+        for value in values {
+            value.serialize(&mut buf);
+        }
+        self.writer.write_all(&buf)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Reads the rows back, each having `width` values.
+    fn into_rows(self, width: usize) -> Result<SpilledRows> {
+        let mut file = self.writer.into_inner()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(SpilledRows {
+            reader: BufReader::new(file),
+            remaining: self.rows,
+            width,
+        })
+    }
+}
+
+struct SpilledRows {
+    reader: BufReader<File>,
+    remaining: usize,
+    width: usize,
+}
+impl SpilledRows {
+    fn next_row(&mut self) -> Result<Option<Vec<Value>>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let values = (0..self.width)
+            .map(|_| Value::deserialize(&mut self.reader))
+            .collect::<Result<_>>()?;
+        Ok(Some(values))
+    }
+}
+
+/// A spilled partition of each side of a hash join, joined on its own.
+struct PartitionPair {
+    build: SpilledRows,
+    probe: SpilledRows,
+    level: u64,
+}
+
+enum Probe {
+    /// The table has not been read into the hash table yet.
+    Unbuilt,
+    /// Probing with the rows of the left side.
+    Left,
+    /// Probing with the left rows of a spilled partition.
+    Spilled(SpilledRows),
+    Done,
+}
+
+/// Reads the table into a hash table on the right join key, then looks up the rows matching
+/// the key of each left row.
+///
+/// When the hash table outgrows the memory budget, both sides are split into
+/// `Self::PARTITIONS` temporary files by the hash of their key, so that rows that can match
+/// land in the same pair of partitions, and each pair is joined on its own. A pair whose
+/// right partition is still too large is split again with a differently seeded hash, up to
+/// `Self::MAX_LEVEL` times, beyond which it is joined in memory regardless: its rows then
+/// mostly share one key, which more splitting would not separate.
+pub struct HashJoin {
+    left: Box<dyn RowSource>,
+    condition: JoinCondition,
+    sides: KeyedSides,
+    memory_budget: usize,
+    hash_table: HashMap<Vec<u8>, Vec<Vec<Value>>>,
+    probe: Probe,
+    pending: Vec<PartitionPair>,
+    output: VecDeque<Vec<Value>>,
+}
+impl HashJoin {
+    const PARTITIONS: usize = 16;
+    const MAX_LEVEL: u64 = 4;
+
+    fn new(
+        left: Box<dyn RowSource>,
+        condition: JoinCondition,
+        sides: KeyedSides,
+        memory_budget: usize,
+    ) -> HashJoin {
+        HashJoin {
+            left,
+            condition,
+            sides,
+            memory_budget,
+            hash_table: HashMap::new(),
+            probe: Probe::Unbuilt,
+            pending: Vec::new(),
+            output: VecDeque::new(),
+        }
+    }
+
+    // Rows with a NULL key never match, so right rows with one are dropped.
+    fn build(&mut self, table: &mut Table) -> Result<()> {
+        let mut right_rows = scan(&self.condition.table, &self.condition.access);
+        let mut memory_used = 0;
+        while let Some(right) = right_rows.next_row(table)? {
+            let Some(key) = self.sides.right_key(&right)? else {
+                continue;
+            };
+            memory_used += right.iter().map(Value::memory_size).sum::<usize>();
+            self.hash_table
+                .entry(hash_key(&key))
+                .or_default()
+                .push(right);
+            if memory_used > self.memory_budget {
+                let mut build = self.partition_hash_table(0)?;
+                while let Some(right) = right_rows.next_row(table)? {
+                    self.partition_right(&mut build, right, 0)?;
+                }
+                let mut probe = Self::new_partitions()?;
+                while let Some(left) = self.left.next_row(table)? {
+                    self.partition_left(&mut probe, left, 0)?;
+                }
+                self.push_pairs(build, probe, 0)?;
+                self.probe = Probe::Done;
+                return Ok(());
+            }
+        }
+        self.probe = Probe::Left;
+        Ok(())
+    }
+
+    fn new_partitions() -> Result<Vec<SpillFile>> {
+        (0..Self::PARTITIONS).map(|_| SpillFile::new()).collect()
+    }
+
+    fn partition_of(key: &[u8], level: u64) -> usize {
+        let mut hasher = DefaultHasher::new();
+        level.hash(&mut hasher);
+        key.hash(&mut hasher);
+        hasher.finish() as usize % Self::PARTITIONS
+    }
+
+    // Moves the rows of the hash table into new right partitions.
+    fn partition_hash_table(&mut self, level: u64) -> Result<Vec<SpillFile>> {
+        let mut partitions = Self::new_partitions()?;
+        for (key, rows) in self.hash_table.drain() {
+            let partition = &mut partitions[Self::partition_of(&key, level)];
+            for row in rows {
+                partition.write(&row)?;
+            }
+        }
+        Ok(partitions)
+    }
+
+    fn partition_right(
+        &self,
+        partitions: &mut [SpillFile],
+        right: Vec<Value>,
+        level: u64,
+    ) -> Result<()> {
+        if let Some(key) = self.sides.right_key(&right)? {
+            partitions[Self::partition_of(&hash_key(&key), level)].write(&right)?;
+        }
+        Ok(())
+    }
+
+    // Left rows with a NULL key match nothing, but a LEFT JOIN still produces them, so they
+    // go to the first partition.
+    fn partition_left(
+        &self,
+        partitions: &mut [SpillFile],
+        left: Vec<Value>,
+        level: u64,
+    ) -> Result<()> {
+        let partition = match self.sides.left_key(&left)? {
+            Some(key) => Self::partition_of(&hash_key(&key), level),
+            None => 0,
+        };
+        partitions[partition].write(&left)
+    }
+
+    fn push_pairs(
+        &mut self,
+        build: Vec<SpillFile>,
+        probe: Vec<SpillFile>,
+        level: u64,
+    ) -> Result<()> {
+        let right_width = self.sides.right_schema.columns.len();
+        let left_width = self.sides.left_schema.columns.len();
+        for (build, probe) in build.into_iter().zip(probe) {
+            if probe.rows > 0 {
+                self.pending.push(PartitionPair {
+                    build: build.into_rows(right_width)?,
+                    probe: probe.into_rows(left_width)?,
+                    level: level + 1,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Loads the right rows of the next pending pair into the hash table, splitting the pair
+    // further if they do not fit. Returns false once no pair is left.
+    fn next_partition(&mut self) -> Result<bool> {
+        while let Some(mut pair) = self.pending.pop() {
+            self.hash_table.clear();
+            let mut memory_used = 0;
+            let mut fits = true;
+            while let Some(right) = pair.build.next_row()? {
+                let Some(key) = self.sides.right_key(&right)? else {
+                    continue;
+                };
+                memory_used += right.iter().map(Value::memory_size).sum::<usize>();
+                self.hash_table
+                    .entry(hash_key(&key))
+                    .or_default()
+                    .push(right);
+                if memory_used > self.memory_budget && pair.level < Self::MAX_LEVEL {
+                    fits = false;
+                    break;
+                }
+            }
+            if fits {
+                self.probe = Probe::Spilled(pair.probe);
+                return Ok(true);
+            }
+            let mut build = self.partition_hash_table(pair.level)?;
+            while let Some(right) = pair.build.next_row()? {
+                self.partition_right(&mut build, right, pair.level)?;
+            }
+            let mut probe = Self::new_partitions()?;
+            while let Some(left) = pair.probe.next_row()? {
+                self.partition_left(&mut probe, left, pair.level)?;
+            }
+            self.push_pairs(build, probe, pair.level)?;
+        }
+        self.hash_table.clear();
+        self.probe = Probe::Done;
+        Ok(false)
+    }
+
+    fn probe_row(&mut self, left: Vec<Value>) -> Result<()> {
+        let mut matched = false;
+        if let Some(key) = self.sides.left_key(&left)? {
+            for right in self.hash_table.get(&hash_key(&key)).into_iter().flatten() {
+                if let Some(row) = self.condition.combine(&left, right)? {
+                    self.output.push_back(row);
+                    matched = true;
+                }
+            }
+        }
+        if !matched {
+            self.output.extend(self.condition.unmatched(&left));
+        }
+        Ok(())
+    }
+}
+impl RowSource for HashJoin {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        loop {
+            if let Some(row) = self.output.pop_front() {
+                return Ok(Some(row));
+            }
+            let left = match &mut self.probe {
+                Probe::Unbuilt => {
+                    self.build(table)?;
+                    continue;
+                }
+                Probe::Left => self.left.next_row(table)?,
+                Probe::Spilled(rows) => rows.next_row()?,
+                Probe::Done => None,
+            };
+            match left {
+                Some(left) => self.probe_row(left)?,
+                None if self.next_partition()? => {}
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// A row with its join key, which is `None` when it has a NULL value.
+type KeyedRow = (Option<Vec<Value>>, Vec<Value>);
+
+/// Sorts both sides on the join key, then walks them together, joining each left row with
+/// the group of right rows that have its key. The output comes in the order of the left key.
+pub struct MergeJoin {
+    left: Box<dyn RowSource>,
+    condition: JoinCondition,
+    sides: KeyedSides,
+    memory_budget: usize,
+    sorted: Option<(SortedRows, SortedRows)>,
+    /// The next right row not yet added to a group, with its key.
+    right_head: Option<KeyedRow>,
+    group_key: Option<Vec<Value>>,
+    group: Vec<Vec<Value>>,
+    output: VecDeque<Vec<Value>>,
+}
+impl MergeJoin {
+    fn new(
+        left: Box<dyn RowSource>,
+        condition: JoinCondition,
+        sides: KeyedSides,
+        memory_budget: usize,
+    ) -> MergeJoin {
+        MergeJoin {
+            left,
+            condition,
+            sides,
+            memory_budget,
+            sorted: None,
+            right_head: None,
+            group_key: None,
+            group: Vec::new(),
+            output: VecDeque::new(),
+        }
+    }
+
+    // NULL sorts first, so rows with a NULL key come out before all others.
+    fn sort(&mut self, table: &mut Table) -> Result<(SortedRows, SortedRows)> {
+        let descending = vec![false; self.sides.keys.left.len()];
+        let mut left_sorter = Sorter::new(descending.clone(), self.memory_budget);
+        while let Some(left) = self.left.next_row(table)? {
+            let row = Tuple {
+                schema: &self.sides.left_schema,
+                values: &left,
+            };
+            let keys = self
+                .sides
+                .keys
+                .left
+                .iter()
+                .map(|key| key.evaluate(&row))
+                .collect::<Result<_>>()?;
+            left_sorter.push(keys, left)?;
+        }
+        let mut right_sorter = Sorter::new(descending, self.memory_budget);
+        let mut right_rows = scan(&self.condition.table, &self.condition.access);
+        while let Some(right) = right_rows.next_row(table)? {
+            let row = Tuple {
+                schema: &self.sides.right_schema,
+                values: &right,
+            };
+            let keys = self
+                .sides
+                .keys
+                .right
+                .iter()
+                .map(|key| key.evaluate(&row))
+                .collect::<Result<_>>()?;
+            right_sorter.push(keys, right)?;
+        }
+        Ok((left_sorter.finish()?, right_sorter.finish()?))
+    }
+
+    fn next_right(&mut self) -> Result<Option<KeyedRow>> {
+        let Some((_, right_rows)) = &mut self.sorted else {
+            return Ok(None);
+        };
+        match right_rows.next().transpose()? {
+            Some(right) => Ok(Some((self.sides.right_key(&right)?, right))),
+            None => Ok(None),
+        }
+    }
+
+    // Makes `group` hold the right rows with the given key, skipping right rows with smaller
+    // keys. Left keys arrive in ascending order, so skipped rows are never needed again.
+    fn advance_group(&mut self, key: &[Value]) -> Result<()> {
+        if let Some(group_key) = &self.group_key {
+            if compare_keys(group_key, key).is_eq() {
+                return Ok(());
+            }
+        }
+        self.group.clear();
+        self.group_key = Some(key.to_vec());
+        loop {
+            if self.right_head.is_none() {
+                self.right_head = self.next_right()?;
+            }
+            let Some((right_key, _)) = &self.right_head else {
+                return Ok(());
+            };
+            let ordering = match right_key {
+                Some(right_key) => compare_keys(right_key, key),
+                None => Ordering::Less,
+            };
+            match ordering {
+                Ordering::Less => self.right_head = None,
+                Ordering::Equal => {
+                    if let Some((_, right)) = self.right_head.take() {
+                        self.group.push(right);
+                    }
+                }
+                Ordering::Greater => return Ok(()),
+            }
+        }
+    }
+}
+impl RowSource for MergeJoin {
+    fn next_row(&mut self, table: &mut Table) -> Result<Option<Vec<Value>>> {
+        if self.sorted.is_none() {
+            self.sorted = Some(self.sort(table)?);
+        }
+        loop {
+            if let Some(row) = self.output.pop_front() {
+                return Ok(Some(row));
+            }
+            let Some((left_rows, _)) = &mut self.sorted else {
+                return Ok(None);
+            };
+            let Some(left) = left_rows.next().transpose()? else {
+                return Ok(None);
+            };
+            let mut matched = false;
+            if let Some(key) = self.sides.left_key(&left)? {
+                self.advance_group(&key)?;
+                for right in &self.group {
+                    if let Some(row) = self.condition.combine(&left, right)? {
+                        self.output.push_back(row);
+                        matched = true;
+                    }
+                }
+            }
+            if !matched {
+                self.output.extend(self.condition.unmatched(&left));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Parser, Row};
+    use heapless::String as HeapLessString;
+
+    fn table(ids: &[i32]) -> Table {
+        let mut table = Table::new();
+        for &id in ids {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(format!("user{}", id).as_str()).unwrap(),
+                email: HeapLessString::try_from("user@example.com").unwrap(),
+            };
+            if table.pager.num_pages() == 0
+                || table
+                    .pager
+                    .get_page(table.pager.num_pages() - 1)
+                    .unwrap()
+                    .is_full()
+            {
+                table.pager.allocate_page();
+            }
+            let page_num = table.pager.num_pages() - 1;
+            table
+                .pager
+                .get_page_mut(page_num)
+                .unwrap()
+                .add_row(row)
+                .unwrap();
+            table.num_rows += 1;
+        }
+        table
+    }
+
+    // Joins with every algorithm and returns the `a.id` and `b.id` of each row, sorted so
+    // that the algorithms can be compared.
+    fn join_ids(
+        table: &mut Table,
+        input: &str,
+        algorithm: JoinAlgorithm,
+        memory_budget: usize,
+    ) -> Vec<(Value, Value)> {
+        let select = Parser::new(input).unwrap().parse_select().unwrap();
+        select.check_columns(&[]).unwrap();
+        let mut plan = QueryPlan::new(&select, table);
+        plan.joins[0].algorithm = algorithm;
+        let mut source = build(&select, &plan, memory_budget);
+        let mut ids = Vec::new();
+        while let Some(values) = source.next_row(table).unwrap() {
+            ids.push((values[0].clone(), values[3].clone()));
+        }
+        ids.sort_by(|a, b| a.0.compare(&b.0).then(a.1.compare(&b.1)));
+        ids
+    }
+
+    fn pairs(pairs: &[(i64, Option<i64>)]) -> Vec<(Value, Value)> {
+        pairs
+            .iter()
+            .map(|(a, b)| (Value::Integer(*a), b.map_or(Value::Null, Value::Integer)))
+            .collect()
+    }
+
+    #[test]
+    fn test_algorithms_agree() {
+        let mut table = table(&[1, 2, 3, 4, 2, 6]);
+        let inner = "select from users a join users b on b.id = a.id * 2 and b.id != 6";
+        let expected = pairs(&[(1, Some(2)), (1, Some(2)), (2, Some(4)), (2, Some(4))]);
+        let left = "select from users a left join users b on a.id * 2 = b.id and b.id != 6";
+        let expected_left = pairs(&[
+            (1, Some(2)),
+            (1, Some(2)),
+            (2, Some(4)),
+            (2, Some(4)),
+            (3, None),
+            (4, None),
+            (6, None),
+        ]);
+        for algorithm in [
+            JoinAlgorithm::NestedLoop,
+            JoinAlgorithm::Hash,
+            JoinAlgorithm::SortMerge,
+        ] {
+            assert_eq!(
+                join_ids(&mut table, inner, algorithm, 1 << 20),
+                expected,
+                "{:?}",
+                algorithm
+            );
+            assert_eq!(
+                join_ids(&mut table, left, algorithm, 1 << 20),
+                expected_left,
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash_join_spills_partitions() {
+        let ids: Vec<i32> = (0..600).map(|id| id % 300).collect();
+        let mut table = table(&ids);
+        let input = "select from users a left join users b on a.id = b.id + 299";
+        let mut expected = pairs(&[
+            (299, Some(0)),
+            (299, Some(0)),
+            (299, Some(0)),
+            (299, Some(0)),
+        ]);
+        expected.extend((0..299).flat_map(|id| pairs(&[(id, None), (id, None)])));
+        expected.sort_by(|a, b| a.0.compare(&b.0).then(a.1.compare(&b.1)));
+        assert_eq!(
+            join_ids(&mut table, input, JoinAlgorithm::Hash, 1 << 20),
+            expected
+        );
+        assert_eq!(
+            join_ids(&mut table, input, JoinAlgorithm::Hash, 512),
+            expected
+        );
+        assert_eq!(
+            join_ids(&mut table, input, JoinAlgorithm::SortMerge, 512),
+            expected
+        );
+
+        let input = "select from users a join users b on a.id = b.id";
+        let joined = join_ids(&mut table, input, JoinAlgorithm::Hash, 512);
+        assert_eq!(joined.len(), 1200);
+        assert_eq!(
+            joined,
+            join_ids(&mut table, input, JoinAlgorithm::NestedLoop, 512)
+        );
+    }
+
+    #[test]
+    fn test_merge_join_output_is_ordered() {
+        let mut table = table(&[5, 3, 1, 4, 2]);
+        let select = Parser::new("select from users a join users b on a.id = b.id")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        let mut plan = QueryPlan::new(&select, &table);
+        plan.joins[0].algorithm = JoinAlgorithm::SortMerge;
+        let mut source = build(&select, &plan, 1 << 20);
+        let mut ids = Vec::new();
+        while let Some(values) = source.next_row(&mut table).unwrap() {
+            ids.push(values[0].clone());
+        }
+        assert_eq!(ids, (1..=5).map(Value::Integer).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_hash_key_matches_equal_numbers() {
+        assert_eq!(
+            hash_key(&[Value::Real(2.0)]),
+            hash_key(&[Value::Integer(2)])
+        );
+        assert_ne!(
+            hash_key(&[Value::Real(2.5)]),
+            hash_key(&[Value::Integer(2)])
+        );
+    }
+}
@@ -4,9 +4,53 @@ pub mod statement;
 pub use statement::Statement;
 pub mod virtual_machine;
 pub use virtual_machine::VirtualMachine;
+pub mod program;
+pub use program::{Instruction, Program};
 pub mod table;
 pub use table::Table;
 pub mod page;
 pub use page::Page;
 pub mod row;
-pub use row::Row;
\ No newline at end of file
+pub use row::Row;
+pub mod pager;
+pub use pager::{DatabaseOptions, Pager};
+pub mod cipher;
+pub mod compression;
+pub use compression::{Compression, CompressionStats};
+pub mod value;
+pub use value::Value;
+pub mod expression;
+pub use expression::Expression;
+pub mod tokenizer;
+pub mod parser;
+pub use parser::Parser;
+pub mod select;
+pub use select::{
+    CommonTableExpression, Join, JoinOperator, OrderingTerm, ResultColumn, Select, SelectOutput,
+    TableReference, TableSource, Union,
+};
+pub mod result_set;
+pub use result_set::ResultSet;
+pub mod sorter;
+pub use sorter::{SortedRows, Sorter};
+pub mod cursor;
+pub use cursor::{CommonTableCursor, Cursor, IndexCursor, SingleRowCursor, StatisticsCursor};
+pub mod aggregate;
+pub use aggregate::{AggregateFunction, Group, HashAggregator};
+pub mod schema;
+pub use schema::{Schema, TableDefinition, Tuple};
+pub mod planner;
+pub use planner::{AccessPath, QueryPlan};
+pub mod statistics;
+pub use statistics::{ColumnStatistics, Statistics};
+pub mod index;
+pub use index::{Index, IndexLookup, IndexProbe};
+pub mod constraint;
+pub use constraint::{CheckConstraint, ColumnDefault, ForeignKey, ForeignKeyAction};
+pub mod catalog;
+pub use catalog::Catalog;
+pub mod join;
+pub mod subquery;
+pub use subquery::{QueryScope, SubqueryCache};
+pub mod window;
+pub use window::{Frame, FrameBound, Window, WindowFunction, WindowScope};
\ No newline at end of file
@@ -1,5 +1,6 @@
 use crate::types::row::Row;
 use anyhow::{anyhow, Result};
+#[derive(Clone, Debug)]
 pub struct Page {
     pub rows: Vec<Row>,
     pub max_rows: usize,
@@ -25,9 +26,65 @@ impl Page {
     pub fn max_rows_per_page(page_size: usize) -> usize {
         page_size / Row::max_size()
     }
+    /// Encodes the page into exactly `page_size` bytes: a row count followed by the rows.
+    pub fn serialize(&self, page_size: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(page_size);
+        buf.extend_from_slice(&(self.rows.len() as u16).to_le_bytes());
+        for row in &self.rows {
+            row.serialize(&mut buf);
+        }
+        if buf.len() > page_size {
+            return Err(anyhow!("Page does not fit in {} bytes", page_size));
+        }
+        buf.resize(page_size, 0);
+        Ok(buf)
+    }
+    pub fn deserialize(bytes: &[u8], page_size: usize) -> Result<Page> {
+        if bytes.len() != page_size {
+            return Err(anyhow!(
+                "Page must be {} bytes but got {}",
+                page_size,
+                bytes.len()
+            ));
+        }
+        let mut page = Page::new(page_size);
+        let num_rows = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        if num_rows > page.max_rows {
+            return Err(anyhow!(
+                "Stored row count {} exceeds the page capacity of {}",
+                num_rows,
+                page.max_rows
+            ));
+        }
+        for cell in bytes[2..].chunks_exact(Row::max_size()).take(num_rows) {
+            page.rows.push(Row::deserialize(cell)?);
+        }
+        Ok(page)
+    }
+    /// Checks the page on its own, `page_num` is only used to label the problems found.
+    pub fn integrity_check(&self, page_num: usize, expected_max_rows: usize) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_rows != expected_max_rows {
+            problems.push(format!(
+                "Page {}: capacity is {} rows but the page size allows {}",
+                page_num, self.max_rows, expected_max_rows
+            ));
+        }
+        if self.rows.len() > self.max_rows {
+            problems.push(format!(
+                "Page {}: holds {} rows but its capacity is {}",
+                page_num,
+                self.rows.len(),
+                self.max_rows
+            ));
+        }
+        problems
+    }
 }
 
 #[cfg(test)]
+// The tests are kept as they were written, before the crate was checked with clippy.
+#[allow(clippy::nonminimal_bool, clippy::bool_assert_comparison, clippy::needless_return)]
 mod tests {
     use crate::types::{Page, Row};
     use rand::distr::Alphanumeric;
@@ -84,6 +141,51 @@ mod tests {
         assert_eq!(Page::max_rows_per_page(2 * row_size), 2);
     }
 
+    #[test]
+    fn test_integrity_check() {
+        let mut page = Page::new(Row::max_size());
+        assert!(page.integrity_check(0, 1).is_empty());
+
+        page.rows.push(generate_test_row());
+        page.rows.push(generate_test_row());
+        assert_eq!(
+            page.integrity_check(3, 2),
+            vec![
+                "Page 3: capacity is 1 rows but the page size allows 2".to_string(),
+                "Page 3: holds 2 rows but its capacity is 1".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let page_size = 4096;
+        let mut page = Page::new(page_size);
+        for _ in 0..3 {
+            page.add_row(generate_test_row()).unwrap();
+        }
+        let bytes = page.serialize(page_size).unwrap();
+        assert_eq!(bytes.len(), page_size);
+
+        let restored = Page::deserialize(&bytes, page_size).unwrap();
+        assert_eq!(restored.rows, page.rows);
+        assert_eq!(restored.max_rows, page.max_rows);
+    }
+
+    #[test]
+    fn test_deserialize_row_count_exceeds_capacity() {
+        let mut bytes = vec![0; 4096];
+        bytes[0] = 200;
+        let err = Page::deserialize(&bytes, 4096).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Stored row count 200 exceeds the page capacity of {}",
+                Page::max_rows_per_page(4096)
+            )
+        );
+    }
+
     fn generate_test_row() -> Row {
         let mut rng = rand::rng();
 
@@ -0,0 +1,1269 @@
+use crate::types::cipher::PageCipher;
+use crate::types::compression::{Compression, CompressionStats};
+use crate::types::Page;
+use anyhow::{anyhow, Context, Result};
+use memmap2::{Mmap, MmapOptions};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 16] = b"ZeroDB format 1\0";
+const KEY_CHECK_SIZE: usize = MAGIC.len() + PageCipher::OVERHEAD;
+const PAGE_MAP_OFFSET: usize = 128;
+const PAGE_LOCATION_SIZE: usize = 12;
+const FREE_PAGE_SIZE: usize = 4;
+/// Associated data the catalog is encrypted with, unlike that of any page number.
+const CATALOG_ASSOCIATED_DATA: [u8; 8] = u64::MAX.to_le_bytes();
+
+/// Options for opening a database file. Encryption and compression are chosen when the
+/// file is created; reopening it requires the same key and, if given, the same compression.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatabaseOptions {
+    pub key: Option<String>,
+    pub compression: Option<Compression>,
+    /// Maximum number of bytes of the file to memory-map for page reads, 0 disables mmap.
+    pub mmap_size: usize,
+}
+
+/// Where a page is stored in the database file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageLocation {
+    pub offset: u64,
+    pub len: u32,
+}
+
+/// What a compressed file stores after its header, each at the `PageLocation` the header
+/// records for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Extent {
+    Page(usize),
+    Catalog,
+}
+
+/// Metadata stored at the start of a database file, always in plaintext.
+#[derive(Debug, PartialEq)]
+pub struct DatabaseHeader {
+    pub page_size: u32,
+    pub num_pages: u32,
+    pub num_rows: u64,
+    /// PBKDF2 iterations used to derive the page key, zero when the file is not encrypted.
+    pub kdf_iterations: u32,
+    pub salt: [u8; PageCipher::SALT_SIZE],
+    /// The magic string encrypted with the page key, used to reject a wrong key on open.
+    pub key_check: [u8; KEY_CHECK_SIZE],
+    pub compression: Compression,
+    /// Where the catalog is stored, see `Pager::catalog`. Its length is zero when the
+    /// database has none.
+    pub catalog: PageLocation,
+    /// Location of every page of a compressed file. Uncompressed pages all have the same
+    /// size and are stored one after another, so they need no map.
+    pub page_map: Vec<PageLocation>,
+    /// Pages that hold nothing and are handed out again before the file grows, in
+    /// ascending order, see `Pager::free_page`. They are stored after the page map.
+    pub free_pages: Vec<u32>,
+}
+impl DatabaseHeader {
+    pub const SIZE: usize = 4096;
+    pub const MAX_MAPPED_PAGES: usize = (Self::SIZE - PAGE_MAP_OFFSET) / PAGE_LOCATION_SIZE;
+
+    pub fn is_encrypted(&self) -> bool {
+        self.kdf_iterations > 0
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        if self.page_map.len() > Self::MAX_MAPPED_PAGES {
+            return Err(anyhow!(
+                "Compressed databases cannot hold more than {} pages",
+                Self::MAX_MAPPED_PAGES
+            ));
+        }
+        let free_list_offset = PAGE_MAP_OFFSET + self.page_map.len() * PAGE_LOCATION_SIZE;
+        if free_list_offset + self.free_pages.len() * FREE_PAGE_SIZE > Self::SIZE {
+            return Err(anyhow!(
+                "Free list of {} pages exceeds the header",
+                self.free_pages.len()
+            ));
+        }
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&self.page_size.to_le_bytes());
+        buf.extend_from_slice(&self.num_pages.to_le_bytes());
+        buf.extend_from_slice(&self.num_rows.to_le_bytes());
+        buf.extend_from_slice(&self.kdf_iterations.to_le_bytes());
+        buf.extend_from_slice(&self.salt);
+        buf.extend_from_slice(&self.key_check);
+        buf.extend_from_slice(&self.compression.to_u32().to_le_bytes());
+        buf.extend_from_slice(&self.catalog.offset.to_le_bytes());
+        buf.extend_from_slice(&self.catalog.len.to_le_bytes());
+        buf.extend_from_slice(&(self.free_pages.len() as u32).to_le_bytes());
+        buf.resize(PAGE_MAP_OFFSET, 0);
+        for location in &self.page_map {
+            buf.extend_from_slice(&location.offset.to_le_bytes());
+            buf.extend_from_slice(&location.len.to_le_bytes());
+        }
+        for page_num in &self.free_pages {
+            buf.extend_from_slice(&page_num.to_le_bytes());
+        }
+        buf.resize(Self::SIZE, 0);
+        Ok(buf)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<DatabaseHeader> {
+        if bytes.len() < Self::SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(anyhow!("File is not a ZeroDB database"));
+        }
+        let field = |offset: usize, size: usize| &bytes[MAGIC.len() + offset..][..size];
+        let catalog_offset = 24 + PageCipher::SALT_SIZE + KEY_CHECK_SIZE;
+        let mut header = DatabaseHeader {
+            page_size: u32::from_le_bytes(field(0, 4).try_into()?),
+            num_pages: u32::from_le_bytes(field(4, 4).try_into()?),
+            num_rows: u64::from_le_bytes(field(8, 8).try_into()?),
+            kdf_iterations: u32::from_le_bytes(field(16, 4).try_into()?),
+            salt: field(20, PageCipher::SALT_SIZE).try_into()?,
+            key_check: field(20 + PageCipher::SALT_SIZE, KEY_CHECK_SIZE).try_into()?,
+            compression: Compression::from_u32(u32::from_le_bytes(
+                field(20 + PageCipher::SALT_SIZE + KEY_CHECK_SIZE, 4).try_into()?,
+            ))?,
+            catalog: PageLocation {
+                offset: u64::from_le_bytes(field(catalog_offset, 8).try_into()?),
+                len: u32::from_le_bytes(field(catalog_offset + 8, 4).try_into()?),
+            },
+            page_map: Vec::new(),
+            free_pages: Vec::new(),
+        };
+        if header.compression != Compression::None {
+            let num_pages = header.num_pages as usize;
+            if num_pages > Self::MAX_MAPPED_PAGES {
+                return Err(anyhow!("Page map of {} pages exceeds the header", num_pages));
+            }
+            header.page_map = bytes[PAGE_MAP_OFFSET..]
+                .chunks_exact(PAGE_LOCATION_SIZE)
+                .take(num_pages)
+                .map(|entry| PageLocation {
+                    offset: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                    len: u32::from_le_bytes(entry[8..].try_into().unwrap()),
+                })
+                .collect();
+        }
+        let free_pages =
+            u32::from_le_bytes(field(catalog_offset + 12, 4).try_into()?) as usize;
+        let free_list_offset = PAGE_MAP_OFFSET + header.page_map.len() * PAGE_LOCATION_SIZE;
+        if free_list_offset + free_pages * FREE_PAGE_SIZE > Self::SIZE {
+            return Err(anyhow!("Free list of {} pages exceeds the header", free_pages));
+        }
+        header.free_pages = bytes[free_list_offset..]
+            .chunks_exact(FREE_PAGE_SIZE)
+            .take(free_pages)
+            .map(|entry| u32::from_le_bytes(entry.try_into().unwrap()))
+            .collect();
+        Ok(header)
+    }
+}
+
+/// Hands out pages by number, loading them from the database file on first access and
+/// writing modified pages back on `flush`. Without a file every page lives in memory only.
+pub struct Pager {
+    file: Option<File>,
+    file_len: u64,
+    mmap: Option<Mmap>,
+    mmap_size: usize,
+    cipher: Option<PageCipher>,
+    header: DatabaseHeader,
+    page_size: usize,
+    pages: Vec<Option<Page>>,
+    dirty: Vec<bool>,
+    catalog: Vec<u8>,
+}
+impl Pager {
+    /// Path that opens a database kept entirely in memory, like `Pager::in_memory`.
+    pub const MEMORY: &'static str = ":memory:";
+
+    pub fn in_memory(page_size: usize) -> Pager {
+        Pager {
+            file: None,
+            file_len: 0,
+            mmap: None,
+            mmap_size: 0,
+            cipher: None,
+            header: DatabaseHeader {
+                page_size: page_size as u32,
+                num_pages: 0,
+                num_rows: 0,
+                kdf_iterations: 0,
+                salt: [0; PageCipher::SALT_SIZE],
+                key_check: [0; KEY_CHECK_SIZE],
+                compression: Compression::None,
+                catalog: PageLocation { offset: 0, len: 0 },
+                page_map: Vec::new(),
+                free_pages: Vec::new(),
+            },
+            page_size,
+            pages: Vec::new(),
+            dirty: Vec::new(),
+            catalog: Vec::new(),
+        }
+    }
+
+    /// Opens the database file at `path`, creating it with the given options when it does
+    /// not exist.
+    pub fn open(path: &Path, page_size: usize, options: &DatabaseOptions) -> Result<Pager> {
+        if path == Path::new(Self::MEMORY) {
+            if options.key.is_some() || options.compression.is_some() {
+                return Err(anyhow!(
+                    "In-memory databases cannot be encrypted or compressed"
+                ));
+            }
+            return Ok(Pager::in_memory(page_size));
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Unable to open {}", path.display()))?;
+
+        let mut pager = Pager::in_memory(page_size);
+        pager.file_len = file.metadata()?.len();
+        if pager.file_len == 0 {
+            if let Some(key) = &options.key {
+                pager.header.kdf_iterations = PageCipher::DEFAULT_KDF_ITERATIONS;
+                pager.header.salt = PageCipher::generate_salt();
+                let cipher = PageCipher::from_passphrase(
+                    key,
+                    &pager.header.salt,
+                    pager.header.kdf_iterations,
+                );
+                pager.header.key_check = cipher.encrypt(MAGIC, MAGIC)?.try_into().unwrap();
+                pager.cipher = Some(cipher);
+            }
+            pager.header.compression = options.compression.unwrap_or_default();
+            file.write_all(&pager.header.serialize()?)?;
+            pager.file_len = DatabaseHeader::SIZE as u64;
+        } else {
+            let mut bytes = vec![0; DatabaseHeader::SIZE];
+            file.read_exact(&mut bytes)
+                .map_err(|_| anyhow!("File is not a ZeroDB database"))?;
+            pager.header = DatabaseHeader::deserialize(&bytes)?;
+            if pager.header.page_size as usize != page_size {
+                return Err(anyhow!(
+                    "Database page size is {} but {} was expected",
+                    pager.header.page_size,
+                    page_size
+                ));
+            }
+            if let Some(compression) = options.compression {
+                if compression != pager.header.compression {
+                    return Err(anyhow!(
+                        "Database compression is {} but {} was requested",
+                        pager.header.compression,
+                        compression
+                    ));
+                }
+            }
+            pager.cipher = match (pager.header.is_encrypted(), &options.key) {
+                (true, Some(key)) => {
+                    let cipher = PageCipher::from_passphrase(
+                        key,
+                        &pager.header.salt,
+                        pager.header.kdf_iterations,
+                    );
+                    cipher
+                        .decrypt(MAGIC, &pager.header.key_check)
+                        .map_err(|_| anyhow!("Invalid encryption key"))?;
+                    Some(cipher)
+                }
+                (true, None) => {
+                    return Err(anyhow!("Database is encrypted, a key is required to open it"))
+                }
+                (false, Some(_)) => {
+                    return Err(anyhow!("Database is not encrypted but a key was given"))
+                }
+                (false, None) => None,
+            };
+            let num_pages = pager.header.num_pages as usize;
+            pager.pages.resize_with(num_pages, || None);
+            pager.dirty.resize(num_pages, false);
+            pager.catalog = pager.read_catalog(&mut file)?;
+        }
+        pager.file = Some(file);
+        pager.mmap_size = options.mmap_size;
+        pager.remap()?;
+        Ok(pager)
+    }
+
+    /// The catalog stored in the database file by the last flush, empty if it has none. The
+    /// pager keeps the bytes without interpreting them, see `Catalog`.
+    pub fn catalog(&self) -> &[u8] {
+        &self.catalog
+    }
+
+    /// Number of rows recorded in the file header by the last flush.
+    pub fn num_rows(&self) -> usize {
+        self.header.num_rows as usize
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn get_page(&mut self, page_num: usize) -> Result<&Page> {
+        self.load_page(page_num)?;
+        Ok(self.pages[page_num].as_ref().unwrap())
+    }
+
+    /// Like `get_page` but marks the page to be written back on the next flush.
+    pub fn get_page_mut(&mut self, page_num: usize) -> Result<&mut Page> {
+        self.load_page(page_num)?;
+        self.dirty[page_num] = true;
+        Ok(self.pages[page_num].as_mut().unwrap())
+    }
+
+    /// Hands out the first free page, or else appends an empty page, and returns its page
+    /// number.
+    pub fn allocate_page(&mut self) -> usize {
+        if !self.header.free_pages.is_empty() {
+            let page_num = self.header.free_pages.remove(0) as usize;
+            self.dirty[page_num] = true;
+            return page_num;
+        }
+        self.pages.push(Some(Page::new(self.page_size)));
+        self.dirty.push(true);
+        self.pages.len() - 1
+    }
+
+    /// Empties page `page_num` and adds it to the free list, so that `allocate_page` hands it
+    /// out again rather than growing the file. The page keeps its place in the file.
+    pub fn free_page(&mut self, page_num: usize) -> Result<()> {
+        if self.is_free(page_num) {
+            return Err(anyhow!("Page {} is already free", page_num));
+        }
+        self.get_page_mut(page_num)?.rows.clear();
+        let position = self
+            .header
+            .free_pages
+            .partition_point(|free| (*free as usize) < page_num);
+        self.header.free_pages.insert(position, page_num as u32);
+        Ok(())
+    }
+
+    /// Whether page `page_num` is on the free list.
+    pub fn is_free(&self, page_num: usize) -> bool {
+        self.header
+            .free_pages
+            .binary_search(&(page_num as u32))
+            .is_ok()
+    }
+
+    /// Writes every modified page, `catalog` and the header to the database file.
+    pub fn flush(&mut self, num_rows: usize, catalog: &[u8]) -> Result<()> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        for page_num in 0..self.pages.len() {
+            if self.dirty[page_num] {
+                self.write_page(page_num)?;
+                self.dirty[page_num] = false;
+            }
+        }
+        self.header.num_pages = self.pages.len() as u32;
+        self.header.num_rows = num_rows as u64;
+        self.write_catalog(catalog)?;
+        if self.header.compression != Compression::None {
+            let end = self.extents_end(None);
+            if end < self.file_len {
+                self.mmap = None;
+                self.file.as_mut().unwrap().set_len(end)?;
+                self.file_len = end;
+            }
+        }
+
+        let header = self.header.serialize()?;
+        let file = self.file.as_mut().unwrap();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.sync_data()?;
+        self.remap()
+    }
+
+    /// Writes a copy of every page to a new database file at `path`, created with `options`.
+    /// This is how an in-memory database is persisted.
+    pub fn save_as(
+        &mut self,
+        path: &Path,
+        options: &DatabaseOptions,
+        num_rows: usize,
+        catalog: &[u8],
+    ) -> Result<()> {
+        let exists = std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0);
+        if exists || path == Path::new(Self::MEMORY) {
+            return Err(anyhow!(
+                "Cannot save to {}, it already exists",
+                path.display()
+            ));
+        }
+        let mut target = Pager::open(path, self.page_size, options)?;
+        for page_num in 0..self.num_pages() {
+            let page = self.get_page(page_num)?.clone();
+            let target_page_num = target.allocate_page();
+            target.pages[target_page_num] = Some(page);
+        }
+        target.header.free_pages = self.header.free_pages.clone();
+        target.flush(num_rows, catalog)
+    }
+
+    /// Reports how much space the pages written by the last flush take in the file.
+    pub fn compression_stats(&self) -> CompressionStats {
+        let pages = self.header.num_pages as usize;
+        let stored_bytes = (0..pages)
+            .filter_map(|page_num| self.page_location(page_num))
+            .map(|location| location.len as u64)
+            .sum();
+        CompressionStats {
+            compression: self.header.compression,
+            pages,
+            uncompressed_bytes: (pages * self.page_size) as u64,
+            stored_bytes,
+        }
+    }
+
+    /// Checks that the free list only names existing pages, each once, that every page
+    /// recorded in the header lies within the file, that no two pages share the same bytes
+    /// and that no bytes are left after the last of them.
+    pub fn integrity_check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (i, page_num) in self.header.free_pages.iter().enumerate() {
+            if *page_num as usize >= self.pages.len() {
+                problems.push(format!(
+                    "Free list: page {} does not exist, the database has {} pages",
+                    page_num,
+                    self.pages.len()
+                ));
+            }
+            if i > 0 && self.header.free_pages[i - 1] >= *page_num {
+                problems.push(format!(
+                    "Free list: page {} is out of order or listed twice",
+                    page_num
+                ));
+            }
+        }
+        if self.file.is_none() {
+            return problems;
+        }
+        let catalog = self.header.catalog;
+        if self.header.compression == Compression::None {
+            let expected_len = self.page_offset(self.header.num_pages as usize);
+            if catalog.len > 0 && self.file_len != expected_len + catalog.len as u64 {
+                problems.push(format!(
+                    "Database file is {} bytes but its {} pages and catalog require {}",
+                    self.file_len,
+                    self.header.num_pages,
+                    expected_len + catalog.len as u64
+                ));
+            } else if catalog.len == 0 && self.file_len != expected_len {
+                problems.push(format!(
+                    "Database file is {} bytes but its {} pages require {}",
+                    self.file_len, self.header.num_pages, expected_len
+                ));
+            }
+            return problems;
+        }
+
+        let catalog_end = catalog.offset + catalog.len as u64;
+        if catalog.len > 0 {
+            if catalog.offset < DatabaseHeader::SIZE as u64 || catalog_end > self.file_len {
+                problems.push(format!(
+                    "Catalog: stored at bytes {}..{} outside the {} byte file",
+                    catalog.offset, catalog_end, self.file_len
+                ));
+            }
+            for (page_num, location) in self.header.page_map.iter().enumerate() {
+                let end = location.offset + location.len as u64;
+                if location.offset < catalog_end && catalog.offset < end {
+                    problems.push(format!(
+                        "Page {} and the catalog overlap in the database file",
+                        page_num
+                    ));
+                }
+            }
+        }
+
+        let mut extents: Vec<(usize, PageLocation)> =
+            self.header.page_map.iter().copied().enumerate().collect();
+        extents.sort_by_key(|(_, location)| location.offset);
+        let mut previous: Option<(usize, u64)> = None;
+        for (page_num, location) in extents {
+            let end = location.offset + location.len as u64;
+            if location.offset < DatabaseHeader::SIZE as u64 || end > self.file_len {
+                problems.push(format!(
+                    "Page {}: stored at bytes {}..{} outside the {} byte file",
+                    page_num, location.offset, end, self.file_len
+                ));
+            }
+            if let Some((previous_page, previous_end)) = previous {
+                if location.offset < previous_end {
+                    problems.push(format!(
+                        "Pages {} and {} overlap in the database file",
+                        previous_page, page_num
+                    ));
+                }
+            }
+            if previous.is_none_or(|(_, previous_end)| end > previous_end) {
+                previous = Some((page_num, end));
+            }
+        }
+        let end = self.extents_end(None);
+        if end < self.file_len {
+            problems.push(format!(
+                "Database file is {} bytes but its pages and catalog end at byte {}",
+                self.file_len, end
+            ));
+        }
+        problems
+    }
+
+    fn slot_size(&self) -> usize {
+        match self.cipher {
+            Some(_) => self.page_size + PageCipher::OVERHEAD,
+            None => self.page_size,
+        }
+    }
+
+    fn page_offset(&self, page_num: usize) -> u64 {
+        (DatabaseHeader::SIZE + page_num * self.slot_size()) as u64
+    }
+
+    /// Location of a page written by a previous flush.
+    fn page_location(&self, page_num: usize) -> Option<PageLocation> {
+        match self.header.compression {
+            Compression::None => {
+                (page_num < self.header.num_pages as usize).then(|| PageLocation {
+                    offset: self.page_offset(page_num),
+                    len: self.slot_size() as u32,
+                })
+            }
+            _ => self.header.page_map.get(page_num).copied(),
+        }
+    }
+
+    /// Where the last page or the catalog of a compressed file ends, leaving out `moved`.
+    fn extents_end(&self, moved: Option<Extent>) -> u64 {
+        self.extents(moved)
+            .map(|location| location.offset + location.len as u64)
+            .fold(DatabaseHeader::SIZE as u64, u64::max)
+    }
+
+    // The locations of the pages and the catalog of a compressed file, except `moved`.
+    fn extents(&self, moved: Option<Extent>) -> impl Iterator<Item = PageLocation> + '_ {
+        let pages = self
+            .header
+            .page_map
+            .iter()
+            .enumerate()
+            .filter(move |(page_num, _)| moved != Some(Extent::Page(*page_num)))
+            .map(|(_, location)| *location);
+        let catalog = (moved != Some(Extent::Catalog)).then_some(self.header.catalog);
+        pages
+            .chain(catalog)
+            .filter(|location| location.len > 0)
+    }
+
+    /// The first offset of a compressed file where `len` bytes fit between the pages and
+    /// the catalog, once `moved` is rewritten elsewhere, or else the end of the last of
+    /// them. The space pages leave when they move is reused this way, and never leaked.
+    fn free_offset(&self, len: usize, moved: Extent) -> u64 {
+        let mut extents: Vec<PageLocation> = self.extents(Some(moved)).collect();
+        extents.sort_by_key(|location| location.offset);
+        let mut offset = DatabaseHeader::SIZE as u64;
+        for location in extents {
+            if location.offset >= offset + len as u64 {
+                return offset;
+            }
+            offset = offset.max(location.offset + location.len as u64);
+        }
+        offset
+    }
+
+    /// Maps the start of the file, up to `mmap_size` bytes, so that page reads are served
+    /// from memory. Writes still go through the file, and the mapping is recreated after
+    /// each flush to cover pages appended to the file.
+    fn remap(&mut self) -> Result<()> {
+        self.mmap = None;
+        let len = self.file_len.min(self.mmap_size as u64) as usize;
+        let Some(file) = self.file.as_ref().filter(|_| len > 0) else {
+            return Ok(());
+        };
+        // SAFETY: the mapping is read-only and only covers bytes that exist in the file.
+        // ZeroDB only shrinks a database file to cut off the bytes after its last page or
+        // catalog, after dropping the mapping; another process truncating it while it is
+        // open is not supported.
+        let mmap = unsafe { MmapOptions::new().len(len).map(file) }
+            .context("Unable to memory-map the database file")?;
+        self.mmap = Some(mmap);
+        Ok(())
+    }
+
+    fn load_page(&mut self, page_num: usize) -> Result<()> {
+        if page_num >= self.pages.len() {
+            return Err(anyhow!(
+                "Page {} is out of bounds, the database has {} pages",
+                page_num,
+                self.pages.len()
+            ));
+        }
+        if self.pages[page_num].is_none() {
+            let page = self.read_page(page_num)?;
+            self.pages[page_num] = Some(page);
+        }
+        Ok(())
+    }
+
+    fn read_page(&self, page_num: usize) -> Result<Page> {
+        let location = self
+            .page_location(page_num)
+            .ok_or_else(|| anyhow!("Page {} was never written", page_num))?;
+        let range = location.offset as usize..location.offset as usize + location.len as usize;
+
+        let mapped = self
+            .mmap
+            .as_ref()
+            .filter(|mmap| range.end <= mmap.len())
+            .map(|mmap| &mmap[range]);
+        let mut bytes = match mapped {
+            Some(bytes) => Cow::Borrowed(bytes),
+            None => {
+                let mut bytes = vec![0; location.len as usize];
+                let mut file = self.file.as_ref().unwrap();
+                file.seek(SeekFrom::Start(location.offset))?;
+                file.read_exact(&mut bytes)
+                    .with_context(|| format!("Unable to read page {}", page_num))?;
+                Cow::Owned(bytes)
+            }
+        };
+
+        if let Some(cipher) = &self.cipher {
+            let decrypted = cipher
+                .decrypt(&(page_num as u64).to_le_bytes(), &bytes)
+                .with_context(|| format!("Page {} failed authentication", page_num))?;
+            bytes = Cow::Owned(decrypted);
+        }
+        if self.header.compression != Compression::None {
+            let decompressed = self.header.compression.decompress(&bytes, self.page_size)?;
+            bytes = Cow::Owned(decompressed);
+        }
+        Page::deserialize(&bytes, self.page_size)
+    }
+
+    fn read_catalog(&self, file: &mut File) -> Result<Vec<u8>> {
+        let location = self.header.catalog;
+        if location.len == 0 {
+            return Ok(Vec::new());
+        }
+        let mut bytes = vec![0; location.len as usize];
+        file.seek(SeekFrom::Start(location.offset))?;
+        file.read_exact(&mut bytes).context("Unable to read the catalog")?;
+        match &self.cipher {
+            Some(cipher) => cipher
+                .decrypt(&CATALOG_ASSOCIATED_DATA, &bytes)
+                .context("The catalog failed authentication"),
+            None => Ok(bytes),
+        }
+    }
+
+    /// The catalog is stored after the pages and encrypted like them. It is written again
+    /// when it changed, and in an uncompressed file also when new pages took its place,
+    /// cutting off the file after it. In a compressed file it is rewritten in place or moved
+    /// like a page.
+    fn write_catalog(&mut self, catalog: &[u8]) -> Result<()> {
+        let old = self.header.catalog;
+        let pages_end = self.page_offset(self.pages.len());
+        let overwritten =
+            self.header.compression == Compression::None && old.len > 0 && old.offset < pages_end;
+        if catalog == self.catalog && !overwritten {
+            return Ok(());
+        }
+        let mut bytes = catalog.to_vec();
+        if let (Some(cipher), false) = (&self.cipher, bytes.is_empty()) {
+            bytes = cipher.encrypt(&CATALOG_ASSOCIATED_DATA, &bytes)?;
+        }
+        let offset = match self.header.compression {
+            _ if bytes.is_empty() => 0,
+            Compression::None => pages_end,
+            _ if bytes.len() <= old.len as usize => old.offset,
+            _ => self.free_offset(bytes.len(), Extent::Catalog),
+        };
+
+        let file = self.file.as_mut().unwrap();
+        if !bytes.is_empty() {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&bytes)?;
+        }
+        if self.header.compression == Compression::None {
+            let end = pages_end + bytes.len() as u64;
+            if end < self.file_len {
+                self.mmap = None;
+                file.set_len(end)?;
+            }
+            self.file_len = end;
+        } else {
+            self.file_len = self.file_len.max(offset + bytes.len() as u64);
+        }
+        self.header.catalog = PageLocation {
+            offset,
+            len: bytes.len() as u32,
+        };
+        self.catalog = catalog.to_vec();
+        Ok(())
+    }
+
+    /// Pages are compressed before they are encrypted. A compressed page is rewritten in
+    /// place when it still fits, otherwise it moves to the first space large enough, see
+    /// `free_offset`.
+    fn write_page(&mut self, page_num: usize) -> Result<()> {
+        let page = self.pages[page_num].as_ref().unwrap();
+        let mut bytes = self.header.compression.compress(page.serialize(self.page_size)?);
+        if let Some(cipher) = &self.cipher {
+            bytes = cipher.encrypt(&(page_num as u64).to_le_bytes(), &bytes)?;
+        }
+
+        let offset = match self.header.compression {
+            Compression::None => self.page_offset(page_num),
+            _ => match self.header.page_map.get(page_num) {
+                Some(old) if bytes.len() <= old.len as usize => old.offset,
+                _ => self.free_offset(bytes.len(), Extent::Page(page_num)),
+            },
+        };
+        let location = PageLocation {
+            offset,
+            len: bytes.len() as u32,
+        };
+        if self.header.compression != Compression::None {
+            if page_num < self.header.page_map.len() {
+                self.header.page_map[page_num] = location;
+            } else {
+                self.header.page_map.push(location);
+            }
+        }
+
+        let file = self.file.as_mut().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&bytes)?;
+        self.file_len = self.file_len.max(offset + bytes.len() as u64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Row;
+    use heapless::String as HeapLessString;
+    use tempfile::NamedTempFile;
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn row(id: i32) -> Row {
+        Row {
+            id,
+            username: HeapLessString::try_from("alice").unwrap(),
+            email: HeapLessString::try_from("alice@example.com").unwrap(),
+        }
+    }
+
+    fn options(key: Option<&str>, compression: Option<Compression>) -> DatabaseOptions {
+        DatabaseOptions {
+            key: key.map(str::to_string),
+            compression,
+            ..Default::default()
+        }
+    }
+
+    // Writes two pages with one row each and flushes them to `path`.
+    fn write_two_pages(path: &Path, options: &DatabaseOptions) {
+        let mut pager = Pager::open(path, PAGE_SIZE, options).unwrap();
+        for id in 0..2 {
+            let page_num = pager.allocate_page();
+            pager.get_page_mut(page_num).unwrap().add_row(row(id)).unwrap();
+        }
+        pager.flush(2, &[]).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_pages() {
+        let mut pager = Pager::in_memory(PAGE_SIZE);
+        assert_eq!(pager.num_pages(), 0);
+        assert!(pager.get_page(0).is_err());
+
+        let page_num = pager.allocate_page();
+        pager.get_page_mut(page_num).unwrap().add_row(row(1)).unwrap();
+        assert_eq!(pager.get_page(page_num).unwrap().rows, vec![row(1)]);
+        assert!(pager.flush(1, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = DatabaseHeader {
+            page_size: 4096,
+            num_pages: 3,
+            num_rows: 27,
+            kdf_iterations: 10,
+            salt: [7; PageCipher::SALT_SIZE],
+            key_check: [9; KEY_CHECK_SIZE],
+            compression: Compression::Lz4,
+            catalog: PageLocation {
+                offset: 4396,
+                len: 30,
+            },
+            page_map: vec![
+                PageLocation {
+                    offset: 4096,
+                    len: 100,
+                },
+                PageLocation {
+                    offset: 4196,
+                    len: 80,
+                },
+                PageLocation {
+                    offset: 4276,
+                    len: 120,
+                },
+            ],
+            free_pages: vec![1],
+        };
+        let bytes = header.serialize().unwrap();
+        assert_eq!(bytes.len(), DatabaseHeader::SIZE);
+        assert_eq!(DatabaseHeader::deserialize(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn test_open_rejects_other_files() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"definitely not a database").unwrap();
+        let err = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).err().unwrap();
+        assert_eq!(err.to_string(), "File is not a ZeroDB database");
+    }
+
+    #[test]
+    fn test_persists_pages() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, None));
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).unwrap();
+        assert!(pager.cipher.is_none());
+        assert_eq!(pager.num_pages(), 2);
+        assert_eq!(pager.num_rows(), 2);
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+        assert!(pager.integrity_check().is_empty());
+    }
+
+    #[test]
+    fn test_persists_encrypted_pages() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(Some("secret"), None));
+
+        let contents = std::fs::read(file.path()).unwrap();
+        let plaintext = b"alice@example.com";
+        assert!(!contents.windows(plaintext.len()).any(|window| window == plaintext));
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options(Some("secret"), None)).unwrap();
+        assert!(pager.cipher.is_some());
+        assert_eq!(pager.get_page(0).unwrap().rows, vec![row(0)]);
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+        assert!(pager.integrity_check().is_empty());
+    }
+
+    #[test]
+    fn test_open_encrypted_with_wrong_or_missing_key() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(Some("secret"), None));
+
+        let err = Pager::open(file.path(), PAGE_SIZE, &options(Some("guess"), None)).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid encryption key");
+
+        let err = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Database is encrypted, a key is required to open it"
+        );
+    }
+
+    #[test]
+    fn test_open_plaintext_with_key() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, None));
+
+        let err = Pager::open(file.path(), PAGE_SIZE, &options(Some("secret"), None)).err().unwrap();
+        assert_eq!(err.to_string(), "Database is not encrypted but a key was given");
+    }
+
+    #[test]
+    fn test_tampered_page_fails_authentication() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(Some("secret"), None));
+
+        let mut contents = std::fs::read(file.path()).unwrap();
+        contents[DatabaseHeader::SIZE + 100] ^= 1;
+        std::fs::write(file.path(), contents).unwrap();
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options(Some("secret"), None)).unwrap();
+        let err = pager.get_page(0).err().unwrap();
+        assert_eq!(err.to_string(), "Page 0 failed authentication");
+        assert!(pager.get_page(1).is_ok());
+    }
+
+    #[test]
+    fn test_integrity_check_truncated_file() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, None));
+        file.as_file().set_len((DatabaseHeader::SIZE + PAGE_SIZE) as u64).unwrap();
+
+        let pager = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).unwrap();
+        assert_eq!(
+            pager.integrity_check(),
+            vec![format!(
+                "Database file is {} bytes but its 2 pages require {}",
+                DatabaseHeader::SIZE + PAGE_SIZE,
+                DatabaseHeader::SIZE + 2 * PAGE_SIZE
+            )]
+        );
+    }
+
+    #[test]
+    fn test_persists_compressed_pages() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, Some(Compression::Lz4)));
+
+        let file_len = std::fs::metadata(file.path()).unwrap().len() as usize;
+        assert!(file_len < DatabaseHeader::SIZE + PAGE_SIZE);
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).unwrap();
+        assert_eq!(pager.get_page(0).unwrap().rows, vec![row(0)]);
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+        assert!(pager.integrity_check().is_empty());
+
+        let stats = pager.compression_stats();
+        assert_eq!(stats.compression, Compression::Lz4);
+        assert_eq!(stats.pages, 2);
+        assert_eq!(stats.uncompressed_bytes, 2 * PAGE_SIZE as u64);
+        assert_eq!(stats.stored_bytes, (file_len - DatabaseHeader::SIZE) as u64);
+        assert!(stats.ratio() > 10.0);
+    }
+
+    #[test]
+    fn test_persists_compressed_encrypted_pages() {
+        let file = NamedTempFile::new().unwrap();
+        let options = options(Some("secret"), Some(Compression::Lz4));
+        write_two_pages(file.path(), &options);
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+        assert!(pager.integrity_check().is_empty());
+        assert!(pager.compression_stats().ratio() > 10.0);
+    }
+
+    #[test]
+    fn test_compressed_pages_grow_across_flushes() {
+        let file = NamedTempFile::new().unwrap();
+        let options = options(None, Some(Compression::Lz4));
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        pager.allocate_page();
+        pager.allocate_page();
+
+        pager.get_page_mut(1).unwrap().add_row(row(0)).unwrap();
+        for id in 0..10 {
+            pager.get_page_mut(0).unwrap().add_row(row(id)).unwrap();
+            pager.flush(id as usize + 2, &[]).unwrap();
+        }
+        // Growing the first page moved it behind the second one, where it keeps growing.
+        assert!(pager.header.page_map[0].offset > pager.header.page_map[1].offset);
+        assert_eq!(
+            pager.header.page_map[0].offset + pager.header.page_map[0].len as u64,
+            pager.file_len
+        );
+        assert!(pager.integrity_check().is_empty());
+
+        // A new page as small as the first one was takes the space it left.
+        let file_len = pager.file_len;
+        let page_num = pager.allocate_page();
+        pager.get_page_mut(page_num).unwrap().add_row(row(0)).unwrap();
+        pager.flush(12, &[]).unwrap();
+        assert_eq!(pager.header.page_map[2].offset, DatabaseHeader::SIZE as u64);
+        assert_eq!(pager.file_len, file_len);
+        assert!(pager.integrity_check().is_empty());
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        let expected: Vec<Row> = (0..10).map(row).collect();
+        assert_eq!(pager.get_page(0).unwrap().rows, expected);
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(0)]);
+        assert_eq!(pager.get_page(2).unwrap().rows, vec![row(0)]);
+
+        // Shrinking the last page cuts off the end of the file, bytes left there are leaked.
+        pager.get_page_mut(0).unwrap().rows.truncate(1);
+        pager.flush(3, &[]).unwrap();
+        let end = pager.header.page_map[0].offset + pager.header.page_map[0].len as u64;
+        assert_eq!(std::fs::metadata(file.path()).unwrap().len(), end);
+        pager.file_len += 10;
+        assert_eq!(
+            pager.integrity_check(),
+            vec![format!(
+                "Database file is {} bytes but its pages and catalog end at byte {}",
+                end + 10,
+                end
+            )]
+        );
+    }
+
+    #[test]
+    fn test_persists_catalog() {
+        let file = NamedTempFile::new().unwrap();
+        let options = options(None, None);
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        pager.allocate_page();
+        pager.flush(0, b"first catalog").unwrap();
+        assert_eq!(pager.file_len, pager.page_offset(1) + 13);
+
+        // A new page takes the place of the catalog, which moves behind it.
+        let page_num = pager.allocate_page();
+        pager.get_page_mut(page_num).unwrap().add_row(row(1)).unwrap();
+        pager.flush(1, b"first catalog").unwrap();
+        assert!(pager.integrity_check().is_empty());
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.catalog(), b"first catalog");
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+
+        // A shorter catalog cuts off the end of the file, and no catalog leaves just pages.
+        pager.flush(1, b"second").unwrap();
+        assert_eq!(std::fs::metadata(file.path()).unwrap().len(), pager.page_offset(2) + 6);
+        assert!(pager.integrity_check().is_empty());
+        pager.flush(1, &[]).unwrap();
+        assert_eq!(std::fs::metadata(file.path()).unwrap().len(), pager.page_offset(2));
+        let pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert!(pager.catalog().is_empty());
+        assert!(pager.integrity_check().is_empty());
+    }
+
+    #[test]
+    fn test_persists_compressed_encrypted_catalog() {
+        let file = NamedTempFile::new().unwrap();
+        let options = options(Some("secret"), Some(Compression::Lz4));
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        let page_num = pager.allocate_page();
+        pager.get_page_mut(page_num).unwrap().add_row(row(0)).unwrap();
+        pager.flush(1, b"CREATE INDEX secret_index").unwrap();
+        let contents = std::fs::read(file.path()).unwrap();
+        assert!(!contents.windows(12).any(|window| window == b"secret_index"));
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.catalog(), b"CREATE INDEX secret_index");
+        pager.flush(1, b"CREATE INDEX a much longer catalog than before").unwrap();
+        let page_num = pager.allocate_page();
+        pager.get_page_mut(page_num).unwrap().add_row(row(1)).unwrap();
+        pager.flush(2, b"CREATE INDEX a much longer catalog than before").unwrap();
+        assert!(pager.integrity_check().is_empty());
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.catalog(), b"CREATE INDEX a much longer catalog than before");
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+        pager.header.catalog.offset = pager.header.page_map[0].offset;
+        assert_eq!(
+            pager.integrity_check(),
+            vec!["Page 0 and the catalog overlap in the database file"]
+        );
+    }
+
+    #[test]
+    fn test_open_with_other_compression() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, None));
+
+        let err = Pager::open(
+            file.path(),
+            PAGE_SIZE,
+            &options(None, Some(Compression::Lz4)),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Database compression is none but lz4 was requested"
+        );
+    }
+
+    #[test]
+    fn test_integrity_check_page_map() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, Some(Compression::Lz4)));
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).unwrap();
+        let file_len = pager.file_len;
+        pager.header.page_map[1].offset = pager.header.page_map[0].offset + 1;
+        pager.header.page_map[0].len = file_len as u32;
+        let page_0 = pager.header.page_map[0];
+        assert_eq!(
+            pager.integrity_check(),
+            vec![
+                format!(
+                    "Page 0: stored at bytes {}..{} outside the {} byte file",
+                    page_0.offset,
+                    page_0.offset + file_len,
+                    file_len
+                ),
+                "Pages 0 and 1 overlap in the database file".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_free_pages_are_reused() {
+        for compression in [None, Some(Compression::Lz4)] {
+            let file = NamedTempFile::new().unwrap();
+            write_two_pages(file.path(), &options(Some("secret"), compression));
+            let mut pager =
+                Pager::open(file.path(), PAGE_SIZE, &options(Some("secret"), None)).unwrap();
+            let file_len = pager.file_len;
+            pager.free_page(1).unwrap();
+            pager.free_page(0).unwrap();
+            let err = pager.free_page(0).unwrap_err();
+            assert_eq!(err.to_string(), "Page 0 is already free");
+            assert!(pager.get_page(1).unwrap().rows.is_empty());
+            pager.flush(0, &[]).unwrap();
+
+            let mut pager =
+                Pager::open(file.path(), PAGE_SIZE, &options(Some("secret"), None)).unwrap();
+            assert_eq!(pager.header.free_pages, vec![0, 1]);
+            assert!(pager.integrity_check().is_empty());
+            for id in 0..2 {
+                let page_num = pager.allocate_page();
+                assert_eq!(page_num, id as usize);
+                pager.get_page_mut(page_num).unwrap().add_row(row(id)).unwrap();
+            }
+            pager.flush(2, &[]).unwrap();
+            assert_eq!(pager.num_pages(), 2);
+            assert!(pager.file_len <= file_len);
+
+            let mut pager =
+                Pager::open(file.path(), PAGE_SIZE, &options(Some("secret"), None)).unwrap();
+            assert!(pager.header.free_pages.is_empty());
+            assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+            assert!(pager.integrity_check().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_integrity_check_free_list() {
+        let mut pager = Pager::in_memory(PAGE_SIZE);
+        pager.allocate_page();
+        pager.header.free_pages = vec![0, 0, 3];
+        assert_eq!(
+            pager.integrity_check(),
+            vec![
+                "Free list: page 0 is out of order or listed twice",
+                "Free list: page 3 does not exist, the database has 1 pages",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mmap_read_path() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, None));
+
+        // The mapping covers the header and the first page only, the second page is read
+        // with a syscall.
+        let mut options = options(None, None);
+        options.mmap_size = DatabaseHeader::SIZE + PAGE_SIZE;
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.mmap.as_ref().unwrap().len(), options.mmap_size);
+        assert_eq!(pager.get_page(0).unwrap().rows, vec![row(0)]);
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+
+        options.mmap_size = 1 << 20;
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.mmap.as_ref().unwrap().len() as u64, pager.file_len);
+
+        // Pages written through the file are visible through the new mapping after a flush.
+        let page_num = pager.allocate_page();
+        pager.get_page_mut(page_num).unwrap().add_row(row(2)).unwrap();
+        pager.flush(3, &[]).unwrap();
+        assert_eq!(pager.mmap.as_ref().unwrap().len() as u64, pager.file_len);
+
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert_eq!(pager.get_page(2).unwrap().rows, vec![row(2)]);
+    }
+
+    #[test]
+    fn test_mmap_read_path_encrypted_compressed() {
+        let file = NamedTempFile::new().unwrap();
+        let mut options = options(Some("secret"), Some(Compression::Lz4));
+        write_two_pages(file.path(), &options);
+
+        options.mmap_size = 1 << 20;
+        let mut pager = Pager::open(file.path(), PAGE_SIZE, &options).unwrap();
+        assert!(pager.mmap.is_some());
+        assert_eq!(pager.get_page(0).unwrap().rows, vec![row(0)]);
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+    }
+
+    #[test]
+    fn test_mmap_disabled() {
+        let file = NamedTempFile::new().unwrap();
+        write_two_pages(file.path(), &options(None, None));
+
+        let pager = Pager::open(file.path(), PAGE_SIZE, &options(None, None)).unwrap();
+        assert!(pager.mmap.is_none());
+        assert!(Pager::in_memory(PAGE_SIZE).mmap.is_none());
+    }
+
+    #[test]
+    fn test_open_memory() {
+        let mut pager =
+            Pager::open(Path::new(Pager::MEMORY), PAGE_SIZE, &options(None, None)).unwrap();
+        assert!(pager.file.is_none());
+        assert_eq!(pager.num_pages(), 0);
+        pager.allocate_page();
+        assert!(pager.flush(0, &[]).is_ok());
+
+        let err = Pager::open(
+            Path::new(Pager::MEMORY),
+            PAGE_SIZE,
+            &options(Some("secret"), None),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "In-memory databases cannot be encrypted or compressed"
+        );
+    }
+
+    #[test]
+    fn test_save_memory_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("saved.db");
+        let mut pager = Pager::in_memory(PAGE_SIZE);
+        for id in 0..2 {
+            let page_num = pager.allocate_page();
+            pager.get_page_mut(page_num).unwrap().add_row(row(id)).unwrap();
+        }
+
+        let options = options(Some("secret"), Some(Compression::Lz4));
+        pager.save_as(&path, &options, 2, &[]).unwrap();
+
+        let mut saved = Pager::open(&path, PAGE_SIZE, &options).unwrap();
+        assert_eq!(saved.num_rows(), 2);
+        assert_eq!(saved.get_page(0).unwrap().rows, vec![row(0)]);
+        assert_eq!(saved.get_page(1).unwrap().rows, vec![row(1)]);
+        assert!(saved.integrity_check().is_empty());
+
+        // The memory database stays usable and is never written over an existing file.
+        assert_eq!(pager.get_page(1).unwrap().rows, vec![row(1)]);
+        let err = pager.save_as(&path, &options, 2, &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Cannot save to {}, it already exists", path.display())
+        );
+    }
+}
@@ -0,0 +1,2196 @@
+use crate::types::expression::{BinaryOperator, Expression, UnaryOperator};
+use crate::types::tokenizer::{tokenize, Token};
+use crate::types::{
+    AggregateFunction, CommonTableExpression, ForeignKeyAction, Frame, FrameBound, Join,
+    JoinOperator, OrderingTerm, ResultColumn, Row, Select, StatementType, Statistics,
+    TableDefinition, TableReference, TableSource, Union, Value, Window, WindowFunction,
+};
+use anyhow::{anyhow, Result};
+
+/// A common table expression that table names in FROM clauses can refer to.
+struct CommonTableBinding {
+    name: String,
+    columns: Vec<String>,
+    /// Whether a FROM clause referred to it since it was bound.
+    referenced: bool,
+}
+
+/// Recursive descent parser over the tokens of a single statement.
+pub struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// The names of the table and its columns, which statements refer to it by.
+    table: TableDefinition,
+    /// The common table expressions in scope, innermost last.
+    common_tables: Vec<CommonTableBinding>,
+    /// The views of the database, which table names in FROM clauses can refer to.
+    views: Vec<CommonTableExpression>,
+    /// The views referred to so far. The statement reads them as common table expressions
+    /// of its outermost query.
+    expanded_views: Vec<CommonTableExpression>,
+    /// Whether the next query parsed defines a common table expression, so that it reads a
+    /// single row when it has no FROM clause.
+    in_common_table: bool,
+}
+impl Parser {
+    pub fn new(input: &str) -> Result<Parser> {
+        Ok(Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            table: TableDefinition::default(),
+            common_tables: Vec::new(),
+            views: Vec::new(),
+            expanded_views: Vec::new(),
+            in_common_table: false,
+        })
+    }
+
+    /// Makes statements refer to the table and its columns by the names of `table`, rather
+    /// than those of the table a new database starts with.
+    pub fn with_table(mut self, table: &TableDefinition) -> Parser {
+        self.table = table.clone();
+        self
+    }
+
+    /// Lets table names in FROM clauses refer to `views`, unless a common table expression
+    /// of the same name hides them.
+    pub fn with_views(mut self, views: &[CommonTableExpression]) -> Parser {
+        self.views = views.to_vec();
+        self
+    }
+
+    /// Parses `SELECT [result_column, ...] [FROM table [join, ...]] [WHERE expression]
+    /// [GROUP BY expression, ... [HAVING expression]] [ORDER BY term, ...] [LIMIT n
+    /// [OFFSET m]]`, where a result column is `*` or `expression [AS alias]`. An empty SELECT
+    /// list selects every column, and without FROM the query reads the table on its own.
+    pub fn parse_select(&mut self) -> Result<Select> {
+        let select = self.parse_query()?;
+        self.expect_end()?;
+        Ok(self.expand_views(select))
+    }
+
+    /// Parses a CREATE TABLE, CREATE INDEX or CREATE VIEW statement.
+    pub fn parse_create(&mut self) -> Result<StatementType> {
+        match self.tokens.get(1) {
+            Some(Token::Keyword(keyword)) if keyword == "VIEW" => self.parse_create_view(),
+            Some(Token::Identifier(word)) if word.eq_ignore_ascii_case("TABLE") => {
+                self.parse_create_table()
+            }
+            _ => self.parse_create_index(),
+        }
+    }
+
+    /// Parses a DROP TABLE, DROP INDEX or DROP VIEW statement.
+    pub fn parse_drop(&mut self) -> Result<StatementType> {
+        match self.tokens.get(1) {
+            Some(Token::Keyword(keyword)) if keyword == "VIEW" => self.parse_drop_view(),
+            Some(Token::Identifier(word)) if word.eq_ignore_ascii_case("TABLE") => {
+                self.parse_drop_table()
+            }
+            _ => self.parse_drop_index(),
+        }
+    }
+
+    /// Parses a statement stored in the `Catalog`: DROP TABLE, CREATE INDEX, CREATE VIEW or
+    /// ALTER TABLE.
+    pub fn parse_definition(&mut self) -> Result<StatementType> {
+        match self.tokens.first() {
+            Some(Token::Identifier(word)) if word.eq_ignore_ascii_case("ALTER") => {
+                self.parse_alter()
+            }
+            Some(Token::Keyword(keyword)) if keyword == "DROP" => self.parse_drop_table(),
+            _ => self.parse_create(),
+        }
+    }
+
+    /// Parses `CREATE TABLE name (column type, ...)`. Rows are stored in a fixed layout, so
+    /// the columns must have the types of those of the table a new database starts with, in
+    /// the same order, but can have other names.
+    pub fn parse_create_table(&mut self) -> Result<StatementType> {
+        self.expect_keyword("CREATE")?;
+        self.expect_word("TABLE")?;
+        let name = self.parse_name("a table name")?;
+        self.expect(&Token::LeftParen)?;
+        let mut columns = Vec::new();
+        loop {
+            let column = self.parse_name("a column name")?;
+            let column_type = self.parse_name("a column type")?.to_ascii_uppercase();
+            columns.push((column, column_type));
+            if !self.consume(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen)?;
+        self.expect_end()?;
+        let types: Vec<&str> = columns
+            .iter()
+            .map(|(_, column_type)| column_type.as_str())
+            .collect();
+        if types != Row::TYPES {
+            return Err(anyhow!(
+                "Only a table with columns of types {} can be created",
+                Row::TYPES.join(", ")
+            ));
+        }
+        Ok(StatementType::CreateTable {
+            name,
+            columns: columns.into_iter().map(|(column, _)| column).collect(),
+        })
+    }
+
+    /// Parses `DROP TABLE name`.
+    pub fn parse_drop_table(&mut self) -> Result<StatementType> {
+        self.expect_keyword("DROP")?;
+        self.expect_word("TABLE")?;
+        let name = self.parse_name("a table name")?;
+        self.expect_end()?;
+        Ok(StatementType::DropTable { name })
+    }
+
+    /// Parses `ALTER TABLE table` followed by `ADD CONSTRAINT name CHECK (expression)`, `ADD
+    /// CONSTRAINT name FOREIGN KEY (column) REFERENCES table (column) [ON DELETE action]`,
+    /// `DROP CONSTRAINT name`, `ALTER [COLUMN] column SET DEFAULT expression`, `ALTER
+    /// [COLUMN] column DROP DEFAULT`, `RENAME TO name` or `RENAME [COLUMN] column TO name`.
+    pub fn parse_alter(&mut self) -> Result<StatementType> {
+        self.expect_word("ALTER")?;
+        self.expect_word("TABLE")?;
+        let name = self.parse_table_name()?;
+        if self.consume_word("RENAME") {
+            if self.consume_word("TO") {
+                let new_name = self.parse_name("a table name")?;
+                self.expect_end()?;
+                return Ok(StatementType::RenameTable { name, new_name });
+            }
+            self.consume_word("COLUMN");
+            let column = self.parse_name("a column name")?;
+            self.expect_word("TO")?;
+            let new_name = self.parse_name("a column name")?;
+            self.expect_end()?;
+            return Ok(StatementType::RenameColumn { column, new_name });
+        }
+        if self.consume_word("ALTER") {
+            self.consume_word("COLUMN");
+            let column = self.parse_name("a column name")?;
+            let default = match self.consume_keyword("DROP") {
+                true => {
+                    self.expect_word("DEFAULT")?;
+                    None
+                }
+                false => {
+                    self.expect_word("SET")?;
+                    self.expect_word("DEFAULT")?;
+                    Some(self.parse_expression()?)
+                }
+            };
+            self.expect_end()?;
+            return Ok(StatementType::SetDefault { column, default });
+        }
+        let add = self.consume_word("ADD");
+        if !add {
+            self.expect_keyword("DROP")?;
+        }
+        self.expect_word("CONSTRAINT")?;
+        let name = self.parse_name("a constraint name")?;
+        if !add {
+            self.expect_end()?;
+            return Ok(StatementType::DropConstraint { name });
+        }
+        if self.consume_word("FOREIGN") {
+            return self.parse_foreign_key(name);
+        }
+        if !self.consume_word("CHECK") {
+            return Err(anyhow!(
+                "Expected CHECK or FOREIGN KEY but found {}",
+                self.describe_next()
+            ));
+        }
+        self.expect(&Token::LeftParen)?;
+        let check = self.parse_expression()?;
+        self.expect(&Token::RightParen)?;
+        self.expect_end()?;
+        Ok(StatementType::AddConstraint { name, check })
+    }
+
+    // The rest of `FOREIGN KEY (column) REFERENCES table (column) [ON DELETE action]`, where
+    // the action is CASCADE, RESTRICT or NO ACTION. The columns cannot hold NULL, so SET NULL
+    // is rejected.
+    fn parse_foreign_key(&mut self, name: String) -> Result<StatementType> {
+        self.expect_word("KEY")?;
+        self.expect(&Token::LeftParen)?;
+        let column = self.parse_name("a column name")?;
+        self.expect(&Token::RightParen)?;
+        self.expect_word("REFERENCES")?;
+        self.parse_table_name()?;
+        self.expect(&Token::LeftParen)?;
+        let parent_column = self.parse_name("a column name")?;
+        self.expect(&Token::RightParen)?;
+        let mut on_delete = ForeignKeyAction::Restrict;
+        if self.consume_keyword("ON") {
+            self.expect_word("DELETE")?;
+            on_delete = if self.consume_word("CASCADE") {
+                ForeignKeyAction::Cascade
+            } else if self.consume_word("RESTRICT") {
+                ForeignKeyAction::Restrict
+            } else if self.consume_word("NO") {
+                self.expect_word("ACTION")?;
+                ForeignKeyAction::Restrict
+            } else if self.consume_word("SET") {
+                return Err(anyhow!(
+                    "Column {} cannot hold NULL or be set by ON DELETE",
+                    column
+                ));
+            } else {
+                return Err(anyhow!(
+                    "Expected CASCADE, RESTRICT or NO ACTION but found {}",
+                    self.describe_next()
+                ));
+            };
+        }
+        self.expect_end()?;
+        Ok(StatementType::AddForeignKey {
+            name,
+            column,
+            parent_column,
+            on_delete,
+        })
+    }
+
+    /// Parses `DELETE FROM table [WHERE expression]`, where the expression only refers to
+    /// columns of the table.
+    pub fn parse_delete(&mut self) -> Result<StatementType> {
+        self.expect_word("DELETE")?;
+        self.expect_keyword("FROM")?;
+        self.parse_table_name()?;
+        let condition = match self.consume_keyword("WHERE") {
+            true => Some(self.parse_expression()?),
+            false => None,
+        };
+        self.expect_end()?;
+        if let Some(condition) = &condition {
+            if condition.contains_subquery() {
+                return Err(anyhow!("Subqueries are not allowed in DELETE"));
+            }
+            if condition.contains_aggregate() || condition.contains_window() {
+                return Err(anyhow!(
+                    "Aggregate and window functions are not allowed in DELETE"
+                ));
+            }
+            condition.check_columns(&[&self.table.schema(&self.table.name)])?;
+        }
+        Ok(StatementType::Delete { condition })
+    }
+
+    /// Parses `CREATE [UNIQUE] INDEX name ON table (column, ...)`.
+    pub fn parse_create_index(&mut self) -> Result<StatementType> {
+        self.expect_keyword("CREATE")?;
+        let unique = self.consume_keyword("UNIQUE");
+        self.expect_keyword("INDEX")?;
+        let name = self.parse_name("an index name")?;
+        self.expect_keyword("ON")?;
+        self.parse_table_name()?;
+        self.expect(&Token::LeftParen)?;
+        let mut columns = vec![self.parse_name("a column name")?];
+        while self.consume(&Token::Comma) {
+            columns.push(self.parse_name("a column name")?);
+        }
+        self.expect(&Token::RightParen)?;
+        self.expect_end()?;
+        Ok(StatementType::CreateIndex {
+            name,
+            columns,
+            unique,
+        })
+    }
+
+    /// Parses `DROP INDEX name`.
+    pub fn parse_drop_index(&mut self) -> Result<StatementType> {
+        self.expect_keyword("DROP")?;
+        self.expect_keyword("INDEX")?;
+        let name = self.parse_name("an index name")?;
+        self.expect_end()?;
+        Ok(StatementType::DropIndex { name })
+    }
+
+    /// Parses `CREATE VIEW name [(column, ...)] AS select`. The view is kept as a common table
+    /// expression, with the views its select refers to expanded into it.
+    pub fn parse_create_view(&mut self) -> Result<StatementType> {
+        self.expect_keyword("CREATE")?;
+        self.expect_keyword("VIEW")?;
+        let name = self.parse_name("a view name")?;
+        let mut columns = Vec::new();
+        if self.consume(&Token::LeftParen) {
+            loop {
+                columns.push(self.parse_name("a column name")?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RightParen)?;
+        }
+        self.expect_keyword("AS")?;
+        let select = self.parse_select()?;
+        if columns.is_empty() {
+            columns = select.column_names();
+        }
+        Ok(StatementType::CreateView(Box::new(CommonTableExpression {
+            name,
+            columns,
+            select,
+            union: None,
+            recursive: false,
+        })))
+    }
+
+    /// Parses `DROP VIEW name`.
+    pub fn parse_drop_view(&mut self) -> Result<StatementType> {
+        self.expect_keyword("DROP")?;
+        self.expect_keyword("VIEW")?;
+        let name = self.parse_name("a view name")?;
+        self.expect_end()?;
+        Ok(StatementType::DropView { name })
+    }
+
+    fn parse_name(&mut self, expected: &str) -> Result<String> {
+        match self.next() {
+            Some(Token::Identifier(name)) => Ok(name),
+            _ => Err(anyhow!("Expected {}", expected)),
+        }
+    }
+
+    // The name of the table, which is the only one a statement other than a query can
+    // refer to.
+    fn parse_table_name(&mut self) -> Result<String> {
+        let name = self.parse_name("a table name")?;
+        if name != self.table.name {
+            return Err(anyhow!("No such table: {}", name));
+        }
+        Ok(name)
+    }
+
+    // Adds the views the statement refers to in front of the WITH clause of `select`, where
+    // every query of the statement can read them.
+    fn expand_views(&mut self, mut select: Select) -> Select {
+        select.with.splice(0..0, self.expanded_views.drain(..));
+        select
+    }
+
+    // A SELECT statement up to the end of the input or the parenthesis closing a subquery.
+    // The common table expressions of its WITH clause are in scope until its end.
+    fn parse_query(&mut self) -> Result<Select> {
+        let scope = self.common_tables.len();
+        let select = self.parse_with_query();
+        self.common_tables.truncate(scope);
+        select
+    }
+
+    fn parse_with_query(&mut self) -> Result<Select> {
+        let in_common_table = std::mem::take(&mut self.in_common_table);
+        let mut with = Vec::new();
+        if self.consume_keyword("WITH") {
+            let recursive = self.consume_keyword("RECURSIVE");
+            loop {
+                with.push(self.parse_common_table(recursive)?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect_keyword("SELECT")?;
+        let mut columns = Vec::new();
+        if !self.at_clause_end(&["FROM", "WHERE", "GROUP", "ORDER", "LIMIT", "UNION"]) {
+            loop {
+                columns.push(self.parse_result_column()?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+        } else {
+            columns.push(ResultColumn::All);
+        }
+        let mut from = match in_common_table {
+            true => TableReference {
+                name: String::new(),
+                alias: None,
+                source: TableSource::SingleRow,
+            },
+            false => TableReference {
+                name: self.table.name.clone(),
+                alias: None,
+                source: TableSource::Table(self.table.columns.clone()),
+            },
+        };
+        let mut joins = Vec::new();
+        if self.consume_keyword("FROM") {
+            from = self.parse_table_reference()?;
+            while let Some(join) = self.parse_join()? {
+                joins.push(join);
+            }
+        }
+        let where_clause = if self.consume_keyword("WHERE") {
+            let where_clause = self.parse_expression()?;
+            if where_clause.contains_aggregate() {
+                return Err(anyhow!("Aggregate functions are not allowed in WHERE"));
+            }
+            if where_clause.contains_window() {
+                return Err(anyhow!("Window functions are not allowed in WHERE"));
+            }
+            Some(where_clause)
+        } else {
+            None
+        };
+        let mut group_by = Vec::new();
+        if self.consume_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            let positions = Self::result_expressions(&columns, &from, &joins);
+            loop {
+                let expression = Self::resolve_alias(self.parse_expression()?, &columns);
+                let expression = Self::resolve_position(expression, &positions, "GROUP BY")?;
+                if expression.contains_aggregate() {
+                    return Err(anyhow!("Aggregate functions are not allowed in GROUP BY"));
+                }
+                if expression.contains_window() {
+                    return Err(anyhow!("Window functions are not allowed in GROUP BY"));
+                }
+                group_by.push(expression);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let having = if self.consume_keyword("HAVING") {
+            if group_by.is_empty() {
+                return Err(anyhow!("A GROUP BY clause is required before HAVING"));
+            }
+            let having = self.parse_expression()?;
+            if having.contains_window() {
+                return Err(anyhow!("Window functions are not allowed in HAVING"));
+            }
+            Some(having)
+        } else {
+            None
+        };
+        let mut order_by = Vec::new();
+        if self.consume_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            let positions = Self::result_expressions(&columns, &from, &joins);
+            loop {
+                let mut term = self.parse_ordering_term()?;
+                term.expression = Self::resolve_alias(term.expression, &columns);
+                term.expression = Self::resolve_position(term.expression, &positions, "ORDER BY")?;
+                order_by.push(term);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let mut limit = None;
+        let mut offset = 0;
+        if self.consume_keyword("LIMIT") {
+            limit = Some(self.parse_count("LIMIT")?);
+            if self.consume_keyword("OFFSET") {
+                offset = self.parse_count("OFFSET")?;
+            }
+        }
+        Ok(Select {
+            with,
+            columns,
+            from,
+            joins,
+            where_clause,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_table_reference(&mut self) -> Result<TableReference> {
+        let name = match self.next() {
+            Some(Token::Identifier(name)) => name,
+            _ => return Err(anyhow!("Expected a table name")),
+        };
+        let alias = if self.consume_keyword("AS") {
+            match self.next() {
+                Some(Token::Identifier(alias)) => Some(alias),
+                _ => return Err(anyhow!("Expected an alias after AS")),
+            }
+        } else if let Some(Token::Identifier(alias)) = self.peek() {
+            let alias = alias.clone();
+            self.position += 1;
+            Some(alias)
+        } else {
+            None
+        };
+        // A common table expression hides the table of the same name.
+        let source = match self.common_tables.iter_mut().rev().find(|t| t.name == name) {
+            Some(common_table) => {
+                common_table.referenced = true;
+                TableSource::CommonTable(common_table.columns.clone())
+            }
+            None => match self.views.iter().find(|view| view.name == name) {
+                Some(view) => {
+                    if !self
+                        .expanded_views
+                        .iter()
+                        .any(|expanded| expanded.name == name)
+                    {
+                        self.expanded_views.push(view.clone());
+                    }
+                    TableSource::CommonTable(view.columns.clone())
+                }
+                None if name == Statistics::TABLE_NAME => TableSource::Statistics,
+                None if name == self.table.name => TableSource::Table(self.table.columns.clone()),
+                None => return Err(anyhow!("No such table: {}", name)),
+            },
+        };
+        Ok(TableReference {
+            name,
+            alias,
+            source,
+        })
+    }
+
+    // `name [(column, ...)] AS (select [UNION [ALL] select])`. In a WITH RECURSIVE clause
+    // the select after UNION can refer to the expression being defined.
+    fn parse_common_table(&mut self, recursive: bool) -> Result<CommonTableExpression> {
+        let name = match self.next() {
+            Some(Token::Identifier(name)) => name,
+            _ => return Err(anyhow!("Expected a name for the common table expression")),
+        };
+        let mut columns = Vec::new();
+        if self.consume(&Token::LeftParen) {
+            loop {
+                match self.next() {
+                    Some(Token::Identifier(column)) => columns.push(column),
+                    _ => return Err(anyhow!("Expected a column name for {}", name)),
+                }
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RightParen)?;
+        }
+        self.expect_keyword("AS")?;
+        self.expect(&Token::LeftParen)?;
+        self.in_common_table = true;
+        let select = self.parse_query()?;
+        if columns.is_empty() {
+            columns = select.column_names();
+        }
+        let mut common_table = CommonTableExpression {
+            name: name.clone(),
+            columns: columns.clone(),
+            select,
+            union: None,
+            recursive: false,
+        };
+        if recursive {
+            self.common_tables.push(CommonTableBinding {
+                name,
+                columns,
+                referenced: false,
+            });
+        }
+        if self.consume_keyword("UNION") {
+            let all = self.consume_keyword("ALL");
+            self.in_common_table = true;
+            let select = self.parse_query()?;
+            common_table.union = Some(Union { all, select });
+        }
+        self.expect(&Token::RightParen)?;
+        match self.common_tables.last_mut() {
+            Some(binding) if recursive => {
+                common_table.recursive = binding.referenced;
+                binding.referenced = false;
+            }
+            _ => self.common_tables.push(CommonTableBinding {
+                name: common_table.name.clone(),
+                columns: common_table.columns.clone(),
+                referenced: false,
+            }),
+        }
+        Ok(common_table)
+    }
+
+    // `, table`, `CROSS JOIN table`, `[INNER] JOIN table [ON expression]` or
+    // `LEFT [OUTER] JOIN table [ON expression]`. A join without ON pairs every row of both
+    // sides, like a cross join.
+    fn parse_join(&mut self) -> Result<Option<Join>> {
+        if self.consume(&Token::Comma) {
+            return Ok(Some(Join {
+                operator: JoinOperator::Cross,
+                table: self.parse_table_reference()?,
+                constraint: None,
+            }));
+        }
+        let operator = if self.consume_keyword("CROSS") {
+            JoinOperator::Cross
+        } else if self.consume_keyword("LEFT") {
+            self.consume_keyword("OUTER");
+            JoinOperator::Left
+        } else if self.consume_keyword("INNER") || self.peek_keyword("JOIN") {
+            JoinOperator::Inner
+        } else {
+            return Ok(None);
+        };
+        self.expect_keyword("JOIN")?;
+        let table = self.parse_table_reference()?;
+        let constraint = if operator != JoinOperator::Cross && self.consume_keyword("ON") {
+            let constraint = self.parse_expression()?;
+            if constraint.contains_aggregate() {
+                return Err(anyhow!("Aggregate functions are not allowed in ON"));
+            }
+            if constraint.contains_window() {
+                return Err(anyhow!("Window functions are not allowed in ON"));
+            }
+            Some(constraint)
+        } else {
+            None
+        };
+        Ok(Some(Join {
+            operator,
+            table,
+            constraint,
+        }))
+    }
+
+    fn parse_count(&mut self, clause: &str) -> Result<usize> {
+        match self.next() {
+            Some(Token::Integer(count)) => Ok(count as usize),
+            _ => Err(anyhow!("{} requires a non-negative integer", clause)),
+        }
+    }
+
+    fn parse_ordering_term(&mut self) -> Result<OrderingTerm> {
+        let expression = self.parse_expression()?;
+        let descending = if self.consume_keyword("DESC") {
+            true
+        } else {
+            self.consume_keyword("ASC");
+            false
+        };
+        Ok(OrderingTerm {
+            expression,
+            descending,
+        })
+    }
+
+    // A bare name in GROUP BY or ORDER BY that matches an alias of the SELECT list refers to
+    // that result column, as in `SELECT id * 2 AS double ORDER BY double`.
+    fn resolve_alias(expression: Expression, columns: &[ResultColumn]) -> Expression {
+        let Expression::Column { table: None, name } = &expression else {
+            return expression;
+        };
+        columns
+            .iter()
+            .find_map(|column| match column {
+                ResultColumn::Expression {
+                    expression,
+                    alias: Some(alias),
+                } if alias == name => Some(expression.clone()),
+                _ => None,
+            })
+            .unwrap_or(expression)
+    }
+
+    // An integer in GROUP BY or ORDER BY refers to the result column at that position,
+    // counted from 1, as in `SELECT username, count(*) GROUP BY 1 ORDER BY 2 DESC`.
+    fn resolve_position(
+        expression: Expression,
+        positions: &[Expression],
+        clause: &str,
+    ) -> Result<Expression> {
+        let Expression::Literal(Value::Integer(position)) = expression else {
+            return Ok(expression);
+        };
+        match usize::try_from(position) {
+            Ok(position) if (1..=positions.len()).contains(&position) => {
+                Ok(positions[position - 1].clone())
+            }
+            _ => Err(anyhow!(
+                "{} position {} is out of range - should be between 1 and {}",
+                clause,
+                position,
+                positions.len()
+            )),
+        }
+    }
+
+    // The expressions of the SELECT list, with `*` expanded to the columns of the tables.
+    fn result_expressions(
+        columns: &[ResultColumn],
+        from: &TableReference,
+        joins: &[Join],
+    ) -> Vec<Expression> {
+        let schema = joins.iter().fold(from.schema(), |schema, join| {
+            schema.join(&join.table.schema())
+        });
+        let mut expressions = Vec::new();
+        for column in columns {
+            match column {
+                ResultColumn::All => {
+                    expressions.extend(schema.columns.iter().map(|column| Expression::Column {
+                        table: column.table.clone(),
+                        name: column.name.clone(),
+                    }))
+                }
+                ResultColumn::Expression { expression, .. } => expressions.push(expression.clone()),
+            }
+        }
+        expressions
+    }
+
+    fn parse_result_column(&mut self) -> Result<ResultColumn> {
+        if self.consume(&Token::Star) {
+            return Ok(ResultColumn::All);
+        }
+        let expression = self.parse_expression()?;
+        let alias = if self.consume_keyword("AS") {
+            match self.next() {
+                Some(Token::Identifier(alias)) => Some(alias),
+                _ => return Err(anyhow!("Expected an alias after AS")),
+            }
+        } else {
+            None
+        };
+        Ok(ResultColumn::Expression { expression, alias })
+    }
+
+    // Operators from the loosest to the tightest binding:
+    // OR, AND, NOT, comparisons, + and -, * / and %, unary minus.
+    pub fn parse_expression(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_and()?;
+        while self.consume_keyword("OR") {
+            expression = Self::binary(BinaryOperator::Or, expression, self.parse_and()?);
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_not()?;
+        while self.consume_keyword("AND") {
+            expression = Self::binary(BinaryOperator::And, expression, self.parse_not()?);
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression> {
+        if self.consume_keyword("NOT") {
+            return Ok(Expression::Unary {
+                operator: UnaryOperator::Not,
+                operand: Box::new(self.parse_not()?),
+            });
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression> {
+        let left = self.parse_additive()?;
+        let negated = self.peek_keyword("NOT")
+            && self.tokens.get(self.position + 1) == Some(&Token::Keyword("IN".to_string()));
+        if negated {
+            self.position += 1;
+        }
+        if self.consume_keyword("IN") {
+            return Ok(Expression::InSubquery {
+                operand: Box::new(left),
+                subquery: Box::new(self.parse_subquery()?),
+                negated,
+            });
+        }
+        let operator = match self.peek() {
+            Some(Token::Eq) => BinaryOperator::Eq,
+            Some(Token::NotEq) => BinaryOperator::NotEq,
+            Some(Token::Lt) => BinaryOperator::Lt,
+            Some(Token::LtEq) => BinaryOperator::LtEq,
+            Some(Token::Gt) => BinaryOperator::Gt,
+            Some(Token::GtEq) => BinaryOperator::GtEq,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        Ok(Self::binary(operator, left, self.parse_additive()?))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_multiplicative()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => BinaryOperator::Add,
+                Some(Token::Minus) => BinaryOperator::Subtract,
+                _ => return Ok(expression),
+            };
+            self.position += 1;
+            expression = Self::binary(operator, expression, self.parse_multiplicative()?);
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expression> {
+        let mut expression = self.parse_unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Star) => BinaryOperator::Multiply,
+                Some(Token::Slash) => BinaryOperator::Divide,
+                Some(Token::Percent) => BinaryOperator::Modulo,
+                _ => return Ok(expression),
+            };
+            self.position += 1;
+            expression = Self::binary(operator, expression, self.parse_unary()?);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression> {
+        if self.consume(&Token::Minus) {
+            return Ok(Expression::Unary {
+                operator: UnaryOperator::Negate,
+                operand: Box::new(self.parse_unary()?),
+            });
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression> {
+        match self.next() {
+            Some(Token::Integer(value)) => Ok(Expression::Literal(Value::Integer(value))),
+            Some(Token::Real(value)) => Ok(Expression::Literal(Value::Real(value))),
+            Some(Token::String(value)) => Ok(Expression::Literal(Value::Text(value))),
+            Some(Token::Keyword(keyword)) if keyword == "NULL" => {
+                Ok(Expression::Literal(Value::Null))
+            }
+            Some(Token::Identifier(name)) if self.consume(&Token::LeftParen) => {
+                self.parse_function_call(&name)
+            }
+            Some(Token::Identifier(table)) if self.consume(&Token::Dot) => match self.next() {
+                Some(Token::Identifier(name)) => Ok(Expression::Column {
+                    table: Some(table),
+                    name,
+                }),
+                _ => Err(anyhow!("Expected a column name after '{}.'", table)),
+            },
+            Some(Token::Identifier(name)) => Ok(Expression::Column { table: None, name }),
+            Some(Token::Keyword(keyword)) if keyword == "EXISTS" => {
+                Ok(Expression::Exists(Box::new(self.parse_subquery()?)))
+            }
+            Some(Token::LeftParen) if self.peek_keyword("SELECT") || self.peek_keyword("WITH") => {
+                let subquery = self.parse_query()?;
+                self.expect(&Token::RightParen)?;
+                Ok(Expression::Subquery(Box::new(subquery)))
+            }
+            Some(Token::LeftParen) => {
+                let expression = self.parse_expression()?;
+                self.expect(&Token::RightParen)?;
+                Ok(expression)
+            }
+            Some(token) => Err(anyhow!(
+                "Unexpected {} in expression",
+                Self::describe(&token)
+            )),
+            None => Err(anyhow!("Expected an expression")),
+        }
+    }
+
+    // `(SELECT ...)` after IN or EXISTS.
+    fn parse_subquery(&mut self) -> Result<Select> {
+        self.expect(&Token::LeftParen)?;
+        let subquery = self.parse_query()?;
+        self.expect(&Token::RightParen)?;
+        Ok(subquery)
+    }
+
+    // Called after the opening parenthesis of `name(`. An aggregate call followed by OVER is
+    // an aggregate window function, whose argument may use the aggregates of the query.
+    fn parse_function_call(&mut self, name: &str) -> Result<Expression> {
+        if let Some(function) = WindowFunction::from_name(name) {
+            let mut arguments = Vec::new();
+            if !self.consume(&Token::RightParen) {
+                loop {
+                    arguments.push(self.parse_expression()?);
+                    if !self.consume(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&Token::RightParen)?;
+            }
+            let (min, max) = function.arity();
+            if arguments.len() < min || arguments.len() > max {
+                return Err(match (min, max) {
+                    (0, 0) => anyhow!("{}() takes no arguments", function),
+                    _ => anyhow!("{}() takes {} to {} arguments", function, min, max),
+                });
+            }
+            if !self.consume_keyword("OVER") {
+                return Err(anyhow!("{}() requires an OVER clause", function));
+            }
+            return self.parse_window_call(function, arguments);
+        }
+        let function = AggregateFunction::from_name(name)
+            .ok_or_else(|| anyhow!("No such function: {}", name))?;
+        let argument = if function == AggregateFunction::Count && self.consume(&Token::Star) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect(&Token::RightParen)?;
+        if self.consume_keyword("OVER") {
+            let arguments = argument.into_iter().collect();
+            return self.parse_window_call(WindowFunction::Aggregate(function), arguments);
+        }
+        if let Some(argument) = &argument {
+            if argument.contains_aggregate() {
+                return Err(anyhow!("Aggregate functions cannot be nested"));
+            }
+            if argument.contains_window() {
+                return Err(anyhow!(
+                    "Window functions are not allowed in aggregate functions"
+                ));
+            }
+        }
+        Ok(Expression::Aggregate {
+            function,
+            argument: argument.map(Box::new),
+        })
+    }
+
+    // Called after OVER: `([PARTITION BY expression, ...] [ORDER BY term, ...] [ROWS frame])`.
+    fn parse_window_call(
+        &mut self,
+        function: WindowFunction,
+        arguments: Vec<Expression>,
+    ) -> Result<Expression> {
+        self.expect(&Token::LeftParen)?;
+        let mut partition_by = Vec::new();
+        if self.consume_keyword("PARTITION") {
+            self.expect_keyword("BY")?;
+            loop {
+                partition_by.push(self.parse_expression()?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.consume_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                order_by.push(self.parse_ordering_term()?);
+                if !self.consume(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let frame = if self.consume_keyword("ROWS") {
+            Some(self.parse_frame()?)
+        } else {
+            None
+        };
+        self.expect(&Token::RightParen)?;
+        let expression = Expression::Window {
+            function,
+            arguments,
+            window: Box::new(Window {
+                partition_by,
+                order_by,
+                frame,
+            }),
+        };
+        if expression
+            .window_operands()
+            .any(Expression::contains_window)
+        {
+            return Err(anyhow!("Window functions cannot be nested"));
+        }
+        Ok(expression)
+    }
+
+    // `BETWEEN bound AND bound`, or a single bound for a frame ending at the current row.
+    fn parse_frame(&mut self) -> Result<Frame> {
+        if !self.consume_keyword("BETWEEN") {
+            return Frame::new(self.parse_frame_bound()?, FrameBound::CurrentRow);
+        }
+        let start = self.parse_frame_bound()?;
+        self.expect_keyword("AND")?;
+        Frame::new(start, self.parse_frame_bound()?)
+    }
+
+    fn parse_frame_bound(&mut self) -> Result<FrameBound> {
+        if self.consume_keyword("CURRENT") {
+            self.expect_keyword("ROW")?;
+            return Ok(FrameBound::CurrentRow);
+        }
+        let rows = match self.next() {
+            Some(Token::Keyword(keyword)) if keyword == "UNBOUNDED" => None,
+            Some(Token::Integer(rows)) => Some(rows as usize),
+            _ => return Err(anyhow!("Expected a window frame bound")),
+        };
+        let preceding = if self.consume_keyword("PRECEDING") {
+            true
+        } else {
+            self.expect_keyword("FOLLOWING")?;
+            false
+        };
+        Ok(match (rows, preceding) {
+            (None, true) => FrameBound::UnboundedPreceding,
+            (None, false) => FrameBound::UnboundedFollowing,
+            (Some(rows), true) => FrameBound::Preceding(rows),
+            (Some(rows), false) => FrameBound::Following(rows),
+        })
+    }
+
+    fn binary(operator: BinaryOperator, left: Expression, right: Expression) -> Expression {
+        Expression::Binary {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Whether the input or the enclosing subquery ends here, or continues with one of the
+    /// given clause keywords.
+    fn at_clause_end(&self, keywords: &[&str]) -> bool {
+        match self.peek() {
+            None | Some(Token::RightParen) => true,
+            Some(Token::Keyword(keyword)) => keywords.contains(&keyword.as_str()),
+            Some(_) => false,
+        }
+    }
+
+    fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Keyword(k)) if k == keyword)
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        self.consume(&Token::Keyword(keyword.to_string()))
+    }
+
+    // Words that are not keywords, see `tokenizer::KEYWORDS`, are identifiers matched in
+    // any case where the grammar expects them.
+    fn peek_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(name)) if name.eq_ignore_ascii_case(word))
+    }
+
+    fn consume_word(&mut self, word: &str) -> bool {
+        if self.peek_word(word) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<()> {
+        if self.consume_word(word) {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Expected {} but found {}",
+            word,
+            self.describe_next()
+        ))
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.consume(token) {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Expected {} but found {}",
+            Self::describe(token),
+            self.describe_next()
+        ))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        self.expect(&Token::Keyword(keyword.to_string()))
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(anyhow!("Unexpected {}", Self::describe(token))),
+        }
+    }
+
+    fn describe_next(&self) -> String {
+        self.peek()
+            .map(Self::describe)
+            .unwrap_or_else(|| "end of input".to_string())
+    }
+
+    fn describe(token: &Token) -> String {
+        match token {
+            Token::Keyword(keyword) => keyword.clone(),
+            Token::Identifier(name) => format!("'{}'", name),
+            Token::Integer(value) => value.to_string(),
+            Token::Real(value) => value.to_string(),
+            Token::String(value) => format!("string '{}'", value),
+            Token::LeftParen => "'('".to_string(),
+            Token::RightParen => "')'".to_string(),
+            Token::Comma => "','".to_string(),
+            Token::Dot => "'.'".to_string(),
+            Token::Star => "'*'".to_string(),
+            Token::Plus => "'+'".to_string(),
+            Token::Minus => "'-'".to_string(),
+            Token::Slash => "'/'".to_string(),
+            Token::Percent => "'%'".to_string(),
+            Token::Eq => "'='".to_string(),
+            Token::NotEq => "'!='".to_string(),
+            Token::Lt => "'<'".to_string(),
+            Token::LtEq => "'<='".to_string(),
+            Token::Gt => "'>'".to_string(),
+            Token::GtEq => "'>='".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Expression> {
+        let mut parser = Parser::new(input)?;
+        let expression = parser.parse_expression()?;
+        parser.expect_end()?;
+        Ok(expression)
+    }
+
+    fn column(name: &str) -> Box<Expression> {
+        Box::new(Expression::Column {
+            table: None,
+            name: name.to_string(),
+        })
+    }
+
+    fn integer(value: i64) -> Box<Expression> {
+        Box::new(Expression::Literal(Value::Integer(value)))
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        // a OR b AND NOT c = 1 + 2 * 3 groups as a OR (b AND (NOT (c = (1 + (2 * 3)))))
+        let expression = parse("a or b and not c = 1 + 2 * 3").unwrap();
+        let expected = Expression::Binary {
+            operator: BinaryOperator::Or,
+            left: column("a"),
+            right: Box::new(Expression::Binary {
+                operator: BinaryOperator::And,
+                left: column("b"),
+                right: Box::new(Expression::Unary {
+                    operator: UnaryOperator::Not,
+                    operand: Box::new(Expression::Binary {
+                        operator: BinaryOperator::Eq,
+                        left: column("c"),
+                        right: Box::new(Expression::Binary {
+                            operator: BinaryOperator::Add,
+                            left: integer(1),
+                            right: Box::new(Expression::Binary {
+                                operator: BinaryOperator::Multiply,
+                                left: integer(2),
+                                right: integer(3),
+                            }),
+                        }),
+                    }),
+                }),
+            }),
+        };
+        assert_eq!(expression, expected);
+    }
+
+    #[test]
+    fn test_parse_parentheses_and_unary() {
+        let expression = parse("(a - 1) * -2").unwrap();
+        let expected = Expression::Binary {
+            operator: BinaryOperator::Multiply,
+            left: Box::new(Expression::Binary {
+                operator: BinaryOperator::Subtract,
+                left: column("a"),
+                right: integer(1),
+            }),
+            right: Box::new(Expression::Unary {
+                operator: UnaryOperator::Negate,
+                operand: integer(2),
+            }),
+        };
+        assert_eq!(expression, expected);
+    }
+
+    #[test]
+    fn test_parse_left_associative() {
+        let expression = parse("10 - 4 - 3").unwrap();
+        let expected = Expression::Binary {
+            operator: BinaryOperator::Subtract,
+            left: Box::new(Expression::Binary {
+                operator: BinaryOperator::Subtract,
+                left: integer(10),
+                right: integer(4),
+            }),
+            right: integer(3),
+        };
+        assert_eq!(expression, expected);
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let mut parser = Parser::new("SELECT * WHERE username = 'bob'").unwrap();
+        let select = parser.parse_select().unwrap();
+        assert_eq!(select.columns, vec![ResultColumn::All]);
+        assert_eq!(
+            select.where_clause,
+            Some(Expression::Binary {
+                operator: BinaryOperator::Eq,
+                left: column("username"),
+                right: Box::new(Expression::Literal(Value::Text("bob".to_string()))),
+            })
+        );
+
+        let select = Parser::new("select").unwrap().parse_select().unwrap();
+        assert_eq!(
+            select,
+            Select {
+                with: Vec::new(),
+                columns: vec![ResultColumn::All],
+                from: TableReference {
+                    name: "users".to_string(),
+                    alias: None,
+                    source: TableSource::Table(TableDefinition::default().columns),
+                },
+                joins: Vec::new(),
+                where_clause: None,
+                group_by: Vec::new(),
+                having: None,
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_select_columns() {
+        let select = Parser::new("select id, id * 2 as double, *")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(
+            select.columns,
+            vec![
+                ResultColumn::Expression {
+                    expression: *column("id"),
+                    alias: None,
+                },
+                ResultColumn::Expression {
+                    expression: Expression::Binary {
+                        operator: BinaryOperator::Multiply,
+                        left: column("id"),
+                        right: integer(2),
+                    },
+                    alias: Some("double".to_string()),
+                },
+                ResultColumn::All,
+            ]
+        );
+        assert_eq!(select.where_clause, None);
+    }
+
+    #[test]
+    fn test_parse_select_order_by() {
+        let select =
+            Parser::new("select id * 2 as double where id > 1 order by username desc, double")
+                .unwrap()
+                .parse_select()
+                .unwrap();
+        assert_eq!(
+            select.order_by,
+            vec![
+                OrderingTerm {
+                    expression: *column("username"),
+                    descending: true,
+                },
+                OrderingTerm {
+                    expression: Expression::Binary {
+                        operator: BinaryOperator::Multiply,
+                        left: column("id"),
+                        right: integer(2),
+                    },
+                    descending: false,
+                },
+            ]
+        );
+
+        let err = Parser::new("select order id")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Expected BY but found 'id'");
+
+        let select = Parser::new("select username, id from users as u order by 2 desc, 1")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(
+            select.order_by,
+            vec![
+                OrderingTerm {
+                    expression: *column("id"),
+                    descending: true,
+                },
+                OrderingTerm {
+                    expression: *column("username"),
+                    descending: false,
+                },
+            ]
+        );
+        let select = Parser::new("select * from users as u order by 3")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(
+            select.order_by[0].expression,
+            Expression::Column {
+                table: Some("u".to_string()),
+                name: "email".to_string(),
+            }
+        );
+
+        let err = Parser::new("select username, id order by 3")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ORDER BY position 3 is out of range - should be between 1 and 2"
+        );
+    }
+
+    #[test]
+    fn test_parse_select_group_by() {
+        let select = Parser::new("select username, count(*) group by username having sum(id) > 2")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(select.group_by, vec![*column("username")]);
+        assert_eq!(
+            select.having,
+            Some(Expression::Binary {
+                operator: BinaryOperator::Gt,
+                left: Box::new(Expression::Aggregate {
+                    function: AggregateFunction::Sum,
+                    argument: Some(column("id")),
+                }),
+                right: integer(2),
+            })
+        );
+        assert_eq!(
+            select.columns[1],
+            ResultColumn::Expression {
+                expression: Expression::Aggregate {
+                    function: AggregateFunction::Count,
+                    argument: None,
+                },
+                alias: None,
+            }
+        );
+
+        let select = Parser::new("select username, count(*) group by 1")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(select.group_by, vec![*column("username")]);
+    }
+
+    #[test]
+    fn test_parse_aggregate_errors() {
+        let cases = [
+            ("select foo(id)", "No such function: foo"),
+            ("select sum(*)", "Unexpected '*' in expression"),
+            (
+                "select max(min(id))",
+                "Aggregate functions cannot be nested",
+            ),
+            (
+                "select where count(*) > 1",
+                "Aggregate functions are not allowed in WHERE",
+            ),
+            (
+                "select group by max(id)",
+                "Aggregate functions are not allowed in GROUP BY",
+            ),
+            (
+                "select username, count(*) group by 2",
+                "Aggregate functions are not allowed in GROUP BY",
+            ),
+            (
+                "select username group by 0",
+                "GROUP BY position 0 is out of range - should be between 1 and 1",
+            ),
+            (
+                "select count(*) having count(*) > 1",
+                "A GROUP BY clause is required before HAVING",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = Parser::new(input).unwrap().parse_select().unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_window_functions() {
+        let cases = [
+            ("row_number() over ()", "row_number() OVER ()"),
+            (
+                "rank() over (partition by username, id % 2 order by id desc)",
+                "rank() OVER (PARTITION BY username, id % 2 ORDER BY id DESC)",
+            ),
+            (
+                "lag(id, 2, 0) over (order by id) + 1",
+                "lag(id, 2, 0) OVER (ORDER BY id) + 1",
+            ),
+            (
+                "count(*) over (order by id rows between unbounded preceding and current row)",
+                "count(*) OVER (ORDER BY id ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW)",
+            ),
+            (
+                "sum(max(id)) over (rows 3 preceding)",
+                "sum(max(id)) OVER (ROWS BETWEEN 3 PRECEDING AND CURRENT ROW)",
+            ),
+            (
+                "avg(id) over (rows between 1 following and unbounded following)",
+                "avg(id) OVER (ROWS BETWEEN 1 FOLLOWING AND UNBOUNDED FOLLOWING)",
+            ),
+        ];
+        for (input, expected) in cases {
+            let expression = parse(input).unwrap();
+            assert_eq!(expression.to_string(), expected, "{}", input);
+            assert!(expression.contains_window(), "{}", input);
+        }
+        assert!(!parse("(select row_number() over ())")
+            .unwrap()
+            .contains_window());
+        assert!(parse("sum(max(id)) over ()").unwrap().contains_aggregate());
+
+        let cases = [
+            (
+                "select row_number()",
+                "row_number() requires an OVER clause",
+            ),
+            ("select rank(id) over ()", "rank() takes no arguments"),
+            ("select lead() over ()", "lead() takes 1 to 3 arguments"),
+            (
+                "select sum(id) over (rows 1 following)",
+                "Invalid window frame: 1 FOLLOWING AND CURRENT ROW",
+            ),
+            (
+                "select count(*) over (rows between unbounded following and current row)",
+                "Invalid window frame: UNBOUNDED FOLLOWING AND CURRENT ROW",
+            ),
+            (
+                "select count(*) over (rows id preceding)",
+                "Expected a window frame bound",
+            ),
+            (
+                "select sum(row_number() over ()) over ()",
+                "Window functions cannot be nested",
+            ),
+            (
+                "select max(row_number() over ())",
+                "Window functions are not allowed in aggregate functions",
+            ),
+            (
+                "select where rank() over () = 1",
+                "Window functions are not allowed in WHERE",
+            ),
+            (
+                "select group by rank() over ()",
+                "Window functions are not allowed in GROUP BY",
+            ),
+            (
+                "select group by id having rank() over () = 1",
+                "Window functions are not allowed in HAVING",
+            ),
+            (
+                "select from users a join users b on rank() over () = 1",
+                "Window functions are not allowed in ON",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = Parser::new(input).unwrap().parse_select().unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_select_joins() {
+        let select = Parser::new(
+            "select a.id from users a left join users as b on a.id = b.id cross join users c, \
+             users where c.id > 1",
+        )
+        .unwrap()
+        .parse_select()
+        .unwrap();
+        let table = |alias: Option<&str>| TableReference {
+            name: "users".to_string(),
+            alias: alias.map(str::to_string),
+            source: TableSource::Table(TableDefinition::default().columns),
+        };
+        let qualified = |table: &str, name: &str| {
+            Box::new(Expression::Column {
+                table: Some(table.to_string()),
+                name: name.to_string(),
+            })
+        };
+        assert_eq!(select.from, table(Some("a")));
+        assert_eq!(
+            select.joins,
+            vec![
+                Join {
+                    operator: JoinOperator::Left,
+                    table: table(Some("b")),
+                    constraint: Some(Expression::Binary {
+                        operator: BinaryOperator::Eq,
+                        left: qualified("a", "id"),
+                        right: qualified("b", "id"),
+                    }),
+                },
+                Join {
+                    operator: JoinOperator::Cross,
+                    table: table(Some("c")),
+                    constraint: None,
+                },
+                Join {
+                    operator: JoinOperator::Cross,
+                    table: table(None),
+                    constraint: None,
+                },
+            ]
+        );
+        assert_eq!(
+            select.columns,
+            vec![ResultColumn::Expression {
+                expression: *qualified("a", "id"),
+                alias: None,
+            }]
+        );
+
+        let cases = [
+            ("select from", "Expected a table name"),
+            ("select from users a join", "Expected a table name"),
+            (
+                "select from users left users",
+                "Expected JOIN but found 'users'",
+            ),
+            (
+                "select from users a join users b on count(*) > 1",
+                "Aggregate functions are not allowed in ON",
+            ),
+            (
+                "select a. from users a",
+                "Expected a column name after 'a.'",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = Parser::new(input).unwrap().parse_select().unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_subqueries() {
+        let cases = [
+            (
+                "id not in (select id from users b where b.id > 1) and exists (select)",
+                "(id NOT IN (SELECT id FROM users AS b WHERE b.id > 1)) AND EXISTS (SELECT * FROM users)",
+            ),
+            (
+                "not id in (select id order by id desc limit 2)",
+                "NOT (id IN (SELECT id FROM users ORDER BY id DESC LIMIT 2))",
+            ),
+            (
+                "(select count(*) from users a join users b on a.id = b.id) + 1",
+                "(SELECT count(*) FROM users AS a JOIN users AS b ON a.id = b.id) + 1",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).unwrap().to_string(), expected, "{}", input);
+        }
+        assert!(!parse("(select count(*))").unwrap().contains_aggregate());
+
+        let cases = [
+            ("id in (1, 2)", "Expected SELECT but found 1"),
+            ("exists select", "Expected '(' but found SELECT"),
+            ("(select id", "Expected ')' but found end of input"),
+            ("id not (select id)", "Unexpected NOT"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).unwrap_err().to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_common_tables() {
+        let select = Parser::new(
+            "with recursive chain(n) as (select 1 union all select n + 1 from chain where n < 3), \
+             named as (select id, username from chain join users on id = n) \
+             select * from named a, chain, users",
+        )
+        .unwrap()
+        .parse_select()
+        .unwrap();
+        assert_eq!(select.with.len(), 2);
+        assert!(select.with[0].recursive);
+        assert_eq!(select.with[0].columns, vec!["n"]);
+        assert!(!select.with[1].recursive);
+        assert_eq!(select.with[1].columns, vec!["id", "username"]);
+        let sources: Vec<&TableSource> = std::iter::once(&select.from)
+            .chain(select.joins.iter().map(|join| &join.table))
+            .map(|table| &table.source)
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                &TableSource::CommonTable(vec!["id".to_string(), "username".to_string()]),
+                &TableSource::CommonTable(vec!["n".to_string()]),
+                &TableSource::Table(TableDefinition::default().columns),
+            ]
+        );
+        assert_eq!(
+            select.to_string(),
+            "WITH RECURSIVE chain(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM chain \
+             WHERE n < 3), named(id, username) AS (SELECT id, username FROM chain JOIN users ON \
+             id = n) SELECT * FROM named AS a CROSS JOIN chain CROSS JOIN users"
+        );
+
+        // Without RECURSIVE, and outside of the query defining it, the name is the table's.
+        let select =
+            Parser::new("select (with users as (select 1) select count(*) from users) from users")
+                .unwrap()
+                .parse_select()
+                .unwrap();
+        assert_eq!(
+            select.from.source,
+            TableSource::Table(TableDefinition::default().columns)
+        );
+        let err = Parser::new("with t as (select 1 union select id from t) select 1")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No such table: t");
+
+        let cases = [
+            (
+                "with select 1",
+                "Expected a name for the common table expression",
+            ),
+            (
+                "with t(1) as (select 1) select 1",
+                "Expected a column name for t",
+            ),
+            ("with t select 1", "Expected AS but found SELECT"),
+            (
+                "with t as (select 1) union select 1",
+                "Expected SELECT but found UNION",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = Parser::new(input).unwrap().parse_select().unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_select_limit() {
+        let select = Parser::new("select limit 10 offset 20")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(select.columns, vec![ResultColumn::All]);
+        assert_eq!((select.limit, select.offset), (Some(10), 20));
+
+        let select = Parser::new("select * order by id limit 5")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!((select.limit, select.offset), (Some(5), 0));
+
+        let err = Parser::new("select limit -1")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "LIMIT requires a non-negative integer");
+
+        let err = Parser::new("select limit 1 offset")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "OFFSET requires a non-negative integer");
+
+        let err = Parser::new("select offset 1")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected OFFSET in expression");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("(id = 1").unwrap_err();
+        assert_eq!(err.to_string(), "Expected ')' but found end of input");
+
+        let err = parse("id = ").unwrap_err();
+        assert_eq!(err.to_string(), "Expected an expression");
+
+        let err = parse("id = = 1").unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected '=' in expression");
+
+        let err = Parser::new("select where")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Expected an expression");
+
+        let err = Parser::new("select id name")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected 'name'");
+
+        let err = Parser::new("select id as 1")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Expected an alias after AS");
+
+        let err = Parser::new("select id,")
+            .unwrap()
+            .parse_select()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Expected an expression");
+    }
+
+    #[test]
+    fn test_parse_create_index() {
+        let create = |input: &str| Parser::new(input).and_then(|mut p| p.parse_create_index());
+        assert_eq!(
+            create("CREATE INDEX idx ON users (username, id)").unwrap(),
+            StatementType::CreateIndex {
+                name: "idx".to_string(),
+                columns: vec!["username".to_string(), "id".to_string()],
+                unique: false,
+            }
+        );
+        assert_eq!(
+            create("create unique index by_email on users (email)").unwrap(),
+            StatementType::CreateIndex {
+                name: "by_email".to_string(),
+                columns: vec!["email".to_string()],
+                unique: true,
+            }
+        );
+
+        let cases = [
+            ("create index on users (id)", "Expected an index name"),
+            ("create index idx on orders (id)", "No such table: orders"),
+            ("create index idx on users ()", "Expected a column name"),
+            (
+                "create index idx on users (id",
+                "Expected ')' but found end of input",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                create(input).unwrap_err().to_string(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_create_table() {
+        let create = |input: &str| Parser::new(input).and_then(|mut p| p.parse_create());
+        assert_eq!(
+            create("create table users (id integer, username text, email TEXT)").unwrap(),
+            StatementType::CreateTable {
+                name: "users".to_string(),
+                columns: vec![
+                    "id".to_string(),
+                    "username".to_string(),
+                    "email".to_string()
+                ],
+            }
+        );
+
+        assert_eq!(
+            create("create table people (number integer, name text, \"e-mail\" text)").unwrap(),
+            StatementType::CreateTable {
+                name: "people".to_string(),
+                columns: vec![
+                    "number".to_string(),
+                    "name".to_string(),
+                    "e-mail".to_string()
+                ],
+            }
+        );
+
+        let layout = "Only a table with columns of types INTEGER, TEXT, TEXT can be created";
+        let cases = [
+            ("create table users (id integer, username text)", layout),
+            (
+                "create table users (id text, username text, email text)",
+                layout,
+            ),
+            (
+                "create table users (id, username, email)",
+                "Expected a column type",
+            ),
+            ("create table users", "Expected '(' but found end of input"),
+        ];
+        for (input, expected) in cases {
+            let err = create(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_drop_index() {
+        let drop = |input: &str| Parser::new(input).and_then(|mut p| p.parse_drop_index());
+        assert_eq!(
+            drop("DROP INDEX idx").unwrap(),
+            StatementType::DropIndex {
+                name: "idx".to_string()
+            }
+        );
+        assert_eq!(
+            drop("drop index").unwrap_err().to_string(),
+            "Expected an index name"
+        );
+        let err = drop("drop index idx on users").unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected ON");
+    }
+
+    #[test]
+    fn test_parse_views() {
+        let create = |input: &str| Parser::new(input).and_then(|mut p| p.parse_create());
+        let StatementType::CreateView(view) =
+            create("create view names (name) as select username from users where id > 1").unwrap()
+        else {
+            panic!("Expected a view");
+        };
+        assert_eq!(view.name, "names");
+        assert_eq!(view.columns, vec!["name"]);
+        assert!(view.select.with.is_empty());
+        let StatementType::CreateView(unnamed) =
+            create("create view v as select id, 1 as one").unwrap()
+        else {
+            panic!("Expected a view");
+        };
+        assert_eq!(unnamed.columns, vec!["id", "one"]);
+        assert_eq!(
+            create("create view as select 1").unwrap_err().to_string(),
+            "Expected a view name"
+        );
+
+        // A view is read as a common table expression of the outermost query, once however
+        // often it is named, unless a common table expression of the same name hides it.
+        let views = [*view];
+        let parse = |input: &str| {
+            Parser::new(input)
+                .unwrap()
+                .with_views(&views)
+                .parse_select()
+                .unwrap()
+        };
+        let select = parse(
+            "select a.name from names a join names b where a.name in (select name from names)",
+        );
+        let names: Vec<&str> = select.with.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["names"]);
+        assert_eq!(
+            select.from.source,
+            TableSource::CommonTable(vec!["name".to_string()])
+        );
+        let select = parse("with names(x) as (select 1) select x from names");
+        assert_eq!(select.with.len(), 1);
+        assert_eq!(select.with[0].columns, vec!["x"]);
+
+        let drop = |input: &str| Parser::new(input).and_then(|mut p| p.parse_drop());
+        assert_eq!(
+            drop("drop view names").unwrap(),
+            StatementType::DropView {
+                name: "names".to_string()
+            }
+        );
+        assert_eq!(
+            drop("drop index names").unwrap(),
+            StatementType::DropIndex {
+                name: "names".to_string()
+            }
+        );
+        assert_eq!(
+            drop("DROP TABLE users").unwrap(),
+            StatementType::DropTable {
+                name: "users".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_alter() {
+        let alter = |input: &str| Parser::new(input).and_then(|mut p| p.parse_definition());
+        let StatementType::AddConstraint { name, check } =
+            alter("alter table users add constraint positive check (id > 0)").unwrap()
+        else {
+            panic!("Expected StatementType::AddConstraint");
+        };
+        assert_eq!(name, "positive");
+        assert_eq!(check.to_string(), "id > 0");
+        assert_eq!(
+            alter("ALTER TABLE users DROP CONSTRAINT positive").unwrap(),
+            StatementType::DropConstraint {
+                name: "positive".to_string()
+            }
+        );
+        let foreign_key = |on_delete: &str| {
+            alter(&format!(
+                "alter table users add constraint boss foreign key (email) references users \
+                 (username){}",
+                on_delete
+            ))
+            .unwrap()
+        };
+        for (on_delete, expected) in [
+            ("", ForeignKeyAction::Restrict),
+            (" on delete cascade", ForeignKeyAction::Cascade),
+            (" ON DELETE RESTRICT", ForeignKeyAction::Restrict),
+            (" on delete no action", ForeignKeyAction::Restrict),
+        ] {
+            assert_eq!(
+                foreign_key(on_delete),
+                StatementType::AddForeignKey {
+                    name: "boss".to_string(),
+                    column: "email".to_string(),
+                    parent_column: "username".to_string(),
+                    on_delete: expected,
+                },
+                "{}",
+                on_delete
+            );
+        }
+        let StatementType::SetDefault { column, default } =
+            alter("alter table users alter column email set default 'none@example.com'").unwrap()
+        else {
+            panic!("Expected StatementType::SetDefault");
+        };
+        assert_eq!(column, "email");
+        assert_eq!(default.unwrap().to_string(), "'none@example.com'");
+        assert_eq!(
+            alter("ALTER TABLE users ALTER username DROP DEFAULT").unwrap(),
+            StatementType::SetDefault {
+                column: "username".to_string(),
+                default: None,
+            }
+        );
+
+        let cases = [
+            (
+                "alter table names drop constraint c",
+                "No such table: names",
+            ),
+            (
+                "alter table users add check (id > 0)",
+                "Expected CONSTRAINT but found 'check'",
+            ),
+            (
+                "alter table users add constraint c (id > 0)",
+                "Expected CHECK or FOREIGN KEY but found '('",
+            ),
+            (
+                "alter table users add constraint c foreign key (id) references names (id)",
+                "No such table: names",
+            ),
+            (
+                "alter table users add constraint c foreign key id references users (id)",
+                "Expected '(' but found 'id'",
+            ),
+            (
+                "alter table users add constraint c foreign key (email) references users \
+                 (username) on delete set null",
+                "Column email cannot hold NULL or be set by ON DELETE",
+            ),
+            (
+                "alter table users add constraint c foreign key (id) references users (id) \
+                 on update cascade",
+                "Expected DELETE but found 'update'",
+            ),
+            (
+                "alter table users add constraint c foreign key (id) references users (id) \
+                 on delete nothing",
+                "Expected CASCADE, RESTRICT or NO ACTION but found 'nothing'",
+            ),
+            (
+                "alter table users add constraint c check id > 0",
+                "Expected '(' but found 'id'",
+            ),
+            (
+                "alter table users drop constraint c cascade",
+                "Unexpected 'cascade'",
+            ),
+            (
+                "alter table users alter column email default 'x'",
+                "Expected SET but found 'default'",
+            ),
+            (
+                "alter table users alter column email drop default 'x'",
+                "Unexpected string 'x'",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = alter(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        let alter = |input: &str| Parser::new(input).and_then(|mut p| p.parse_alter());
+        assert_eq!(
+            alter("alter table users rename to people").unwrap(),
+            StatementType::RenameTable {
+                name: "users".to_string(),
+                new_name: "people".to_string(),
+            }
+        );
+        for input in [
+            "ALTER TABLE users RENAME COLUMN username TO name",
+            "alter table users rename username to name",
+        ] {
+            assert_eq!(
+                alter(input).unwrap(),
+                StatementType::RenameColumn {
+                    column: "username".to_string(),
+                    new_name: "name".to_string(),
+                },
+                "{}",
+                input
+            );
+        }
+        let cases = [
+            ("alter table users rename", "Expected a column name"),
+            ("alter table users rename to", "Expected a table name"),
+            (
+                "alter table users rename column id",
+                "Expected TO but found end of input",
+            ),
+            ("alter table users rename to people now", "Unexpected 'now'"),
+        ];
+        for (input, expected) in cases {
+            let err = alter(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+
+        // Once renamed, statements refer to the table and its columns by their new names.
+        let columns = ["id", "name", "email"].map(String::from);
+        let table = TableDefinition::new("people", &columns).unwrap();
+        let parse = |input: &str| Parser::new(input).map(|parser| parser.with_table(&table));
+        let select = parse("select name from people")
+            .unwrap()
+            .parse_select()
+            .unwrap();
+        assert_eq!(select.from.source, TableSource::Table(columns.to_vec()));
+        let select = parse("select").unwrap().parse_select().unwrap();
+        assert_eq!(select.to_string(), "SELECT * FROM people");
+        let err = parse("select * from users").unwrap().parse_select();
+        assert_eq!(err.unwrap_err().to_string(), "No such table: users");
+        let err = parse("alter table users rename to people")
+            .unwrap()
+            .parse_alter();
+        assert_eq!(err.unwrap_err().to_string(), "No such table: users");
+        let err = parse("delete from people where username = 'bob'")
+            .unwrap()
+            .parse_delete();
+        assert_eq!(err.unwrap_err().to_string(), "No such column: username");
+    }
+
+    #[test]
+    fn test_parse_delete() {
+        let delete = |input: &str| Parser::new(input).and_then(|mut p| p.parse_delete());
+        assert_eq!(
+            delete("delete from users").unwrap(),
+            StatementType::Delete { condition: None }
+        );
+        let StatementType::Delete {
+            condition: Some(condition),
+        } = delete("DELETE FROM users WHERE id > 2").unwrap()
+        else {
+            panic!("Expected a DELETE with a condition");
+        };
+        assert_eq!(condition.to_string(), "id > 2");
+
+        let cases = [
+            ("delete users", "Expected FROM but found 'users'"),
+            ("delete from names", "No such table: names"),
+            ("delete from users where age > 2", "No such column: age"),
+            (
+                "delete from users where id in (select id from users)",
+                "Subqueries are not allowed in DELETE",
+            ),
+            (
+                "delete from users where count(*) > 1",
+                "Aggregate and window functions are not allowed in DELETE",
+            ),
+            ("delete from users limit 1", "Unexpected LIMIT"),
+        ];
+        for (input, expected) in cases {
+            let err = delete(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_statement_words_as_names() {
+        // The words of ALTER TABLE and DROP TABLE are only matched there, and can name
+        // columns and tables in queries.
+        let select = Parser::new("select id as table, email as check from users as alter")
+            .and_then(|mut p| p.parse_select())
+            .unwrap();
+        let names: Vec<String> = select
+            .columns
+            .iter()
+            .map(|column| match column {
+                ResultColumn::Expression { alias, .. } => alias.clone().unwrap(),
+                _ => panic!("Expected a column expression"),
+            })
+            .collect();
+        assert_eq!(names, ["table", "check"]);
+        assert_eq!(select.from.alias.as_deref(), Some("alter"));
+        let alter = |input: &str| Parser::new(input).and_then(|mut p| p.parse_definition());
+        let cases = [
+            ("alter table users add constraint add check (id > 0)", "add"),
+            ("alter table users drop constraint constraint", "constraint"),
+        ];
+        for (input, expected) in cases {
+            match alter(input).unwrap() {
+                StatementType::AddConstraint { name, .. }
+                | StatementType::DropConstraint { name } => assert_eq!(name, expected),
+                statement_type => panic!("Unexpected {:?}", statement_type),
+            }
+        }
+    }
+}
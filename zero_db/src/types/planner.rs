@@ -0,0 +1,1238 @@
+use crate::types::expression::{BinaryOperator, UnaryOperator};
+use crate::types::{
+    ColumnStatistics, Expression, IndexLookup, IndexProbe, Join, JoinOperator, Program, Schema,
+    Select, Table, TableReference, TableSource, Value,
+};
+use std::fmt;
+use std::ops::Bound;
+
+/// How the rows of a join are matched, see the operators in `join.rs`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinAlgorithm {
+    NestedLoop,
+    Hash,
+    SortMerge,
+}
+impl fmt::Display for JoinAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinAlgorithm::NestedLoop => write!(f, "NESTED LOOP JOIN"),
+            JoinAlgorithm::Hash => write!(f, "HASH JOIN"),
+            JoinAlgorithm::SortMerge => write!(f, "MERGE JOIN"),
+        }
+    }
+}
+
+/// The equalities of a join constraint that match rows by key: `left[i]` is evaluated
+/// against the rows of the tables joined so far and `right[i]` against the joined table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JoinKeys {
+    pub left: Vec<Expression>,
+    pub right: Vec<Expression>,
+}
+impl JoinKeys {
+    /// Collects the terms `x = y` of the AND-ed terms of `constraint` where one side only
+    /// refers to columns of `left` and the other only to columns of `right`.
+    pub fn extract(constraint: Option<&Expression>, left: &Schema, right: &Schema) -> JoinKeys {
+        let mut keys = JoinKeys::default();
+        if let Some(constraint) = constraint {
+            keys.collect(constraint, left, right);
+        }
+        keys
+    }
+
+    fn collect(&mut self, expression: &Expression, left: &Schema, right: &Schema) {
+        let Expression::Binary {
+            operator,
+            left: a,
+            right: b,
+        } = expression
+        else {
+            return;
+        };
+        match operator {
+            BinaryOperator::And => {
+                self.collect(a, left, right);
+                self.collect(b, left, right);
+            }
+            BinaryOperator::Eq => {
+                if a.check_columns(&[left]).is_ok() && b.check_columns(&[right]).is_ok() {
+                    self.left.push(*a.clone());
+                    self.right.push(*b.clone());
+                } else if b.check_columns(&[left]).is_ok() && a.check_columns(&[right]).is_ok() {
+                    self.left.push(*b.clone());
+                    self.right.push(*a.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JoinPlan {
+    pub algorithm: JoinAlgorithm,
+    pub keys: JoinKeys,
+}
+
+/// How the rows of a table named in a FROM clause are read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccessPath {
+    /// Every row, in storage order.
+    FullScan,
+    /// The rows an index finds, in storage order.
+    Index(IndexLookup),
+    /// The rows an index finds for the join keys of each row of the left side of a nested
+    /// loop join, in storage order. On every column of a unique index, each search is a key
+    /// lookup finding at most one row.
+    Probe(IndexProbe),
+}
+
+/// How a select reads its rows: the access path of each table of its FROM clause and the
+/// algorithm of each join.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryPlan {
+    /// For the first table of the FROM clause followed by each joined table.
+    pub access: Vec<AccessPath>,
+    pub joins: Vec<JoinPlan>,
+    /// Whether the joined rows already come in the order of the ORDER BY clause, so that
+    /// they need no sorting.
+    pub presorted: bool,
+    /// The select with the tables of its leading inner joins in the order they are joined,
+    /// when that differs from the FROM clause, see `QueryPlan::reorder`. `access` and
+    /// `joins` follow this order.
+    pub reordered: Option<Select>,
+}
+impl QueryPlan {
+    /// Joins with fewer row pairs than this compare every pair rather than build a hash
+    /// table or sort.
+    pub const NESTED_LOOP_MAX_PAIRS: usize = 4096;
+    /// Reading a row through an index costs a page lookup where a scan reads the next row
+    /// of the page, so an index is only used when it is estimated to find at most this
+    /// fraction of the rows.
+    pub const INDEX_MAX_SELECTIVITY: f64 = 0.25;
+    /// Fraction of the rows an equality on an indexed column is assumed to keep when ANALYZE
+    /// has not run.
+    pub const DEFAULT_EQUALITY_SELECTIVITY: f64 = 0.1;
+    /// Fraction of the rows each bound of a range on an indexed column is assumed to keep
+    /// when ANALYZE has not run.
+    pub const DEFAULT_RANGE_SELECTIVITY: f64 = 0.25;
+
+    /// Joins the tables of the leading inner joins in the order estimated to cost the
+    /// least, see `QueryPlan::reorder`, then plans how they are read and joined.
+    ///
+    /// Reads each table through the index finding the fewest rows for the comparisons of
+    /// columns with literals in the WHERE clause, when there is one finding few enough of
+    /// them, see `INDEX_MAX_SELECTIVITY`. The table on the right of a LEFT JOIN is always
+    /// scanned, as its rows are padded with NULLs rather than filtered.
+    ///
+    /// Then chooses an algorithm for each join from the estimated row counts of its sides
+    /// and the ordering the query asks for:
+    ///
+    /// - Without an equality between the two sides, only a nested loop can evaluate the
+    ///   constraint.
+    /// - When the last join can produce the ORDER BY order, a sort-merge join does so and the
+    ///   final sort is skipped.
+    /// - Joins with few row pairs use a nested loop.
+    /// - Anything else uses a hash join, which partitions both sides to disk when the
+    ///   joined table does not fit in memory.
+    ///
+    /// Unless it merges, a join whose keys on the joined table are the first columns of an
+    /// index is instead a nested loop searching the index for the keys of each left row,
+    /// when that reads fewer rows than the algorithm above, see `QueryPlan::probe_rows`.
+    ///
+    /// The left side of a join is estimated from the statistics of the table when ANALYZE
+    /// collected them for its keys, and otherwise at the product of the row counts of its
+    /// tables.
+    pub fn new(select: &Select, table: &Table) -> QueryPlan {
+        let reordered = Self::reorder(select, table);
+        let mut plan = Self::plan(reordered.as_ref().unwrap_or(select), table);
+        plan.reordered = reordered;
+        plan
+    }
+
+    fn plan(select: &Select, table: &Table) -> QueryPlan {
+        let estimator = Estimator { select, table };
+        let access: Vec<(AccessPath, f64)> = std::iter::once((&select.from, JoinOperator::Inner))
+            .chain(select.joins.iter().map(|join| (&join.table, join.operator)))
+            .map(|(table, operator)| match operator {
+                JoinOperator::Left => (AccessPath::FullScan, 1.0),
+                _ => estimator.access_path(table),
+            })
+            .collect();
+        let rows: Vec<usize> = access
+            .iter()
+            .map(|(_, selectivity)| (table.num_rows as f64 * selectivity).ceil() as usize)
+            .collect();
+        let mut access: Vec<AccessPath> = access.into_iter().map(|(path, _)| path).collect();
+        let schemas = select.schemas();
+        let mut joins = Vec::with_capacity(select.joins.len());
+        let mut presorted = false;
+        let mut left_rows = rows[0];
+        for (i, join) in select.joins.iter().enumerate() {
+            let right_rows = rows[i + 1];
+            let keys =
+                JoinKeys::extract(join.constraint.as_ref(), &schemas[i], &join.table.schema());
+            let is_last = i + 1 == select.joins.len();
+            let algorithm =
+                if !keys.is_empty() && is_last && Self::orders_by_key(select, join.operator, &keys)
+                {
+                    presorted = true;
+                    JoinAlgorithm::SortMerge
+                } else {
+                    let (algorithm, probe, _) =
+                        Self::join_algorithm(&estimator, &join.table, left_rows, right_rows, &keys);
+                    if let Some(probe) = probe {
+                        access[i + 1] = AccessPath::Probe(probe);
+                    }
+                    algorithm
+                };
+            left_rows = estimator
+                .join_rows(left_rows, right_rows, &keys)
+                .unwrap_or(left_rows.saturating_mul(right_rows));
+            joins.push(JoinPlan { algorithm, keys });
+        }
+        QueryPlan {
+            access,
+            joins,
+            presorted,
+            reordered: None,
+        }
+    }
+
+    /// The algorithm joining `left` rows with the `right` rows the access path of `table`
+    /// reads, on `keys`, with the index to probe instead of that access path, see
+    /// `QueryPlan::new`. Also returns the rows the join goes through: every pair for a
+    /// nested loop, each row of both sides for a hash join, and those the searches find for
+    /// a probe.
+    fn join_algorithm(
+        estimator: &Estimator,
+        table: &TableReference,
+        left: usize,
+        right: usize,
+        keys: &JoinKeys,
+    ) -> (JoinAlgorithm, Option<IndexProbe>, usize) {
+        let pairs = left.saturating_mul(right);
+        if keys.is_empty() {
+            return (JoinAlgorithm::NestedLoop, None, pairs);
+        }
+        let (algorithm, read) = match pairs <= Self::NESTED_LOOP_MAX_PAIRS {
+            true => (JoinAlgorithm::NestedLoop, pairs),
+            false => (JoinAlgorithm::Hash, left.saturating_add(right)),
+        };
+        match estimator.probe(table, keys) {
+            Some((probe, selectivity)) => {
+                let probed = Self::probe_rows(left, selectivity, estimator.table);
+                match probed < read {
+                    true => (JoinAlgorithm::NestedLoop, Some(probe), probed),
+                    false => (algorithm, None, read),
+                }
+            }
+            None => (algorithm, None, read),
+        }
+    }
+
+    /// The tables before the first LEFT JOIN can be joined in any order, as an inner join
+    /// keeps the same rows whichever side comes first. Starting from each of them in turn,
+    /// the table joined next is the one costing the least, and the order costing the least
+    /// over its first table and every join wins, the order of the FROM clause on ties. The
+    /// terms of the ON constraints of these joins are attached to the first join having all
+    /// of their columns.
+    ///
+    /// The cost of a table is the rows its access path reads: those of its index search,
+    /// or all of them for a scan. The cost of a join is the rows it goes through, see
+    /// `QueryPlan::join_algorithm`, plus the rows it produces. Those are estimated from the
+    /// rows of each table the WHERE clause keeps according to the statistics. `None` when
+    /// the order does not change.
+    fn reorder(select: &Select, table: &Table) -> Option<Select> {
+        let count = 1 + select
+            .joins
+            .iter()
+            .take_while(|join| join.operator != JoinOperator::Left)
+            .count();
+        if count < 2 {
+            return None;
+        }
+        let tables: Vec<&TableReference> = std::iter::once(&select.from)
+            .chain(select.joins[..count - 1].iter().map(|join| &join.table))
+            .collect();
+        let mut terms = Vec::new();
+        for constraint in select.joins[..count - 1]
+            .iter()
+            .flat_map(|join| &join.constraint)
+        {
+            Estimator::conjuncts(constraint, &mut terms);
+        }
+        // An unqualified column could name another table's column in a shorter prefix.
+        let schema = &select.schemas()[count - 1];
+        if terms
+            .iter()
+            .any(|term| term.check_columns(&[schema]).is_err())
+        {
+            return None;
+        }
+
+        let estimator = Estimator { select, table };
+        let read: Vec<usize> = tables.iter().map(|t| estimator.read_rows(t)).collect();
+        let rows: Vec<usize> = tables.iter().map(|t| estimator.filtered_rows(t)).collect();
+        let mut best: Option<(usize, Vec<usize>)> = None;
+        for start in 0..count {
+            let mut order = vec![start];
+            let mut schema = tables[start].schema();
+            let (mut left, mut cost) = (rows[start], read[start]);
+            while order.len() < count {
+                let (next, joined, join_cost) = (0..count)
+                    .filter(|i| !order.contains(i))
+                    .map(|i| {
+                        let right = tables[i].schema();
+                        let mut keys = JoinKeys::default();
+                        for term in &terms {
+                            keys.collect(term, &schema, &right);
+                        }
+                        let (_, _, read) =
+                            Self::join_algorithm(&estimator, tables[i], left, read[i], &keys);
+                        let joined = estimator.joined_rows(left, rows[i], &keys);
+                        (i, joined, read.saturating_add(joined))
+                    })
+                    .min_by_key(|&(_, _, cost)| cost)?;
+                order.push(next);
+                schema = schema.join(&tables[next].schema());
+                left = joined;
+                cost = cost.saturating_add(join_cost);
+            }
+            if best.as_ref().is_none_or(|(best, _)| cost < *best) {
+                best = Some((cost, order));
+            }
+        }
+        let (_, order) = best?;
+        if order.iter().enumerate().all(|(i, &j)| i == j) {
+            return None;
+        }
+
+        let mut reordered = select.clone();
+        reordered.from = tables[order[0]].clone();
+        let mut schema = reordered.from.schema();
+        let mut placed = vec![false; terms.len()];
+        let mut joins = Vec::with_capacity(select.joins.len());
+        for &i in &order[1..] {
+            schema = schema.join(&tables[i].schema());
+            let mut constraint: Option<Expression> = None;
+            for (term, placed) in terms.iter().zip(&mut placed) {
+                if *placed || term.check_columns(&[&schema]).is_err() {
+                    continue;
+                }
+                *placed = true;
+                let term = (*term).clone();
+                constraint = Some(match constraint {
+                    Some(left) => Expression::Binary {
+                        operator: BinaryOperator::And,
+                        left: Box::new(left),
+                        right: Box::new(term),
+                    },
+                    None => term,
+                });
+            }
+            joins.push(Join {
+                operator: match constraint {
+                    Some(_) => JoinOperator::Inner,
+                    None => JoinOperator::Cross,
+                },
+                table: tables[i].clone(),
+                constraint,
+            });
+        }
+        joins.extend(select.joins[count - 1..].iter().cloned());
+        reordered.joins = joins;
+        Some(reordered)
+    }
+
+    /// Rows read by searching an index for each of `left` rows, each search finding
+    /// `selectivity` of the rows of the table and costing at least the row it reads.
+    fn probe_rows(left: usize, selectivity: f64, table: &Table) -> usize {
+        let found = (table.num_rows as f64 * selectivity).ceil() as usize;
+        left.saturating_mul(found.max(1))
+    }
+
+    // A merge join outputs rows in ascending order of the left key, which for an inner join
+    // is also the order of the equal right key. Grouping loses that order.
+    fn orders_by_key(select: &Select, operator: JoinOperator, keys: &JoinKeys) -> bool {
+        if select.order_by.is_empty()
+            || select.order_by.len() > keys.left.len()
+            || select.is_aggregate()
+        {
+            return false;
+        }
+        select.order_by.iter().enumerate().all(|(i, term)| {
+            !term.descending
+                && (term.expression == keys.left[i]
+                    || (operator != JoinOperator::Left && term.expression == keys.right[i]))
+        })
+    }
+}
+
+/// A step of running a query as EXPLAIN QUERY PLAN shows it. Steps form a tree through
+/// `parent`, the id of the step they are part of, or 0 for those of the query itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanStep {
+    pub id: usize,
+    pub parent: usize,
+    pub detail: String,
+}
+
+/// Describes how `select` runs on `table`: the common tables it computes, the scans, index
+/// searches and joins reading its rows, the WHERE clause filtering them, its subqueries,
+/// then grouping, window functions and sorting, in the order they happen.
+///
+/// Reads, joins and filters show how many rows they are estimated to produce, from the
+/// statistics of the table where ANALYZE collected them for the columns involved.
+/// Otherwise constraints are assumed to keep every row, except those an index is searched
+/// for, and a join on keys to match each row of the larger side once.
+///
+/// `select` is the statement itself, grouped by sorting when its program does so, see
+/// `Program::sorts_groups`. Common tables, views and subqueries are grouped in a hash table.
+pub fn explain(select: &Select, table: &Table) -> Vec<PlanStep> {
+    let mut explainer = Explainer {
+        table,
+        steps: Vec::new(),
+        common_tables: Vec::new(),
+    };
+    explainer.query(select, 0);
+    explainer.steps
+}
+
+struct Explainer<'a> {
+    table: &'a Table,
+    steps: Vec<PlanStep>,
+    /// Estimated rows of the common tables in scope, innermost last.
+    common_tables: Vec<(String, usize)>,
+}
+impl Explainer<'_> {
+    // Adds the steps of `select` under `parent` and returns the estimated number of rows
+    // it returns.
+    fn query(&mut self, select: &Select, parent: usize) -> usize {
+        let scope = self.common_tables.len();
+        for common_table in &select.with {
+            let id = self.step(parent, format!("MATERIALIZE {}", common_table.name));
+            let mut rows = self.query(&common_table.select, id);
+            if let Some(union) = &common_table.union {
+                let detail = match (common_table.recursive, union.all) {
+                    (true, true) => "RECURSIVE UNION ALL",
+                    (true, false) => "RECURSIVE UNION",
+                    (false, true) => "UNION ALL",
+                    (false, false) => "UNION",
+                };
+                let union_id = self.step(id, detail.to_string());
+                // A recursive select reads the rows added by the previous run, estimated as
+                // those of the initial select.
+                if common_table.recursive {
+                    self.common_tables.push((common_table.name.clone(), rows));
+                }
+                let union_rows = self.query(&union.select, union_id);
+                if common_table.recursive {
+                    self.common_tables.pop();
+                }
+                rows = rows.saturating_add(union_rows);
+            }
+            self.common_tables.push((common_table.name.clone(), rows));
+        }
+
+        let plan = QueryPlan::new(select, self.table);
+        let estimator = Estimator {
+            select,
+            table: self.table,
+        };
+        let joined = plan.reordered.as_ref().unwrap_or(select);
+        // Rows as if every table were scanned, which the WHERE clause is estimated against.
+        let mut unfiltered = self.table_rows(&joined.from);
+        let (mut rows, using) = self.read(&estimator, &joined.from, &plan.access[0]);
+        let detail = match using.is_empty() {
+            _ if joined.from.source == TableSource::SingleRow => "SCAN CONSTANT ROW".to_string(),
+            true => format!("SCAN {} (~{} rows)", joined.from, rows),
+            false => format!("SEARCH {}{} (~{} rows)", joined.from, using, rows),
+        };
+        self.step(parent, detail);
+        for (i, (join, join_plan)) in joined.joins.iter().zip(&plan.joins).enumerate() {
+            let (right, using) = self.read(&estimator, &join.table, &plan.access[i + 1]);
+            let keys = &join_plan.keys;
+            rows = estimator.joined_rows(rows, right, keys);
+            unfiltered = estimator.joined_rows(unfiltered, self.table_rows(&join.table), keys);
+            let detail = format!(
+                "{} {}{} (~{} rows)",
+                join_plan.algorithm, join.table, using, rows
+            );
+            self.step(parent, detail);
+        }
+        if let Some(where_clause) = &select.where_clause {
+            if let Some(selectivity) = estimator.selectivity(where_clause) {
+                rows = rows.min((unfiltered as f64 * selectivity).ceil() as usize);
+            }
+            self.step(parent, format!("FILTER (~{} rows)", rows));
+        }
+        for subquery in select.subqueries() {
+            let detail = match subquery.check_columns(&[]) {
+                Ok(()) => "SUBQUERY",
+                Err(_) => "CORRELATED SUBQUERY",
+            };
+            let id = self.step(parent, detail.to_string());
+            self.query(subquery, id);
+        }
+        if select.is_aggregate() {
+            if select.group_by.is_empty() {
+                rows = 1;
+                self.step(parent, "AGGREGATE".to_string());
+            } else {
+                let terms = Self::join(&select.group_by);
+                let method = match parent == 0 && Program::sorts_groups(select) {
+                    true => "SORT",
+                    false => "HASH",
+                };
+                self.step(parent, format!("{} AGGREGATE BY {}", method, terms));
+            }
+        }
+        for window in select.windows() {
+            self.step(parent, format!("WINDOW {}", window));
+        }
+        if !select.order_by.is_empty() && !plan.presorted {
+            let terms: Vec<String> = select
+                .order_by
+                .iter()
+                .map(|term| match term.descending {
+                    true => format!("{} DESC", term.expression),
+                    false => term.expression.to_string(),
+                })
+                .collect();
+            self.step(parent, format!("SORT BY {}", terms.join(", ")));
+        }
+        self.common_tables.truncate(scope);
+        match select.limit {
+            Some(limit) => rows.saturating_sub(select.offset).min(limit),
+            None => rows.saturating_sub(select.offset),
+        }
+    }
+
+    // The estimated rows read from `table` through `access`, and how an index is used for
+    // them, or nothing for a scan.
+    fn read(
+        &self,
+        estimator: &Estimator,
+        table: &TableReference,
+        access: &AccessPath,
+    ) -> (usize, String) {
+        match access {
+            AccessPath::FullScan => (self.table_rows(table), String::new()),
+            AccessPath::Index(lookup) => {
+                let selectivity = estimator.access_path(table).1;
+                let rows = (self.table.num_rows as f64 * selectivity).ceil() as usize;
+                (rows, format!(" USING INDEX {}", lookup))
+            }
+            // The rows a search finds depend on the left row, and the join estimates how
+            // many match in all.
+            AccessPath::Probe(probe) => {
+                let using = match probe.unique {
+                    true => "KEY",
+                    false => "INDEX",
+                };
+                (
+                    self.table_rows(table),
+                    format!(" USING {} {}", using, probe),
+                )
+            }
+        }
+    }
+
+    fn table_rows(&self, table: &TableReference) -> usize {
+        match table.source {
+            TableSource::Table(_) => self.table.num_rows,
+            TableSource::Statistics => self.table.statistics.as_ref().map_or(0, |statistics| {
+                statistics.table_rows(&self.table.definition.name).count()
+            }),
+            TableSource::CommonTable(_) => self
+                .common_tables
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == table.name)
+                .map_or(0, |(_, rows)| *rows),
+            TableSource::SingleRow => 1,
+        }
+    }
+
+    fn join(expressions: &[Expression]) -> String {
+        let terms: Vec<String> = expressions.iter().map(|e| e.to_string()).collect();
+        terms.join(", ")
+    }
+
+    fn step(&mut self, parent: usize, detail: String) -> usize {
+        let id = self.steps.len() + 1;
+        self.steps.push(PlanStep { id, parent, detail });
+        id
+    }
+}
+
+/// Estimates how many rows the parts of a query keep, from the statistics of the columns
+/// of the table they refer to. Every estimate is `None` when the statistics say nothing
+/// about it, for the caller to fall back on its own assumption.
+struct Estimator<'a> {
+    select: &'a Select,
+    table: &'a Table,
+}
+impl<'a> Estimator<'a> {
+    // The reference to the table `expression` is a column of, with the name of the column,
+    // if it is one of a table rather than of a common table expression.
+    fn table_column<'e>(
+        &self,
+        expression: &'e Expression,
+    ) -> Option<(&'a TableReference, &'e str)> {
+        let Expression::Column { table, name } = expression else {
+            return None;
+        };
+        let schema = self.select.schema();
+        let i = schema.find(table.as_deref(), name).ok()??;
+        let alias = schema.columns[i].table.as_deref()?;
+        let reference = std::iter::once(&self.select.from)
+            .chain(self.select.joins.iter().map(|join| &join.table))
+            .find(|reference| reference.alias() == alias)?;
+        matches!(reference.source, TableSource::Table(_)).then_some((reference, name.as_str()))
+    }
+
+    fn column(&self, expression: &Expression) -> Option<&'a ColumnStatistics> {
+        let (_, name) = self.table_column(expression)?;
+        self.table.statistics.as_ref()?.column(name)
+    }
+
+    // Rows added since ANALYZE ran are not counted, but a column cannot have more distinct
+    // values than the table has rows.
+    fn distinct(&self, expression: &Expression) -> Option<usize> {
+        let distinct = self.column(expression)?.distinct;
+        Some(distinct.clamp(1, self.table.num_rows.max(1)))
+    }
+
+    /// The index to read `table` through, see `QueryPlan::new`, with the estimated fraction
+    /// of its rows it finds. An index is searched for equalities on its first columns and
+    /// bounds on the column after them.
+    fn access_path(&self, table: &TableReference) -> (AccessPath, f64) {
+        let mut terms = Vec::new();
+        if let Some(where_clause) = &self.select.where_clause {
+            Self::conjuncts(where_clause, &mut terms);
+        }
+        let comparisons: Vec<(&str, BinaryOperator, &Value, f64)> = terms
+            .into_iter()
+            .filter_map(|term| {
+                let (column, operator, value) = Self::comparison(term)?;
+                let (reference, name) = self.table_column(column)?;
+                if reference.alias() != table.alias() {
+                    return None;
+                }
+                let default = match operator {
+                    BinaryOperator::Eq => QueryPlan::DEFAULT_EQUALITY_SELECTIVITY,
+                    _ => QueryPlan::DEFAULT_RANGE_SELECTIVITY,
+                };
+                let selectivity = self
+                    .column(column)
+                    .and_then(|statistics| statistics.selectivity(operator, value))
+                    .unwrap_or(default);
+                Some((name, operator, value, selectivity))
+            })
+            .collect();
+
+        let mut best = (AccessPath::FullScan, 1.0);
+        for index in &self.table.indexes {
+            let mut lookup = IndexLookup {
+                index: index.name.clone(),
+                columns: Vec::new(),
+                equal: Vec::new(),
+                lower: Bound::Unbounded,
+                upper: Bound::Unbounded,
+            };
+            let mut selectivity = 1.0;
+            for column in &index.columns {
+                let terms = comparisons.iter().filter(|term| term.0 == column);
+                if let Some((_, _, value, equal)) =
+                    terms.clone().find(|term| term.1 == BinaryOperator::Eq)
+                {
+                    lookup.columns.push(column.clone());
+                    lookup.equal.push((*value).clone());
+                    selectivity *= equal;
+                    continue;
+                }
+                for &(_, operator, value, range) in terms {
+                    let value = value.clone();
+                    let (bound, new) = match operator {
+                        BinaryOperator::Gt => (&mut lookup.lower, Bound::Excluded(value)),
+                        BinaryOperator::GtEq => (&mut lookup.lower, Bound::Included(value)),
+                        BinaryOperator::Lt => (&mut lookup.upper, Bound::Excluded(value)),
+                        BinaryOperator::LtEq => (&mut lookup.upper, Bound::Included(value)),
+                        _ => continue,
+                    };
+                    if *bound == Bound::Unbounded {
+                        *bound = new;
+                        selectivity *= range;
+                    }
+                }
+                if lookup.lower != Bound::Unbounded || lookup.upper != Bound::Unbounded {
+                    lookup.columns.push(column.clone());
+                }
+                break;
+            }
+            if index.unique && lookup.equal.len() == index.columns.len() {
+                selectivity = f64::min(selectivity, 1.0 / self.table.num_rows.max(1) as f64);
+            }
+            if !lookup.columns.is_empty()
+                && selectivity <= QueryPlan::INDEX_MAX_SELECTIVITY
+                && selectivity < best.1
+            {
+                best = (AccessPath::Index(lookup), selectivity);
+            }
+        }
+        best
+    }
+
+    /// The index to search for the keys of each left row of a join with `table` on `keys`,
+    /// with the estimated fraction of the rows of `table` a search finds. The columns of
+    /// `table` equal to a key must be the first columns of the index.
+    fn probe(&self, table: &TableReference, keys: &JoinKeys) -> Option<(IndexProbe, f64)> {
+        if !matches!(table.source, TableSource::Table(_)) {
+            return None;
+        }
+        let mut best: Option<(IndexProbe, f64)> = None;
+        for index in &self.table.indexes {
+            let mut probe = IndexProbe {
+                index: index.name.clone(),
+                columns: Vec::new(),
+                keys: Vec::new(),
+                unique: false,
+            };
+            let mut selectivity = 1.0;
+            for column in &index.columns {
+                let key = keys.right.iter().position(
+                    |key| matches!(key, Expression::Column { name, .. } if name == column),
+                );
+                let Some(key) = key else {
+                    break;
+                };
+                probe.columns.push(column.clone());
+                probe.keys.push(keys.left[key].clone());
+                selectivity *= self
+                    .distinct(&keys.right[key])
+                    .map_or(QueryPlan::DEFAULT_EQUALITY_SELECTIVITY, |distinct| {
+                        1.0 / distinct as f64
+                    });
+            }
+            probe.unique = index.unique && probe.columns.len() == index.columns.len();
+            if probe.unique {
+                selectivity = f64::min(selectivity, 1.0 / self.table.num_rows.max(1) as f64);
+            }
+            if !probe.columns.is_empty() && best.as_ref().is_none_or(|best| selectivity < best.1) {
+                best = Some((probe, selectivity));
+            }
+        }
+        best
+    }
+
+    fn conjuncts<'e>(expression: &'e Expression, terms: &mut Vec<&'e Expression>) {
+        match expression {
+            Expression::Binary {
+                operator: BinaryOperator::And,
+                left,
+                right,
+            } => {
+                Self::conjuncts(left, terms);
+                Self::conjuncts(right, terms);
+            }
+            term => terms.push(term),
+        }
+    }
+
+    // `column operator value` when `term` compares an expression with a literal other than
+    // NULL, with the operator swapped when the literal comes first.
+    fn comparison(term: &Expression) -> Option<(&Expression, BinaryOperator, &Value)> {
+        let Expression::Binary {
+            operator,
+            left,
+            right,
+        } = term
+        else {
+            return None;
+        };
+        if !operator.is_comparison() {
+            return None;
+        }
+        match (left.as_ref(), right.as_ref()) {
+            (_, Expression::Literal(Value::Null)) | (Expression::Literal(Value::Null), _) => None,
+            (column, Expression::Literal(value)) => Some((column, *operator, value)),
+            (Expression::Literal(value), column) => Some((column, Self::swap(*operator), value)),
+            _ => None,
+        }
+    }
+
+    /// Rows of a join of `left` with `right` rows on `keys`. Each key pair matches a row
+    /// with those of the other side sharing its value, assuming the side with fewer distinct
+    /// values has all of its values on the other side.
+    fn join_rows(&self, left: usize, right: usize, keys: &JoinKeys) -> Option<usize> {
+        let mut rows = left.saturating_mul(right) as f64;
+        let mut known = false;
+        for (left, right) in keys.left.iter().zip(&keys.right) {
+            if let Some(distinct) = self.distinct(left).max(self.distinct(right)) {
+                rows /= distinct as f64;
+                known = true;
+            }
+        }
+        known.then(|| rows.ceil() as usize)
+    }
+
+    /// `join_rows`, or without statistics for the keys a match for each row of the larger
+    /// side, and every pair of rows without keys.
+    fn joined_rows(&self, left: usize, right: usize, keys: &JoinKeys) -> usize {
+        match self.join_rows(left, right, keys) {
+            Some(rows) => rows,
+            None if keys.is_empty() => left.saturating_mul(right),
+            None => left.max(right),
+        }
+    }
+
+    // Rows of `table` its access path reads.
+    fn read_rows(&self, table: &TableReference) -> usize {
+        (self.table.num_rows as f64 * self.access_path(table).1).ceil() as usize
+    }
+
+    // Rows of `table` found by its access path or kept by the terms of the WHERE clause
+    // on its columns alone, whichever is fewer.
+    fn filtered_rows(&self, table: &TableReference) -> usize {
+        let schema = table.schema();
+        let mut terms = Vec::new();
+        if let Some(where_clause) = &self.select.where_clause {
+            Self::conjuncts(where_clause, &mut terms);
+        }
+        let filter: f64 = terms
+            .into_iter()
+            .filter(|term| !term.contains_subquery() && term.check_columns(&[&schema]).is_ok())
+            .filter_map(|term| self.selectivity(term))
+            .product();
+        let selectivity = filter.min(self.access_path(table).1);
+        (self.table.num_rows as f64 * selectivity).ceil() as usize
+    }
+
+    /// Fraction of the rows `condition` keeps, from its comparisons of a column with a
+    /// literal. Terms of AND and OR are assumed independent, and terms the statistics say
+    /// nothing about are left out.
+    fn selectivity(&self, condition: &Expression) -> Option<f64> {
+        match condition {
+            Expression::Binary {
+                operator: BinaryOperator::And,
+                left,
+                right,
+            } => match (self.selectivity(left), self.selectivity(right)) {
+                (Some(left), Some(right)) => Some(left * right),
+                (left, right) => left.or(right),
+            },
+            Expression::Binary {
+                operator: BinaryOperator::Or,
+                left,
+                right,
+            } => {
+                let (left, right) = (self.selectivity(left)?, self.selectivity(right)?);
+                Some(left + right - left * right)
+            }
+            Expression::Unary {
+                operator: UnaryOperator::Not,
+                operand,
+            } => self
+                .selectivity(operand)
+                .map(|selectivity| 1.0 - selectivity),
+            term => {
+                let (column, operator, value) = Self::comparison(term)?;
+                self.column(column)?.selectivity(operator, value)
+            }
+        }
+    }
+
+    // The operator comparing the same values with its sides swapped.
+    fn swap(operator: BinaryOperator) -> BinaryOperator {
+        match operator {
+            BinaryOperator::Lt => BinaryOperator::Gt,
+            BinaryOperator::LtEq => BinaryOperator::GtEq,
+            BinaryOperator::Gt => BinaryOperator::Lt,
+            BinaryOperator::GtEq => BinaryOperator::LtEq,
+            operator => operator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Parser, Row, Statistics, Value};
+
+    // The planner only looks at the row count, the statistics and the indexes of the table.
+    fn table(num_rows: usize, statistics: Option<Statistics>) -> Table {
+        let mut table = Table::new();
+        table.num_rows = num_rows;
+        table.statistics = statistics;
+        table
+    }
+
+    fn plan(input: &str, num_rows: usize) -> QueryPlan {
+        let select = Parser::new(input).unwrap().parse_select().unwrap();
+        select.check_columns(&[]).unwrap();
+        QueryPlan::new(&select, &table(num_rows, None))
+    }
+
+    fn algorithms(plan: &QueryPlan) -> Vec<JoinAlgorithm> {
+        plan.joins.iter().map(|join| join.algorithm).collect()
+    }
+
+    #[test]
+    fn test_extract_keys() {
+        let plan = plan(
+            "select from users a join users b \
+             on b.id = a.id + 1 and a.username = b.username and a.id > 2 or 0",
+            0,
+        );
+        assert!(plan.joins[0].keys.is_empty());
+
+        let plan = self::plan(
+            "select from users a join users b \
+             on b.id = a.id + 1 and a.username = b.username and a.id > 2",
+            0,
+        );
+        let keys = &plan.joins[0].keys;
+        let names = |keys: &[Expression]| -> Vec<String> {
+            keys.iter().map(|key| key.to_string()).collect()
+        };
+        assert_eq!(names(&keys.left), vec!["a.id + 1", "a.username"]);
+        assert_eq!(names(&keys.right), vec!["b.id", "b.username"]);
+    }
+
+    #[test]
+    fn test_choose_join_algorithm() {
+        let join = "select from users a join users b on a.id = b.id";
+        assert_eq!(algorithms(&plan(join, 10)), vec![JoinAlgorithm::NestedLoop]);
+        assert_eq!(algorithms(&plan(join, 1000)), vec![JoinAlgorithm::Hash]);
+
+        let no_keys = "select from users a join users b on a.id < b.id";
+        assert_eq!(
+            algorithms(&plan(no_keys, 1000)),
+            vec![JoinAlgorithm::NestedLoop]
+        );
+
+        // The left side of the second join is estimated at 30 * 30 rows.
+        let three = "select from users a join users b on a.id = b.id join users c on c.id = b.id";
+        assert_eq!(
+            algorithms(&plan(three, 30)),
+            vec![JoinAlgorithm::NestedLoop, JoinAlgorithm::Hash]
+        );
+    }
+
+    #[test]
+    fn test_merge_join_for_order_by() {
+        let sorted = plan(
+            "select from users a join users b on a.id = b.id order by b.id",
+            10,
+        );
+        assert_eq!(algorithms(&sorted), vec![JoinAlgorithm::SortMerge]);
+        assert!(sorted.presorted);
+
+        let cases = [
+            "select from users a join users b on a.id = b.id order by a.id desc",
+            "select from users a join users b on a.id = b.id order by a.id, b.username",
+            "select from users a left join users b on a.id = b.id order by b.id",
+            "select a.id from users a join users b on a.id = b.id group by a.id order by a.id",
+            "select from users a join users b on a.id = b.id join users c order by a.id",
+        ];
+        for input in cases {
+            let plan = plan(input, 10000);
+            assert!(!plan.presorted, "{}", input);
+            assert!(
+                !algorithms(&plan).contains(&JoinAlgorithm::SortMerge),
+                "{}",
+                input
+            );
+        }
+    }
+
+    fn tables(plan: &QueryPlan) -> Vec<String> {
+        let select = plan.reordered.as_ref().unwrap();
+        std::iter::once(&select.from)
+            .chain(select.joins.iter().map(|join| &join.table))
+            .map(|table| table.alias().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder_joins() {
+        // Joining b last keeps the intermediate rows at those of the join on keys.
+        let cross = plan(
+            "select from users a cross join users b join users c on c.id = a.id \
+             left join users d on d.id = b.id",
+            100,
+        );
+        assert_eq!(tables(&cross), vec!["a", "c", "b", "d"]);
+        let reordered = cross.reordered.as_ref().unwrap();
+        let constraints: Vec<Option<String>> = reordered
+            .joins
+            .iter()
+            .map(|join| join.constraint.as_ref().map(|c| c.to_string()))
+            .collect();
+        assert_eq!(
+            constraints,
+            vec![
+                Some("c.id = a.id".to_string()),
+                None,
+                Some("d.id = b.id".to_string())
+            ]
+        );
+        assert_eq!(reordered.joins[1].operator, JoinOperator::Cross);
+
+        // The index finds the few rows of c the WHERE clause keeps, so the joins start from
+        // it and look up the rows of b and a by key.
+        let select = Parser::new(
+            "select from users a join users b on a.id = b.id join users c on b.id = c.id \
+             where c.id < 10",
+        )
+        .unwrap()
+        .parse_select()
+        .unwrap();
+        let mut by_id = table(100, Some(statistics(100)));
+        by_id
+            .create_index("by_id", &["id".to_string()], true)
+            .unwrap();
+        let plan = QueryPlan::new(&select, &by_id);
+        assert_eq!(tables(&plan), vec!["c", "b", "a"]);
+        assert_eq!(
+            plan.access[1..],
+            [
+                AccessPath::Probe(IndexProbe {
+                    index: "by_id".to_string(),
+                    columns: vec!["id".to_string()],
+                    keys: vec![Expression::Column {
+                        table: Some("c".to_string()),
+                        name: "id".to_string()
+                    }],
+                    unique: true,
+                }),
+                AccessPath::Probe(IndexProbe {
+                    index: "by_id".to_string(),
+                    columns: vec!["id".to_string()],
+                    keys: vec![Expression::Column {
+                        table: Some("b".to_string()),
+                        name: "id".to_string()
+                    }],
+                    unique: true,
+                }),
+            ]
+        );
+
+        // Scanning a would read every row, where searching b by username reads a tenth of
+        // them and each joins the row of a with its id.
+        let select = Parser::new(
+            "select from users a join users b on a.id = b.id where b.username = 'user1'",
+        )
+        .unwrap()
+        .parse_select()
+        .unwrap();
+        let mut indexed = table(200, None);
+        indexed
+            .create_index("by_id", &["id".to_string()], true)
+            .unwrap();
+        indexed
+            .create_index("by_name", &["username".to_string()], false)
+            .unwrap();
+        let details: Vec<String> = explain(&select, &indexed)
+            .into_iter()
+            .map(|step| step.detail)
+            .collect();
+        assert_eq!(
+            details,
+            vec![
+                "SEARCH users AS b USING INDEX by_name (username=?) (~20 rows)",
+                "NESTED LOOP JOIN users AS a USING KEY by_id (id=?) (~200 rows)",
+                "FILTER (~200 rows)",
+            ]
+        );
+
+        let unchanged = [
+            "select from users a join users b on a.id = b.id join users c on c.id = b.id",
+            "select from users a cross join users b",
+            "select from users a left join users b cross join users c join users d on d.id = a.id",
+        ];
+        for input in unchanged {
+            assert_eq!(self::plan(input, 100).reordered, None, "{}", input);
+        }
+    }
+
+    fn steps(input: &str, num_rows: usize) -> Vec<(usize, usize, String)> {
+        let select = Parser::new(input).unwrap().parse_select().unwrap();
+        select.check_columns(&[]).unwrap();
+        explain(&select, &table(num_rows, None))
+            .into_iter()
+            .map(|step| (step.id, step.parent, step.detail))
+            .collect()
+    }
+
+    #[test]
+    fn test_explain() {
+        let expected = |steps: &[(usize, usize, &str)]| -> Vec<(usize, usize, String)> {
+            steps
+                .iter()
+                .map(|&(id, parent, detail)| (id, parent, detail.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            steps("select id where id > 1 order by id desc limit 2", 10),
+            expected(&[
+                (1, 0, "SCAN users (~10 rows)"),
+                (2, 0, "FILTER (~10 rows)"),
+                (3, 0, "SORT BY id DESC"),
+            ])
+        );
+        assert_eq!(
+            steps(
+                "select a.username, count(*) from users a join users b on a.id = b.id \
+                 cross join users c where a.id in (select id from users d where d.id = a.id) \
+                 group by a.username",
+                100,
+            ),
+            expected(&[
+                (1, 0, "SCAN users AS a (~100 rows)"),
+                (2, 0, "HASH JOIN users AS b (~100 rows)"),
+                (3, 0, "NESTED LOOP JOIN users AS c (~10000 rows)"),
+                (4, 0, "FILTER (~10000 rows)"),
+                (5, 0, "CORRELATED SUBQUERY"),
+                (6, 5, "SCAN users AS d (~100 rows)"),
+                (7, 5, "FILTER (~100 rows)"),
+                (8, 0, "SORT AGGREGATE BY a.username"),
+            ])
+        );
+        assert_eq!(
+            steps(
+                "with recursive t(n) as (select 1 union all select n + 1 from t) \
+                 select a.n, rank() over (order by a.n) from t a join t b on a.n = b.n order by a.n",
+                5,
+            ),
+            expected(&[
+                (1, 0, "MATERIALIZE t"),
+                (2, 1, "SCAN CONSTANT ROW"),
+                (3, 1, "RECURSIVE UNION ALL"),
+                (4, 3, "SCAN t (~1 rows)"),
+                (5, 0, "SCAN t AS a (~2 rows)"),
+                (6, 0, "MERGE JOIN t AS b (~2 rows)"),
+                (7, 0, "WINDOW rank() OVER (ORDER BY a.n)"),
+            ])
+        );
+    }
+
+    fn statistics(num_rows: i64) -> Statistics {
+        let rows: Vec<Vec<Value>> = (1..=num_rows)
+            .map(|id| {
+                vec![
+                    Value::Integer(id),
+                    Value::Text(format!("user{}", id % 4)),
+                    Value::Text("user@example.com".to_string()),
+                ]
+            })
+            .collect();
+        Statistics::collect(&Row::COLUMNS, &rows)
+    }
+
+    #[test]
+    fn test_estimate_with_statistics() {
+        // Matching ids keep 40 of the 40 * 40 row pairs, so the second join is small too.
+        let three = "select from users a join users b on a.id = b.id join users c on c.id = b.id";
+        let select = Parser::new(three).unwrap().parse_select().unwrap();
+        assert_eq!(
+            algorithms(&QueryPlan::new(&select, &table(40, None))),
+            vec![JoinAlgorithm::NestedLoop, JoinAlgorithm::Hash]
+        );
+        let analyzed = table(40, Some(statistics(40)));
+        assert_eq!(
+            algorithms(&QueryPlan::new(&select, &analyzed)),
+            vec![JoinAlgorithm::NestedLoop, JoinAlgorithm::NestedLoop]
+        );
+
+        let analyzed = table(100, Some(statistics(100)));
+        let details = |input: &str| -> Vec<String> {
+            let select = Parser::new(input).unwrap().parse_select().unwrap();
+            select.check_columns(&[]).unwrap();
+            explain(&select, &analyzed)
+                .into_iter()
+                .map(|step| step.detail)
+                .collect()
+        };
+        assert_eq!(
+            details("select from users a join users b on a.username = b.username"),
+            vec![
+                "SCAN users AS a (~100 rows)",
+                "HASH JOIN users AS b (~2500 rows)"
+            ]
+        );
+        let cases = [
+            ("id <= 10 and username = 'user1'", 2),
+            ("50 > id", 44),
+            ("id = 1 or id = 2", 2),
+            ("not id = 1", 99),
+            ("id + 1 > 3 and id < 30", 32),
+            ("id + 1 > 3", 100),
+            ("id in (select id from zero_stat t where t.col = 'id')", 100),
+        ];
+        for (condition, rows) in cases {
+            let steps = details(&format!("select id from users where {}", condition));
+            assert_eq!(
+                steps[1],
+                format!("FILTER (~{} rows)", rows),
+                "{}",
+                condition
+            );
+        }
+        assert_eq!(
+            details("select * from zero_stat"),
+            vec!["SCAN zero_stat (~24 rows)"]
+        );
+    }
+
+    #[test]
+    fn test_choose_index() {
+        let mut table = table(100, None);
+        table
+            .create_index("by_id", &["id".to_string()], true)
+            .unwrap();
+        table
+            .create_index(
+                "by_name",
+                &["username".to_string(), "id".to_string()],
+                false,
+            )
+            .unwrap();
+        let first_step = |table: &Table, condition: &str| -> String {
+            let input = format!("select id from users where {}", condition);
+            let select = Parser::new(&input).unwrap().parse_select().unwrap();
+            select.check_columns(&[]).unwrap();
+            explain(&select, table).swap_remove(0).detail
+        };
+        let cases = [
+            // An id matches a single row, as the index on it is unique.
+            ("id = 5", "SEARCH users USING INDEX by_id (id=?) (~1 rows)"),
+            ("5 < id", "SEARCH users USING INDEX by_id (id>?) (~25 rows)"),
+            (
+                "username = 'user1' and id >= 3 and id < 9",
+                "SEARCH users USING INDEX by_name (username=? AND id>=? AND id<?) (~1 rows)",
+            ),
+            ("id = 5 or id = 6", "SCAN users (~100 rows)"),
+            ("email = 'user@example.com'", "SCAN users (~100 rows)"),
+            ("id <> 5", "SCAN users (~100 rows)"),
+        ];
+        for (condition, expected) in cases {
+            assert_eq!(first_step(&table, condition), expected, "{}", condition);
+        }
+
+        // Most ids are above 10, so reading them through the index would cost more.
+        table.statistics = Some(statistics(100));
+        assert_eq!(first_step(&table, "id > 10"), "SCAN users (~100 rows)");
+        assert_eq!(
+            first_step(&table, "id > 90"),
+            "SEARCH users USING INDEX by_id (id>?) (~7 rows)"
+        );
+    }
+}
@@ -0,0 +1,2061 @@
+use crate::types::expression::{BinaryOperator, UnaryOperator};
+use crate::types::planner::{self, JoinAlgorithm};
+use crate::types::{
+    AccessPath, AggregateFunction, CommonTableExpression, Expression, ForeignKeyAction,
+    IndexLookup, IndexProbe, JoinOperator, QueryPlan, ResultColumn, Schema, Select, Statement,
+    StatementType, Table, TableReference, TableSource, Value,
+};
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// One operation of a compiled program. Registers and cursors are numbered from 0, and jump
+/// targets are addresses of instructions in the program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    /// Stores `value` in `register`.
+    Value {
+        value: Value,
+        register: usize,
+    },
+    /// Opens `cursor` on the rows of `table`, before the first one.
+    OpenRead {
+        cursor: usize,
+        table: String,
+    },
+    /// Stores the number of rows of `table`, kept in its header, in `register` without
+    /// reading them.
+    Count {
+        table: String,
+        register: usize,
+    },
+    /// Opens `cursor` on the rows of the table `lookup` finds, before the first one.
+    OpenIndex {
+        cursor: usize,
+        lookup: IndexLookup,
+    },
+    /// Opens `cursor` on the rows of the table `probe` finds for the values of its keys in
+    /// registers `start..start + probe.keys.len()`, before the first one.
+    SeekIndex {
+        cursor: usize,
+        probe: IndexProbe,
+        start: usize,
+    },
+    /// Runs `select` with the query executor and opens `cursor` on the rows it returns. Used
+    /// for what the compiler has no instructions for, such as common tables, hash joins and
+    /// window functions.
+    OpenQuery {
+        cursor: usize,
+        select: Box<Select>,
+    },
+    /// Opens `cursor` on an empty sorter, ordering rows by keys compared in turn, with
+    /// `descending[i]` reversing the order of the i-th key.
+    SorterOpen {
+        cursor: usize,
+        descending: Vec<bool>,
+    },
+    /// Adds the values in registers `start..start + count` to the sorter of `cursor` as a
+    /// row whose first `keys` values are its sort keys, which the sorted row leaves out.
+    SorterInsert {
+        cursor: usize,
+        start: usize,
+        keys: usize,
+        count: usize,
+    },
+    /// Sorts the rows added to the sorter of `cursor` and moves to the first one, or jumps
+    /// to `target` if there are none. `Next` reads the others in order.
+    SorterSort {
+        cursor: usize,
+        target: usize,
+    },
+    /// Moves `cursor` to a row whose columns are all NULL, the right side of a LEFT JOIN
+    /// for a left row nothing matched.
+    NullRow {
+        cursor: usize,
+    },
+    /// Moves `cursor` to its first row, or jumps to `target` if it has none.
+    Rewind {
+        cursor: usize,
+        target: usize,
+    },
+    /// Moves `cursor` to its next row and jumps to `target`, or falls through after the last.
+    Next {
+        cursor: usize,
+        target: usize,
+    },
+    /// Stores column `column` of the current row of `cursor` in `register`.
+    Column {
+        cursor: usize,
+        column: usize,
+        register: usize,
+    },
+    /// Applies `operator` to the value in `operand` and stores the result in `register`.
+    Unary {
+        operator: UnaryOperator,
+        operand: usize,
+        register: usize,
+    },
+    /// Applies `operator` to the values in `left` and `right` and stores the result in
+    /// `register`. AND and OR skip their right side with `If` and `IfNot` beforehand.
+    Binary {
+        operator: BinaryOperator,
+        left: usize,
+        right: usize,
+        register: usize,
+    },
+    /// Stores a copy of the value in `source` in `register`.
+    Copy {
+        source: usize,
+        register: usize,
+    },
+    /// Adds the value in `argument`, or a row for `COUNT(*)` when there is none, to the
+    /// accumulator of `function` kept for `register`, starting one if there is none.
+    AggStep {
+        function: AggregateFunction,
+        argument: Option<usize>,
+        register: usize,
+    },
+    /// Stores the value of the accumulator kept for `register` in `register` and discards
+    /// the accumulator, so that the next `AggStep` starts a new group. Without one, stores
+    /// the value of `function` over no rows.
+    AggFinal {
+        function: AggregateFunction,
+        register: usize,
+    },
+    /// Jumps to `target` if the value in `register` is true.
+    If {
+        register: usize,
+        target: usize,
+    },
+    /// Jumps to `target` if the value in `register` is false, or NULL when `null` is set.
+    IfNot {
+        register: usize,
+        target: usize,
+        null: bool,
+    },
+    /// Jumps to `target` if any value in registers `left..left + count` differs from the one
+    /// in the same position from `right`, NULLs being equal to each other, which tells where
+    /// a group of sorted rows ends.
+    IfDistinct {
+        left: usize,
+        right: usize,
+        count: usize,
+        target: usize,
+    },
+    /// Decrements the integer in `register` and jumps to `target` if it was positive, which
+    /// skips the rows of an OFFSET.
+    IfPositive {
+        register: usize,
+        target: usize,
+    },
+    /// Decrements the integer in `register` and jumps to `target` once it reaches zero,
+    /// which ends a query at its LIMIT.
+    DecrementJumpZero {
+        register: usize,
+        target: usize,
+    },
+    Goto {
+        target: usize,
+    },
+    /// Outputs the values in registers `start..start + count` as a result row.
+    ResultRow {
+        start: usize,
+        count: usize,
+    },
+    /// Inserts the row whose columns are in the registers from `start`, in the order of
+    /// `Row::COLUMNS`, or halts with `ExecutionFailure::TableFull`,
+    /// `ExecutionFailure::CheckViolation` or `ExecutionFailure::UniqueViolation` without
+    /// inserting it.
+    Insert {
+        start: usize,
+    },
+    /// Deletes the rows `condition` holds for, or halts with
+    /// `ExecutionFailure::ForeignKeyViolation` without deleting any, see `Table::delete`.
+    Delete {
+        condition: Option<Expression>,
+    },
+    /// Checks the table and halts with the problems found.
+    IntegrityCheck,
+    /// Halts with the compression statistics of the table.
+    CompressionStats,
+    /// Collects the statistics of the table for the planner, see `Table::analyze`.
+    Analyze,
+    /// Creates the table `name` with `columns` after it was dropped.
+    CreateTable {
+        name: String,
+        columns: Vec<String>,
+    },
+    /// Removes the table `name`, returning its pages to the free list.
+    DropTable {
+        name: String,
+    },
+    /// Renames the table `name` to `new_name`.
+    RenameTable {
+        name: String,
+        new_name: String,
+    },
+    /// Renames the column `column` of the table to `new_name`.
+    RenameColumn {
+        column: String,
+        new_name: String,
+    },
+    /// Builds the index `name` on `columns` from the rows of the table, or halts with
+    /// `ExecutionFailure::UniqueViolation` if it is unique and two rows conflict.
+    CreateIndex {
+        name: String,
+        columns: Vec<String>,
+        unique: bool,
+    },
+    /// Removes the index `name`.
+    DropIndex {
+        name: String,
+    },
+    /// Adds `view` to the views of the database.
+    CreateView {
+        view: Box<CommonTableExpression>,
+    },
+    /// Removes the view `name`.
+    DropView {
+        name: String,
+    },
+    /// Adds the CHECK constraint `name`, or halts with `ExecutionFailure::CheckViolation` if
+    /// a row of the table does not satisfy `check`.
+    AddConstraint {
+        name: String,
+        check: Expression,
+    },
+    /// Adds the foreign key `name`, or halts with `ExecutionFailure::ForeignKeyViolation` if
+    /// a row of the table refers to no parent.
+    AddForeignKey {
+        name: String,
+        column: String,
+        parent_column: String,
+        on_delete: ForeignKeyAction,
+    },
+    /// Removes the CHECK constraint or foreign key `name`.
+    DropConstraint {
+        name: String,
+    },
+    /// Sets the value inserts leave out `column` with to that of `default`, or removes it.
+    SetDefault {
+        column: String,
+        default: Option<Expression>,
+    },
+    /// Stores the recursion limit of common table expressions in `register`.
+    RecursionLimit {
+        register: usize,
+    },
+    SetRecursionLimit {
+        limit: usize,
+    },
+    /// Stores 1 in `register` if foreign keys are enforced, and 0 otherwise.
+    ForeignKeys {
+        register: usize,
+    },
+    SetForeignKeys {
+        enabled: bool,
+    },
+    Halt,
+}
+impl Instruction {
+    pub fn opcode(&self) -> &'static str {
+        match self {
+            Instruction::Value { .. } => "Value",
+            Instruction::OpenRead { .. } => "OpenRead",
+            Instruction::Count { .. } => "Count",
+            Instruction::OpenIndex { .. } => "OpenIndex",
+            Instruction::SeekIndex { .. } => "SeekIndex",
+            Instruction::OpenQuery { .. } => "OpenQuery",
+            Instruction::SorterOpen { .. } => "SorterOpen",
+            Instruction::SorterInsert { .. } => "SorterInsert",
+            Instruction::SorterSort { .. } => "SorterSort",
+            Instruction::NullRow { .. } => "NullRow",
+            Instruction::Rewind { .. } => "Rewind",
+            Instruction::Next { .. } => "Next",
+            Instruction::Column { .. } => "Column",
+            Instruction::Unary { .. } => "Unary",
+            Instruction::Binary { .. } => "Binary",
+            Instruction::Copy { .. } => "Copy",
+            Instruction::AggStep { .. } => "AggStep",
+            Instruction::AggFinal { .. } => "AggFinal",
+            Instruction::If { .. } => "If",
+            Instruction::IfNot { .. } => "IfNot",
+            Instruction::IfDistinct { .. } => "IfDistinct",
+            Instruction::IfPositive { .. } => "IfPositive",
+            Instruction::DecrementJumpZero { .. } => "DecrementJumpZero",
+            Instruction::Goto { .. } => "Goto",
+            Instruction::ResultRow { .. } => "ResultRow",
+            Instruction::Insert { .. } => "Insert",
+            Instruction::Delete { .. } => "Delete",
+            Instruction::IntegrityCheck => "IntegrityCheck",
+            Instruction::CompressionStats => "CompressionStats",
+            Instruction::Analyze => "Analyze",
+            Instruction::CreateTable { .. } => "CreateTable",
+            Instruction::DropTable { .. } => "DropTable",
+            Instruction::RenameTable { .. } => "RenameTable",
+            Instruction::RenameColumn { .. } => "RenameColumn",
+            Instruction::CreateIndex { .. } => "CreateIndex",
+            Instruction::DropIndex { .. } => "DropIndex",
+            Instruction::CreateView { .. } => "CreateView",
+            Instruction::DropView { .. } => "DropView",
+            Instruction::AddConstraint { .. } => "AddConstraint",
+            Instruction::AddForeignKey { .. } => "AddForeignKey",
+            Instruction::DropConstraint { .. } => "DropConstraint",
+            Instruction::SetDefault { .. } => "SetDefault",
+            Instruction::RecursionLimit { .. } => "RecursionLimit",
+            Instruction::SetRecursionLimit { .. } => "SetRecursionLimit",
+            Instruction::ForeignKeys { .. } => "ForeignKeys",
+            Instruction::SetForeignKeys { .. } => "SetForeignKeys",
+            Instruction::Halt => "Halt",
+        }
+    }
+
+    // Where the instruction jumps to, so that forward jumps can be emitted before their
+    // target is known and patched once it is.
+    fn target_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Instruction::Rewind { target, .. }
+            | Instruction::SorterSort { target, .. }
+            | Instruction::Next { target, .. }
+            | Instruction::If { target, .. }
+            | Instruction::IfNot { target, .. }
+            | Instruction::IfDistinct { target, .. }
+            | Instruction::IfPositive { target, .. }
+            | Instruction::DecrementJumpZero { target, .. }
+            | Instruction::Goto { target } => Some(target),
+            _ => None,
+        }
+    }
+
+    /// The operands of the instruction by name, as in `cursor=0 column=1 register=2`.
+    pub fn operands(&self) -> String {
+        match self {
+            Instruction::Value { value, register } => {
+                format!("value={} register={}", value, register)
+            }
+            Instruction::OpenRead { cursor, table } => {
+                format!("cursor={} table={}", cursor, table)
+            }
+            Instruction::Count { table, register } => {
+                format!("table={} register={}", table, register)
+            }
+            Instruction::OpenIndex { cursor, lookup } => {
+                format!("cursor={} index={}", cursor, lookup)
+            }
+            Instruction::SeekIndex {
+                cursor,
+                probe,
+                start,
+            } => format!("cursor={} index={} start={}", cursor, probe, start),
+            Instruction::OpenQuery { cursor, select } => {
+                format!("cursor={} select=({})", cursor, select)
+            }
+            Instruction::SorterOpen { cursor, descending } => {
+                let order: Vec<&str> = descending
+                    .iter()
+                    .map(|&descending| if descending { "DESC" } else { "ASC" })
+                    .collect();
+                format!("cursor={} order=({})", cursor, order.join(", "))
+            }
+            Instruction::SorterInsert {
+                cursor,
+                start,
+                keys,
+                count,
+            } => format!(
+                "cursor={} start={} keys={} count={}",
+                cursor, start, keys, count
+            ),
+            Instruction::Rewind { cursor, target }
+            | Instruction::SorterSort { cursor, target }
+            | Instruction::Next { cursor, target } => {
+                format!("cursor={} target={}", cursor, target)
+            }
+            Instruction::NullRow { cursor } => format!("cursor={}", cursor),
+            Instruction::Column {
+                cursor,
+                column,
+                register,
+            } => format!("cursor={} column={} register={}", cursor, column, register),
+            Instruction::Unary {
+                operator,
+                operand,
+                register,
+            } => format!(
+                "operator={:?} operand={} register={}",
+                operator, operand, register
+            ),
+            Instruction::Binary {
+                operator,
+                left,
+                right,
+                register,
+            } => format!(
+                "operator={} left={} right={} register={}",
+                operator.symbol(),
+                left,
+                right,
+                register
+            ),
+            Instruction::Copy { source, register } => {
+                format!("source={} register={}", source, register)
+            }
+            Instruction::AggStep {
+                function,
+                argument: Some(argument),
+                register,
+            } => format!(
+                "function={} argument={} register={}",
+                function, argument, register
+            ),
+            Instruction::AggStep {
+                function,
+                argument: None,
+                register,
+            }
+            | Instruction::AggFinal { function, register } => {
+                format!("function={} register={}", function, register)
+            }
+            Instruction::If { register, target }
+            | Instruction::IfPositive { register, target }
+            | Instruction::DecrementJumpZero { register, target } => {
+                format!("register={} target={}", register, target)
+            }
+            Instruction::IfNot {
+                register,
+                target,
+                null,
+            } => format!("register={} target={} null={}", register, target, null),
+            Instruction::IfDistinct {
+                left,
+                right,
+                count,
+                target,
+            } => format!(
+                "left={} right={} count={} target={}",
+                left, right, count, target
+            ),
+            Instruction::Goto { target } => format!("target={}", target),
+            Instruction::ResultRow { start, count } => {
+                format!("start={} count={}", start, count)
+            }
+            Instruction::Insert { start } => format!("start={}", start),
+            Instruction::RecursionLimit { register } | Instruction::ForeignKeys { register } => {
+                format!("register={}", register)
+            }
+            Instruction::SetRecursionLimit { limit } => format!("limit={}", limit),
+            Instruction::SetForeignKeys { enabled } => format!("enabled={}", enabled),
+            Instruction::Delete {
+                condition: Some(condition),
+            } => format!("condition=({})", condition),
+            Instruction::AddForeignKey {
+                name,
+                column,
+                parent_column,
+                on_delete,
+            } => format!(
+                "name={} column={} parent={} on_delete={}",
+                name,
+                column,
+                parent_column,
+                on_delete.keyword()
+            ),
+            Instruction::CreateIndex {
+                name,
+                columns,
+                unique,
+            } => {
+                let unique = if *unique { " unique" } else { "" };
+                format!("name={} columns=({}){}", name, columns.join(", "), unique)
+            }
+            Instruction::CreateTable { name, columns } => {
+                format!("name={} columns=({})", name, columns.join(", "))
+            }
+            Instruction::RenameTable { name, new_name } => {
+                format!("name={} new_name={}", name, new_name)
+            }
+            Instruction::RenameColumn { column, new_name } => {
+                format!("column={} new_name={}", column, new_name)
+            }
+            Instruction::CreateView { view } => {
+                format!("name={} columns=({})", view.name, view.columns.join(", "))
+            }
+            Instruction::AddConstraint { name, check } => {
+                format!("name={} check=({})", name, check)
+            }
+            Instruction::SetDefault {
+                column,
+                default: Some(default),
+            } => format!("column={} default=({})", column, default),
+            Instruction::SetDefault {
+                column,
+                default: None,
+            } => format!("column={}", column),
+            Instruction::DropTable { name }
+            | Instruction::DropIndex { name }
+            | Instruction::DropView { name }
+            | Instruction::DropConstraint { name } => format!("name={}", name),
+            Instruction::Delete { condition: None }
+            | Instruction::IntegrityCheck
+            | Instruction::CompressionStats
+            | Instruction::Analyze
+            | Instruction::Halt => String::new(),
+        }
+    }
+}
+/// Writes the opcode followed by the operands, as in `Column cursor=0 column=1 register=2`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operands().as_str() {
+            "" => write!(f, "{}", self.opcode()),
+            operands => write!(f, "{} {}", self.opcode(), operands),
+        }
+    }
+}
+
+/// A statement compiled into instructions for the `VirtualMachine`, which runs them from
+/// the first one until `Halt`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    /// How many registers the instructions use.
+    pub registers: usize,
+    /// Names of the columns of the rows the program outputs with `ResultRow`, or `None` if
+    /// it outputs no rows.
+    pub columns: Option<Vec<String>>,
+}
+impl Program {
+    /// Whether a compiled program groups the rows of `select` itself, by sorting them on
+    /// their GROUP BY keys. Queries it leaves to the executor are grouped in a hash table.
+    pub fn sorts_groups(select: &Select) -> bool {
+        select.is_aggregate() && Compiler::computes(select)
+    }
+
+    /// Compiles `statement` to run against `table`, whose row count and statistics guide the
+    /// choice of join algorithms.
+    pub fn compile(statement: &Statement, table: &Table) -> Result<Program> {
+        let mut compiler = Compiler::default();
+        let columns = match &statement.statement_type {
+            StatementType::Insert => {
+                let row = statement
+                    .row_to_insert
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No row for insertion"))?;
+                let start = compiler.registers;
+                for value in row.values() {
+                    let register = compiler.allocate();
+                    compiler.emit(Instruction::Value { value, register });
+                }
+                compiler.emit(Instruction::Insert { start });
+                None
+            }
+            StatementType::Select => {
+                let select = statement
+                    .select
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No query for select"))?;
+                compiler.select(select, table)?;
+                Some(select.column_names())
+            }
+            StatementType::IntegrityCheck => {
+                compiler.emit(Instruction::IntegrityCheck);
+                None
+            }
+            StatementType::CompressionStats => {
+                compiler.emit(Instruction::CompressionStats);
+                None
+            }
+            StatementType::Analyze => {
+                compiler.emit(Instruction::Analyze);
+                None
+            }
+            StatementType::CreateTable { name, columns } => {
+                compiler.emit(Instruction::CreateTable {
+                    name: name.clone(),
+                    columns: columns.clone(),
+                });
+                None
+            }
+            StatementType::DropTable { name } => {
+                compiler.emit(Instruction::DropTable { name: name.clone() });
+                None
+            }
+            StatementType::RenameTable { name, new_name } => {
+                compiler.emit(Instruction::RenameTable {
+                    name: name.clone(),
+                    new_name: new_name.clone(),
+                });
+                None
+            }
+            StatementType::RenameColumn { column, new_name } => {
+                compiler.emit(Instruction::RenameColumn {
+                    column: column.clone(),
+                    new_name: new_name.clone(),
+                });
+                None
+            }
+            StatementType::CreateIndex {
+                name,
+                columns,
+                unique,
+            } => {
+                compiler.emit(Instruction::CreateIndex {
+                    name: name.clone(),
+                    columns: columns.clone(),
+                    unique: *unique,
+                });
+                None
+            }
+            StatementType::DropIndex { name } => {
+                compiler.emit(Instruction::DropIndex { name: name.clone() });
+                None
+            }
+            StatementType::CreateView(view) => {
+                compiler.emit(Instruction::CreateView { view: view.clone() });
+                None
+            }
+            StatementType::DropView { name } => {
+                compiler.emit(Instruction::DropView { name: name.clone() });
+                None
+            }
+            StatementType::AddConstraint { name, check } => {
+                compiler.emit(Instruction::AddConstraint {
+                    name: name.clone(),
+                    check: check.clone(),
+                });
+                None
+            }
+            StatementType::DropConstraint { name } => {
+                compiler.emit(Instruction::DropConstraint { name: name.clone() });
+                None
+            }
+            StatementType::SetDefault { column, default } => {
+                compiler.emit(Instruction::SetDefault {
+                    column: column.clone(),
+                    default: default.clone(),
+                });
+                None
+            }
+            StatementType::RecursionLimit(None) => {
+                let register = compiler.allocate();
+                compiler.emit(Instruction::RecursionLimit { register });
+                compiler.emit(Instruction::ResultRow {
+                    start: register,
+                    count: 1,
+                });
+                Some(vec!["recursion_limit".to_string()])
+            }
+            StatementType::RecursionLimit(Some(limit)) => {
+                compiler.emit(Instruction::SetRecursionLimit { limit: *limit });
+                None
+            }
+            StatementType::ForeignKeys(None) => {
+                let register = compiler.allocate();
+                compiler.emit(Instruction::ForeignKeys { register });
+                compiler.emit(Instruction::ResultRow {
+                    start: register,
+                    count: 1,
+                });
+                Some(vec!["foreign_keys".to_string()])
+            }
+            StatementType::ForeignKeys(Some(enabled)) => {
+                compiler.emit(Instruction::SetForeignKeys { enabled: *enabled });
+                None
+            }
+            StatementType::Delete { condition } => {
+                compiler.emit(Instruction::Delete {
+                    condition: condition.clone(),
+                });
+                None
+            }
+            StatementType::AddForeignKey {
+                name,
+                column,
+                parent_column,
+                on_delete,
+            } => {
+                compiler.emit(Instruction::AddForeignKey {
+                    name: name.clone(),
+                    column: column.clone(),
+                    parent_column: parent_column.clone(),
+                    on_delete: *on_delete,
+                });
+                None
+            }
+            StatementType::Explain {
+                statement_type,
+                query_plan: false,
+            } => {
+                let explained = Statement {
+                    statement_type: (**statement_type).clone(),
+                    row_to_insert: statement.row_to_insert.clone(),
+                    select: statement.select.clone(),
+                };
+                let program = Program::compile(&explained, table)?;
+                let rows = program
+                    .instructions
+                    .iter()
+                    .enumerate()
+                    .map(|(address, instruction)| {
+                        vec![
+                            Value::Integer(address as i64),
+                            Value::Text(instruction.opcode().to_string()),
+                            Value::Text(instruction.operands()),
+                        ]
+                    });
+                compiler.constant_rows(rows.collect());
+                Some(vec![
+                    "addr".to_string(),
+                    "opcode".to_string(),
+                    "operands".to_string(),
+                ])
+            }
+            StatementType::Explain {
+                query_plan: true, ..
+            } => {
+                let select = statement
+                    .select
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("EXPLAIN QUERY PLAN requires a SELECT"))?;
+                let rows = planner::explain(select, table).into_iter().map(|step| {
+                    vec![
+                        Value::Integer(step.id as i64),
+                        Value::Integer(step.parent as i64),
+                        Value::Text(step.detail),
+                    ]
+                });
+                compiler.constant_rows(rows.collect());
+                Some(vec![
+                    "id".to_string(),
+                    "parent".to_string(),
+                    "detail".to_string(),
+                ])
+            }
+        };
+        compiler.emit(Instruction::Halt);
+        Ok(Program {
+            instructions: compiler.instructions,
+            registers: compiler.registers,
+            columns,
+        })
+    }
+}
+/// One instruction per line, preceded by its address.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(address, instruction)| format!("{} {}", address, instruction))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Where the columns of `schema` are read from: column `i` is column `columns[i].1` of the
+/// current row of cursor `columns[i].0`.
+// Where an expression reads a value from: a column of the current row of a cursor, or a
+// register holding a copy of it.
+#[derive(Clone, Copy)]
+enum Slot {
+    Column { cursor: usize, column: usize },
+    Register(usize),
+}
+
+struct Layout<'a> {
+    schema: &'a Schema,
+    columns: Vec<Slot>,
+    /// The aggregate calls of a grouped query with the registers holding their values.
+    aggregates: Vec<(Expression, usize)>,
+}
+impl<'a> Layout<'a> {
+    // The columns of a schema joining `tables`, opened on the cursors numbered by their
+    // position, each found by the alias of the table it belongs to.
+    fn new(schema: &'a Schema, tables: &[&TableReference]) -> Layout<'a> {
+        let columns = schema
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let cursor = tables
+                    .iter()
+                    .position(|table| column.table.as_deref() == Some(table.alias()))
+                    .unwrap_or(0);
+                let before = &schema.columns[..i];
+                Slot::Column {
+                    cursor,
+                    column: before.iter().filter(|c| c.table == column.table).count(),
+                }
+            })
+            .collect();
+        Layout {
+            schema,
+            columns,
+            aggregates: Vec::new(),
+        }
+    }
+
+    // The columns of `schema` as the columns of the rows of `cursor`, in order.
+    fn cursor(schema: &'a Schema, cursor: usize) -> Layout<'a> {
+        let columns = (0..schema.columns.len())
+            .map(|column| Slot::Column { cursor, column })
+            .collect();
+        Layout {
+            schema,
+            columns,
+            aggregates: Vec::new(),
+        }
+    }
+
+    // The columns of `schema` in the registers from `start`, with `aggregates` read from
+    // the registers paired with them.
+    fn registers(
+        schema: &'a Schema,
+        start: usize,
+        aggregates: Vec<(Expression, usize)>,
+    ) -> Layout<'a> {
+        let columns = (0..schema.columns.len())
+            .map(|column| Slot::Register(start + column))
+            .collect();
+        Layout {
+            schema,
+            columns,
+            aggregates,
+        }
+    }
+}
+
+// The addresses of the loop over the rows of a joined table that are jumped to or patched
+// once the loops inside it are emitted.
+struct JoinLoop {
+    cursor: usize,
+    rewind: usize,
+    body: usize,
+    /// Jumps taken when the constraint does not hold, to the next row.
+    misses: Vec<usize>,
+    /// For a LEFT JOIN, the register set once a row matched.
+    matched: Option<usize>,
+    /// Where the inner loops start, which a row of NULLs jumps to.
+    resume: usize,
+}
+
+// Where a query sends its rows: into the ORDER BY sorter, which a last loop reads back in
+// order, or straight out, past the OFFSET and until the LIMIT.
+struct Output {
+    sorter: Option<usize>,
+    /// How many ORDER BY keys precede the values of a row in the sorter, 0 without one.
+    keys: usize,
+    count: usize,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    /// Jumps that end the query, patched once it is emitted.
+    exits: Vec<usize>,
+}
+
+// How the rows of a grouped query are grouped.
+enum GroupBy {
+    /// Added to the sorter on `cursor` by their GROUP BY keys, and grouped once sorted.
+    Keys { cursor: usize },
+    /// Without GROUP BY, accumulated into a single group as they are read, with `started`
+    /// set once the first row is.
+    Single { started: usize },
+}
+
+// The registers of the group a grouped query is at: the columns of its first row, which
+// supply the columns that are not aggregated, and the values of its aggregates.
+struct Grouping {
+    group_by: GroupBy,
+    row: usize,
+    aggregates: Vec<(Expression, usize)>,
+}
+
+#[derive(Default)]
+struct Compiler {
+    instructions: Vec<Instruction>,
+    registers: usize,
+}
+impl Compiler {
+    // Emits nested loops over the tables of a query reading nothing but the rows of the
+    // table, one loop per table in the order the planner joins them. The loops evaluate ON,
+    // WHERE, the SELECT list and ORDER BY in registers, and read the tables through the
+    // indexes the planner chooses, searching the index of a joined table again for the keys
+    // of each left row. A LEFT JOIN outputs a row of NULLs for its table when no row
+    // matched. With ORDER BY the rows go into a sorter, which a last loop reads back in
+    // order, otherwise they are output as they are found, until the LIMIT.
+    //
+    // A grouped query accumulates its aggregates with `AggStep`, then finishes them with
+    // `AggFinal` and outputs the group if it passes HAVING. With GROUP BY the rows go into a
+    // sorter on their keys first, see `Compiler::groups`, and without it they make up a
+    // single group. COUNT(*) alone over the table reads its row count instead. When the
+    // grouped rows come from WITH, common tables, views, zero_stat, a subquery in WHERE or
+    // a hash or merge join, which have no instructions, they are read from the query
+    // executor through `OpenQuery` instead of the loops.
+    //
+    // Queries with window functions or subqueries in the SELECT list, HAVING or ORDER BY
+    // are left to the executor but for their sorting, see `Compiler::query`.
+    fn select(&mut self, select: &Select, table: &Table) -> Result<()> {
+        let count = select.column_names().len();
+        if !Self::computes(select) {
+            return self.query(select, count);
+        }
+        let plan = QueryPlan::new(select, table);
+        let loops = select.with.is_empty()
+            && std::iter::once(&select.from)
+                .chain(select.joins.iter().map(|join| &join.table))
+                .all(|table| matches!(table.source, TableSource::Table(_)))
+            && plan
+                .joins
+                .iter()
+                .all(|join| join.algorithm == JoinAlgorithm::NestedLoop)
+            && !select
+                .where_clause
+                .as_ref()
+                .is_some_and(Expression::contains_subquery);
+        if !loops && !select.is_aggregate() {
+            return self.query(select, count);
+        }
+        // COUNT(*) over the whole table is the row count kept in the table header.
+        let count_star = Expression::Aggregate {
+            function: AggregateFunction::Count,
+            argument: None,
+        };
+        if loops
+            && select.joins.is_empty()
+            && select.where_clause.is_none()
+            && select.group_by.is_empty()
+            && select.aggregates() == [count_star.clone()]
+        {
+            let mut output = self.open_output(select, count, 1);
+            let schema = select.schema();
+            let row = self.registers;
+            self.registers += schema.columns.len();
+            let register = self.allocate();
+            self.emit(Instruction::Count {
+                table: table.definition.name.clone(),
+                register,
+            });
+            let layout = Layout::registers(&schema, row, vec![(count_star, register)]);
+            self.group_output(select, &layout, &mut output)?;
+            self.close_output(output);
+            return Ok(());
+        }
+
+        let joined = plan.reordered.as_ref().unwrap_or(select);
+        let tables: Vec<&TableReference> = std::iter::once(&joined.from)
+            .chain(joined.joins.iter().map(|join| &join.table))
+            .collect();
+        let cursors = if loops { tables.len() } else { 1 };
+        let mut output = self.open_output(select, count, cursors);
+        let schema = select.schema();
+        let grouping = select.is_aggregate().then(|| {
+            let cursor = cursors + output.sorter.map_or(0, |_| 1);
+            self.open_grouping(select, &schema, cursor)
+        });
+        if !loops {
+            self.emit(Instruction::OpenQuery {
+                cursor: 0,
+                select: Box::new(Self::grouped_rows(select)),
+            });
+            let rewind = self.emit(Instruction::Rewind {
+                cursor: 0,
+                target: 0,
+            });
+            let body = self.instructions.len();
+            let row = Layout::cursor(&schema, 0);
+            if let Some(grouping) = &grouping {
+                self.group_row(select, &row, grouping)?;
+            }
+            self.emit(Instruction::Next {
+                cursor: 0,
+                target: body,
+            });
+            self.jump_here(rewind);
+            if let Some(grouping) = &grouping {
+                self.groups(select, &schema, grouping, &mut output)?;
+            }
+            self.close_output(output);
+            return Ok(());
+        }
+
+        // A table searched for the keys of each left row is opened inside the loop.
+        for (cursor, access) in plan.access.iter().enumerate() {
+            match access {
+                AccessPath::FullScan => self.emit(Instruction::OpenRead {
+                    cursor,
+                    table: table.definition.name.clone(),
+                }),
+                AccessPath::Index(lookup) => self.emit(Instruction::OpenIndex {
+                    cursor,
+                    lookup: lookup.clone(),
+                }),
+                AccessPath::Probe(_) => continue,
+            };
+        }
+        let scanned = self.emit(Instruction::Rewind {
+            cursor: 0,
+            target: 0,
+        });
+        let body = self.instructions.len();
+        let schemas = joined.schemas();
+        let mut joins = Vec::with_capacity(joined.joins.len());
+        for (i, join) in joined.joins.iter().enumerate() {
+            let cursor = i + 1;
+            let matched = (join.operator == JoinOperator::Left).then(|| {
+                let register = self.allocate();
+                self.emit(Instruction::Value {
+                    value: Value::Integer(0),
+                    register,
+                });
+                register
+            });
+            if let AccessPath::Probe(probe) = &plan.access[cursor] {
+                let layout = Layout::new(&schemas[i], &tables);
+                let start = self.registers;
+                self.registers += probe.keys.len();
+                for (j, key) in probe.keys.iter().enumerate() {
+                    self.expression(key, &layout, start + j)?;
+                }
+                self.emit(Instruction::SeekIndex {
+                    cursor,
+                    probe: probe.clone(),
+                    start,
+                });
+            }
+            let rewind = self.emit(Instruction::Rewind { cursor, target: 0 });
+            let body = self.instructions.len();
+            let mut misses = Vec::new();
+            if let Some(constraint) = &join.constraint {
+                let layout = Layout::new(&schemas[cursor], &tables);
+                let register = self.allocate();
+                self.expression(constraint, &layout, register)?;
+                misses.push(self.emit(Instruction::IfNot {
+                    register,
+                    target: 0,
+                    null: true,
+                }));
+            }
+            if let Some(register) = matched {
+                self.emit(Instruction::Value {
+                    value: Value::Integer(1),
+                    register,
+                });
+            }
+            let resume = self.instructions.len();
+            joins.push(JoinLoop {
+                cursor,
+                rewind,
+                body,
+                misses,
+                matched,
+                resume,
+            });
+        }
+
+        let row = Layout::new(&schema, &tables);
+        let mut skip = None;
+        if let Some(condition) = &select.where_clause {
+            let register = self.allocate();
+            self.expression(condition, &row, register)?;
+            skip = Some(self.emit(Instruction::IfNot {
+                register,
+                target: 0,
+                null: true,
+            }));
+        }
+        match &grouping {
+            Some(grouping) => self.group_row(select, &row, grouping)?,
+            None => {
+                let keys = output.keys;
+                self.output(&mut output, |compiler, start| {
+                    compiler.row_values(select, &row, keys, start)
+                })?;
+            }
+        }
+        if let Some(skip) = skip {
+            self.jump_here(skip);
+        }
+        for join in joins.into_iter().rev() {
+            for miss in join.misses {
+                self.jump_here(miss);
+            }
+            self.emit(Instruction::Next {
+                cursor: join.cursor,
+                target: join.body,
+            });
+            self.jump_here(join.rewind);
+            // After the last row of the table, a left row nothing matched goes through the
+            // inner loops once more with the table on a row of NULLs.
+            if let Some(register) = join.matched {
+                let done = self.emit(Instruction::If {
+                    register,
+                    target: 0,
+                });
+                self.emit(Instruction::Value {
+                    value: Value::Integer(1),
+                    register,
+                });
+                self.emit(Instruction::NullRow {
+                    cursor: join.cursor,
+                });
+                self.emit(Instruction::Goto {
+                    target: join.resume,
+                });
+                self.jump_here(done);
+            }
+        }
+        self.emit(Instruction::Next {
+            cursor: 0,
+            target: body,
+        });
+        self.jump_here(scanned);
+        if let Some(grouping) = &grouping {
+            self.groups(select, &schema, grouping, &mut output)?;
+        }
+        self.close_output(output);
+        Ok(())
+    }
+
+    // Emits a loop over the rows the query executor returns for `select`, for queries with
+    // values that have no instructions, such as window functions and subqueries. The
+    // executor leaves ORDER BY, OFFSET and LIMIT to the instructions: it returns the ORDER
+    // BY keys of each row after its `count` columns, and the rows go into a sorter.
+    fn query(&mut self, select: &Select, count: usize) -> Result<()> {
+        let mut query = select.clone();
+        if !select.order_by.is_empty() {
+            let keys = select.order_by.iter().map(|term| ResultColumn::Expression {
+                expression: term.expression.clone(),
+                alias: None,
+            });
+            query.columns.extend(keys);
+            query.order_by.clear();
+            query.limit = None;
+            query.offset = 0;
+        }
+        let mut output = match select.order_by.is_empty() {
+            true => Output {
+                sorter: None,
+                keys: 0,
+                count,
+                limit: None,
+                offset: None,
+                exits: Vec::new(),
+            },
+            false => self.open_output(select, count, 1),
+        };
+        let cursor = 0;
+        self.emit(Instruction::OpenQuery {
+            cursor,
+            select: Box::new(query),
+        });
+        let rewind = self.emit(Instruction::Rewind { cursor, target: 0 });
+        let body = self.instructions.len();
+        let keys = output.keys;
+        self.output(&mut output, |compiler, start| {
+            for (i, column) in (count..count + keys).chain(0..count).enumerate() {
+                compiler.emit(Instruction::Column {
+                    cursor,
+                    column,
+                    register: start + i,
+                });
+            }
+            Ok(())
+        })?;
+        self.emit(Instruction::Next {
+            cursor,
+            target: body,
+        });
+        self.jump_here(rewind);
+        self.close_output(output);
+        Ok(())
+    }
+
+    // Whether the values the query computes from its rows or groups, in the SELECT list,
+    // HAVING and ORDER BY, have instructions: they call no window functions and hold no
+    // subqueries.
+    fn computes(select: &Select) -> bool {
+        select.windows().is_empty()
+            && !Self::result_expressions(select)
+                .chain(&select.having)
+                .chain(select.order_by.iter().map(|term| &term.expression))
+                .any(Expression::contains_subquery)
+    }
+
+    // The query the executor runs for the rows a grouped query groups: every column of its
+    // FROM clause, for the rows that pass WHERE.
+    fn grouped_rows(select: &Select) -> Select {
+        Select {
+            with: select.with.clone(),
+            columns: vec![ResultColumn::All],
+            from: select.from.clone(),
+            joins: select.joins.clone(),
+            where_clause: select.where_clause.clone(),
+            group_by: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: 0,
+        }
+    }
+
+    // Emits the registers of LIMIT and OFFSET, and with ORDER BY opens a sorter on `cursor`
+    // for the rows of a query outputting `count` columns.
+    fn open_output(&mut self, select: &Select, count: usize, cursor: usize) -> Output {
+        let mut exits = Vec::new();
+        let limit = select.limit.map(|limit| {
+            let register = self.allocate();
+            self.emit(Instruction::Value {
+                value: Value::Integer(limit as i64),
+                register,
+            });
+            exits.push(self.emit(Instruction::IfNot {
+                register,
+                target: 0,
+                null: false,
+            }));
+            register
+        });
+        let offset = (select.offset > 0).then(|| {
+            let register = self.allocate();
+            self.emit(Instruction::Value {
+                value: Value::Integer(select.offset as i64),
+                register,
+            });
+            register
+        });
+        let sorter = (!select.order_by.is_empty()).then(|| {
+            let descending = select.order_by.iter().map(|term| term.descending).collect();
+            self.emit(Instruction::SorterOpen { cursor, descending });
+            cursor
+        });
+        Output {
+            sorter,
+            keys: sorter.map_or(0, |_| select.order_by.len()),
+            count,
+            limit,
+            offset,
+            exits,
+        }
+    }
+
+    // Emits instructions outputting a row whose values `values` stores in the registers
+    // from the one it is given: the ORDER BY keys first when there is a sorter, then the
+    // columns. The values of rows skipped by OFFSET are not computed.
+    fn output(
+        &mut self,
+        output: &mut Output,
+        values: impl FnOnce(&mut Compiler, usize) -> Result<()>,
+    ) -> Result<()> {
+        let skip = match output.sorter {
+            None => output.offset.map(|register| {
+                self.emit(Instruction::IfPositive {
+                    register,
+                    target: 0,
+                })
+            }),
+            Some(_) => None,
+        };
+        let start = self.registers;
+        self.registers += output.keys + output.count;
+        values(self, start)?;
+        match output.sorter {
+            Some(cursor) => {
+                self.emit(Instruction::SorterInsert {
+                    cursor,
+                    start,
+                    keys: output.keys,
+                    count: output.keys + output.count,
+                });
+            }
+            None => {
+                self.emit(Instruction::ResultRow {
+                    start,
+                    count: output.count,
+                });
+                if let Some(register) = output.limit {
+                    output.exits.push(self.emit(Instruction::DecrementJumpZero {
+                        register,
+                        target: 0,
+                    }));
+                }
+            }
+        }
+        if let Some(skip) = skip {
+            self.jump_here(skip);
+        }
+        Ok(())
+    }
+
+    // Emits the loop reading the rows of the ORDER BY sorter back in order, past the OFFSET
+    // and until the LIMIT, and points the jumps that end the query past it.
+    fn close_output(&mut self, mut output: Output) {
+        if let Some(cursor) = output.sorter {
+            let count = output.count;
+            let start = self.registers;
+            self.registers += count;
+            output
+                .exits
+                .push(self.emit(Instruction::SorterSort { cursor, target: 0 }));
+            let body = self.instructions.len();
+            let skip = output.offset.map(|register| {
+                self.emit(Instruction::IfPositive {
+                    register,
+                    target: 0,
+                })
+            });
+            for column in 0..count {
+                self.emit(Instruction::Column {
+                    cursor,
+                    column,
+                    register: start + column,
+                });
+            }
+            self.emit(Instruction::ResultRow { start, count });
+            if let Some(register) = output.limit {
+                output.exits.push(self.emit(Instruction::DecrementJumpZero {
+                    register,
+                    target: 0,
+                }));
+            }
+            if let Some(skip) = skip {
+                self.jump_here(skip);
+            }
+            self.emit(Instruction::Next {
+                cursor,
+                target: body,
+            });
+        }
+        for exit in output.exits {
+            self.jump_here(exit);
+        }
+    }
+
+    // Emits instructions storing the first `keys` ORDER BY keys of the row or group `layout`
+    // reads in the registers from `start`, followed by the values of the SELECT list.
+    fn row_values(
+        &mut self,
+        select: &Select,
+        layout: &Layout,
+        keys: usize,
+        start: usize,
+    ) -> Result<()> {
+        for (i, term) in select.order_by.iter().take(keys).enumerate() {
+            self.expression(&term.expression, layout, start + i)?;
+        }
+        self.result_columns(select, layout, start + keys)
+    }
+
+    // Emits the sorter grouping the rows of `select` on `cursor` if it has GROUP BY, and
+    // allocates the registers of its groups, whose rows are described by `schema`.
+    fn open_grouping(&mut self, select: &Select, schema: &Schema, cursor: usize) -> Grouping {
+        let group_by = match select.group_by.is_empty() {
+            true => GroupBy::Single {
+                started: self.allocate(),
+            },
+            false => {
+                let descending = vec![false; select.group_by.len()];
+                self.emit(Instruction::SorterOpen { cursor, descending });
+                GroupBy::Keys { cursor }
+            }
+        };
+        let row = self.registers;
+        self.registers += schema.columns.len();
+        let aggregates = select
+            .aggregates()
+            .into_iter()
+            .map(|aggregate| (aggregate, self.allocate()))
+            .collect();
+        Grouping {
+            group_by,
+            row,
+            aggregates,
+        }
+    }
+
+    // Emits instructions grouping the row `row` reads. The sorter gets the GROUP BY keys,
+    // to sort on and again to tell where a group ends, then the arguments of the aggregates
+    // in order and the columns of the row. A single group accumulates the row at once.
+    fn group_row(&mut self, select: &Select, row: &Layout, grouping: &Grouping) -> Result<()> {
+        let cursor = match grouping.group_by {
+            GroupBy::Keys { cursor } => cursor,
+            GroupBy::Single { started } => {
+                // The first row supplies the values of the columns that are not aggregated.
+                let accumulate = self.emit(Instruction::If {
+                    register: started,
+                    target: 0,
+                });
+                self.emit(Instruction::Value {
+                    value: Value::Integer(1),
+                    register: started,
+                });
+                for (i, &slot) in row.columns.iter().enumerate() {
+                    self.read(slot, grouping.row + i);
+                }
+                self.jump_here(accumulate);
+                for (aggregate, register) in &grouping.aggregates {
+                    let Expression::Aggregate { function, argument } = aggregate else {
+                        continue;
+                    };
+                    let argument = match argument {
+                        Some(argument) => {
+                            let value = self.allocate();
+                            self.expression(argument, row, value)?;
+                            Some(value)
+                        }
+                        None => None,
+                    };
+                    self.emit(Instruction::AggStep {
+                        function: *function,
+                        argument,
+                        register: *register,
+                    });
+                }
+                return Ok(());
+            }
+        };
+        let keys = select.group_by.len();
+        let arguments: Vec<&Expression> = grouping
+            .aggregates
+            .iter()
+            .filter_map(|(aggregate, _)| match aggregate {
+                Expression::Aggregate { argument, .. } => argument.as_deref(),
+                _ => None,
+            })
+            .collect();
+        let start = self.registers;
+        self.registers += 2 * keys + arguments.len() + row.columns.len();
+        for (i, expression) in select.group_by.iter().enumerate() {
+            self.expression(expression, row, start + i)?;
+            self.emit(Instruction::Copy {
+                source: start + i,
+                register: start + keys + i,
+            });
+        }
+        let mut register = start + 2 * keys;
+        for argument in arguments {
+            self.expression(argument, row, register)?;
+            register += 1;
+        }
+        for &slot in &row.columns {
+            self.read(slot, register);
+            register += 1;
+        }
+        self.emit(Instruction::SorterInsert {
+            cursor,
+            start,
+            keys,
+            count: register - start,
+        });
+        Ok(())
+    }
+
+    // Emits instructions outputting the groups once every row is grouped. The sorted rows
+    // are read in a loop that accumulates the aggregates of a group until `IfDistinct`
+    // finds the keys of the next one, and outputs each group once the row after its last
+    // is read. A single group is output even if there were no rows.
+    fn groups(
+        &mut self,
+        select: &Select,
+        schema: &Schema,
+        grouping: &Grouping,
+        output: &mut Output,
+    ) -> Result<()> {
+        let layout = Layout::registers(schema, grouping.row, grouping.aggregates.clone());
+        let cursor = match grouping.group_by {
+            GroupBy::Keys { cursor } => cursor,
+            GroupBy::Single { .. } => return self.finish_group(select, &layout, output),
+        };
+        let keys = select.group_by.len();
+        let arguments = grouping
+            .aggregates
+            .iter()
+            .filter(|(aggregate, _)| {
+                matches!(
+                    aggregate,
+                    Expression::Aggregate {
+                        argument: Some(_),
+                        ..
+                    }
+                )
+            })
+            .count();
+        let current = self.registers;
+        let previous = current + keys;
+        self.registers += 2 * keys;
+
+        let sorted = self.emit(Instruction::SorterSort { cursor, target: 0 });
+        let first = self.emit(Instruction::Goto { target: 0 });
+        let next = self.instructions.len();
+        for i in 0..keys {
+            self.emit(Instruction::Column {
+                cursor,
+                column: i,
+                register: current + i,
+            });
+        }
+        let distinct = self.emit(Instruction::IfDistinct {
+            left: current,
+            right: previous,
+            count: keys,
+            target: 0,
+        });
+        let same = self.emit(Instruction::Goto { target: 0 });
+        self.jump_here(distinct);
+        self.finish_group(select, &layout, output)?;
+        // The first row of a group supplies the values of the columns that are not grouped.
+        self.jump_here(first);
+        for i in 0..keys {
+            self.emit(Instruction::Column {
+                cursor,
+                column: i,
+                register: previous + i,
+            });
+        }
+        for i in 0..schema.columns.len() {
+            self.emit(Instruction::Column {
+                cursor,
+                column: keys + arguments + i,
+                register: grouping.row + i,
+            });
+        }
+        self.jump_here(same);
+        let mut column = keys;
+        for (aggregate, register) in &grouping.aggregates {
+            let Expression::Aggregate { function, argument } = aggregate else {
+                continue;
+            };
+            let argument = argument.as_ref().map(|_| {
+                let argument = self.allocate();
+                self.emit(Instruction::Column {
+                    cursor,
+                    column,
+                    register: argument,
+                });
+                column += 1;
+                argument
+            });
+            self.emit(Instruction::AggStep {
+                function: *function,
+                argument,
+                register: *register,
+            });
+        }
+        self.emit(Instruction::Next {
+            cursor,
+            target: next,
+        });
+        self.finish_group(select, &layout, output)?;
+        self.jump_here(sorted);
+        Ok(())
+    }
+
+    // Emits instructions finishing the aggregates of a group in their registers, then
+    // outputting it.
+    fn finish_group(
+        &mut self,
+        select: &Select,
+        layout: &Layout,
+        output: &mut Output,
+    ) -> Result<()> {
+        for (aggregate, register) in &layout.aggregates {
+            if let Expression::Aggregate { function, .. } = aggregate {
+                self.emit(Instruction::AggFinal {
+                    function: *function,
+                    register: *register,
+                });
+            }
+        }
+        self.group_output(select, layout, output)
+    }
+
+    // Emits instructions outputting the group `layout` reads if it passes HAVING.
+    fn group_output(
+        &mut self,
+        select: &Select,
+        layout: &Layout,
+        output: &mut Output,
+    ) -> Result<()> {
+        let mut skip = None;
+        if let Some(condition) = &select.having {
+            let register = self.allocate();
+            self.expression(condition, layout, register)?;
+            skip = Some(self.emit(Instruction::IfNot {
+                register,
+                target: 0,
+                null: true,
+            }));
+        }
+        let keys = output.keys;
+        self.output(output, |compiler, start| {
+            compiler.row_values(select, layout, keys, start)
+        })?;
+        if let Some(skip) = skip {
+            self.jump_here(skip);
+        }
+        Ok(())
+    }
+
+    // Emits instructions storing the values of the SELECT list in the registers from
+    // `start`, with `*` standing for every column of `row`.
+    fn result_columns(&mut self, select: &Select, row: &Layout, start: usize) -> Result<()> {
+        let mut register = start;
+        for column in &select.columns {
+            match column {
+                ResultColumn::All => {
+                    for &slot in &row.columns {
+                        self.read(slot, register);
+                        register += 1;
+                    }
+                }
+                ResultColumn::Expression { expression, .. } => {
+                    self.expression(expression, row, register)?;
+                    register += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Emits instructions outputting `rows`, which all have the same number of columns,
+    // through the same registers.
+    fn constant_rows(&mut self, rows: Vec<Vec<Value>>) {
+        let count = rows.first().map_or(0, |row| row.len());
+        let start = self.registers;
+        self.registers += count;
+        for row in rows {
+            for (i, value) in row.into_iter().enumerate() {
+                self.emit(Instruction::Value {
+                    value,
+                    register: start + i,
+                });
+            }
+            self.emit(Instruction::ResultRow { start, count });
+        }
+    }
+
+    fn result_expressions(select: &Select) -> impl Iterator<Item = &Expression> {
+        select.columns.iter().filter_map(|column| match column {
+            ResultColumn::All => None,
+            ResultColumn::Expression { expression, .. } => Some(expression),
+        })
+    }
+
+    // Emits instructions storing the value of `expression` in `register`, with its columns
+    // read from the cursors `layout` places them in.
+    fn expression(
+        &mut self,
+        expression: &Expression,
+        layout: &Layout,
+        register: usize,
+    ) -> Result<()> {
+        match expression {
+            Expression::Literal(value) => {
+                self.emit(Instruction::Value {
+                    value: value.clone(),
+                    register,
+                });
+            }
+            Expression::Column { table, name } => {
+                let slot = layout.columns[layout.schema.resolve(table.as_deref(), name)?];
+                self.read(slot, register);
+            }
+            Expression::Aggregate { function, .. } => {
+                let source = layout
+                    .aggregates
+                    .iter()
+                    .find(|(aggregate, _)| aggregate == expression)
+                    .map(|(_, source)| *source)
+                    .ok_or_else(|| anyhow!("Misuse of aggregate function {}()", function))?;
+                self.emit(Instruction::Copy { source, register });
+            }
+            Expression::Unary { operator, operand } => {
+                self.expression(operand, layout, register)?;
+                self.emit(Instruction::Unary {
+                    operator: *operator,
+                    operand: register,
+                    register,
+                });
+            }
+            // The right side is skipped when the left one decides the result, as in
+            // `Expression::evaluate`, so that it cannot fail for rows it does not matter for.
+            Expression::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                self.expression(left, layout, register)?;
+                let decided = match operator {
+                    BinaryOperator::Or => self.emit(Instruction::If {
+                        register,
+                        target: 0,
+                    }),
+                    _ => self.emit(Instruction::IfNot {
+                        register,
+                        target: 0,
+                        null: false,
+                    }),
+                };
+                let right_register = self.allocate();
+                self.expression(right, layout, right_register)?;
+                self.emit(Instruction::Binary {
+                    operator: *operator,
+                    left: register,
+                    right: right_register,
+                    register,
+                });
+                let done = self.emit(Instruction::Goto { target: 0 });
+                self.jump_here(decided);
+                self.emit(Instruction::Value {
+                    value: Value::from_bool(*operator == BinaryOperator::Or),
+                    register,
+                });
+                self.jump_here(done);
+            }
+            Expression::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                self.expression(left, layout, register)?;
+                let right_register = self.allocate();
+                self.expression(right, layout, right_register)?;
+                self.emit(Instruction::Binary {
+                    operator: *operator,
+                    left: register,
+                    right: right_register,
+                    register,
+                });
+            }
+            _ => return Err(anyhow!("Cannot compile {}", expression)),
+        }
+        Ok(())
+    }
+
+    // Emits the instruction storing the value `slot` holds in `register`.
+    fn read(&mut self, slot: Slot, register: usize) {
+        match slot {
+            Slot::Column { cursor, column } => self.emit(Instruction::Column {
+                cursor,
+                column,
+                register,
+            }),
+            Slot::Register(source) => self.emit(Instruction::Copy { source, register }),
+        };
+    }
+
+    fn allocate(&mut self) -> usize {
+        self.registers += 1;
+        self.registers - 1
+    }
+
+    // Appends `instruction` and returns its address.
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.instructions.push(instruction);
+        self.instructions.len() - 1
+    }
+
+    // Points the jump at `address` to the next instruction to be emitted.
+    fn jump_here(&mut self, address: usize) {
+        let next = self.instructions.len();
+        if let Some(target) = self.instructions[address].target_mut() {
+            *target = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TableDefinition;
+
+    fn compile_select(input: &str) -> Program {
+        let statement = Statement {
+            statement_type: StatementType::Select,
+            row_to_insert: None,
+            select: Some(
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap(),
+            ),
+        };
+        Program::compile(&statement, &Table::new()).unwrap()
+    }
+
+    #[test]
+    fn test_compile_scan() {
+        let program = compile_select("select id, username where id > 1 limit 2 offset 1");
+        let expected = [
+            "0 Value value=2 register=0",
+            "1 IfNot register=0 target=15 null=false",
+            "2 Value value=1 register=1",
+            "3 OpenRead cursor=0 table=users",
+            "4 Rewind cursor=0 target=15",
+            "5 Column cursor=0 column=0 register=2",
+            "6 Value value=1 register=3",
+            "7 Binary operator=> left=2 right=3 register=2",
+            "8 IfNot register=2 target=14 null=true",
+            "9 IfPositive register=1 target=14",
+            "10 Column cursor=0 column=0 register=4",
+            "11 Column cursor=0 column=1 register=5",
+            "12 ResultRow start=4 count=2",
+            "13 DecrementJumpZero register=0 target=15",
+            "14 Next cursor=0 target=5",
+            "15 Halt",
+        ];
+        assert_eq!(program.to_string(), expected.join("\n"));
+        assert_eq!(program.registers, 6);
+        assert_eq!(
+            program.columns,
+            Some(vec!["id".to_string(), "username".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_compile_grouping() {
+        // Rows are sorted on their GROUP BY keys, and each group is output once the row after
+        // its last one is read, or after the last row.
+        let program = compile_select(
+            "select username, count(*) group by username having count(*) > 1 order by 2 desc",
+        );
+        let expected = [
+            "0 SorterOpen cursor=1 order=(DESC)",
+            "1 SorterOpen cursor=2 order=(ASC)",
+            "2 OpenRead cursor=0 table=users",
+            "3 Rewind cursor=0 target=11",
+            "4 Column cursor=0 column=1 register=4",
+            "5 Copy source=4 register=5",
+            "6 Column cursor=0 column=0 register=6",
+            "7 Column cursor=0 column=1 register=7",
+            "8 Column cursor=0 column=2 register=8",
+            "9 SorterInsert cursor=2 start=4 keys=1 count=5",
+            "10 Next cursor=0 target=4",
+            "11 SorterSort cursor=2 target=40",
+            "12 Goto target=25",
+            "13 Column cursor=2 column=0 register=9",
+            "14 IfDistinct left=9 right=10 count=1 target=16",
+            "15 Goto target=29",
+            "16 AggFinal function=count register=3",
+            "17 Copy source=3 register=11",
+            "18 Value value=1 register=12",
+            "19 Binary operator=> left=11 right=12 register=11",
+            "20 IfNot register=11 target=25 null=true",
+            "21 Copy source=3 register=13",
+            "22 Copy source=1 register=14",
+            "23 Copy source=3 register=15",
+            "24 SorterInsert cursor=1 start=13 keys=1 count=3",
+            "25 Column cursor=2 column=0 register=10",
+            "26 Column cursor=2 column=1 register=0",
+            "27 Column cursor=2 column=2 register=1",
+            "28 Column cursor=2 column=3 register=2",
+            "29 AggStep function=count register=3",
+            "30 Next cursor=2 target=13",
+            "31 AggFinal function=count register=3",
+            "32 Copy source=3 register=16",
+            "33 Value value=1 register=17",
+            "34 Binary operator=> left=16 right=17 register=16",
+            "35 IfNot register=16 target=40 null=true",
+            "36 Copy source=3 register=18",
+            "37 Copy source=1 register=19",
+            "38 Copy source=3 register=20",
+            "39 SorterInsert cursor=1 start=18 keys=1 count=3",
+            "40 SorterSort cursor=1 target=45",
+            "41 Column cursor=1 column=0 register=21",
+            "42 Column cursor=1 column=1 register=22",
+            "43 ResultRow start=21 count=2",
+            "44 Next cursor=1 target=41",
+            "45 Halt",
+        ];
+        assert_eq!(program.to_string(), expected.join("\n"));
+
+        // Without GROUP BY the rows are accumulated as they are read.
+        let program = compile_select("select count(id), max(id)");
+        let expected = [
+            "0 OpenRead cursor=0 table=users",
+            "1 Rewind cursor=0 target=12",
+            "2 If register=0 target=7",
+            "3 Value value=1 register=0",
+            "4 Column cursor=0 column=0 register=1",
+            "5 Column cursor=0 column=1 register=2",
+            "6 Column cursor=0 column=2 register=3",
+            "7 Column cursor=0 column=0 register=6",
+            "8 AggStep function=count argument=6 register=4",
+            "9 Column cursor=0 column=0 register=7",
+            "10 AggStep function=max argument=7 register=5",
+            "11 Next cursor=0 target=2",
+            "12 AggFinal function=count register=4",
+            "13 AggFinal function=max register=5",
+            "14 Copy source=4 register=8",
+            "15 Copy source=5 register=9",
+            "16 ResultRow start=8 count=2",
+            "17 Halt",
+        ];
+        assert_eq!(program.to_string(), expected.join("\n"));
+
+        let program = compile_select("select count(*)");
+        assert_eq!(
+            program.instructions[0].to_string(),
+            "Count table=users register=3"
+        );
+    }
+
+    #[test]
+    fn test_compile_query() {
+        // The rows of a common table are read from the executor and grouped by instructions.
+        let program =
+            compile_select("with t as (select 1 as n) select n, count(*) from t group by n");
+        assert_eq!(
+            program.instructions[1].to_string(),
+            "OpenQuery cursor=0 select=(WITH t(n) AS (SELECT 1 AS n) SELECT * FROM t)"
+        );
+        assert!(program
+            .instructions
+            .iter()
+            .any(|instruction| instruction.opcode() == "AggStep"));
+
+        // The executor computes window functions and leaves sorting to the instructions,
+        // returning the ORDER BY keys after the columns.
+        let program = compile_select(
+            "select id, rank() over (order by id) order by id desc limit 2 offset 1",
+        );
+        let expected = [
+            "0 Value value=2 register=0",
+            "1 IfNot register=0 target=18 null=false",
+            "2 Value value=1 register=1",
+            "3 SorterOpen cursor=1 order=(DESC)",
+            "4 OpenQuery cursor=0 select=(SELECT id, rank() OVER (ORDER BY id), id FROM users)",
+            "5 Rewind cursor=0 target=11",
+            "6 Column cursor=0 column=2 register=2",
+            "7 Column cursor=0 column=0 register=3",
+            "8 Column cursor=0 column=1 register=4",
+            "9 SorterInsert cursor=1 start=2 keys=1 count=3",
+            "10 Next cursor=0 target=6",
+            "11 SorterSort cursor=1 target=18",
+            "12 IfPositive register=1 target=17",
+            "13 Column cursor=1 column=0 register=5",
+            "14 Column cursor=1 column=1 register=6",
+            "15 ResultRow start=5 count=2",
+            "16 DecrementJumpZero register=0 target=18",
+            "17 Next cursor=1 target=12",
+            "18 Halt",
+        ];
+        assert_eq!(program.to_string(), expected.join("\n"));
+
+        let program = compile_select("select id where id > (select min(id))");
+        let opcodes: Vec<&str> = program.instructions.iter().map(|i| i.opcode()).collect();
+        assert_eq!(
+            opcodes,
+            ["OpenQuery", "Rewind", "Column", "ResultRow", "Next", "Halt"]
+        );
+    }
+
+    #[test]
+    fn test_compile_join_and_sort() {
+        let program = compile_select(
+            "select b.id from users a left join users b on b.id = a.id order by b.id desc limit 1",
+        );
+        // A left row nothing matched runs the inner loop body again from 13 on a row of
+        // NULLs, and the sorted rows are read back from 22.
+        let expected = [
+            "0 Value value=1 register=0",
+            "1 IfNot register=0 target=27 null=false",
+            "2 SorterOpen cursor=2 order=(DESC)",
+            "3 OpenRead cursor=0 table=users",
+            "4 OpenRead cursor=1 table=users",
+            "5 Rewind cursor=0 target=22",
+            "6 Value value=0 register=1",
+            "7 Rewind cursor=1 target=17",
+            "8 Column cursor=1 column=0 register=2",
+            "9 Column cursor=0 column=0 register=3",
+            "10 Binary operator== left=2 right=3 register=2",
+            "11 IfNot register=2 target=16 null=true",
+            "12 Value value=1 register=1",
+            "13 Column cursor=1 column=0 register=4",
+            "14 Column cursor=1 column=0 register=5",
+            "15 SorterInsert cursor=2 start=4 keys=1 count=2",
+            "16 Next cursor=1 target=8",
+            "17 If register=1 target=21",
+            "18 Value value=1 register=1",
+            "19 NullRow cursor=1",
+            "20 Goto target=13",
+            "21 Next cursor=0 target=6",
+            "22 SorterSort cursor=2 target=27",
+            "23 Column cursor=2 column=0 register=6",
+            "24 ResultRow start=6 count=1",
+            "25 DecrementJumpZero register=0 target=27",
+            "26 Next cursor=2 target=23",
+            "27 Halt",
+        ];
+        assert_eq!(program.to_string(), expected.join("\n"));
+
+        // Joins the planner runs as hash joins are left to the executor.
+        let mut table = Table::new();
+        table.num_rows = 1000;
+        let statement = Statement {
+            statement_type: StatementType::Select,
+            row_to_insert: None,
+            select: Some(
+                Statement::parse_select_input(
+                    "select from users a join users b on a.id = b.id",
+                    &TableDefinition::default(),
+                    &[],
+                )
+                .unwrap(),
+            ),
+        };
+        let program = Program::compile(&statement, &table).unwrap();
+        assert_eq!(program.instructions[0].opcode(), "OpenQuery");
+    }
+
+    #[test]
+    fn test_compile_index_probe() {
+        let mut table = Table::new();
+        table.num_rows = 1000;
+        table
+            .create_index("by_id", &["id".to_string()], true)
+            .unwrap();
+        let statement = Statement {
+            statement_type: StatementType::Select,
+            row_to_insert: None,
+            select: Some(
+                Statement::parse_select_input(
+                    "select b.id from users a join users b on b.id = a.id + 1",
+                    &TableDefinition::default(),
+                    &[],
+                )
+                .unwrap(),
+            ),
+        };
+        // The index is searched for the key of each row of a before reading b.
+        let program = Program::compile(&statement, &table).unwrap();
+        let expected = [
+            "0 OpenRead cursor=0 table=users",
+            "1 Rewind cursor=0 target=17",
+            "2 Column cursor=0 column=0 register=0",
+            "3 Value value=1 register=1",
+            "4 Binary operator=+ left=0 right=1 register=0",
+            "5 SeekIndex cursor=1 index=by_id (id=?) start=0",
+            "6 Rewind cursor=1 target=16",
+        ];
+        let lines: Vec<String> = program.to_string().lines().map(String::from).collect();
+        assert_eq!(lines[..expected.len()], expected);
+    }
+
+    #[test]
+    fn test_compile_other_statements() {
+        let statement = Statement {
+            statement_type: StatementType::RecursionLimit(None),
+            row_to_insert: None,
+            select: None,
+        };
+        let program = Program::compile(&statement, &Table::new()).unwrap();
+        assert_eq!(
+            program.to_string(),
+            "0 RecursionLimit register=0\n1 ResultRow start=0 count=1\n2 Halt"
+        );
+
+        let statement = Statement {
+            statement_type: StatementType::Insert,
+            row_to_insert: None,
+            select: None,
+        };
+        let err = Program::compile(&statement, &Table::new()).unwrap_err();
+        assert_eq!(err.to_string(), "No row for insertion");
+    }
+}
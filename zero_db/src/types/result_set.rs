@@ -0,0 +1,48 @@
+use crate::types::Value;
+use std::fmt;
+
+/// Rows returned by a query, each holding one value per named column.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+impl ResultSet {
+    pub fn new(columns: Vec<String>) -> ResultSet {
+        ResultSet {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+}
+/// Prints a header line with the column names followed by one line per row, with the values
+/// separated by `|`.
+impl fmt::Display for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.columns.join("|"))?;
+        for row in &self.rows {
+            let values: Vec<String> = row.iter().map(Value::to_string).collect();
+            write!(f, "\n{}", values.join("|"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let mut result_set = ResultSet::new(vec!["id".to_string(), "name".to_string()]);
+        assert_eq!(result_set.to_string(), "id|name");
+
+        result_set
+            .rows
+            .push(vec![Value::Integer(1), Value::Text("alice".to_string())]);
+        result_set
+            .rows
+            .push(vec![Value::Integer(2), Value::Text("bob".to_string())]);
+        assert_eq!(result_set.to_string(), "id|name\n1|alice\n2|bob");
+    }
+}
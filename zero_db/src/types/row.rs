@@ -1,4 +1,11 @@
+use crate::types::Value;
+use anyhow::{anyhow, Result};
 use heapless::String as HeapLessString;
+
+const ID_SIZE: usize = size_of::<i32>();
+const USERNAME_SIZE: usize = size_of::<HeapLessString<32>>();
+const EMAIL_SIZE: usize = size_of::<HeapLessString<256>>();
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Row {
     pub id: i32,
@@ -6,9 +13,90 @@ pub struct Row {
     pub email: HeapLessString<256>,
 }
 impl Row {
+    pub const COLUMNS: [&'static str; 3] = ["id", "username", "email"];
+    /// The types of the columns, as CREATE TABLE declares them.
+    pub const TYPES: [&'static str; 3] = ["INTEGER", "TEXT", "TEXT"];
+
     pub fn max_size() -> usize {
         size_of::<i32>() + size_of::<HeapLessString<32>>() + size_of::<HeapLessString<256>>()
     }
+
+    /// Returns the value of the named column, or `None` if there is no such column.
+    pub fn value(&self, column: &str) -> Option<Value> {
+        match column {
+            "id" => Some(Value::Integer(self.id as i64)),
+            "username" => Some(Value::Text(self.username.to_string())),
+            "email" => Some(Value::Text(self.email.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The values of the columns in the order of `Row::COLUMNS`.
+    pub fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Integer(self.id as i64),
+            Value::Text(self.username.to_string()),
+            Value::Text(self.email.to_string()),
+        ]
+    }
+
+    /// Builds a row from the values of its columns in the order of `Row::COLUMNS`, the
+    /// inverse of `values`.
+    pub fn from_values(values: &[Value]) -> Result<Row> {
+        let [Value::Integer(id), Value::Text(username), Value::Text(email)] = values else {
+            return Err(anyhow!("Expected an INTEGER id, TEXT username and TEXT email"));
+        };
+        Ok(Row {
+            id: i32::try_from(*id).map_err(|_| anyhow!("Id {} is out of range", id))?,
+            username: HeapLessString::try_from(username.as_str())
+                .map_err(|_| anyhow!("Username is longer than 32 bytes"))?,
+            email: HeapLessString::try_from(email.as_str())
+                .map_err(|_| anyhow!("Email is longer than 256 bytes"))?,
+        })
+    }
+
+    /// Appends the row to `buf` using exactly `Row::max_size()` bytes: the id followed by
+    /// the username and email, each stored as a length prefix and zero padded bytes.
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        Self::serialize_str(buf, &self.username, USERNAME_SIZE);
+        Self::serialize_str(buf, &self.email, EMAIL_SIZE);
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Row> {
+        if bytes.len() != Self::max_size() {
+            return Err(anyhow!(
+                "Row must be {} bytes but got {}",
+                Self::max_size(),
+                bytes.len()
+            ));
+        }
+        let (id, rest) = bytes.split_at(ID_SIZE);
+        let (username, email) = rest.split_at(USERNAME_SIZE);
+
+        Ok(Row {
+            id: i32::from_le_bytes(id.try_into()?),
+            username: HeapLessString::try_from(Self::deserialize_str(username)?)
+                .map_err(|_| anyhow!("Stored username exceeds the configured length: 32"))?,
+            email: HeapLessString::try_from(Self::deserialize_str(email)?)
+                .map_err(|_| anyhow!("Stored email exceeds the configured length: 256"))?,
+        })
+    }
+
+    fn serialize_str(buf: &mut Vec<u8>, value: &str, field_size: usize) {
+        let start = buf.len();
+        buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+        buf.resize(start + field_size, 0);
+    }
+
+    fn deserialize_str(bytes: &[u8]) -> Result<&str> {
+        let len = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        let value = bytes
+            .get(2..2 + len)
+            .ok_or_else(|| anyhow!("Stored string length {} exceeds its field", len))?;
+        Ok(std::str::from_utf8(value)?)
+    }
 }
 
 #[cfg(test)]
@@ -20,4 +108,53 @@ pub mod tests {
         let total = size_of::<i32>() + size_of::<HeapLessString<32>>() + size_of::<HeapLessString<256>>();
         assert_eq!(Row::max_size(), total);
     }
+
+    #[test]
+    fn test_row_serialize_roundtrip() {
+        let row = Row {
+            id: -7,
+            username: HeapLessString::try_from("a".repeat(32).as_str()).unwrap(),
+            email: HeapLessString::try_from("alice@example.com").unwrap(),
+        };
+        let mut buf = Vec::new();
+        row.serialize(&mut buf);
+        assert_eq!(buf.len(), Row::max_size());
+        assert_eq!(Row::deserialize(&buf).unwrap(), row);
+    }
+
+    #[test]
+    fn test_row_from_values() {
+        let row = Row {
+            id: 3,
+            username: HeapLessString::try_from("alice").unwrap(),
+            email: HeapLessString::try_from("alice@example.com").unwrap(),
+        };
+        assert_eq!(Row::from_values(&row.values()).unwrap(), row);
+
+        let mut values = row.values();
+        values[0] = Value::Integer(i64::MAX);
+        let err = Row::from_values(&values).unwrap_err();
+        assert_eq!(err.to_string(), format!("Id {} is out of range", i64::MAX));
+
+        values[0] = Value::Null;
+        let err = Row::from_values(&values).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected an INTEGER id, TEXT username and TEXT email"
+        );
+    }
+
+    #[test]
+    fn test_row_deserialize_invalid() {
+        let err = Row::deserialize(&[0; 3]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Row must be {} bytes but got 3", Row::max_size())
+        );
+
+        let mut buf = vec![0; Row::max_size()];
+        buf[ID_SIZE] = 0xff;
+        let err = Row::deserialize(&buf).unwrap_err();
+        assert_eq!(err.to_string(), "Stored string length 255 exceeds its field");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+use crate::types::expression::Scope;
+use crate::types::tokenizer::quote_identifier;
+use crate::types::{Row, Table, Value};
+use anyhow::{anyhow, Result};
+
+/// The name of the table and of its columns, which ALTER TABLE can rename. Rows are stored
+/// in a fixed layout, so the columns are always an INTEGER and two TEXT columns, in the
+/// order of `Row::COLUMNS`, whatever they are called.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableDefinition {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+impl Default for TableDefinition {
+    /// The table a new database starts with, `users (id, username, email)`.
+    fn default() -> TableDefinition {
+        TableDefinition {
+            name: Table::NAME.to_string(),
+            columns: Row::COLUMNS
+                .iter()
+                .map(|column| column.to_string())
+                .collect(),
+        }
+    }
+}
+impl TableDefinition {
+    /// A table named `name` whose columns, one for each of `Row::COLUMNS`, have distinct
+    /// names.
+    pub fn new(name: &str, columns: &[String]) -> Result<TableDefinition> {
+        if columns.len() != Row::COLUMNS.len() {
+            return Err(anyhow!(
+                "Table {} must have {} columns",
+                name,
+                Row::COLUMNS.len()
+            ));
+        }
+        for (i, column) in columns.iter().enumerate() {
+            if columns[..i].contains(column) {
+                return Err(anyhow!("Duplicate column name: {}", column));
+            }
+        }
+        Ok(TableDefinition {
+            name: name.to_string(),
+            columns: columns.to_vec(),
+        })
+    }
+
+    /// The CREATE TABLE statement that gives the table its names again, as stored in the
+    /// `Catalog`.
+    pub fn definition(&self) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .zip(Row::TYPES)
+            .map(|(column, column_type)| format!("{} {}", quote_identifier(column), column_type))
+            .collect();
+        format!(
+            "CREATE TABLE {} ({})",
+            quote_identifier(&self.name),
+            columns.join(", ")
+        )
+    }
+
+    /// Position of `column` among the columns, which is that of its value in a row.
+    pub fn position(&self, column: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|name| name == column)
+            .ok_or_else(|| anyhow!("No such column: {}", column))
+    }
+
+    /// The columns of the table, qualified by `alias`.
+    pub fn schema(&self, alias: &str) -> Schema {
+        Schema::with_columns(alias, &self.columns)
+    }
+}
+
+/// A column of the rows a query works on, with the alias of the table it comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaColumn {
+    pub table: Option<String>,
+    pub name: String,
+}
+
+/// The columns of the rows a query works on, such as the combined rows of a join.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    pub columns: Vec<SchemaColumn>,
+}
+impl Schema {
+    /// Columns with the given names, qualified by `alias`, as for a common table expression.
+    pub fn with_columns<S: AsRef<str>>(alias: &str, names: &[S]) -> Schema {
+        Schema {
+            columns: names
+                .iter()
+                .map(|name| SchemaColumn {
+                    table: Some(alias.to_string()),
+                    name: name.as_ref().to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The columns of `self` followed by those of `other`, as in a joined row.
+    pub fn join(&self, other: &Schema) -> Schema {
+        let mut columns = self.columns.clone();
+        columns.extend(other.columns.iter().cloned());
+        Schema { columns }
+    }
+
+    /// Position of the column `[table.]name`. An unqualified name must be unique among the
+    /// columns of all tables.
+    pub fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize> {
+        self.find(table, name)?.ok_or_else(|| match table {
+            Some(table) => anyhow!("No such column: {}.{}", table, name),
+            None => anyhow!("No such column: {}", name),
+        })
+    }
+
+    /// Like `resolve`, but a missing column is `None` rather than an error, so that the
+    /// schemas of enclosing queries can be searched next.
+    pub fn find(&self, table: Option<&str>, name: &str) -> Result<Option<usize>> {
+        let mut matches = self.columns.iter().enumerate().filter(|(_, column)| {
+            column.name == name && (table.is_none() || column.table.as_deref() == table)
+        });
+        match (matches.next(), matches.next()) {
+            (Some((i, _)), None) => Ok(Some(i)),
+            (Some(_), Some(_)) => match table {
+                Some(table) => Err(anyhow!("Ambiguous column name: {}.{}", table, name)),
+                None => Err(anyhow!("Ambiguous column name: {}", name)),
+            },
+            (None, _) => Ok(None),
+        }
+    }
+}
+
+/// Values laid out as described by a schema, such as one combined row of a join.
+pub struct Tuple<'a> {
+    pub schema: &'a Schema,
+    pub values: &'a [Value],
+}
+impl Scope for Tuple<'_> {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<Value> {
+        let i = self.schema.resolve(table, name).ok()?;
+        self.values.get(i).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(alias: &str) -> Schema {
+        TableDefinition::default().schema(alias)
+    }
+
+    #[test]
+    fn test_resolve() {
+        let schema = table("a").join(&table("b"));
+        assert_eq!(schema.resolve(Some("a"), "email").unwrap(), 2);
+        assert_eq!(schema.resolve(Some("b"), "id").unwrap(), 3);
+
+        let err = schema.resolve(None, "id").unwrap_err();
+        assert_eq!(err.to_string(), "Ambiguous column name: id");
+        let err = schema.resolve(Some("c"), "id").unwrap_err();
+        assert_eq!(err.to_string(), "No such column: c.id");
+        let err = schema.resolve(Some("a"), "age").unwrap_err();
+        assert_eq!(err.to_string(), "No such column: a.age");
+
+        assert_eq!(table("users").resolve(None, "username").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_tuple() {
+        let schema = table("a").join(&table("b"));
+        let values: Vec<Value> = (0..6).map(Value::Integer).collect();
+        let tuple = Tuple {
+            schema: &schema,
+            values: &values,
+        };
+        assert_eq!(tuple.column(Some("b"), "id"), Some(Value::Integer(3)));
+        assert_eq!(tuple.column(None, "id"), None);
+    }
+
+    #[test]
+    fn test_table_definition() {
+        let columns = ["id", "name", "email"].map(String::from);
+        let table = TableDefinition::new("people", &columns).unwrap();
+        assert_eq!(table.position("name").unwrap(), 1);
+        let err = table.position("username").unwrap_err();
+        assert_eq!(err.to_string(), "No such column: username");
+        assert_eq!(
+            table.definition(),
+            "CREATE TABLE people (id INTEGER, name TEXT, email TEXT)"
+        );
+        let table = TableDefinition::new("my people", &columns).unwrap();
+        assert!(table
+            .definition()
+            .starts_with("CREATE TABLE \"my people\" ("));
+
+        let err = TableDefinition::new("people", &columns[..2]).unwrap_err();
+        assert_eq!(err.to_string(), "Table people must have 3 columns");
+        let columns = ["id", "name", "name"].map(String::from);
+        let err = TableDefinition::new("people", &columns).unwrap_err();
+        assert_eq!(err.to_string(), "Duplicate column name: name");
+    }
+}
@@ -0,0 +1,713 @@
+use crate::types::expression::Scope;
+use crate::types::tokenizer::quote_identifier;
+use crate::types::{Expression, ResultSet, Schema, Sorter, Statistics, Value};
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// One entry of a SELECT list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResultColumn {
+    /// `*`, every column of the joined tables in declaration order.
+    All,
+    Expression {
+        expression: Expression,
+        alias: Option<String>,
+    },
+}
+
+/// One `expression [ASC|DESC]` term of an ORDER BY clause.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderingTerm {
+    pub expression: Expression,
+    pub descending: bool,
+}
+
+/// Where the rows of a table named in a FROM clause come from, decided by the parser.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableSource {
+    /// The table of the database, with the names its columns had when the query was parsed.
+    Table(Vec<String>),
+    /// A common table expression of an enclosing WITH clause, with its column names.
+    CommonTable(Vec<String>),
+    /// The statistics collected by ANALYZE, see `Statistics::table_rows`.
+    Statistics,
+    /// A single row without columns, read by a query of a WITH clause that has no FROM
+    /// clause, as in `WITH one(n) AS (SELECT 1)`.
+    SingleRow,
+}
+
+/// A table named in a FROM clause, as in `users` or `users AS u`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableReference {
+    pub name: String,
+    pub alias: Option<String>,
+    pub source: TableSource,
+}
+impl TableReference {
+    /// The name columns of this table are qualified with: the alias if there is one.
+    pub fn alias(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn schema(&self) -> Schema {
+        match &self.source {
+            TableSource::Table(columns) => Schema::with_columns(self.alias(), columns),
+            TableSource::CommonTable(columns) => Schema::with_columns(self.alias(), columns),
+            TableSource::Statistics => Schema::with_columns(self.alias(), &Statistics::COLUMNS),
+            TableSource::SingleRow => Schema::default(),
+        }
+    }
+}
+impl fmt::Display for TableReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.alias {
+            Some(alias) => write!(
+                f,
+                "{} AS {}",
+                quote_identifier(&self.name),
+                quote_identifier(alias)
+            ),
+            None => write!(f, "{}", quote_identifier(&self.name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinOperator {
+    Inner,
+    /// Keeps every row of the left side, padding it with NULLs when no row of the joined
+    /// table satisfies the constraint.
+    Left,
+    Cross,
+}
+
+/// One `JOIN table [ON expression]` of a FROM clause.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Join {
+    pub operator: JoinOperator,
+    pub table: TableReference,
+    pub constraint: Option<Expression>,
+}
+
+/// The `UNION [ALL] select` ending the definition of a common table expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Union {
+    /// Whether duplicate rows are kept rather than removed.
+    pub all: bool,
+    pub select: Select,
+}
+
+/// One `name [(column, ...)] AS (select [UNION [ALL] select])` of a WITH clause. Its rows
+/// are computed before the query that defines it runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommonTableExpression {
+    pub name: String,
+    /// The column names listed after the name, or else the names of the result columns of
+    /// `select`.
+    pub columns: Vec<String>,
+    pub select: Select,
+    pub union: Option<Union>,
+    /// Whether the select of `union` reads the expression itself. It then runs repeatedly
+    /// against the rows the previous run added, until a run adds none.
+    pub recursive: bool,
+}
+
+/// A parsed `[WITH [RECURSIVE] common_table, ...] SELECT result_column, ... [FROM table
+/// [join, ...]] [WHERE expression] [GROUP BY expression, ... [HAVING expression]] [ORDER BY
+/// term, ...] [LIMIT n [OFFSET m]]` statement. Without a FROM clause the query reads the
+/// table on its own, except in a WITH clause, where it reads a single row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    pub with: Vec<CommonTableExpression>,
+    pub columns: Vec<ResultColumn>,
+    pub from: TableReference,
+    pub joins: Vec<Join>,
+    pub where_clause: Option<Expression>,
+    pub group_by: Vec<Expression>,
+    pub having: Option<Expression>,
+    pub order_by: Vec<OrderingTerm>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+impl Select {
+    /// Returns an error naming the first unknown table or column. A join constraint may only
+    /// refer to the tables joined so far. `outer` holds the schemas of the queries this one
+    /// is a subquery of, innermost first, whose columns the WHERE clause, the SELECT list,
+    /// HAVING and ORDER BY may refer to. Join constraints, GROUP BY and aggregate arguments
+    /// are evaluated while the rows are read and grouped, before any enclosing row is known,
+    /// so they may refer neither to outer columns nor to subqueries.
+    pub fn check_columns(&self, outer: &[&Schema]) -> Result<()> {
+        for common_table in &self.with {
+            common_table.check_columns(outer)?;
+        }
+        let mut aliases = Vec::new();
+        for table in std::iter::once(&self.from).chain(self.joins.iter().map(|join| &join.table)) {
+            if aliases.contains(&table.alias()) {
+                return Err(anyhow!("Duplicate table alias: {}", table.alias()));
+            }
+            aliases.push(table.alias());
+        }
+        let schemas = self.schemas();
+        for (join, schema) in self.joins.iter().zip(&schemas[1..]) {
+            if let Some(constraint) = &join.constraint {
+                if constraint.contains_subquery() {
+                    return Err(anyhow!("Subqueries are not allowed in ON"));
+                }
+                constraint.check_columns(&[schema])?;
+            }
+        }
+        let schema = &schemas[schemas.len() - 1];
+        for expression in &self.group_by {
+            if expression.contains_subquery() {
+                return Err(anyhow!("Subqueries are not allowed in GROUP BY"));
+            }
+            expression.check_columns(&[schema])?;
+        }
+        for aggregate in self.aggregates() {
+            if aggregate.contains_subquery() {
+                return Err(anyhow!("Subqueries are not allowed in aggregate functions"));
+            }
+            aggregate.check_columns(&[schema])?;
+        }
+        let scopes: Vec<&Schema> = std::iter::once(schema)
+            .chain(outer.iter().copied())
+            .collect();
+        for expression in self.expressions().chain(&self.where_clause) {
+            expression.check_columns(&scopes)?;
+        }
+        Ok(())
+    }
+
+    /// Schemas of the rows produced as the FROM clause is evaluated: the first table alone,
+    /// then combined with each joined table in turn.
+    pub fn schemas(&self) -> Vec<Schema> {
+        let mut schemas = vec![self.from.schema()];
+        for join in &self.joins {
+            let schema = schemas[schemas.len() - 1].join(&join.table.schema());
+            schemas.push(schema);
+        }
+        schemas
+    }
+
+    /// Schema of the rows the FROM clause produces.
+    pub fn schema(&self) -> Schema {
+        self.schemas().pop().unwrap_or_default()
+    }
+
+    // Expressions evaluated after grouping: the SELECT list, GROUP BY, HAVING and ORDER BY.
+    fn expressions(&self) -> impl Iterator<Item = &Expression> {
+        let columns = self.columns.iter().filter_map(|column| match column {
+            ResultColumn::All => None,
+            ResultColumn::Expression { expression, .. } => Some(expression),
+        });
+        columns
+            .chain(&self.group_by)
+            .chain(&self.having)
+            .chain(self.order_by.iter().map(|term| &term.expression))
+    }
+
+    /// The distinct aggregate calls of the query, in order of appearance.
+    pub fn aggregates(&self) -> Vec<Expression> {
+        let mut aggregates = Vec::new();
+        for expression in self.expressions() {
+            expression.collect_aggregates(&mut aggregates);
+        }
+        aggregates
+    }
+
+    /// The distinct window function calls of the query, in order of appearance.
+    pub fn windows(&self) -> Vec<Expression> {
+        let mut windows = Vec::new();
+        for expression in self.expressions() {
+            expression.collect_windows(&mut windows);
+        }
+        windows
+    }
+
+    /// The subqueries of the query outside of its FROM clause, in order of appearance.
+    pub fn subqueries(&self) -> Vec<&Select> {
+        let mut subqueries = Vec::new();
+        for expression in self.where_clause.iter().chain(self.expressions()) {
+            expression.collect_subqueries(&mut subqueries);
+        }
+        subqueries
+    }
+
+    /// Whether rows are combined into groups, either by GROUP BY or by an aggregate call.
+    pub fn is_aggregate(&self) -> bool {
+        !self.group_by.is_empty() || !self.aggregates().is_empty()
+    }
+
+    /// Names of the result columns: the alias if there is one, otherwise the expression as
+    /// it would be written in SQL.
+    pub fn column_names(&self) -> Vec<String> {
+        let schema = self.schema();
+        let mut names = Vec::new();
+        for column in &self.columns {
+            match column {
+                ResultColumn::All => names.extend(schema.columns.iter().map(|c| c.name.clone())),
+                ResultColumn::Expression {
+                    alias: Some(alias), ..
+                } => names.push(alias.clone()),
+                ResultColumn::Expression { expression, .. } => names.push(expression.to_string()),
+            }
+        }
+        names
+    }
+
+    /// Whether a row passes the WHERE clause.
+    pub fn matches(&self, row: &dyn Scope) -> Result<bool> {
+        match &self.where_clause {
+            Some(condition) => condition.evaluate(row)?.is_truthy(),
+            None => Ok(true),
+        }
+    }
+
+    /// Whether a group passes the HAVING clause.
+    pub fn having_matches(&self, group: &dyn Scope) -> Result<bool> {
+        match &self.having {
+            Some(condition) => condition.evaluate(group)?.is_truthy(),
+            None => Ok(true),
+        }
+    }
+
+    /// Evaluates the ORDER BY terms against a row or group.
+    pub fn sort_keys(&self, scope: &dyn Scope) -> Result<Vec<Value>> {
+        self.order_by
+            .iter()
+            .map(|term| term.expression.evaluate(scope))
+            .collect()
+    }
+
+    /// Evaluates the SELECT list against a row or group whose columns are described by
+    /// `schema`.
+    pub fn project(&self, schema: &Schema, scope: &dyn Scope) -> Result<Vec<Value>> {
+        let mut values = Vec::with_capacity(self.columns.len());
+        for column in &self.columns {
+            match column {
+                ResultColumn::All => {
+                    for column in &schema.columns {
+                        values.push(
+                            scope
+                                .column(column.table.as_deref(), &column.name)
+                                .ok_or_else(|| anyhow!("No such column: {}", column.name))?,
+                        );
+                    }
+                }
+                ResultColumn::Expression { expression, .. } => {
+                    values.push(expression.evaluate(scope)?)
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl CommonTableExpression {
+    /// Its column names separated by commas, as written after its name.
+    pub fn column_list(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|c| quote_identifier(c)).collect();
+        columns.join(", ")
+    }
+
+    /// The CREATE VIEW statement that defines a view kept as this common table expression,
+    /// as stored in the `Catalog`. The views its select reads are part of its WITH clause, so
+    /// it does not depend on them.
+    pub fn view_definition(&self) -> String {
+        format!(
+            "CREATE VIEW {}({}) AS {}",
+            quote_identifier(&self.name),
+            self.column_list(),
+            self.select
+        )
+    }
+
+    /// Its selects are checked like subqueries of the query that defines it.
+    pub fn check_columns(&self, outer: &[&Schema]) -> Result<()> {
+        self.select.check_columns(outer)?;
+        let num_columns = self.select.column_names().len();
+        if num_columns != self.columns.len() {
+            return Err(anyhow!(
+                "Table {} has {} values for {} columns",
+                self.name,
+                num_columns,
+                self.columns.len()
+            ));
+        }
+        if let Some(union) = &self.union {
+            union.select.check_columns(outer)?;
+            if union.select.column_names().len() != num_columns {
+                return Err(anyhow!(
+                    "The selects of the UNION in {} return different numbers of columns",
+                    self.name
+                ));
+            }
+            if self.recursive && union.select.is_aggregate() {
+                return Err(anyhow!(
+                    "Aggregate functions are not allowed in the recursive select of {}",
+                    self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the query back as SQL, with its FROM clause spelled out.
+impl fmt::Display for Select {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.with.is_empty() {
+            let recursive = self.with.iter().any(|common_table| common_table.recursive);
+            write!(f, "WITH {}", if recursive { "RECURSIVE " } else { "" })?;
+            for (i, common_table) in self.with.iter().enumerate() {
+                let separator = if i == 0 { "" } else { ", " };
+                write!(
+                    f,
+                    "{}{}({}) AS ({}",
+                    separator,
+                    quote_identifier(&common_table.name),
+                    common_table.column_list(),
+                    common_table.select
+                )?;
+                match &common_table.union {
+                    Some(union) if union.all => write!(f, " UNION ALL {})", union.select)?,
+                    Some(union) => write!(f, " UNION {})", union.select)?,
+                    None => write!(f, ")")?,
+                }
+            }
+            write!(f, " ")?;
+        }
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                ResultColumn::All => "*".to_string(),
+                ResultColumn::Expression {
+                    expression,
+                    alias: Some(alias),
+                } => format!("{} AS {}", expression, quote_identifier(alias)),
+                ResultColumn::Expression { expression, .. } => expression.to_string(),
+            })
+            .collect();
+        write!(f, "SELECT {}", columns.join(", "))?;
+        if self.from.source != TableSource::SingleRow {
+            write!(f, " FROM {}", self.from)?;
+        }
+        for join in &self.joins {
+            match join.operator {
+                JoinOperator::Inner => write!(f, " JOIN {}", join.table)?,
+                JoinOperator::Left => write!(f, " LEFT JOIN {}", join.table)?,
+                JoinOperator::Cross => write!(f, " CROSS JOIN {}", join.table)?,
+            }
+            if let Some(constraint) = &join.constraint {
+                write!(f, " ON {}", constraint)?;
+            }
+        }
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {}", where_clause)?;
+        }
+        if !self.group_by.is_empty() {
+            let terms: Vec<String> = self.group_by.iter().map(|e| e.to_string()).collect();
+            write!(f, " GROUP BY {}", terms.join(", "))?;
+        }
+        if let Some(having) = &self.having {
+            write!(f, " HAVING {}", having)?;
+        }
+        if !self.order_by.is_empty() {
+            let terms: Vec<String> = self
+                .order_by
+                .iter()
+                .map(|term| match term.descending {
+                    true => format!("{} DESC", term.expression),
+                    false => term.expression.to_string(),
+                })
+                .collect();
+            write!(f, " ORDER BY {}", terms.join(", "))?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        if self.offset > 0 {
+            write!(f, " OFFSET {}", self.offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the result set of a select from its output rows, applying ORDER BY, OFFSET and
+/// LIMIT.
+pub struct SelectOutput<'a> {
+    select: &'a Select,
+    schema: Schema,
+    result_set: ResultSet,
+    sorter: Option<Sorter>,
+    skipped: usize,
+}
+impl<'a> SelectOutput<'a> {
+    /// `memory_budget` bounds the rows an ORDER BY sorts in memory, see `Sorter`. When
+    /// `presorted` the rows are pushed in ORDER BY order already and are not sorted again.
+    pub fn new(select: &'a Select, memory_budget: usize, presorted: bool) -> SelectOutput<'a> {
+        let sorter = if select.order_by.is_empty() || presorted {
+            None
+        } else {
+            let descending = select.order_by.iter().map(|term| term.descending).collect();
+            Some(Sorter::new(descending, memory_budget))
+        };
+        SelectOutput {
+            select,
+            schema: select.schema(),
+            result_set: ResultSet::new(select.column_names()),
+            sorter,
+            skipped: 0,
+        }
+    }
+
+    /// Adds the output row of a row or group that passed the WHERE and HAVING clauses.
+    /// Returns false once the result is complete, so the caller can stop producing rows.
+    /// With ORDER BY that is only known after every row was seen.
+    pub fn push(&mut self, scope: &dyn Scope) -> Result<bool> {
+        if let Some(sorter) = &mut self.sorter {
+            let values = self.select.project(&self.schema, scope)?;
+            sorter.push(self.select.sort_keys(scope)?, values)?;
+            return Ok(true);
+        }
+        let limit = self.select.limit.unwrap_or(usize::MAX);
+        if self.result_set.rows.len() >= limit {
+            return Ok(false);
+        }
+        if self.skipped < self.select.offset {
+            self.skipped += 1;
+        } else {
+            self.result_set
+                .rows
+                .push(self.select.project(&self.schema, scope)?);
+        }
+        Ok(self.result_set.rows.len() < limit)
+    }
+
+    pub fn finish(mut self) -> Result<ResultSet> {
+        if let Some(sorter) = self.sorter {
+            let limit = self.select.limit.unwrap_or(usize::MAX);
+            for values in sorter.finish()?.skip(self.select.offset).take(limit) {
+                self.result_set.rows.push(values?);
+            }
+        }
+        Ok(self.result_set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Parser, Row, Tuple};
+    use heapless::String as HeapLessString;
+
+    fn select(input: &str) -> Select {
+        Parser::new(input).unwrap().parse_select().unwrap()
+    }
+
+    // Parses and checks `input`, as unknown tables are found by the parser.
+    fn check(input: &str) -> Result<()> {
+        Parser::new(input)?.parse_select()?.check_columns(&[])
+    }
+
+    #[test]
+    fn test_column_names() {
+        let select = select("select id, id * 2 as double, *, -(id + 1), username = 'bob'");
+        assert_eq!(
+            select.column_names(),
+            vec![
+                "id",
+                "double",
+                "id",
+                "username",
+                "email",
+                "-(id + 1)",
+                "username = 'bob'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_project() {
+        let row = Row {
+            id: 3,
+            username: HeapLessString::try_from("carol").unwrap(),
+            email: HeapLessString::try_from("carol@example.com").unwrap(),
+        };
+        let select = select("select email, id % 2 as odd, * where id > 1");
+        let schema = select.schema();
+        let values = row.values();
+        let row = Tuple {
+            schema: &schema,
+            values: &values,
+        };
+        assert!(select.matches(&row).unwrap());
+        assert_eq!(
+            select.project(&schema, &row).unwrap(),
+            vec![
+                Value::Text("carol@example.com".to_string()),
+                Value::Integer(1),
+                Value::Integer(3),
+                Value::Text("carol".to_string()),
+                Value::Text("carol@example.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_columns() {
+        let err = select("select id, age").check_columns(&[]).unwrap_err();
+        assert_eq!(err.to_string(), "No such column: age");
+        assert!(select("select * where id = 1").check_columns(&[]).is_ok());
+        assert!(select("select users.id from users where users.id = 1")
+            .check_columns(&[])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_check_join_columns() {
+        let cases = [
+            ("select * from orders", "No such table: orders"),
+            (
+                "select from users u join users u",
+                "Duplicate table alias: u",
+            ),
+            (
+                "select from users join users",
+                "Duplicate table alias: users",
+            ),
+            (
+                "select id from users a join users b on a.id = b.id",
+                "Ambiguous column name: id",
+            ),
+            (
+                "select from users a join users b on a.id = c.id join users c",
+                "No such column: c.id",
+            ),
+            ("select users.id from users u", "No such column: users.id"),
+        ];
+        for (input, expected) in cases {
+            let err = check(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+        assert!(
+            select("select a.id, b.id from users a left join users as b on a.id < b.id")
+                .check_columns(&[])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_check_subquery_columns() {
+        let correlated = [
+            "select * from users a where exists (select from users b where b.id = a.id)",
+            "select id, (select max(b.id) from users b where b.id < a.id) from users a",
+            "select * from users a where id in (select id from users b where b.id != a.id)",
+        ];
+        for input in correlated {
+            assert!(select(input).check_columns(&[]).is_ok(), "{}", input);
+        }
+
+        let cases = [
+            (
+                "select (select id, username)",
+                "Subquery returns 2 columns but must return 1",
+            ),
+            (
+                "select where id in (select *)",
+                "Subquery returns 3 columns but must return 1",
+            ),
+            (
+                "select * from users a where exists (select from users b where b.id = c.id)",
+                "No such column: c.id",
+            ),
+            (
+                "select from users a join users b on b.id in (select id)",
+                "Subqueries are not allowed in ON",
+            ),
+            (
+                "select count(*) group by (select 1)",
+                "Subqueries are not allowed in GROUP BY",
+            ),
+            (
+                "select sum((select 1))",
+                "Subqueries are not allowed in aggregate functions",
+            ),
+            (
+                "select * from users a where exists (select sum(a.id) from users b)",
+                "No such column: a.id",
+            ),
+        ];
+        for (input, expected) in cases {
+            let err = check(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_check_common_tables() {
+        assert!(select(
+            "with recursive t(n) as (select 1 union all select n + 1 from t where n < 9) \
+             select t.n, users.id from t join users on users.id = t.n"
+        )
+        .check_columns(&[])
+        .is_ok());
+
+        let cases = [
+            (
+                "with t(a, b) as (select id from users) select * from t",
+                "Table t has 1 values for 2 columns",
+            ),
+            (
+                "with t as (select id from users union select id, email from users) select * from t",
+                "The selects of the UNION in t return different numbers of columns",
+            ),
+            (
+                "with recursive t as (select 1 union select count(*) from t) select * from t",
+                "Aggregate functions are not allowed in the recursive select of t",
+            ),
+            (
+                "with t as (select id from users) select username from t",
+                "No such column: username",
+            ),
+            ("with t as (select id from t) select 1", "No such table: t"),
+        ];
+        for (input, expected) in cases {
+            let err = check(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_join_column_names() {
+        let joined = select("select a.username, * from users a, users b");
+        assert_eq!(
+            joined.column_names(),
+            vec![
+                "a.username",
+                "id",
+                "username",
+                "email",
+                "id",
+                "username",
+                "email"
+            ]
+        );
+        assert_eq!(joined.schema().columns.len(), 6);
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let grouped = select(
+            "select username, count(*), sum(id) + 1 group by username \
+             having count(*) > 1 order by sum(id)",
+        );
+        assert!(grouped.is_aggregate());
+        let names: Vec<String> = grouped.aggregates().iter().map(|a| a.to_string()).collect();
+        assert_eq!(names, vec!["count(*)", "sum(id)"]);
+
+        assert!(select("select username group by username").is_aggregate());
+        assert!(!select("select username order by id").is_aggregate());
+    }
+}
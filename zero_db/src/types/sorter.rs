@@ -0,0 +1,258 @@
+use crate::types::Value;
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// A row waiting to be sorted: the values of the ORDER BY terms and the row itself.
+struct SortEntry {
+    keys: Vec<Value>,
+    values: Vec<Value>,
+}
+
+/// A sorted run spilled to an anonymous temporary file.
+struct Run {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+/// Sorts rows by their keys, buffering them in memory until the buffer exceeds the memory
+/// budget. Each full buffer is sorted and written to a temporary file as a run, and the runs
+/// are merged when the sorted rows are read back. Rows with equal keys keep their input order.
+pub struct Sorter {
+    descending: Vec<bool>,
+    memory_budget: usize,
+    buffer: Vec<SortEntry>,
+    buffered_bytes: usize,
+    runs: Vec<Run>,
+}
+impl Sorter {
+    pub const DEFAULT_MEMORY_BUDGET: usize = 16 << 20;
+
+    /// Creates a sorter for keys compared in order, with `descending[i]` reversing the
+    /// order of the i-th key.
+    pub fn new(descending: Vec<bool>, memory_budget: usize) -> Sorter {
+        Sorter {
+            descending,
+            memory_budget,
+            buffer: Vec::new(),
+            buffered_bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, keys: Vec<Value>, values: Vec<Value>) -> Result<()> {
+        self.buffered_bytes += keys
+            .iter()
+            .chain(&values)
+            .map(Value::memory_size)
+            .sum::<usize>();
+        self.buffer.push(SortEntry { keys, values });
+        if self.buffered_bytes > self.memory_budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the rows in sorted order. Without spilled runs the buffer is sorted in memory,
+    /// otherwise it is spilled as a last run and the runs are merged.
+    pub fn finish(mut self) -> Result<SortedRows> {
+        if self.runs.is_empty() {
+            self.sort_buffer();
+            return Ok(SortedRows {
+                descending: self.descending,
+                source: Source::Memory(self.buffer.into_iter()),
+            });
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        let heads = self
+            .runs
+            .iter_mut()
+            .map(|run| Self::read_entry(run, self.descending.len()))
+            .collect::<Result<_>>()?;
+        Ok(SortedRows {
+            descending: self.descending,
+            source: Source::Runs {
+                runs: self.runs,
+                heads,
+            },
+        })
+    }
+
+    fn sort_buffer(&mut self) {
+        let descending = &self.descending;
+        self.buffer
+            .sort_by(|a, b| compare_keys(descending, &a.keys, &b.keys));
+    }
+
+    // Each entry is written as its keys, the number of values and the values.
+    fn spill(&mut self) -> Result<()> {
+        self.sort_buffer();
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        let mut buf = Vec::new();
+        for entry in &self.buffer {
+            buf.clear();
+            for value in &entry.keys {
+                value.serialize(&mut buf);
+            }
+            buf.extend_from_slice(&(entry.values.len() as u32).to_le_bytes());
+            for value in &entry.values {
+                value.serialize(&mut buf);
+            }
+            writer.write_all(&buf)?;
+        }
+        let mut file = writer.into_inner()?;
+        file.seek(SeekFrom::Start(0))?;
+        self.runs.push(Run {
+            reader: BufReader::new(file),
+            remaining: self.buffer.len(),
+        });
+        self.buffer.clear();
+        self.buffered_bytes = 0;
+        Ok(())
+    }
+
+    fn read_entry(run: &mut Run, num_keys: usize) -> Result<Option<SortEntry>> {
+        if run.remaining == 0 {
+            return Ok(None);
+        }
+        run.remaining -= 1;
+        let keys = (0..num_keys)
+            .map(|_| Value::deserialize(&mut run.reader))
+            .collect::<Result<_>>()?;
+        let mut len = [0; 4];
+        run.reader.read_exact(&mut len)?;
+        let values = (0..u32::from_le_bytes(len))
+            .map(|_| Value::deserialize(&mut run.reader))
+            .collect::<Result<_>>()?;
+        Ok(Some(SortEntry { keys, values }))
+    }
+}
+
+fn compare_keys(descending: &[bool], a: &[Value], b: &[Value]) -> Ordering {
+    for ((a, b), descending) in a.iter().zip(b).zip(descending) {
+        let ordering = a.compare(b);
+        if ordering != Ordering::Equal {
+            return if *descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+        }
+    }
+    Ordering::Equal
+}
+
+enum Source {
+    Memory(std::vec::IntoIter<SortEntry>),
+    Runs {
+        runs: Vec<Run>,
+        heads: Vec<Option<SortEntry>>,
+    },
+}
+
+/// Iterator over the rows of a finished `Sorter`.
+pub struct SortedRows {
+    descending: Vec<bool>,
+    source: Source,
+}
+impl Iterator for SortedRows {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            Source::Memory(entries) => entries.next().map(|entry| Ok(entry.values)),
+            Source::Runs { runs, heads } => {
+                // The runs are few, so the smallest head is found by a linear scan. Ties go
+                // to the earliest run, which holds the earliest input rows.
+                let mut smallest: Option<usize> = None;
+                for (i, head) in heads.iter().enumerate() {
+                    let Some(head) = head else { continue };
+                    let is_smaller = match smallest.and_then(|j| heads[j].as_ref()) {
+                        Some(current) => {
+                            compare_keys(&self.descending, &head.keys, &current.keys)
+                                == Ordering::Less
+                        }
+                        None => true,
+                    };
+                    if is_smaller {
+                        smallest = Some(i);
+                    }
+                }
+                let i = smallest?;
+                let entry = heads[i].take()?;
+                match Sorter::read_entry(&mut runs[i], self.descending.len()) {
+                    Ok(next) => heads[i] = next,
+                    Err(err) => return Some(Err(err)),
+                }
+                Some(Ok(entry.values))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(rows: &[(i64, &str)], descending: Vec<bool>, memory_budget: usize) -> Vec<i64> {
+        let mut sorter = Sorter::new(descending, memory_budget);
+        for (id, name) in rows {
+            let keys = vec![Value::Text(name.to_string()), Value::Integer(*id)];
+            sorter.push(keys, vec![Value::Integer(*id)]).unwrap();
+        }
+        sorter
+            .finish()
+            .unwrap()
+            .map(|values| match values.unwrap()[..] {
+                [Value::Integer(id)] => id,
+                ref values => panic!("Unexpected values {:?}", values),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_in_memory() {
+        let rows = [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice")];
+        assert_eq!(sort(&rows, vec![false, false], 1 << 20), vec![2, 4, 3, 1]);
+        assert_eq!(sort(&rows, vec![false, true], 1 << 20), vec![4, 2, 3, 1]);
+        assert_eq!(sort(&rows, vec![true, false], 1 << 20), vec![1, 3, 2, 4]);
+    }
+
+    #[test]
+    fn test_sort_spills_runs() {
+        let names = ["dave", "alice", "carol", "bob", "erin"];
+        let rows: Vec<(i64, &str)> = (0..1000).map(|i| (i, names[i as usize % 5])).collect();
+
+        let mut sorter = Sorter::new(vec![false], 1024);
+        for (id, name) in &rows {
+            let keys = vec![Value::Text(name.to_string())];
+            sorter.push(keys, vec![Value::Integer(*id)]).unwrap();
+        }
+        assert!(sorter.runs.len() > 1);
+
+        // Equal names keep the input order, so the ids of each name come out ascending.
+        let mut expected: Vec<(i64, &str)> = rows.clone();
+        expected.sort_by_key(|(_, name)| *name);
+        let expected: Vec<i64> = expected.iter().map(|(id, _)| *id).collect();
+        assert_eq!(sort(&rows, vec![false, false], 1 << 20), expected);
+        assert_eq!(sort(&rows, vec![false, false], 1024), expected);
+
+        let sorted: Vec<Vec<Value>> = sorter.finish().unwrap().map(Result::unwrap).collect();
+        let ids: Vec<i64> = sorted
+            .iter()
+            .map(|values| match values[0] {
+                Value::Integer(id) => id,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_sort_empty() {
+        assert!(sort(&[], vec![false], 0).is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::types::{PrepareResult, Row, StatementType};
+use crate::types::{
+    ColumnDefault, CommonTableExpression, Parser, PrepareResult, Row, Select, StatementType,
+    TableDefinition, Value,
+};
 use anyhow::{anyhow, Result};
 use heapless::String as HeapLessString;
 
@@ -6,54 +9,252 @@ use heapless::String as HeapLessString;
 pub struct Statement {
     pub(crate) statement_type: StatementType,
     pub(crate) row_to_insert: Option<Row>,
+    pub(crate) select: Option<Select>,
 }
 impl Statement {
-    pub fn prepare(input: &str) -> PrepareResult {
-        if input.starts_with("insert") {
-            match Statement::parse_insert_input(input) {
-                Ok(row) => PrepareResult::Success(Statement {
+    /// Prepares `input`, which refers to the table and its columns by the names of `table`,
+    /// whose queries can read `views`, the views of the database, like tables, and whose
+    /// inserts can leave out the last columns when they have one of `defaults`.
+    pub fn prepare(
+        input: &str,
+        table: &TableDefinition,
+        views: &[CommonTableExpression],
+        defaults: &[ColumnDefault],
+    ) -> PrepareResult {
+        let keyword = input.split_whitespace().next().unwrap_or_default();
+        if keyword.eq_ignore_ascii_case("insert") {
+            match Statement::parse_insert_input(input, table, defaults) {
+                Ok(row) => PrepareResult::Success(Box::new(Statement {
                     statement_type: StatementType::Insert,
                     row_to_insert: Some(row),
-                }),
+                    select: None,
+                })),
                 Err(err) => PrepareResult::SyntaxError(err.to_string()),
             }
-        } else if input.starts_with("select") {
-            PrepareResult::Success(Statement {
-                statement_type: StatementType::Select,
-                row_to_insert: None,
-            })
+        } else if keyword.eq_ignore_ascii_case("select") || keyword.eq_ignore_ascii_case("with")
+        {
+            match Statement::parse_select_input(input, table, views) {
+                Ok(select) => PrepareResult::Success(Box::new(Statement {
+                    statement_type: StatementType::Select,
+                    row_to_insert: None,
+                    select: Some(select),
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("pragma") {
+            match Statement::parse_pragma_input(input) {
+                Ok(statement_type) => PrepareResult::Success(Box::new(Statement {
+                    statement_type,
+                    row_to_insert: None,
+                    select: None,
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("analyze") {
+            match Statement::parse_analyze_input(input, table) {
+                Ok(statement_type) => PrepareResult::Success(Box::new(Statement {
+                    statement_type,
+                    row_to_insert: None,
+                    select: None,
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("create") {
+            let statement_type = Parser::new(input)
+                .and_then(|parser| parser.with_table(table).with_views(views).parse_create())
+                .and_then(|statement_type| {
+                    if let StatementType::CreateView(view) = &statement_type {
+                        view.check_columns(&[])?;
+                    }
+                    Ok(statement_type)
+                });
+            match statement_type {
+                Ok(statement_type) => PrepareResult::Success(Box::new(Statement {
+                    statement_type,
+                    row_to_insert: None,
+                    select: None,
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("drop") {
+            match Parser::new(input).and_then(|parser| parser.with_table(table).parse_drop()) {
+                Ok(statement_type) => PrepareResult::Success(Box::new(Statement {
+                    statement_type,
+                    row_to_insert: None,
+                    select: None,
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("delete") {
+            match Parser::new(input).and_then(|parser| parser.with_table(table).parse_delete()) {
+                Ok(statement_type) => PrepareResult::Success(Box::new(Statement {
+                    statement_type,
+                    row_to_insert: None,
+                    select: None,
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("alter") {
+            match Parser::new(input).and_then(|parser| parser.with_table(table).parse_alter()) {
+                Ok(statement_type) => PrepareResult::Success(Box::new(Statement {
+                    statement_type,
+                    row_to_insert: None,
+                    select: None,
+                })),
+                Err(err) => PrepareResult::SyntaxError(err.to_string()),
+            }
+        } else if keyword.eq_ignore_ascii_case("explain") {
+            Statement::prepare_explain(input, table, views, defaults)
         } else {
             PrepareResult::UnrecognizedStatement
         }
     }
 
-    pub fn parse_insert_input(input: &str) -> Result<Row> {
+    // `explain [query plan] statement` wraps the prepared statement, whose type it keeps.
+    fn prepare_explain(
+        input: &str,
+        table: &TableDefinition,
+        views: &[CommonTableExpression],
+        defaults: &[ColumnDefault],
+    ) -> PrepareResult {
+        let input = Self::strip_keyword(input, "explain").unwrap_or_default();
+        let (input, query_plan) = match Self::strip_keyword(input, "query")
+            .and_then(|rest| Self::strip_keyword(rest, "plan"))
+        {
+            Some(rest) => (rest, true),
+            None => (input, false),
+        };
+        if input.is_empty() {
+            return PrepareResult::SyntaxError("No statement to explain".to_string());
+        }
+        let statement = match Statement::prepare(input, table, views, defaults) {
+            PrepareResult::Success(statement) => statement,
+            result => return result,
+        };
+        if query_plan && statement.statement_type != StatementType::Select {
+            return PrepareResult::SyntaxError("EXPLAIN QUERY PLAN requires a SELECT".to_string());
+        }
+        PrepareResult::Success(Box::new(Statement {
+            statement_type: StatementType::Explain {
+                statement_type: Box::new(statement.statement_type),
+                query_plan,
+            },
+            row_to_insert: statement.row_to_insert,
+            select: statement.select,
+        }))
+    }
+
+    // The rest of `input` after its first word if that is `keyword`, in any case.
+    fn strip_keyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+        let input = input.trim_start();
+        let end = input.find(char::is_whitespace).unwrap_or(input.len());
+        input[..end]
+            .eq_ignore_ascii_case(keyword)
+            .then(|| input[end..].trim_start())
+    }
+
+    // `insert id username email`, where the last values can be left out for the columns
+    // of `table` that have one of `defaults`.
+    pub fn parse_insert_input(
+        input: &str,
+        table: &TableDefinition,
+        defaults: &[ColumnDefault],
+    ) -> Result<Row> {
         let mut parts = input.split_whitespace();
         parts.next(); // Skip the "insert" keyword
+        let default = |position: usize| {
+            defaults
+                .iter()
+                .find(|default| default.column == table.columns[position])
+                .map(|default| default.value.clone())
+        };
+
+        let id = match parts.next() {
+            Some(id) => Value::Integer(
+                id.parse::<i32>().map_err(|_| anyhow!("Id should be a number"))? as i64,
+            ),
+            None => default(0).ok_or_else(|| anyhow!("No id provided"))?,
+        };
+
+        let username = match parts.next() {
+            Some(username) => {
+                HeapLessString::<32>::try_from(username)
+                    .map_err(|_| anyhow!("Input provided for field(Username) length exceeds the configured length: 32"))?;
+                Value::Text(username.to_string())
+            }
+            None => default(1).ok_or_else(|| anyhow!("Username not provided"))?,
+        };
 
-        let id: i32 = parts
-            .next()
-            .ok_or_else(|| anyhow!("No id provided"))?
-            .parse()
-            .map_err(|_| anyhow!("Id should be a number"))?;
-
-        let username = HeapLessString::<32>::try_from(
-            parts
-                .next()
-                .ok_or_else(|| anyhow!("Username not provided"))?,
-        )
-            .map_err(|_| anyhow!("Input provided for field(Username) length exceeds the configured length: 32"))?;
-
-        let email = HeapLessString::<256>::try_from(
-            parts.next().ok_or_else(|| anyhow!("Email not provided"))?,
-        )
-            .map_err(|_| anyhow!("Input provided for field(Email) length exceeds the configured length: 256"))?;
-
-        Ok(Row {
-            id,
-            username,
-            email,
-        })
+        let email = match parts.next() {
+            Some(email) => {
+                HeapLessString::<256>::try_from(email)
+                    .map_err(|_| anyhow!("Input provided for field(Email) length exceeds the configured length: 256"))?;
+                Value::Text(email.to_string())
+            }
+            None => default(2).ok_or_else(|| anyhow!("Email not provided"))?,
+        };
+
+        Row::from_values(&[id, username, email])
+    }
+
+    pub fn parse_select_input(
+        input: &str,
+        table: &TableDefinition,
+        views: &[CommonTableExpression],
+    ) -> Result<Select> {
+        let select = Parser::new(input)?
+            .with_table(table)
+            .with_views(views)
+            .parse_select()?;
+        select.check_columns(&[])?;
+        Ok(select)
+    }
+
+    // `analyze [table]`, where the table can only be the one there is.
+    pub fn parse_analyze_input(input: &str, table: &TableDefinition) -> Result<StatementType> {
+        let mut parts = input.split_whitespace();
+        parts.next(); // Skip the "analyze" keyword
+
+        match (parts.next(), parts.next()) {
+            (None, _) => Ok(StatementType::Analyze),
+            (Some(name), None) if name.eq_ignore_ascii_case(&table.name) => {
+                Ok(StatementType::Analyze)
+            }
+            (Some(name), None) => Err(anyhow!("No such table: {}", name)),
+            (Some(_), Some(extra)) => Err(anyhow!("Unexpected input after table name: {}", extra)),
+        }
+    }
+
+    pub fn parse_pragma_input(input: &str) -> Result<StatementType> {
+        let mut parts = input.split_whitespace();
+        parts.next(); // Skip the "pragma" keyword
+
+        // `pragma name` or `pragma name = value`, with or without spaces around `=`.
+        let pragma = parts.collect::<Vec<_>>().concat();
+        let (name, value) = match pragma.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (pragma.as_str(), None),
+        };
+        match (name, value) {
+            ("", _) => Err(anyhow!("No pragma provided")),
+            ("integrity_check", None) => Ok(StatementType::IntegrityCheck),
+            ("compression_stats", None) => Ok(StatementType::CompressionStats),
+            ("recursion_limit", None) => Ok(StatementType::RecursionLimit(None)),
+            ("recursion_limit", Some(value)) => value
+                .parse()
+                .map(|limit| StatementType::RecursionLimit(Some(limit)))
+                .map_err(|_| anyhow!("recursion_limit requires a non-negative integer")),
+            ("foreign_keys", None) => Ok(StatementType::ForeignKeys(None)),
+            ("foreign_keys", Some(value)) => match value.to_ascii_lowercase().as_str() {
+                "on" | "true" | "1" => Ok(StatementType::ForeignKeys(Some(true))),
+                "off" | "false" | "0" => Ok(StatementType::ForeignKeys(Some(false))),
+                _ => Err(anyhow!("foreign_keys requires on or off")),
+            },
+            ("integrity_check" | "compression_stats", Some(_)) => {
+                Err(anyhow!("Pragma {} cannot be set", name))
+            }
+            _ => Err(anyhow!("Unknown pragma: {}", name)),
+        }
     }
 }
 
@@ -65,7 +266,11 @@ mod tests {
     #[test]
     fn test_parse_insert_input_valid() {
         let input = "insert 42 john john@example.com";
-        let row = Statement::parse_insert_input(input).expect("Parsing should succeed");
+        let row = Statement::parse_insert_input(
+            input,
+            &TableDefinition::default(),
+            &[],
+        ).expect("Parsing should succeed");
 
         assert_eq!(row.id, 42);
         // Assuming HeapLessString implements PartialEq<&str>
@@ -77,7 +282,8 @@ mod tests {
     #[test]
     fn test_parse_insert_input_no_id() {
         let input = "insert";
-        let err = Statement::parse_insert_input(input).unwrap_err();
+        let err =
+            Statement::parse_insert_input(input, &TableDefinition::default(), &[]).unwrap_err();
         assert_eq!(err.to_string(), "No id provided");
     }
 
@@ -85,7 +291,8 @@ mod tests {
     #[test]
     fn test_parse_insert_input_invalid_id() {
         let input = "insert abc john john@example.com";
-        let err = Statement::parse_insert_input(input).unwrap_err();
+        let err =
+            Statement::parse_insert_input(input, &TableDefinition::default(), &[]).unwrap_err();
         assert_eq!(err.to_string(), "Id should be a number");
     }
 
@@ -93,7 +300,8 @@ mod tests {
     #[test]
     fn test_parse_insert_input_no_username() {
         let input = "insert 42";
-        let err = Statement::parse_insert_input(input).unwrap_err();
+        let err =
+            Statement::parse_insert_input(input, &TableDefinition::default(), &[]).unwrap_err();
         assert_eq!(err.to_string(), "Username not provided");
     }
 
@@ -101,7 +309,8 @@ mod tests {
     #[test]
     fn test_parse_insert_input_no_email() {
         let input = "insert 42 john";
-        let err = Statement::parse_insert_input(input).unwrap_err();
+        let err =
+            Statement::parse_insert_input(input, &TableDefinition::default(), &[]).unwrap_err();
         assert_eq!(err.to_string(), "Email not provided");
     }
 
@@ -111,7 +320,8 @@ mod tests {
         // Create a username of 33 characters (exceeds capacity of 32)
         let long_username = "a".repeat(33);
         let input = format!("insert 42 {} john@example.com", long_username);
-        let err = Statement::parse_insert_input(&input).unwrap_err();
+        let err =
+            Statement::parse_insert_input(&input, &TableDefinition::default(), &[]).unwrap_err();
         assert_eq!(
             err.to_string(),
             "Input provided for field(Username) length exceeds the configured length: 32"
@@ -124,7 +334,8 @@ mod tests {
         // Create an email of 257 characters (exceeds capacity of 256)
         let long_email = "a".repeat(257);
         let input = format!("insert 42 john {}", long_email);
-        let err = Statement::parse_insert_input(&input).unwrap_err();
+        let err =
+            Statement::parse_insert_input(&input, &TableDefinition::default(), &[]).unwrap_err();
         assert_eq!(
             err.to_string(),
             "Input provided for field(Email) length exceeds the configured length: 256"
@@ -134,7 +345,7 @@ mod tests {
     fn test_prepare_insert_success() {
         // This input should be parsed successfully.
         let input = "insert 1 alice alice@example.com";
-        let result = Statement::prepare(input);
+        let result = Statement::prepare(input, &TableDefinition::default(), &[], &[]);
 
         if let PrepareResult::Success(statement) = result {
             assert_eq!(statement.statement_type, StatementType::Insert);
@@ -153,7 +364,7 @@ mod tests {
     fn test_prepare_insert_syntax_error() {
         // Missing username and email should trigger a syntax error.
         let input = "insert 1";
-        let result = Statement::prepare(input);
+        let result = Statement::prepare(input, &TableDefinition::default(), &[], &[]);
         match result {
             PrepareResult::SyntaxError(err) => {
                 // The error string may mention the missing field.
@@ -170,7 +381,7 @@ mod tests {
     #[test]
     fn test_prepare_select_success() {
         let input = "select";
-        let result = Statement::prepare(input);
+        let result = Statement::prepare(input, &TableDefinition::default(), &[], &[]);
         match result {
             PrepareResult::Success(statement) => {
                 // For a select statement, we expect StatementType::Select and no row.
@@ -181,10 +392,164 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_prepare_select_where() {
+        let input = "SELECT * WHERE id > 1 AND email != 'bob@example.com'";
+        match Statement::prepare(input, &TableDefinition::default(), &[], &[]) {
+            PrepareResult::Success(statement) => {
+                assert_eq!(statement.statement_type, StatementType::Select);
+                assert!(statement.select.unwrap().where_clause.is_some());
+            }
+            result => panic!("Expected Success for a select command, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_prepare_select_errors() {
+        match Statement::prepare("select where age > 3", &TableDefinition::default(), &[], &[]) {
+            PrepareResult::SyntaxError(err) => assert_eq!(err, "No such column: age"),
+            result => panic!("Expected SyntaxError, got {:?}", result),
+        }
+        match Statement::prepare(
+            "select id,
+            age * 2 as double",
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::SyntaxError(err) => assert_eq!(err, "No such column: age"),
+            result => panic!("Expected SyntaxError, got {:?}", result),
+        }
+        match Statement::prepare("select where id >", &TableDefinition::default(), &[], &[]) {
+            PrepareResult::SyntaxError(err) => assert_eq!(err, "Expected an expression"),
+            result => panic!("Expected SyntaxError, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_prepare_explain() {
+        let explained = |input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => statement.statement_type,
+            result => panic!("Expected Success, got {:?}", result),
+        };
+        assert_eq!(
+            explained("explain insert 1 alice alice@example.com"),
+            StatementType::Explain {
+                statement_type: Box::new(StatementType::Insert),
+                query_plan: false,
+            }
+        );
+        assert_eq!(
+            explained("EXPLAIN  Query Plan select id where id > 1"),
+            StatementType::Explain {
+                statement_type: Box::new(StatementType::Select),
+                query_plan: true,
+            }
+        );
+
+        let cases = [
+            ("explain", "No statement to explain"),
+            ("explain query plan", "No statement to explain"),
+            ("explain query plan pragma integrity_check", "EXPLAIN QUERY PLAN requires a SELECT"),
+            ("explain select where age > 3", "No such column: age"),
+        ];
+        for (input, expected) in cases {
+            match Statement::prepare(input, &TableDefinition::default(), &[], &[]) {
+                PrepareResult::SyntaxError(err) => assert_eq!(err, expected, "{}", input),
+                result => panic!("Expected SyntaxError, got {:?}", result),
+            }
+        }
+        assert!(matches!(
+            Statement::prepare("explain foobar", &TableDefinition::default(), &[], &[]),
+            PrepareResult::UnrecognizedStatement
+        ));
+    }
+
+    #[test]
+    fn test_parse_analyze_input() {
+        for input in ["analyze", "ANALYZE users"] {
+            let statement_type =
+                Statement::parse_analyze_input(input, &TableDefinition::default()).unwrap();
+            assert_eq!(statement_type, StatementType::Analyze);
+        }
+
+        let err = Statement::parse_analyze_input(
+            "analyze orders",
+            &TableDefinition::default(),
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "No such table: orders");
+
+        let err = Statement::parse_analyze_input(
+            "analyze users now",
+            &TableDefinition::default(),
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected input after table name: now");
+    }
+
+    #[test]
+    fn test_parse_pragma_input_integrity_check() {
+        let statement_type = Statement::parse_pragma_input("pragma integrity_check").unwrap();
+        assert_eq!(statement_type, StatementType::IntegrityCheck);
+    }
+
+    #[test]
+    fn test_parse_pragma_input_compression_stats() {
+        let statement_type = Statement::parse_pragma_input("pragma compression_stats").unwrap();
+        assert_eq!(statement_type, StatementType::CompressionStats);
+    }
+
+    #[test]
+    fn test_parse_pragma_input_errors() {
+        let err = Statement::parse_pragma_input("pragma").unwrap_err();
+        assert_eq!(err.to_string(), "No pragma provided");
+
+        let err = Statement::parse_pragma_input("pragma foo").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown pragma: foo");
+
+        let err = Statement::parse_pragma_input("pragma integrity_check = 1").unwrap_err();
+        assert_eq!(err.to_string(), "Pragma integrity_check cannot be set");
+
+        let err = Statement::parse_pragma_input("pragma recursion_limit = -1").unwrap_err();
+        assert_eq!(err.to_string(), "recursion_limit requires a non-negative integer");
+    }
+
+    #[test]
+    fn test_parse_pragma_input_recursion_limit() {
+        let cases = [
+            ("pragma recursion_limit", None),
+            ("pragma recursion_limit = 50", Some(50)),
+            ("pragma recursion_limit=7", Some(7)),
+        ];
+        for (input, expected) in cases {
+            let statement_type = Statement::parse_pragma_input(input).unwrap();
+            assert_eq!(statement_type, StatementType::RecursionLimit(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_pragma_input_foreign_keys() {
+        let cases = [
+            ("pragma foreign_keys", None),
+            ("pragma foreign_keys = OFF", Some(false)),
+            ("pragma foreign_keys=1", Some(true)),
+        ];
+        for (input, expected) in cases {
+            let statement_type = Statement::parse_pragma_input(input).unwrap();
+            assert_eq!(statement_type, StatementType::ForeignKeys(expected), "{}", input);
+        }
+        let err = Statement::parse_pragma_input("pragma foreign_keys = maybe").unwrap_err();
+        assert_eq!(err.to_string(), "foreign_keys requires on or off");
+    }
+
     #[test]
     fn test_prepare_unrecognized_statement() {
         let input = "foobar";
-        let result = Statement::prepare(input);
+        let result = Statement::prepare(input, &TableDefinition::default(), &[], &[]);
         match result {
             PrepareResult::UnrecognizedStatement => {
                 // Test passes as we expect unrecognized statement.
@@ -0,0 +1,206 @@
+use crate::types::expression::BinaryOperator;
+use crate::types::Value;
+
+/// What ANALYZE found out about one column of the table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnStatistics {
+    pub column: String,
+    /// How many different values the column holds.
+    pub distinct: usize,
+    /// The upper bounds of an equi-depth histogram: the sorted values are split into at most
+    /// `Statistics::BUCKETS` runs of about as many rows each, and every bound is the largest
+    /// value of its run.
+    pub histogram: Vec<Value>,
+}
+impl ColumnStatistics {
+    fn collect(column: &str, mut values: Vec<Value>) -> ColumnStatistics {
+        values.sort_by(Value::compare);
+        let distinct = match values.is_empty() {
+            true => 0,
+            false => 1 + values.windows(2).filter(|pair| pair[0] != pair[1]).count(),
+        };
+        let buckets = Statistics::BUCKETS.min(values.len());
+        let histogram = (1..=buckets)
+            .map(|bucket| values[bucket * values.len() / buckets - 1].clone())
+            .collect();
+        ColumnStatistics {
+            column: column.to_string(),
+            distinct,
+            histogram,
+        }
+    }
+
+    /// Estimated fraction of the rows whose value satisfies `column operator value`, or
+    /// `None` for operators other than comparisons. Equality assumes the distinct values
+    /// are equally frequent. A range keeps the buckets on its side of `value`, and half of
+    /// the bucket `value` falls in.
+    pub fn selectivity(&self, operator: BinaryOperator, value: &Value) -> Option<f64> {
+        if self.histogram.is_empty() || *value == Value::Null {
+            return operator.is_comparison().then_some(0.0);
+        }
+        let equal = 1.0 / self.distinct as f64;
+        let buckets = self.histogram.len() as f64;
+        let below = self
+            .histogram
+            .iter()
+            .filter(|bound| bound.compare(value).is_lt())
+            .count() as f64;
+        let below = match below < buckets {
+            true => (below + 0.5) / buckets,
+            false => 1.0,
+        };
+        match operator {
+            BinaryOperator::Eq => Some(equal),
+            BinaryOperator::NotEq => Some(1.0 - equal),
+            BinaryOperator::Lt | BinaryOperator::LtEq => Some(below),
+            BinaryOperator::Gt | BinaryOperator::GtEq => Some(1.0 - below),
+            _ => None,
+        }
+    }
+}
+
+/// Row count, distinct counts and histograms of the table, collected by ANALYZE for the
+/// planner to estimate how many rows constraints and joins keep. Queries read them as the
+/// `zero_stat` system table, with one row per histogram bucket of each column.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statistics {
+    pub rows: usize,
+    /// In the order of the columns of the table.
+    pub columns: Vec<ColumnStatistics>,
+}
+impl Statistics {
+    /// The name queries refer to the statistics by in a FROM clause.
+    pub const TABLE_NAME: &'static str = "zero_stat";
+    pub const COLUMNS: [&'static str; 6] = [
+        "tbl",
+        "col",
+        "num_rows",
+        "num_distinct",
+        "bucket",
+        "upper_bound",
+    ];
+    pub const BUCKETS: usize = 8;
+
+    /// Collects the statistics of `rows`, whose values are those of `columns`.
+    pub fn collect<S: AsRef<str>>(columns: &[S], rows: &[Vec<Value>]) -> Statistics {
+        let columns = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let values = rows.iter().map(|row| row[i].clone()).collect();
+                ColumnStatistics::collect(column.as_ref(), values)
+            })
+            .collect();
+        Statistics {
+            rows: rows.len(),
+            columns,
+        }
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStatistics> {
+        self.columns.iter().find(|column| column.column == name)
+    }
+
+    /// Follows the rename of the column `column` of the table to `new_name`.
+    pub fn rename_column(&mut self, column: &str, new_name: &str) {
+        for statistics in &mut self.columns {
+            if statistics.column == column {
+                statistics.column = new_name.to_string();
+            }
+        }
+    }
+
+    /// The rows of the `zero_stat` system table, laid out as in `Statistics::COLUMNS`, for
+    /// the table named `table`.
+    pub fn table_rows<'a>(&'a self, table: &'a str) -> impl Iterator<Item = Vec<Value>> + 'a {
+        self.columns.iter().flat_map(move |column| {
+            column
+                .histogram
+                .iter()
+                .enumerate()
+                .map(move |(bucket, bound)| {
+                    vec![
+                        Value::Text(table.to_string()),
+                        Value::Text(column.column.clone()),
+                        Value::Integer(self.rows as i64),
+                        Value::Integer(column.distinct as i64),
+                        Value::Integer(bucket as i64),
+                        bound.clone(),
+                    ]
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Row, Table};
+
+    fn rows(ids: impl Iterator<Item = i64>) -> Vec<Vec<Value>> {
+        ids.map(|id| {
+            vec![
+                Value::Integer(id),
+                Value::Text(format!("user{}", id % 4)),
+                Value::Text("user@example.com".to_string()),
+            ]
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_collect_statistics() {
+        let statistics = Statistics::collect(&Row::COLUMNS, &rows(1..=100));
+        assert_eq!(statistics.rows, 100);
+        let distinct: Vec<usize> = statistics.columns.iter().map(|c| c.distinct).collect();
+        assert_eq!(distinct, vec![100, 4, 1]);
+
+        let id = statistics.column("id").unwrap();
+        let bounds: Vec<String> = id.histogram.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            bounds,
+            vec!["12", "25", "37", "50", "62", "75", "87", "100"]
+        );
+        let email = statistics.column("email").unwrap();
+        assert_eq!(email.histogram.len(), Statistics::BUCKETS);
+
+        let empty = Statistics::collect(&Row::COLUMNS, &[]);
+        assert_eq!(empty.columns[0].distinct, 0);
+        assert!(empty.columns[0].histogram.is_empty());
+        assert_eq!(empty.table_rows(Table::NAME).count(), 0);
+
+        let small = Statistics::collect(&Row::COLUMNS, &rows(1..=3));
+        let table_rows: Vec<Vec<Value>> = small.table_rows(Table::NAME).take(4).collect();
+        assert_eq!(table_rows.len(), 4);
+        assert_eq!(
+            table_rows[3],
+            vec![
+                Value::Text("users".to_string()),
+                Value::Text("username".to_string()),
+                Value::Integer(3),
+                Value::Integer(3),
+                Value::Integer(0),
+                Value::Text("user1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_column_selectivity() {
+        let statistics = Statistics::collect(&Row::COLUMNS, &rows(1..=100));
+        let id = statistics.column("id").unwrap();
+        let selectivity =
+            |operator, value: i64| id.selectivity(operator, &Value::Integer(value)).unwrap();
+        assert_eq!(selectivity(BinaryOperator::Eq, 7), 0.01);
+        assert_eq!(selectivity(BinaryOperator::NotEq, 7), 0.99);
+        assert_eq!(selectivity(BinaryOperator::Lt, 30), 0.3125);
+        assert_eq!(selectivity(BinaryOperator::GtEq, 30), 0.6875);
+        assert_eq!(selectivity(BinaryOperator::Gt, 500), 0.0);
+        assert_eq!(selectivity(BinaryOperator::Lt, -5), 0.0625);
+        assert_eq!(
+            id.selectivity(BinaryOperator::Add, &Value::Integer(1)),
+            None
+        );
+        assert_eq!(id.selectivity(BinaryOperator::Eq, &Value::Null), Some(0.0));
+    }
+}
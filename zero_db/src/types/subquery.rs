@@ -0,0 +1,50 @@
+use crate::types::expression::{Scope, SubqueryRows};
+use crate::types::{Expression, Select, Table, Value};
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Results of the subqueries of a running query that do not refer to its columns, so that
+/// each is run once rather than once per row. Subqueries are keyed by address, which is
+/// stable while the query runs; `None` marks a correlated subquery, which is never cached.
+#[derive(Default)]
+pub struct SubqueryCache {
+    results: RefCell<HashMap<*const Select, Option<SubqueryRows>>>,
+}
+
+/// What the expressions of a running query are evaluated against: columns resolve against
+/// the current row or group first and then against the rows of the enclosing queries, and
+/// subqueries run against the table.
+pub struct QueryScope<'a, 't> {
+    pub row: &'a dyn Scope,
+    pub outer: Option<&'a dyn Scope>,
+    pub table: &'a RefCell<&'t mut Table>,
+    pub subqueries: &'a SubqueryCache,
+}
+impl Scope for QueryScope<'_, '_> {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<Value> {
+        self.row
+            .column(table, name)
+            .or_else(|| self.outer?.column(table, name))
+    }
+
+    fn aggregate(&self, expression: &Expression) -> Option<Value> {
+        self.row.aggregate(expression)
+    }
+
+    fn subquery(&self, select: &Select) -> Result<SubqueryRows> {
+        let key = select as *const Select;
+        let cached = self.subqueries.results.borrow().get(&key).cloned();
+        if let Some(Some(rows)) = cached {
+            return Ok(rows);
+        }
+        let rows = Rc::new(self.table.borrow_mut().run_select(select, Some(self))?.rows);
+        if cached.is_none() {
+            let correlated = select.check_columns(&[]).is_err();
+            let result = (!correlated).then(|| rows.clone());
+            self.subqueries.results.borrow_mut().insert(key, result);
+        }
+        Ok(rows)
+    }
+}
@@ -1,65 +1,1037 @@
-use crate::types::{ExecuteResult, ExecutionFailure, Page, Row, Statement, StatementType};
+use crate::types::{
+    AggregateFunction, Catalog, CheckConstraint, ColumnDefault, CommonTableExpression, Cursor,
+    DatabaseOptions, ExecuteResult, ExecutionFailure, Expression, ForeignKey, ForeignKeyAction,
+    Group, HashAggregator, Index, Page, Pager, Parser, Program, QueryPlan, QueryScope, ResultSet,
+    Row, Select, SelectOutput, Sorter, Statement, StatementType, Statistics, SubqueryCache,
+    TableDefinition, TableSource, Tuple, Value, WindowScope,
+};
+use crate::types::expression::{Scope, SubqueryRows};
+use crate::types::join::RowSource;
+use crate::types::tokenizer::quote_identifier;
+use crate::types::{join, virtual_machine, window};
 use anyhow::{anyhow, Context, Result};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::Path;
+use std::rc::Rc;
 
 pub struct Table {
+    /// The names of the table and its columns, which ALTER TABLE can change. They are saved
+    /// to the database file when they are not those of a new database.
+    pub definition: TableDefinition,
     pub num_rows: usize,
-    pub pages: Vec<Page>,
+    /// Whether the table exists. DROP TABLE removes it until CREATE TABLE creates it again,
+    /// and meanwhile statements that read or change its rows fail.
+    pub exists: bool,
+    pub pager: Pager,
+    /// Bytes of rows an ORDER BY or GROUP BY may hold in memory before spilling to temporary
+    /// files.
+    pub memory_budget: usize,
+    /// How many times the recursive select of a common table expression may add rows before
+    /// the query fails, to stop recursions that never reach a fixpoint.
+    pub recursion_limit: usize,
+    /// What the last ANALYZE found out about the rows, if it has run. Statistics are saved to
+    /// the database file, and not updated as rows are inserted.
+    pub statistics: Option<Statistics>,
+    /// The indexes of the table, kept up to date as rows are inserted. Their definitions are
+    /// saved to the database file and they are rebuilt from the rows when it is opened.
+    pub indexes: Vec<Index>,
+    /// The views of the database, each kept as the common table expression that queries naming
+    /// it read, see `Statement::prepare`. Their definitions are saved to the database file.
+    pub views: Vec<CommonTableExpression>,
+    /// The CHECK constraints every inserted row must satisfy. Their definitions are saved to
+    /// the database file.
+    pub checks: Vec<CheckConstraint>,
+    /// The values of the columns an insert leaves out, in the order of the columns.
+    /// Their definitions are saved to the database file.
+    pub defaults: Vec<ColumnDefault>,
+    /// The foreign keys checked as rows are inserted and deleted. Their definitions are
+    /// saved to the database file.
+    pub foreign_keys: Vec<ForeignKey>,
+    /// Whether foreign keys are checked, which `pragma foreign_keys = off` turns off for bulk
+    /// loads. Rows inserted meanwhile are not checked when it is turned on again.
+    pub foreign_keys_enabled: bool,
+    /// Rows of the common table expressions of the running queries, innermost last.
+    common_tables: Vec<(String, SubqueryRows)>,
 }
 impl Table {
+    /// The name of the table of a new database, see `TableDefinition`.
+    pub const NAME: &'static str = "users";
     const TABLE_MAX_PAGES: usize = 100;
     const TABLE_PAGE_SIZE: usize = 4096;
+    pub const DEFAULT_RECURSION_LIMIT: usize = 1000;
     pub fn new() -> Table {
         Table {
+            definition: TableDefinition::default(),
             num_rows: 0,
-            pages: Vec::new(),
+            exists: true,
+            pager: Pager::in_memory(Self::TABLE_PAGE_SIZE),
+            memory_budget: Sorter::DEFAULT_MEMORY_BUDGET,
+            recursion_limit: Self::DEFAULT_RECURSION_LIMIT,
+            statistics: None,
+            indexes: Vec::new(),
+            views: Vec::new(),
+            checks: Vec::new(),
+            defaults: Vec::new(),
+            foreign_keys: Vec::new(),
+            foreign_keys_enabled: true,
+            common_tables: Vec::new(),
+        }
+    }
+    /// Opens a file-backed table, see `DatabaseOptions` for how the options are applied.
+    /// The objects defined in the catalog of the file are created again.
+    pub fn open(path: &Path, options: &DatabaseOptions) -> Result<Table> {
+        let pager = Pager::open(path, Self::TABLE_PAGE_SIZE, options)?;
+        let mut table = Table {
+            definition: TableDefinition::default(),
+            num_rows: pager.num_rows(),
+            exists: true,
+            pager,
+            memory_budget: Sorter::DEFAULT_MEMORY_BUDGET,
+            recursion_limit: Self::DEFAULT_RECURSION_LIMIT,
+            statistics: None,
+            indexes: Vec::new(),
+            views: Vec::new(),
+            checks: Vec::new(),
+            defaults: Vec::new(),
+            foreign_keys: Vec::new(),
+            foreign_keys_enabled: true,
+            common_tables: Vec::new(),
+        };
+        let catalog = Catalog::deserialize(table.pager.catalog())?;
+        for definition in &catalog.definitions {
+            let statement_type = Parser::new(definition)
+                .and_then(|parser| parser.with_table(&table.definition).parse_definition())
+                .with_context(|| format!("Invalid catalog entry: {}", definition))?;
+            match statement_type {
+                // The pages of the table went to the free list when it was dropped.
+                StatementType::DropTable { name } => {
+                    table.definition.name = name;
+                    table.exists = false;
+                }
+                // The table was renamed, or created again with other names, and it keeps its
+                // rows.
+                StatementType::CreateTable { name, columns } => {
+                    table.definition = TableDefinition::new(&name, &columns)?;
+                }
+                StatementType::CreateIndex {
+                    name,
+                    columns,
+                    unique,
+                } => {
+                    if let Some(failure) = table.create_index(&name, &columns, unique)? {
+                        return Err(anyhow!("Unable to rebuild index {}: {:?}", name, failure));
+                    }
+                }
+                StatementType::CreateView(view) => table.create_view(*view)?,
+                StatementType::AddConstraint { name, check } => {
+                    if let Some(failure) = table.add_constraint(&name, check)? {
+                        return Err(anyhow!("Unable to add constraint {}: {:?}", name, failure));
+                    }
+                }
+                StatementType::SetDefault { column, default } => {
+                    table.set_default(&column, default.as_ref())?
+                }
+                StatementType::AddForeignKey {
+                    name,
+                    column,
+                    parent_column,
+                    on_delete,
+                } => {
+                    let foreign_key = ForeignKey::new(
+                        &name,
+                        &column,
+                        &parent_column,
+                        on_delete,
+                        &table.definition,
+                    )?;
+                    if let Some(failure) = table.add_foreign_key(foreign_key)? {
+                        return Err(anyhow!("Unable to add constraint {}: {:?}", name, failure));
+                    }
+                }
+                _ => return Err(anyhow!("Invalid catalog entry: {}", definition)),
+            }
+        }
+        table.statistics = catalog.statistics;
+        Ok(table)
+    }
+    /// Writes a copy of the table to a new database file, see `Pager::save_as`.
+    pub fn save(&mut self, path: &Path, options: &DatabaseOptions) -> Result<()> {
+        let catalog = self.catalog().serialize();
+        self.pager.save_as(path, options, self.num_rows, &catalog)
+    }
+    /// Writes all pending changes to the database file.
+    pub fn close(&mut self) -> Result<()> {
+        let catalog = self.catalog().serialize();
+        self.pager.flush(self.num_rows, &catalog)
+    }
+    /// The definitions of the indexes, views and constraints and the statistics, stored in
+    /// the database file. A dropped table is recorded as the DROP TABLE statement, and a
+    /// table with other names than those of a new database as its CREATE TABLE statement.
+    fn catalog(&self) -> Catalog {
+        let table = &self.definition;
+        let created = match self.exists {
+            true => (*table != TableDefinition::default()).then(|| table.definition()),
+            false => Some(format!("DROP TABLE {}", quote_identifier(&table.name))),
+        };
+        let indexes = self.indexes.iter().map(|index| index.definition(table));
+        let views = self.views.iter().map(CommonTableExpression::view_definition);
+        let checks = self.checks.iter().map(|check| check.definition(table));
+        let defaults = self.defaults.iter().map(|default| default.definition(table));
+        let foreign_keys = self.foreign_keys.iter().map(|key| key.definition(table));
+        Catalog {
+            definitions: created
+                .into_iter()
+                .chain(indexes)
+                .chain(views)
+                .chain(checks)
+                .chain(defaults)
+                .chain(foreign_keys)
+                .collect(),
+            statistics: self.statistics.clone(),
         }
     }
     fn max_rows() -> usize {
         Self::TABLE_MAX_PAGES * Page::max_rows_per_page(Self::TABLE_PAGE_SIZE)
     }
 
+    /// Compiles `statement` and runs the program, see `VirtualMachine`.
     pub fn execute(&mut self, statement: Statement) -> Result<ExecuteResult> {
-        match statement.statement_type {
-            StatementType::Insert => Self::execute_insert(self, statement.row_to_insert),
-            StatementType::Select => Self::execute_select(self),
+        virtual_machine::run(&Program::compile(&statement, self)?, self)
+    }
+
+    /// Appends `row` to the last page, allocating a new page when it is full. Returns false
+    /// without inserting if the table already holds `Self::max_rows()` rows.
+    pub fn insert(&mut self, row: &Row) -> Result<bool> {
+        self.check_exists()?;
+        if self.num_rows >= Self::max_rows() {
+            return Ok(false);
+        }
+
+        // Rows fill the pages in order, and freed pages are handed out again lowest first,
+        // so the next page allocated is the one after the last row.
+        let rows_per_page = Page::max_rows_per_page(Self::TABLE_PAGE_SIZE);
+        let page_num = self.num_rows / rows_per_page;
+        if self.num_rows.is_multiple_of(rows_per_page) {
+            let allocated = self.pager.allocate_page();
+            if allocated != page_num {
+                return Err(anyhow!(
+                    "Page {} was allocated for row {} instead of page {}",
+                    allocated,
+                    self.num_rows,
+                    page_num
+                ));
+            }
+        }
+
+        self.pager.get_page_mut(page_num)?.add_row(row.clone())?;
+        let values = row.values();
+        for index in &mut self.indexes {
+            index.insert(&values, self.num_rows);
+        }
+        self.num_rows += 1;
+        Ok(true)
+    }
+
+    /// The values of row number `row`, counting from 0 in storage order. Rows fill each page
+    /// before the next one is allocated, so the number locates the page and the row in it.
+    pub fn read_row(&mut self, row: usize) -> Result<Vec<Value>> {
+        let rows_per_page = Page::max_rows_per_page(Self::TABLE_PAGE_SIZE);
+        let page = self.pager.get_page(row / rows_per_page)?;
+        page.rows
+            .get(row % rows_per_page)
+            .map(Row::values)
+            .ok_or_else(|| anyhow!("No row {} in the table", row))
+    }
+
+    /// Fails with "No such table" after the table was dropped.
+    pub fn check_exists(&self) -> Result<()> {
+        if !self.exists {
+            return Err(anyhow!("No such table: {}", self.definition.name));
+        }
+        Ok(())
+    }
+
+    /// Creates the table `name` with `columns` again after it was dropped. Its layout is
+    /// fixed, see `Parser::parse_create_table`, but it can have other names.
+    pub fn create_table(&mut self, name: &str, columns: &[String]) -> Result<()> {
+        if self.exists {
+            return Err(anyhow!("Table {} already exists", self.definition.name));
+        }
+        self.check_table_name(name)?;
+        self.definition = TableDefinition::new(name, columns)?;
+        self.exists = true;
+        Ok(())
+    }
+
+    // Fails if `name` is taken by a view or the statistics, which the table cannot be called.
+    fn check_table_name(&self, name: &str) -> Result<()> {
+        if name == Statistics::TABLE_NAME || self.views.iter().any(|view| view.name == name) {
+            return Err(anyhow!("Table {} already exists", name));
+        }
+        Ok(())
+    }
+
+    /// Renames the table to `new_name`. The constraints follow, but views are not rewritten,
+    /// so the rename fails while a view reads the table, see `Table::check_views`.
+    pub fn rename_table(&mut self, name: &str, new_name: &str) -> Result<()> {
+        self.check_exists()?;
+        if name != self.definition.name {
+            return Err(anyhow!("No such table: {}", name));
+        }
+        if new_name == name {
+            return Err(anyhow!("Table {} already exists", new_name));
+        }
+        self.check_table_name(new_name)?;
+        let definition = TableDefinition {
+            name: new_name.to_string(),
+            columns: self.definition.columns.clone(),
+        };
+        self.views = self.check_views(&definition)?;
+        for check in &mut self.checks {
+            check.rename_table(name, new_name);
+        }
+        self.definition = definition;
+        Ok(())
+    }
+
+    /// Renames the column `column` of the table to `new_name`, in its indexes, constraints,
+    /// default values and statistics too. The rename fails while a view reads the column,
+    /// see `Table::check_views`.
+    pub fn rename_column(&mut self, column: &str, new_name: &str) -> Result<()> {
+        self.check_exists()?;
+        let position = self.definition.position(column)?;
+        let mut columns = self.definition.columns.clone();
+        columns[position] = new_name.to_string();
+        let definition = TableDefinition::new(&self.definition.name, &columns)?;
+        self.views = self.check_views(&definition)?;
+        for index in &mut self.indexes {
+            index.rename_column(column, new_name);
+        }
+        for check in &mut self.checks {
+            check.rename_column(column, new_name);
+        }
+        for default in &mut self.defaults {
+            if default.column == column {
+                default.column = new_name.to_string();
+            }
+        }
+        for foreign_key in &mut self.foreign_keys {
+            foreign_key.rename_column(column, new_name);
+        }
+        if let Some(statistics) = &mut self.statistics {
+            statistics.rename_column(column, new_name);
+        }
+        self.definition = definition;
+        Ok(())
+    }
+
+    // The views parsed again from their definitions with the table named as in `definition`.
+    // A view whose query refers to a name the table or its columns no longer have fails,
+    // rather than being rewritten.
+    fn check_views(&self, definition: &TableDefinition) -> Result<Vec<CommonTableExpression>> {
+        let mut views = Vec::with_capacity(self.views.len());
+        for view in &self.views {
+            let statement_type = Parser::new(&view.view_definition())
+                .and_then(|parser| parser.with_table(definition).parse_create_view())
+                .and_then(|statement_type| match statement_type {
+                    StatementType::CreateView(view) => {
+                        view.check_columns(&[])?;
+                        Ok(*view)
+                    }
+                    _ => Err(anyhow!("Expected a view")),
+                });
+            match statement_type {
+                Ok(view) => views.push(view),
+                Err(err) => return Err(anyhow!("Error in view {}: {}", view.name, err)),
+            }
+        }
+        Ok(views)
+    }
+
+    /// Removes the table with its rows, indexes, constraints, defaults and statistics. Its
+    /// pages go to the free list of the pager, and rows inserted into a table created again
+    /// reuse them. Views are kept and fail when read, as they refer to the table by name.
+    pub fn drop_table(&mut self, name: &str) -> Result<()> {
+        if name != self.definition.name || !self.exists {
+            return Err(anyhow!("No such table: {}", name));
+        }
+        for page_num in 0..self.pager.num_pages() {
+            if !self.pager.is_free(page_num) {
+                self.pager.free_page(page_num)?;
+            }
+        }
+        self.num_rows = 0;
+        self.indexes.clear();
+        self.checks.clear();
+        self.defaults.clear();
+        self.foreign_keys.clear();
+        self.statistics = None;
+        self.exists = false;
+        Ok(())
+    }
+
+    /// Builds an index named `name` on `columns` from the rows of the table. A unique index
+    /// is not created if two rows have the same values, and the failure names them.
+    pub fn create_index(
+        &mut self,
+        name: &str,
+        columns: &[String],
+        unique: bool,
+    ) -> Result<Option<ExecutionFailure>> {
+        if self.indexes.iter().any(|index| index.name == name) {
+            return Err(anyhow!("Index {} already exists", name));
+        }
+        let mut index = Index::new(name, columns, unique, &self.definition)?;
+        let mut cursor = Cursor::table_start();
+        let mut row = 0;
+        while let Some(values) = cursor.next_row(self)? {
+            if let Some(key) = index.conflict(&values) {
+                return Ok(Some(ExecutionFailure::unique_violation(&index, &key)));
+            }
+            index.insert(&values, row);
+            row += 1;
+        }
+        self.indexes.push(index);
+        Ok(None)
+    }
+
+    /// Removes the index named `name`. Queries planned afterwards scan the table instead.
+    /// The last unique index a foreign key finds parent rows with cannot be removed.
+    pub fn drop_index(&mut self, name: &str) -> Result<()> {
+        let position = self
+            .indexes
+            .iter()
+            .position(|index| index.name == name)
+            .ok_or_else(|| anyhow!("No such index: {}", name))?;
+        let index = self.indexes.remove(position);
+        if let Some(foreign_key) = self
+            .foreign_keys
+            .iter()
+            .find(|foreign_key| self.parent_index(foreign_key).is_none())
+        {
+            let foreign_key = foreign_key.name.clone();
+            self.indexes.insert(position, index);
+            return Err(anyhow!(
+                "Index {} is needed by foreign key {}",
+                name,
+                foreign_key
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adds `view`, whose name must not be taken by a table or another view.
+    pub fn create_view(&mut self, view: CommonTableExpression) -> Result<()> {
+        if view.name == self.definition.name || view.name == Statistics::TABLE_NAME {
+            return Err(anyhow!("Table {} already exists", view.name));
+        }
+        if self.views.iter().any(|existing| existing.name == view.name) {
+            return Err(anyhow!("View {} already exists", view.name));
+        }
+        self.views.push(view);
+        Ok(())
+    }
+
+    /// Removes the view named `name`. Views created from it keep working, as they hold a copy
+    /// of its select.
+    pub fn drop_view(&mut self, name: &str) -> Result<()> {
+        let position = self
+            .views
+            .iter()
+            .position(|view| view.name == name)
+            .ok_or_else(|| anyhow!("No such view: {}", name))?;
+        self.views.remove(position);
+        Ok(())
+    }
+
+    /// Adds the CHECK constraint `name`, unless a row of the table does not satisfy it, which
+    /// is reported as `ExecutionFailure::CheckViolation`.
+    pub fn add_constraint(
+        &mut self,
+        name: &str,
+        check: Expression,
+    ) -> Result<Option<ExecutionFailure>> {
+        self.check_constraint_name(name)?;
+        let constraint = CheckConstraint::new(name, check, &self.definition)?;
+        let mut cursor = Cursor::table_start();
+        while let Some(values) = cursor.next_row(self)? {
+            if !constraint.holds(&self.definition, &values)? {
+                let constraint = constraint.name;
+                return Ok(Some(ExecutionFailure::CheckViolation { constraint }));
+            }
+        }
+        self.checks.push(constraint);
+        Ok(None)
+    }
+
+    /// Adds `foreign_key`, unless a row of the table refers to no parent, which is reported
+    /// as `ExecutionFailure::ForeignKeyViolation`. Parents are found with a unique index on
+    /// the parent column, which must exist.
+    pub fn add_foreign_key(&mut self, foreign_key: ForeignKey) -> Result<Option<ExecutionFailure>> {
+        self.check_constraint_name(&foreign_key.name)?;
+        if self.parent_index(&foreign_key).is_none() {
+            return Err(anyhow!(
+                "Foreign key {} requires a unique index on {}",
+                foreign_key.name,
+                foreign_key.parent_column
+            ));
+        }
+        let mut cursor = Cursor::table_start();
+        while let Some(values) = cursor.next_row(self)? {
+            if !self.has_parent(&foreign_key, &values) {
+                let constraint = foreign_key.name;
+                return Ok(Some(ExecutionFailure::ForeignKeyViolation { constraint }));
+            }
+        }
+        self.foreign_keys.push(foreign_key);
+        Ok(None)
+    }
+
+    fn check_constraint_name(&self, name: &str) -> Result<()> {
+        let checks = self.checks.iter().map(|check| &check.name);
+        let mut names = checks.chain(self.foreign_keys.iter().map(|key| &key.name));
+        if names.any(|existing| existing == name) {
+            return Err(anyhow!("Constraint {} already exists", name));
+        }
+        Ok(())
+    }
+
+    // The unique index on the parent column of `foreign_key` alone, which finds parents.
+    fn parent_index(&self, foreign_key: &ForeignKey) -> Option<&Index> {
+        self.indexes
+            .iter()
+            .find(|index| index.unique && index.columns == [foreign_key.parent_column.as_str()])
+    }
+
+    // Whether the row with `values` is its own parent or another row is.
+    fn has_parent(&self, foreign_key: &ForeignKey, values: &[Value]) -> bool {
+        let key = foreign_key.key(values);
+        key.compare(&foreign_key.parent_key(values)).is_eq()
+            || self
+                .parent_index(foreign_key)
+                .is_some_and(|index| index.contains(&[key]))
+    }
+
+    /// Removes the CHECK constraint or foreign key named `name`.
+    pub fn drop_constraint(&mut self, name: &str) -> Result<()> {
+        if let Some(position) = self.checks.iter().position(|check| check.name == name) {
+            self.checks.remove(position);
+            return Ok(());
+        }
+        let position = self
+            .foreign_keys
+            .iter()
+            .position(|foreign_key| foreign_key.name == name)
+            .ok_or_else(|| anyhow!("No such constraint: {}", name))?;
+        self.foreign_keys.remove(position);
+        Ok(())
+    }
+
+    /// Sets the value of `column` in the rows inserts leave it out of to that of `default`,
+    /// or removes it.
+    pub fn set_default(&mut self, column: &str, default: Option<&Expression>) -> Result<()> {
+        self.check_exists()?;
+        self.definition.position(column)?;
+        let default = default
+            .map(|expression| ColumnDefault::new(column, expression, &self.definition))
+            .transpose()?;
+        self.defaults.retain(|existing| existing.column != column);
+        self.defaults.extend(default);
+        let columns = &self.definition.columns;
+        self.defaults
+            .sort_by_key(|default| columns.iter().position(|name| *name == default.column));
+        Ok(())
+    }
+
+    /// The failure inserting `row` would end in because it does not satisfy a CHECK
+    /// constraint, the first one in the order they were added.
+    pub fn check_violation(&self, row: &Row) -> Result<Option<ExecutionFailure>> {
+        let values = row.values();
+        for check in &self.checks {
+            if !check.holds(&self.definition, &values)? {
+                let constraint = check.name.clone();
+                return Ok(Some(ExecutionFailure::CheckViolation { constraint }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The failure inserting `row` would end in because a unique index already has its
+    /// values, checked against every index before any is changed.
+    pub fn unique_violation(&self, row: &Row) -> Option<ExecutionFailure> {
+        let values = row.values();
+        self.indexes.iter().find_map(|index| {
+            let key = index.conflict(&values)?;
+            Some(ExecutionFailure::unique_violation(index, &key))
+        })
+    }
+
+    /// The failure inserting `row` would end in because it refers to no parent through a
+    /// foreign key, unless foreign keys are turned off.
+    pub fn foreign_key_violation(&self, row: &Row) -> Option<ExecutionFailure> {
+        if !self.foreign_keys_enabled {
+            return None;
+        }
+        let values = row.values();
+        let foreign_key = self
+            .foreign_keys
+            .iter()
+            .find(|foreign_key| !self.has_parent(foreign_key, &values))?;
+        let constraint = foreign_key.name.clone();
+        Some(ExecutionFailure::ForeignKeyViolation { constraint })
+    }
+
+    /// Deletes the rows `condition` holds for, or every row without one, along with the rows
+    /// referring to them through foreign keys with ON DELETE CASCADE. Nothing is deleted if a
+    /// row left would refer to a deleted one through another foreign key, which is reported as
+    /// `ExecutionFailure::ForeignKeyViolation`. The rows after the first one deleted move up
+    /// to fill the gaps, and the pages left empty go to the free list.
+    pub fn delete(&mut self, condition: Option<&Expression>) -> Result<Option<ExecutionFailure>> {
+        let schema = self.definition.schema(&self.definition.name);
+        let mut rows = Vec::with_capacity(self.num_rows);
+        let mut deleted = Vec::with_capacity(self.num_rows);
+        let mut cursor = Cursor::table_start();
+        while let Some(values) = cursor.next_row(self)? {
+            let matches = match condition {
+                Some(condition) => {
+                    let row = Tuple {
+                        schema: &schema,
+                        values: &values,
+                    };
+                    condition.evaluate(&row)?.as_bool()?.unwrap_or(false)
+                }
+                None => true,
+            };
+            deleted.push(matches);
+            rows.push(values);
+        }
+        if self.foreign_keys_enabled {
+            if let Some(failure) = self.delete_children(&rows, &mut deleted) {
+                return Ok(Some(failure));
+            }
+        }
+        let Some(first) = deleted.iter().position(|deleted| *deleted) else {
+            return Ok(None);
+        };
+
+        let rows_per_page = Page::max_rows_per_page(Self::TABLE_PAGE_SIZE);
+        let kept: Vec<Vec<Value>> = rows
+            .into_iter()
+            .zip(&deleted)
+            .filter(|(_, deleted)| !**deleted)
+            .map(|(values, _)| values)
+            .collect();
+        let first_page = first / rows_per_page;
+        let pages = kept.len().div_ceil(rows_per_page);
+        for page_num in first_page..self.pager.num_pages() {
+            if self.pager.is_free(page_num) {
+                continue;
+            }
+            if page_num >= pages {
+                self.pager.free_page(page_num)?;
+                continue;
+            }
+            let page = self.pager.get_page_mut(page_num)?;
+            page.rows.clear();
+            let start = page_num * rows_per_page;
+            for values in kept.iter().skip(start).take(rows_per_page) {
+                page.add_row(Row::from_values(values)?)?;
+            }
+        }
+        self.num_rows = kept.len();
+        for index in &mut self.indexes {
+            index.clear();
+            for (row, values) in kept.iter().enumerate() {
+                index.insert(values, row);
+            }
+        }
+        Ok(None)
+    }
+
+    // Marks the rows referring to `deleted` ones through foreign keys with ON DELETE CASCADE
+    // as deleted too, until no more are. Then the failure of the first foreign key a row left
+    // refers to a deleted row through.
+    fn delete_children(
+        &self,
+        rows: &[Vec<Value>],
+        deleted: &mut [bool],
+    ) -> Option<ExecutionFailure> {
+        // The keys of the deleted rows each foreign key refers to parents by, sorted.
+        let parent_keys = |foreign_key: &ForeignKey, deleted: &[bool]| {
+            let mut keys: Vec<Value> = rows
+                .iter()
+                .zip(deleted)
+                .filter(|(_, deleted)| **deleted)
+                .map(|(values, _)| foreign_key.parent_key(values))
+                .collect();
+            keys.sort_by(Value::compare);
+            keys
+        };
+        let orphans = |foreign_key: &ForeignKey, deleted: &[bool]| {
+            let keys = parent_keys(foreign_key, deleted);
+            let orphan = |values: &Vec<Value>| {
+                let key = foreign_key.key(values);
+                keys.binary_search_by(|parent| parent.compare(&key)).is_ok()
+            };
+            let orphans: Vec<usize> = (0..rows.len())
+                .filter(|row| !deleted[*row] && orphan(&rows[*row]))
+                .collect();
+            orphans
+        };
+        loop {
+            let mut cascaded = false;
+            for foreign_key in &self.foreign_keys {
+                if foreign_key.on_delete == ForeignKeyAction::Cascade {
+                    for row in orphans(foreign_key, deleted) {
+                        deleted[row] = true;
+                        cascaded = true;
+                    }
+                }
+            }
+            if !cascaded {
+                break;
+            }
+        }
+        let foreign_key = self
+            .foreign_keys
+            .iter()
+            .find(|foreign_key| !orphans(foreign_key, deleted).is_empty())?;
+        let constraint = foreign_key.name.clone();
+        Some(ExecutionFailure::ForeignKeyViolation { constraint })
+    }
+
+    /// Reads every row to collect the statistics the planner estimates row counts from,
+    /// replacing those of an earlier ANALYZE.
+    pub fn analyze(&mut self) -> Result<()> {
+        let mut cursor = Cursor::table_start();
+        let mut rows = Vec::with_capacity(self.num_rows);
+        while let Some(values) = cursor.next_row(self)? {
+            rows.push(values);
+        }
+        self.statistics = Some(Statistics::collect(&self.definition.columns, &rows));
+        Ok(())
+    }
+
+    /// Runs `select`, as a subquery of the query whose current row is `outer` if given. The
+    /// common table expressions of its WITH clause are computed first and can be read by the
+    /// query and its subqueries until it ends.
+    pub fn run_select(&mut self, select: &Select, outer: Option<&dyn Scope>) -> Result<ResultSet> {
+        let scope = self.common_tables.len();
+        let mut result = Ok(());
+        for common_table in &select.with {
+            result = self.materialize(common_table, outer).map(|rows| {
+                let rows = Rc::new(rows);
+                self.common_tables.push((common_table.name.clone(), rows));
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        let result = result.and_then(|()| self.run_query(select, outer));
+        self.common_tables.truncate(scope);
+        result
+    }
+
+    /// Rows of the common table expression `name` of a running query.
+    pub fn common_table(&self, name: &str) -> Option<SubqueryRows> {
+        self.common_tables
+            .iter()
+            .rev()
+            .find(|(common_table, _)| common_table == name)
+            .map(|(_, rows)| rows.clone())
+    }
+
+    // The select of a recursive common table expression runs once, and its recursive select
+    // then runs against the rows the previous run added until a run adds none. At most
+    // `recursion_limit` runs may add rows. With UNION rather than UNION ALL, rows already
+    // produced are not added again, which also ends recursions that only revisit rows.
+    fn materialize(
+        &mut self,
+        common_table: &CommonTableExpression,
+        outer: Option<&dyn Scope>,
+    ) -> Result<Vec<Vec<Value>>> {
+        let mut rows = self.run_select(&common_table.select, outer)?.rows;
+        let Some(union) = &common_table.union else {
+            return Ok(rows);
+        };
+        let mut seen = HashSet::new();
+        let mut is_new = |row: &Vec<Value>| {
+            let mut key = Vec::new();
+            for value in row {
+                value.serialize(&mut key);
+            }
+            union.all || seen.insert(key)
+        };
+        rows.retain(&mut is_new);
+        if !common_table.recursive {
+            let added = self.run_select(&union.select, outer)?.rows;
+            rows.extend(added.into_iter().filter(&mut is_new));
+            return Ok(rows);
+        }
+        let mut added = rows.clone();
+        let mut runs = 0;
+        while !added.is_empty() {
+            self.common_tables
+                .push((common_table.name.clone(), Rc::new(added)));
+            let result = self.run_select(&union.select, outer);
+            self.common_tables.pop();
+            added = result?.rows;
+            added.retain(&mut is_new);
+            if added.is_empty() {
+                break;
+            }
+            runs += 1;
+            if runs > self.recursion_limit {
+                return Err(anyhow!(
+                    "Recursion limit of {} exceeded by {}",
+                    self.recursion_limit,
+                    common_table.name
+                ));
+            }
+            rows.extend(added.iter().cloned());
         }
+        Ok(rows)
     }
 
-    fn execute_insert(&mut self, row: Option<Row>) -> Result<ExecuteResult> {
-        let row = row.ok_or_else(|| anyhow!("No row for insertion"))?;
+    // Rows are read through a cursor that stops as soon as the output is complete, so with
+    // LIMIT and without ORDER BY the pages after the last needed row are never read.
+    fn run_query(&mut self, select: &Select, outer: Option<&dyn Scope>) -> Result<ResultSet> {
+        let schema = select.schema();
+        let plan = QueryPlan::new(select, self);
+        let mut output = SelectOutput::new(select, self.memory_budget, plan.presorted);
+        let subqueries = SubqueryCache::default();
+        // Window functions need every row of the query, so the rows or groups passing WHERE
+        // and HAVING are kept rather than output until all of them are known.
+        let windows = select.windows();
+        let mut kept = Vec::new();
+        if !select.is_aggregate() {
+            self.scan(select, &plan, &mut |table, values| {
+                let table = RefCell::new(table);
+                let row = QueryScope {
+                    row: &Tuple {
+                        schema: &schema,
+                        values,
+                    },
+                    outer,
+                    table: &table,
+                    subqueries: &subqueries,
+                };
+                if !select.matches(&row)? {
+                    Ok(true)
+                } else if windows.is_empty() {
+                    output.push(&row)
+                } else {
+                    kept.push(Group {
+                        row: Some(values.to_vec()),
+                        values: Vec::new(),
+                    });
+                    Ok(true)
+                }
+            })?;
+            return self.output_windows(select, &[], kept, outer, &subqueries, output);
+        }
 
-        if self.pages.is_empty() {
-            self.pages.push(Page::new(Self::TABLE_PAGE_SIZE));
+        let aggregates = select.aggregates();
+        let count_star = Expression::Aggregate {
+            function: AggregateFunction::Count,
+            argument: None,
+        };
+        // COUNT(*) over the whole table is the row count kept in the table header.
+        if matches!(select.from.source, TableSource::Table(_))
+            && select.joins.is_empty()
+            && select.where_clause.is_none()
+            && select.group_by.is_empty()
+            && aggregates == [count_star]
+        {
+            self.check_exists()?;
+            kept.push(Group {
+                row: None,
+                values: vec![Value::Integer(self.num_rows as i64)],
+            });
+            return self.output_windows(select, &aggregates, kept, outer, &subqueries, output);
         }
 
-        if self.pages.last().unwrap().is_full() {
-            if self.num_rows < Self::max_rows() {
-                return Ok(ExecuteResult::ExecutionFailure(ExecutionFailure::TableFull));
+        let mut aggregator = HashAggregator::new(
+            schema.clone(),
+            select.group_by.clone(),
+            aggregates.clone(),
+            self.memory_budget,
+        );
+        self.scan(select, &plan, &mut |table, values| {
+            let table = RefCell::new(table);
+            let row = QueryScope {
+                row: &Tuple {
+                    schema: &schema,
+                    values,
+                },
+                outer,
+                table: &table,
+                subqueries: &subqueries,
+            };
+            if select.matches(&row)? {
+                aggregator.push(values)?;
+            }
+            Ok(true)
+        })?;
+        for group in aggregator.finish()? {
+            let group = group?;
+            let table = RefCell::new(&mut *self);
+            let scope = QueryScope {
+                row: &group.scope(&schema, &aggregates),
+                outer,
+                table: &table,
+                subqueries: &subqueries,
+            };
+            if !select.having_matches(&scope)? {
+                continue;
+            }
+            if !windows.is_empty() {
+                kept.push(group);
+            } else if !output.push(&scope)? {
+                break;
             }
-            self.pages.push(Page::new(Self::TABLE_PAGE_SIZE));
         }
+        self.output_windows(select, &aggregates, kept, outer, &subqueries, output)
+    }
 
-        self.pages
-            .last_mut()
-            .context("Last page not found")?
-            .add_row(row.clone())?;
+    /// Computes the window functions of `select` over the rows or groups kept by `run_query`
+    /// and outputs each of them along with its values.
+    fn output_windows(
+        &mut self,
+        select: &Select,
+        aggregates: &[Expression],
+        kept: Vec<Group>,
+        outer: Option<&dyn Scope>,
+        subqueries: &SubqueryCache,
+        mut output: SelectOutput,
+    ) -> Result<ResultSet> {
+        let schema = select.schema();
+        let windows = select.windows();
+        let table = RefCell::new(self);
+        let groups: Vec<_> = kept
+            .iter()
+            .map(|group| group.scope(&schema, aggregates))
+            .collect();
+        let scopes: Vec<_> = groups
+            .iter()
+            .map(|group| QueryScope {
+                row: group,
+                outer,
+                table: &table,
+                subqueries,
+            })
+            .collect();
+        let rows: Vec<&dyn Scope> = scopes.iter().map(|scope| scope as &dyn Scope).collect();
+        let values = window::compute(&windows, &rows)?;
+        for (row, values) in rows.into_iter().zip(&values) {
+            let scope = WindowScope {
+                row,
+                windows: &windows,
+                values,
+            };
+            if !output.push(&scope)? {
+                break;
+            }
+        }
+        output.finish()
+    }
 
-        let rows_inserted = vec![row.clone()];
-        Ok(ExecuteResult::Success(rows_inserted))
+    /// Calls `emit` with each row of the FROM clause of `select` until it returns false. The
+    /// table is passed back to `emit` so that subqueries can read it.
+    fn scan(
+        &mut self,
+        select: &Select,
+        plan: &QueryPlan,
+        emit: &mut dyn FnMut(&mut Table, &[Value]) -> Result<bool>,
+    ) -> Result<()> {
+        let mut source = join::build(select, plan, self.memory_budget);
+        while let Some(values) = source.next_row(self)? {
+            if !emit(self, &values)? {
+                break;
+            }
+        }
+        Ok(())
     }
 
-    fn execute_select(&mut self) -> Result<ExecuteResult> {
-        let rows: Vec<Row> = self.pages.iter().flat_map(|page| page.rows.clone()).collect();
-        Ok(ExecuteResult::Success(rows))
+    /// Walks every page of the table and returns a description of each problem found.
+    /// An empty result means the table is consistent.
+    pub fn integrity_check(&mut self) -> Vec<String> {
+        let mut problems = self.pager.integrity_check();
+
+        let num_pages = self.pager.num_pages();
+        if num_pages > Self::TABLE_MAX_PAGES {
+            problems.push(format!(
+                "Table has {} pages but at most {} are allowed",
+                num_pages,
+                Self::TABLE_MAX_PAGES
+            ));
+        }
+
+        let max_rows_per_page = Page::max_rows_per_page(Self::TABLE_PAGE_SIZE);
+        let mut rows_in_pages = 0;
+        let last_page = (0..num_pages).rev().find(|page_num| !self.pager.is_free(*page_num));
+        for page_num in 0..num_pages {
+            let free = self.pager.is_free(page_num);
+            let page = match self.pager.get_page(page_num) {
+                Ok(page) => page,
+                Err(err) => {
+                    problems.push(format!("Page {}: {:#}", page_num, err));
+                    continue;
+                }
+            };
+            problems.extend(page.integrity_check(page_num, max_rows_per_page));
+
+            let is_last_page = Some(page_num) == last_page;
+            if free {
+                if !page.rows.is_empty() {
+                    problems.push(format!(
+                        "Page {}: is on the free list but holds {} rows",
+                        page_num,
+                        page.rows.len()
+                    ));
+                }
+                if let Some(last_page) = last_page.filter(|last_page| *last_page > page_num) {
+                    problems.push(format!(
+                        "Page {}: is on the free list but rows continue on page {}",
+                        page_num, last_page
+                    ));
+                }
+            } else if page.rows.is_empty() {
+                problems.push(format!(
+                    "Page {}: page is allocated but holds no rows",
+                    page_num
+                ));
+            } else if !is_last_page && !page.is_full() {
+                problems.push(format!(
+                    "Page {}: holds {} of {} rows but only the last page may be partially filled",
+                    page_num,
+                    page.rows.len(),
+                    page.max_rows
+                ));
+            }
+            rows_in_pages += page.rows.len();
+        }
+
+        if rows_in_pages != self.num_rows {
+            problems.push(format!(
+                "Table row count is {} but its pages hold {} rows",
+                self.num_rows, rows_in_pages
+            ));
+        }
+
+        problems
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{planner, PrepareResult, ResultSet, StatementType};
     use std::convert::TryFrom;
     use std::time::Instant;
     use heapless::String as HeapLessString;
+    use tempfile::NamedTempFile;
 
     // Helper function to create a dummy Row.
     fn dummy_row() -> Row {
@@ -70,11 +1042,25 @@ mod tests {
         }
     }
 
+    // Helper to list the values of a row as a select returns them.
+    fn row_values(row: &Row) -> Vec<Value> {
+        Row::COLUMNS.iter().map(|column| row.value(column).unwrap()).collect()
+    }
+
     // Helper to create a dummy Insert Statement.
     fn insert_statement(row: Option<Row>) -> Statement {
         Statement {
             statement_type: StatementType::Insert,
             row_to_insert: row,
+            select: None,
+        }
+    }
+
+    fn query_statement(select: Option<Select>) -> Statement {
+        Statement {
+            statement_type: StatementType::Select,
+            row_to_insert: None,
+            select,
         }
     }
 
@@ -83,6 +1069,20 @@ mod tests {
         Statement {
             statement_type: StatementType::Select,
             row_to_insert: None,
+            select: Some(Statement::parse_select_input(
+                "select *",
+                &TableDefinition::default(),
+                &[],
+            ).unwrap()),
+        }
+    }
+
+    // Helper to create a PRAGMA integrity_check Statement.
+    fn integrity_check_statement() -> Statement {
+        Statement {
+            statement_type: StatementType::IntegrityCheck,
+            row_to_insert: None,
+            select: None,
         }
     }
 
@@ -127,10 +1127,11 @@ mod tests {
         let result = table.execute(statement).expect("Select should succeed");
 
         match result {
-            ExecuteResult::Success(rows) => {
-                assert!(rows.is_empty(), "Expected no rows in a new table");
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(result_set.columns, Row::COLUMNS);
+                assert!(result_set.rows.is_empty(), "Expected no rows in a new table");
             }
-            _ => panic!("Expected ExecuteResult::Success, got {:?}", result),
+            _ => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
         }
     }
 
@@ -155,14 +1156,1827 @@ mod tests {
         let select_result = table.execute(select_statement).expect("Select should succeed");
 
         match select_result {
-            ExecuteResult::Success(rows) => {
+            ExecuteResult::ResultSet(result_set) => {
                 // Depending on your implementation, the table may contain both rows.
                 // Adjust the assertions if your logic updates num_rows or page boundaries differently.
-                assert_eq!(rows.len(), 2);
-                assert!(rows.contains(&row1));
-                assert!(rows.contains(&row2));
+                assert_eq!(result_set.rows.len(), 2);
+                assert!(result_set.rows.contains(&row_values(&row1)));
+                assert!(result_set.rows.contains(&row_values(&row2)));
+            }
+            _ => panic!("Expected ExecuteResult::ResultSet, got {:?}", select_result),
+        }
+    }
+
+    #[test]
+    fn test_execute_select_where() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "alice"), (2, "bob"), (3, "carol"), (4, "dave")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                email: HeapLessString::try_from(format!("{}@example.com", username).as_str())
+                    .unwrap(),
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select_ids = |table: &mut Table, input: &str| -> Vec<i64> {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set
+                    .rows
+                    .iter()
+                    .map(|row| match row[0] {
+                        Value::Integer(id) => id,
+                        _ => panic!("Expected an integer id, got {:?}", row[0]),
+                    })
+                    .collect(),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        assert_eq!(select_ids(&mut table, "select where id % 2 = 0"), vec![2, 4]);
+        assert_eq!(
+            select_ids(
+                &mut table,
+                "select where username >= 'bob' and not email = 'dave@example.com'"
+            ),
+            vec![2, 3]
+        );
+        assert_eq!(
+            select_ids(&mut table, "select where (id < 2 or id > 3) and id * 10 != 40"),
+            vec![1]
+        );
+        assert_eq!(select_ids(&mut table, "select where 1"), vec![1, 2, 3, 4]);
+
+        let select = Statement::parse_select_input(
+            "select where id / 0",
+            &TableDefinition::default(),
+            &[],
+        ).unwrap();
+        let err = table.execute(query_statement(Some(select))).unwrap_err();
+        assert_eq!(err.to_string(), "Division by zero");
+    }
+
+    #[test]
+    fn test_execute_select_columns() {
+        let mut table = Table::new();
+        table.execute(insert_statement(Some(dummy_row()))).unwrap();
+
+        let select = Statement::parse_select_input(
+            "select username, id * 10 + 1 as score, * where id = 1",
+            &TableDefinition::default(),
+            &[],
+        )
+        .unwrap();
+        match table.execute(query_statement(Some(select))).unwrap() {
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(
+                    result_set.columns,
+                    vec!["username", "score", "id", "username", "email"]
+                );
+                assert_eq!(
+                    result_set.rows,
+                    vec![vec![
+                        Value::Text("alice".to_string()),
+                        Value::Integer(11),
+                        Value::Integer(1),
+                        Value::Text("alice".to_string()),
+                        Value::Text("alice@example.com".to_string()),
+                    ]]
+                );
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+
+        let err = table.execute(query_statement(None)).unwrap_err();
+        assert_eq!(err.to_string(), "No query for select");
+    }
+
+    #[test]
+    fn test_execute_select_order_by() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                email: HeapLessString::try_from("user@example.com").unwrap(),
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select_ids = |table: &mut Table, input: &str| -> Vec<Vec<Value>> {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.rows,
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let ids = |ids: &[i64]| -> Vec<Vec<Value>> {
+            ids.iter().map(|id| vec![Value::Integer(*id)]).collect()
+        };
+        assert_eq!(
+            select_ids(&mut table, "select id order by username, id desc"),
+            ids(&[4, 2, 3, 1])
+        );
+        assert_eq!(
+            select_ids(&mut table, "select id where id > 1 order by id % 3 desc, id"),
+            ids(&[2, 4, 3])
+        );
+        assert_eq!(
+            select_ids(&mut table, "select -id as negated order by negated"),
+            ids(&[-4, -3, -2, -1])
+        );
+        assert_eq!(select_ids(&mut table, "select id order by 1 desc"), ids(&[4, 3, 2, 1]));
+    }
+
+    #[test]
+    fn test_execute_select_order_by_spills() {
+        let mut table = Table::new();
+        table.memory_budget = 4096;
+        for id in 0..1000 {
+            let row = Row {
+                id: (id * 7919) % 1000,
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = Statement::parse_select_input(
+            "select * order by id desc",
+            &TableDefinition::default(),
+            &[],
+        ).unwrap();
+        match table.execute(query_statement(Some(select))).unwrap() {
+            ExecuteResult::ResultSet(result_set) => {
+                let expected: Vec<Vec<Value>> = (0..1000)
+                    .rev()
+                    .map(|id| row_values(&Row { id, ..dummy_row() }))
+                    .collect();
+                assert_eq!(result_set.rows, expected);
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_execute_select_limit_offset() {
+        let mut table = Table::new();
+        for id in 1..=10 {
+            let row = Row { id, ..dummy_row() };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select_ids = |table: &mut Table, input: &str| -> Vec<Vec<Value>> {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.rows,
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let ids = |ids: &[i64]| -> Vec<Vec<Value>> {
+            ids.iter().map(|id| vec![Value::Integer(*id)]).collect()
+        };
+        assert_eq!(select_ids(&mut table, "select id limit 3"), ids(&[1, 2, 3]));
+        assert_eq!(
+            select_ids(&mut table, "select id where id % 2 = 0 limit 2 offset 1"),
+            ids(&[4, 6])
+        );
+        assert_eq!(
+            select_ids(&mut table, "select id order by id desc limit 2 offset 3"),
+            ids(&[7, 6])
+        );
+        assert_eq!(select_ids(&mut table, "select id limit 5 offset 20"), ids(&[]));
+        assert_eq!(select_ids(&mut table, "select id limit 0"), ids(&[]));
+    }
+
+    #[test]
+    fn test_execute_select_limit_stops_scanning() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions {
+            key: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut table = Table::open(file.path(), &options).unwrap();
+        for _ in 0..Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1 {
+            table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        }
+        table.close().unwrap();
+
+        // Corrupt the last page: only a select that reads it fails.
+        let mut contents = std::fs::read(file.path()).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 1;
+        std::fs::write(file.path(), contents).unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let select = Statement::parse_select_input(
+            "select limit 3",
+            &TableDefinition::default(),
+            &[],
+        ).unwrap();
+        match table.execute(query_statement(Some(select))).unwrap() {
+            ExecuteResult::ResultSet(result_set) => assert_eq!(result_set.rows.len(), 3),
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+        let select =
+            Statement::parse_select_input("select", &TableDefinition::default(), &[]).unwrap();
+        assert!(table.execute(query_statement(Some(select))).is_err());
+    }
+
+    #[test]
+    fn test_execute_select_group_by() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice"), (5, "bob")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = |table: &mut Table, input: &str| -> ResultSet {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set,
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let result_set = select(
+            &mut table,
+            "select username, count(*) as n, sum(id), avg(id) where id > 1 \
+             group by username having max(id) > 3 order by username",
+        );
+        assert_eq!(result_set.columns, vec!["username", "n", "sum(id)", "avg(id)"]);
+        assert_eq!(
+            result_set.to_string(),
+            "username|n|sum(id)|avg(id)\nalice|2|6|3.0\nbob|2|8|4.0"
+        );
+
+        let result_set = select(&mut table, "select id % 2 as odd, min(username) group by odd");
+        assert_eq!(result_set.to_string(), "odd|min(username)\n0|alice\n1|bob");
+
+        let result_set = select(&mut table, "select id % 2, min(username) group by 1 order by 1");
+        assert_eq!(result_set.to_string(), "id % 2|min(username)\n0|alice\n1|bob");
+
+        let result_set = select(&mut table, "select count(id), max(id) - min(id) + 1");
+        assert_eq!(result_set.to_string(), "count(id)|(max(id) - min(id)) + 1\n5|5");
+
+        let result_set = select(&mut table, "select count(*), sum(id) where id > 10");
+        assert_eq!(result_set.to_string(), "count(*)|sum(id)\n0|NULL");
+
+        // Columns that are not grouped come from the first row of the group, or are NULL
+        // for the group of no rows.
+        let result_set = select(&mut table, "select username, id, count(*) group by username");
+        assert_eq!(result_set.to_string(), "username|id|count(*)\nalice|2|2\nbob|3|2\ncarol|1|1");
+        let result_set = select(&mut table, "select id, max(id) where id > 10");
+        assert_eq!(result_set.to_string(), "id|max(id)\nNULL|NULL");
+        let result_set = select(&mut table, "select count(*) where id > 10 group by username");
+        assert_eq!(result_set.to_string(), "count(*)");
+
+        // The rows of a common table or of a query filtered by a subquery come from the
+        // executor, and are grouped and sorted the same way.
+        let result_set = select(
+            &mut table,
+            "with t as (select username as name, id from users) \
+             select name, sum(id) from t group by name order by 2 desc limit 1",
+        );
+        assert_eq!(result_set.to_string(), "name|sum(id)\nbob|8");
+        let result_set = select(
+            &mut table,
+            "select username, count(*) where id in (select id from users where id > 2) \
+             group by username having count(*) > 1",
+        );
+        assert_eq!(result_set.to_string(), "username|count(*)\nbob|2");
+        let result_set = select(
+            &mut table,
+            "select a.username, count(*) from users a join users b on a.username = b.username \
+             group by a.username order by 1 desc",
+        );
+        assert_eq!(result_set.to_string(), "a.username|count(*)\ncarol|1\nbob|4\nalice|4");
+    }
+
+    #[test]
+    fn test_execute_select_group_by_spills() {
+        let mut table = Table::new();
+        table.memory_budget = 4096;
+        for id in 0..1000 {
+            let row = Row {
+                id: (id * 7919) % 1000,
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = Statement::parse_select_input(
+            "select id % 10 as digit, count(*), min(id), max(id) group by digit",
+            &TableDefinition::default(),
+            &[],
+        ).unwrap();
+        match table.execute(query_statement(Some(select))).unwrap() {
+            ExecuteResult::ResultSet(result_set) => {
+                let expected: Vec<Vec<Value>> = (0..10)
+                    .map(|digit| {
+                        [digit, 100, digit, 990 + digit].map(Value::Integer).to_vec()
+                    })
+                    .collect();
+                assert_eq!(result_set.rows, expected);
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_execute_select_joins() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "dave")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = |table: &mut Table, input: &str| -> String {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        assert_eq!(
+            select(
+                &mut table,
+                "select a.username, b.username from users a join users b on b.id = a.id + 1"
+            ),
+            "a.username|b.username\ncarol|alice\nalice|bob\nbob|dave"
+        );
+        assert_eq!(
+            select(
+                &mut table,
+                "select a.id, b.id from users as a left outer join users as b on b.id = a.id * 2"
+            ),
+            "a.id|b.id\n1|2\n2|4\n3|NULL\n4|NULL"
+        );
+        assert_eq!(
+            select(&mut table, "select count(*) from users a cross join users b"),
+            "count(*)\n16"
+        );
+        assert_eq!(
+            select(
+                &mut table,
+                "select a.id, b.id from users a, users b where a.id + b.id = 7 order by a.id"
+            ),
+            "a.id|b.id\n3|4\n4|3"
+        );
+        assert_eq!(
+            select(
+                &mut table,
+                "select a.id, c.id from users a join users b on b.id = a.id + 1 \
+                 inner join users c on c.id = b.id + 1 limit 1"
+            ),
+            "a.id|c.id\n1|3"
+        );
+        assert_eq!(
+            select(
+                &mut table,
+                "select a.username, count(b.id) from users a left join users b on b.id > a.id \
+                 group by a.username order by a.username"
+            ),
+            "a.username|count(b.id)\nalice|2\nbob|1\ncarol|3\ndave|0"
+        );
+        // The planner joins c before the cross join with b, but `*` keeps the FROM order.
+        assert_eq!(
+            select(
+                &mut table,
+                "select * from users a cross join users b join users c on c.id = a.id + 2 \
+                 left join users d on d.id = b.id * 2 where b.id = 1 order by a.id"
+            ),
+            "id|username|email|id|username|email|id|username|email|id|username|email\n\
+             1|carol|alice@example.com|1|carol|alice@example.com|\
+             3|bob|alice@example.com|2|alice|alice@example.com\n\
+             2|alice|alice@example.com|1|carol|alice@example.com|\
+             4|dave|alice@example.com|2|alice|alice@example.com"
+        );
+    }
+
+    #[test]
+    fn test_execute_select_common_tables() {
+        let mut table = Table::new();
+        // Each user reports to the user whose id is half of theirs.
+        for (id, username) in [(1, "ceo"), (2, "cto"), (3, "cfo"), (4, "dev"), (5, "ops")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = |table: &mut Table, input: &str| -> Result<String> {
+            let select = Statement::parse_select_input(input, &TableDefinition::default(), &[])?;
+            match table.execute(query_statement(Some(select)))? {
+                ExecuteResult::ResultSet(result_set) => Ok(result_set.to_string()),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let cases = [
+            (
+                "with managers as (select id, username from users where id < 3) \
+                 select m.username, u.username from users u join managers m on u.id / 2 = m.id",
+                "m.username|u.username\nceo|cto\nceo|cfo\ncto|dev\ncto|ops",
+            ),
+            (
+                "with recursive reports(id, depth) as (select id, 0 from users where id = 2 \
+                 union all select u.id, r.depth + 1 from reports r \
+                 join users u on u.id / 2 = r.id) \
+                 select username, depth from reports join users on users.id = reports.id \
+                 order by users.id",
+                "username|depth\ncto|0\ndev|1\nops|1",
+            ),
+            (
+                "with recursive chain(id) as (select 5 \
+                 union all select id / 2 from chain where id > 1) select * from chain",
+                "id\n5\n2\n1",
+            ),
+            // UNION drops rows already produced, which ends a recursion that cycles.
+            (
+                "with recursive cycle(n) as (select 1 union select (n + 1) % 3 from cycle) \
+                 select n from cycle order by n",
+                "n\n0\n1\n2",
+            ),
+            // A common table expression hides the table and is visible to subqueries.
+            (
+                "with users(id) as (select 2) \
+                 select count(*), (select max(id) from users) from users",
+                "count(*)|(SELECT max(id) FROM users)\n1|2",
+            ),
+            (
+                "select id from users a where id = (with t as \
+                 (select id + 1 as next from users b where b.id = a.id) select * from t) - 1",
+                "id\n1\n2\n3\n4\n5",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(select(&mut table, input).unwrap(), expected, "{}", input);
+        }
+
+        let runaway = "with recursive count(n) as (select 1 \
+                       union all select n + 1 from count) select * from count";
+        let err = select(&mut table, runaway).unwrap_err();
+        assert_eq!(err.to_string(), "Recursion limit of 1000 exceeded by count");
+        let statement = Statement {
+            statement_type: StatementType::RecursionLimit(Some(3)),
+            row_to_insert: None,
+            select: None,
+        };
+        table.execute(statement).unwrap();
+        let err = select(&mut table, runaway).unwrap_err();
+        assert_eq!(err.to_string(), "Recursion limit of 3 exceeded by count");
+        let bounded = "with recursive c(n) as (select 1 \
+                       union all select n + 1 from c where n < 4) select * from c";
+        assert_eq!(select(&mut table, bounded).unwrap(), "n\n1\n2\n3\n4");
+        assert!(table.common_tables.is_empty());
+    }
+
+    #[test]
+    fn test_execute_common_table_without_from() {
+        let mut table = Table::new();
+        let input = "with recursive c(n) as (select 1 union all select n + 1 from c where n < 5) \
+                     select n from c";
+        let select = |table: &mut Table| {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        assert_eq!(select(&mut table), "n\n1\n2\n3\n4\n5");
+        for id in 1..5 {
+            let row = Row { id, ..dummy_row() };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+        assert_eq!(select(&mut table), "n\n1\n2\n3\n4\n5");
+    }
+
+    #[test]
+    fn test_execute_explain() {
+        let mut table = Table::new();
+        table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        let mut explain = |input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => match table.execute(*statement).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            },
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+        assert_eq!(
+            explain("explain select username where id = 1"),
+            "addr|opcode|operands\n\
+             0|OpenRead|cursor=0 table=users\n\
+             1|Rewind|cursor=0 target=9\n\
+             2|Column|cursor=0 column=0 register=0\n\
+             3|Value|value=1 register=1\n\
+             4|Binary|operator== left=0 right=1 register=0\n\
+             5|IfNot|register=0 target=8 null=true\n\
+             6|Column|cursor=0 column=1 register=2\n\
+             7|ResultRow|start=2 count=1\n\
+             8|Next|cursor=0 target=2\n\
+             9|Halt|"
+        );
+        assert_eq!(
+            explain("explain query plan select count(*) from users a join users b on a.id = b.id"),
+            "id|parent|detail\n\
+             1|0|SCAN users AS a (~1 rows)\n\
+             2|0|NESTED LOOP JOIN users AS b (~1 rows)\n\
+             3|0|AGGREGATE"
+        );
+        // Explaining a statement does not run it.
+        assert!(explain("explain insert 2 bob bob@example.com").ends_with("|Halt|"));
+        assert_eq!(table.num_rows, 1);
+    }
+
+    #[test]
+    fn test_execute_analyze() {
+        let mut table = Table::new();
+        for id in 1..=20 {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(format!("user{}", id % 2).as_str()).unwrap(),
+                email: HeapLessString::try_from("user@example.com").unwrap(),
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+        let mut run = |input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => match table.execute(*statement).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                ExecuteResult::Success(rows) => format!("{} rows", rows.len()),
+                result => panic!("Expected a result set, got {:?}", result),
+            },
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+        let plan = "explain query plan select id where username = 'user1' and id > 15";
+        assert_eq!(
+            run(plan),
+            "id|parent|detail\n1|0|SCAN users (~20 rows)\n2|0|FILTER (~20 rows)"
+        );
+        assert_eq!(run("select count(*) from zero_stat"), "count(*)\n0");
+
+        assert_eq!(run("analyze"), "0 rows");
+        assert_eq!(
+            run("select col, num_rows, num_distinct, max(upper_bound) from zero_stat group by col"),
+            "col|num_rows|num_distinct|max(upper_bound)\n\
+             email|20|1|user@example.com\n\
+             id|20|20|20\n\
+             username|20|2|user1"
+        );
+        assert_eq!(
+            run(plan),
+            "id|parent|detail\n1|0|SCAN users (~20 rows)\n2|0|FILTER (~4 rows)"
+        );
+        // A common table expression hides the statistics.
+        assert_eq!(
+            run("with zero_stat(n) as (select 1) select n from zero_stat"),
+            "n\n1"
+        );
+    }
+
+    #[test]
+    fn test_execute_create_index() {
+        let mut table = Table::new();
+        let insert = |table: &mut Table, id: i32| {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(format!("user{}", id % 4).as_str()).unwrap(),
+                email: HeapLessString::try_from("user@example.com").unwrap(),
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        };
+        for id in (1..=40).rev() {
+            insert(&mut table, id);
+        }
+        let run = |table: &mut Table, input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => match table.execute(*statement).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                ExecuteResult::Success(rows) => format!("{} rows", rows.len()),
+                result => panic!("Expected a result set, got {:?}", result),
+            },
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+        let queries = [
+            "select id from users where username = 'user1' and id > 20",
+            "select id from users where id <= 3 or id = 40",
+            "select a.id, b.id from users a join users b on b.username = a.username \
+             where a.id = 7 and b.id < 12",
+            "select count(*) from users where username = 'user2'",
+        ];
+        let scanned: Vec<String> = queries.iter().map(|q| run(&mut table, q)).collect();
+
+        assert_eq!(run(&mut table, "create index idx on users (username, id)"), "0 rows");
+        let err = table
+            .execute(match Statement::prepare(
+                "create index idx on users (email)",
+                &TableDefinition::default(),
+                &[],
+                &[],
+            ) {
+                PrepareResult::Success(statement) => *statement,
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Index idx already exists");
+        for (query, expected) in queries.iter().zip(&scanned) {
+            assert_eq!(run(&mut table, query), *expected, "{}", query);
+        }
+        assert_eq!(
+            run(&mut table, &format!("explain query plan {}", queries[0])),
+            "id|parent|detail\n\
+             1|0|SEARCH users USING INDEX idx (username=? AND id>?) (~1 rows)\n\
+             2|0|FILTER (~1 rows)"
+        );
+        let program = run(&mut table, &format!("explain {}", queries[0]));
+        assert!(program.contains("|OpenIndex|"), "{}", program);
+
+        // Rows inserted after the index was created are found through it.
+        insert(&mut table, 41);
+        assert_eq!(
+            run(&mut table, "select id, username from users where username = 'user1' and id > 36"),
+            "id|username\n37|user1\n41|user1"
+        );
+
+        // Without the index, the same query scans the table again.
+        assert_eq!(run(&mut table, "drop index idx"), "0 rows");
+        assert_eq!(
+            run(&mut table, &format!("explain query plan {}", queries[0])),
+            "id|parent|detail\n1|0|SCAN users (~41 rows)\n2|0|FILTER (~41 rows)"
+        );
+        assert_eq!(run(&mut table, queries[0]), "id\n37\n33\n29\n25\n21\n41");
+        let err = table.drop_index("idx").unwrap_err();
+        assert_eq!(err.to_string(), "No such index: idx");
+    }
+
+    #[test]
+    fn test_execute_unique_index() {
+        let mut table = Table::new();
+        let row = |id: i32, username: &str, email: &str| Row {
+            id,
+            username: HeapLessString::try_from(username).unwrap(),
+            email: HeapLessString::try_from(email).unwrap(),
+        };
+        for (id, username) in [(1, "alice"), (2, "bob"), (3, "alice")] {
+            let email = format!("{}{}@example.com", username, id);
+            let result = table.execute(insert_statement(Some(row(id, username, &email))));
+            assert!(matches!(result.unwrap(), ExecuteResult::Success(_)));
+        }
+        let execute = |table: &mut Table, input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => table.execute(*statement).unwrap(),
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+        let violation = |result: ExecuteResult| match result {
+            ExecuteResult::ExecutionFailure(ExecutionFailure::UniqueViolation {
+                constraint,
+                value,
+            }) => (constraint, value),
+            result => panic!("Expected a unique violation, got {:?}", result),
+        };
+
+        // Two rows already share a username, so the index is not created.
+        let result = execute(&mut table, "create unique index by_name on users (username)");
+        assert_eq!(violation(result), ("by_name".to_string(), "alice".to_string()));
+        assert!(table.indexes.is_empty());
+
+        execute(&mut table, "create unique index by_email on users (email)");
+        execute(&mut table, "create unique index by_name_id on users (username, id)");
+        let duplicates = [
+            (row(4, "carol", "bob2@example.com"), "by_email", "bob2@example.com"),
+            (row(1, "alice", "alice4@example.com"), "by_name_id", "alice, 1"),
+        ];
+        for (row, constraint, value) in duplicates {
+            let result = table.execute(insert_statement(Some(row))).unwrap();
+            assert_eq!(violation(result), (constraint.to_string(), value.to_string()));
+        }
+        // Nothing of the rejected rows was written, to the table or to any index.
+        assert_eq!(table.num_rows, 3);
+        let result = table.execute(insert_statement(Some(row(1, "carol", "alice4@example.com"))));
+        assert!(matches!(result.unwrap(), ExecuteResult::Success(_)));
+        let select = "select id, username from users where email = 'alice4@example.com'";
+        match execute(&mut table, select) {
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(result_set.to_string(), "id|username\n1|carol")
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_unique_index_survives_reopen_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("unique.db");
+        let saved = dir.path().join("saved.db");
+        let options = DatabaseOptions::default();
+        let insert = |table: &mut Table, id: i32| {
+            let row = Row { id, ..dummy_row() };
+            table.execute(insert_statement(Some(row))).unwrap()
+        };
+        let is_violation = |result: ExecuteResult| {
+            matches!(
+                result,
+                ExecuteResult::ExecutionFailure(ExecutionFailure::UniqueViolation { .. })
+            )
+        };
+
+        let mut table = Table::open(&file, &options).unwrap();
+        insert(&mut table, 1);
+        match Statement::prepare(
+            "create unique index by_id on users (id)",
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => table.execute(*statement).unwrap(),
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+        table.close().unwrap();
+
+        let mut table = Table::open(&file, &options).unwrap();
+        assert!(is_violation(insert(&mut table, 1)));
+        assert!(matches!(insert(&mut table, 2), ExecuteResult::Success(_)));
+        table.save(&saved, &options).unwrap();
+
+        let mut table = Table::open(&saved, &options).unwrap();
+        assert!(is_violation(insert(&mut table, 2)));
+        assert_eq!(table.num_rows, 2);
+    }
+
+    #[test]
+    fn test_quoted_index_names_survive_reopen() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let run = |table: &mut Table, input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => table.execute(*statement).unwrap(),
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        run(&mut table, "create index \"my idx\" on users (username)");
+        run(&mut table, "create unique index \"select\" on users (id)");
+        assert_eq!(
+            table.catalog().definitions,
+            vec![
+                "CREATE INDEX \"my idx\" ON users (username)",
+                "CREATE UNIQUE INDEX \"select\" ON users (id)",
+            ]
+        );
+        table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let names: Vec<&str> = table.indexes.iter().map(|index| index.name.as_str()).collect();
+        assert_eq!(names, vec!["my idx", "select"]);
+        assert!(matches!(
+            table.execute(insert_statement(Some(dummy_row()))).unwrap(),
+            ExecuteResult::ExecutionFailure(ExecutionFailure::UniqueViolation { .. })
+        ));
+        run(&mut table, "drop index \"my idx\"");
+        assert_eq!(table.indexes.len(), 1);
+    }
+
+    #[test]
+    fn test_check_constraints() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let insert = |table: &mut Table, id: i32| {
+            let row = Row { id, ..dummy_row() };
+            table.execute(insert_statement(Some(row))).unwrap()
+        };
+        let run = |table: &mut Table, input: &str| match Statement::prepare(
+            input,
+            &TableDefinition::default(),
+            &[],
+            &[],
+        ) {
+            PrepareResult::Success(statement) => table.execute(*statement),
+            result => panic!("Expected PrepareResult::Success, got {:?}", result),
+        };
+        let violated = |result: ExecuteResult| match result {
+            ExecuteResult::ExecutionFailure(ExecutionFailure::CheckViolation { constraint }) => {
+                constraint
+            }
+            result => panic!("Expected a CHECK constraint failure, got {:?}", result),
+        };
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        insert(&mut table, 5);
+        let add = "alter table users add constraint small check (id < 10 and id % 2 = 1)";
+        run(&mut table, add).unwrap();
+        let err = run(&mut table, add).unwrap_err();
+        assert_eq!(err.to_string(), "Constraint small already exists");
+        let result = run(&mut table, "alter table users add constraint big check (id > 5)");
+        assert_eq!(violated(result.unwrap()), "big");
+        assert_eq!(violated(insert(&mut table, 12)), "small");
+        assert_eq!(violated(insert(&mut table, 4)), "small");
+        assert!(matches!(insert(&mut table, 7), ExecuteResult::Success(_)));
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let names: Vec<&str> = table.checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(names, vec!["small"]);
+        assert_eq!(violated(insert(&mut table, 11)), "small");
+        assert_eq!(table.num_rows, 2);
+
+        run(&mut table, "alter table users drop constraint small").unwrap();
+        let err = run(&mut table, "alter table users drop constraint small").unwrap_err();
+        assert_eq!(err.to_string(), "No such constraint: small");
+        assert!(matches!(insert(&mut table, 12), ExecuteResult::Success(_)));
+        table.close().unwrap();
+        let table = Table::open(file.path(), &options).unwrap();
+        assert!(table.checks.is_empty());
+    }
+
+    #[test]
+    fn test_column_defaults() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let run = |table: &mut Table, input: &str| -> Result<ExecuteResult> {
+            match Statement::prepare(input, &table.definition, &table.views, &table.defaults) {
+                PrepareResult::Success(statement) => table.execute(*statement),
+                PrepareResult::SyntaxError(err) => Err(anyhow!(err)),
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            }
+        };
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let err = run(&mut table, "insert 1 alice").unwrap_err();
+        assert_eq!(err.to_string(), "Email not provided");
+        run(&mut table, "alter table users alter column email set default 'none'").unwrap();
+        run(&mut table, "alter table users alter username set default 'nobody'").unwrap();
+        run(&mut table, "insert 1 alice").unwrap();
+        run(&mut table, "insert 2").unwrap();
+        let err = run(&mut table, "insert").unwrap_err();
+        assert_eq!(err.to_string(), "No id provided");
+        let err = run(&mut table, "alter table users alter age set default 1").unwrap_err();
+        assert_eq!(err.to_string(), "No such column: age");
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let columns: Vec<&str> = table.defaults.iter().map(|d| d.column.as_str()).collect();
+        assert_eq!(columns, vec!["username", "email"]);
+        run(&mut table, "alter table users alter column username drop default").unwrap();
+        let err = run(&mut table, "insert 3").unwrap_err();
+        assert_eq!(err.to_string(), "Username not provided");
+        run(&mut table, "insert 3 carol").unwrap();
+        let ExecuteResult::ResultSet(result_set) =
+            run(&mut table, "select id, username, email").unwrap()
+        else {
+            panic!("Expected a result set");
+        };
+        let row = |id: i64, username: &str| {
+            vec![
+                Value::Integer(id),
+                Value::Text(username.to_string()),
+                Value::Text("none".to_string()),
+            ]
+        };
+        assert_eq!(
+            result_set.rows,
+            vec![row(1, "alice"), row(2, "nobody"), row(3, "carol")]
+        );
+        table.close().unwrap();
+        let table = Table::open(file.path(), &options).unwrap();
+        assert_eq!(table.defaults.len(), 1);
+        assert_eq!(
+            table.catalog().definitions,
+            vec!["ALTER TABLE users ALTER COLUMN email SET DEFAULT 'none'"]
+        );
+    }
+
+    #[test]
+    fn test_drop_table_reuses_pages() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let run = |table: &mut Table, input: &str| -> Result<ExecuteResult> {
+            match Statement::prepare(input, &table.definition, &table.views, &table.defaults) {
+                PrepareResult::Success(statement) => table.execute(*statement),
+                PrepareResult::SyntaxError(err) => Err(anyhow!(err)),
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            }
+        };
+        let insert_rows = |table: &mut Table| {
+            for id in 0..2 * Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1 {
+                run(table, &format!("insert {} user{} user@example.com", id, id)).unwrap();
+            }
+        };
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        insert_rows(&mut table);
+        run(&mut table, "create unique index by_id on users (id)").unwrap();
+        run(&mut table, "create view names as select username from users").unwrap();
+        table.close().unwrap();
+        let file_len = std::fs::metadata(file.path()).unwrap().len();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        run(&mut table, "drop table users").unwrap();
+        assert_eq!(table.num_rows, 0);
+        assert!(table.indexes.is_empty());
+        assert!(table.integrity_check().is_empty());
+        for input in [
+            "select * from users",
+            "select count(*) from users",
+            "select * from names",
+            "insert 1 alice alice@example.com",
+            "create index by_name on users (username)",
+            "drop table users",
+        ] {
+            let err = run(&mut table, input).unwrap_err();
+            assert_eq!(err.to_string(), "No such table: users", "{}", input);
+        }
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert!(!table.exists);
+        assert_eq!(
+            table.catalog().definitions,
+            vec![
+                "DROP TABLE users",
+                "CREATE VIEW names(username) AS SELECT username FROM users"
+            ]
+        );
+        run(&mut table, "create table users (id integer, username text, email text)").unwrap();
+        let err = run(&mut table, "create table users (id integer, username text, email text)");
+        assert_eq!(err.unwrap_err().to_string(), "Table users already exists");
+        insert_rows(&mut table);
+        assert_eq!(table.pager.num_pages(), 3);
+        assert!(table.integrity_check().is_empty());
+        table.close().unwrap();
+        // The rows went back into the freed pages, and only the catalog lost the index.
+        assert!(std::fs::metadata(file.path()).unwrap().len() < file_len);
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert!(table.catalog().definitions[0].starts_with("CREATE VIEW"));
+        let ExecuteResult::ResultSet(result_set) =
+            run(&mut table, "select count(*) from names").unwrap()
+        else {
+            panic!("Expected a result set");
+        };
+        assert_eq!(result_set.rows, vec![vec![Value::Integer(27)]]);
+    }
+
+    #[test]
+    fn test_rename_table_and_columns() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let run = |table: &mut Table, input: &str| -> Result<ExecuteResult> {
+            match Statement::prepare(input, &table.definition, &table.views, &table.defaults) {
+                PrepareResult::Success(statement) => table.execute(*statement),
+                PrepareResult::SyntaxError(err) => Err(anyhow!(err)),
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            }
+        };
+        let query = |table: &mut Table, input: &str| {
+            let ExecuteResult::ResultSet(result_set) = run(table, input).unwrap() else {
+                panic!("Expected a result set");
+            };
+            result_set
+        };
+        let failure = |result: ExecuteResult| match result {
+            ExecuteResult::ExecutionFailure(failure) => failure,
+            result => panic!("Expected an execution failure, got {:?}", result),
+        };
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        for input in [
+            "insert 1 alice alice",
+            "insert 2 bob alice",
+            "create unique index by_name on users (username)",
+            "alter table users add constraint positive check (users.id > 0 and username <> '')",
+            "alter table users alter column email set default 'alice'",
+            "alter table users add constraint boss foreign key (email) references users \
+             (username)",
+            "analyze",
+            "create view names as select username from users",
+        ] {
+            run(&mut table, input).unwrap();
+        }
+        let cases = [
+            (
+                "alter table users rename username to name",
+                "Error in view names: No such column: username",
+            ),
+            (
+                "alter table users rename username to email",
+                "Duplicate column name: email",
+            ),
+            ("alter table users rename age to name", "No such column: age"),
+            ("alter table users rename to names", "Table names already exists"),
+            ("alter table users rename to zero_stat", "Table zero_stat already exists"),
+            ("alter table users rename to users", "Table users already exists"),
+        ];
+        for (input, expected) in cases {
+            let err = run(&mut table, input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+        run(&mut table, "drop view names").unwrap();
+        run(&mut table, "create view ids as select id from users").unwrap();
+        run(&mut table, "alter table users rename column username to name").unwrap();
+        let err = run(&mut table, "alter table users rename to people").unwrap_err();
+        assert_eq!(err.to_string(), "Error in view ids: No such table: users");
+        run(&mut table, "drop view ids").unwrap();
+        run(&mut table, "alter table users rename to people").unwrap();
+
+        let err = run(&mut table, "select * from users").unwrap_err();
+        assert_eq!(err.to_string(), "No such table: users");
+        run(&mut table, "insert 3 carol").unwrap();
+        let result_set = query(&mut table, "select name, email from people where id = 3");
+        assert_eq!(result_set.columns, vec!["name", "email"]);
+        assert_eq!(
+            result_set.rows,
+            vec![vec![
+                Value::Text("carol".to_string()),
+                Value::Text("alice".to_string())
+            ]]
+        );
+        assert!(matches!(
+            failure(run(&mut table, "insert 4 bob bob").unwrap()),
+            ExecutionFailure::UniqueViolation { .. }
+        ));
+        assert!(matches!(
+            failure(run(&mut table, "insert 0 dave dave").unwrap()),
+            ExecutionFailure::CheckViolation { .. }
+        ));
+        assert!(matches!(
+            failure(run(&mut table, "insert 4 dave erin").unwrap()),
+            ExecutionFailure::ForeignKeyViolation { .. }
+        ));
+        let result_set = query(&mut table, "select tbl, col from zero_stat where bucket = 0");
+        assert_eq!(
+            result_set.rows[1],
+            vec![
+                Value::Text("people".to_string()),
+                Value::Text("name".to_string())
+            ]
+        );
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert_eq!(
+            table.catalog().definitions,
+            vec![
+                "CREATE TABLE people (id INTEGER, name TEXT, email TEXT)",
+                "CREATE UNIQUE INDEX by_name ON people (name)",
+                "ALTER TABLE people ADD CONSTRAINT positive CHECK ((people.id > 0) AND \
+                 (name != ''))",
+                "ALTER TABLE people ALTER COLUMN email SET DEFAULT 'alice'",
+                "ALTER TABLE people ADD CONSTRAINT boss FOREIGN KEY (email) REFERENCES people \
+                 (name) ON DELETE RESTRICT",
+            ]
+        );
+        assert_eq!(table.num_rows, 3);
+        assert!(matches!(
+            failure(run(&mut table, "insert 4 bob bob").unwrap()),
+            ExecutionFailure::UniqueViolation { .. }
+        ));
+        assert!(matches!(
+            failure(run(&mut table, "insert 4 dave erin").unwrap()),
+            ExecutionFailure::ForeignKeyViolation { .. }
+        ));
+        run(&mut table, "alter table people rename to users").unwrap();
+        run(&mut table, "alter table users rename name to username").unwrap();
+        assert_eq!(
+            table.catalog().definitions[0],
+            "CREATE UNIQUE INDEX by_name ON users (username)"
+        );
+
+        run(&mut table, "drop table users").unwrap();
+        run(&mut table, "create table \"my people\" (id integer, name text, email text)").unwrap();
+        run(&mut table, "insert 1 alice alice@example.com").unwrap();
+        table.close().unwrap();
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let result_set = query(&mut table, "select name from \"my people\"");
+        assert_eq!(result_set.rows, vec![vec![Value::Text("alice".to_string())]]);
+        run(&mut table, "drop table \"my people\"").unwrap();
+        table.close().unwrap();
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert_eq!(table.catalog().definitions, vec!["DROP TABLE \"my people\""]);
+        let err = run(&mut table, "insert 1 alice alice@example.com").unwrap_err();
+        assert_eq!(err.to_string(), "No such table: my people");
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let run = |table: &mut Table, input: &str| -> Result<ExecuteResult> {
+            match Statement::prepare(input, &table.definition, &table.views, &table.defaults) {
+                PrepareResult::Success(statement) => table.execute(*statement),
+                PrepareResult::SyntaxError(err) => Err(anyhow!(err)),
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            }
+        };
+        let violated = |result: ExecuteResult| match result {
+            ExecuteResult::ExecutionFailure(ExecutionFailure::ForeignKeyViolation {
+                constraint,
+            }) => constraint,
+            result => panic!("Expected a FOREIGN KEY constraint failure, got {:?}", result),
+        };
+        let usernames = |table: &mut Table| {
+            let ExecuteResult::ResultSet(result_set) =
+                run(table, "select username from users").unwrap()
+            else {
+                panic!("Expected a result set");
+            };
+            let usernames: Vec<Value> = result_set.rows.into_iter().flatten().collect();
+            usernames
+        };
+        let text = |values: &[&str]| -> Vec<Value> {
+            values.iter().map(|value| Value::Text(value.to_string())).collect()
+        };
+        let boss = "alter table users add constraint boss foreign key (email) \
+                    references users (username)";
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        for input in ["insert 1 alice alice", "insert 2 bob alice", "insert 3 carol bob"] {
+            run(&mut table, input).unwrap();
+        }
+        let err = run(&mut table, boss).unwrap_err();
+        assert_eq!(err.to_string(), "Foreign key boss requires a unique index on username");
+        run(&mut table, "create unique index by_name on users (username)").unwrap();
+        run(&mut table, boss).unwrap();
+        let err = run(&mut table, boss).unwrap_err();
+        assert_eq!(err.to_string(), "Constraint boss already exists");
+        let err = run(&mut table, "drop index by_name").unwrap_err();
+        assert_eq!(err.to_string(), "Index by_name is needed by foreign key boss");
+
+        assert_eq!(violated(run(&mut table, "insert 4 dave erin").unwrap()), "boss");
+        run(&mut table, "insert 4 dave dave").unwrap();
+        assert_eq!(
+            violated(run(&mut table, "delete from users where username = 'bob'").unwrap()),
+            "boss"
+        );
+        assert_eq!(table.num_rows, 4);
+        run(&mut table, "pragma foreign_keys = off").unwrap();
+        run(&mut table, "insert 5 erin nobody").unwrap();
+        run(&mut table, "pragma foreign_keys = on").unwrap();
+        run(&mut table, "delete from users where id = 5").unwrap();
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert!(table.foreign_keys_enabled);
+        assert_eq!(
+            table.catalog().definitions,
+            vec![
+                "CREATE UNIQUE INDEX by_name ON users (username)",
+                "ALTER TABLE users ADD CONSTRAINT boss FOREIGN KEY (email) \
+                 REFERENCES users (username) ON DELETE RESTRICT"
+            ]
+        );
+        run(&mut table, "alter table users drop constraint boss").unwrap();
+        run(
+            &mut table,
+            "alter table users add constraint boss foreign key (email) \
+             references users (username) on delete cascade",
+        )
+        .unwrap();
+        run(&mut table, "delete from users where username = 'bob'").unwrap();
+        assert_eq!(usernames(&mut table), text(&["alice", "dave"]));
+        assert_eq!(violated(run(&mut table, "insert 5 erin carol").unwrap()), "boss");
+        run(&mut table, "insert 5 erin dave").unwrap();
+        run(&mut table, "delete from users where username = 'dave'").unwrap();
+        assert_eq!(usernames(&mut table), text(&["alice"]));
+        assert!(table.integrity_check().is_empty());
+    }
+
+    #[test]
+    fn test_delete_moves_rows_up() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions::default();
+        let run = |table: &mut Table, input: &str| -> Result<ExecuteResult> {
+            match Statement::prepare(input, &table.definition, &table.views, &table.defaults) {
+                PrepareResult::Success(statement) => table.execute(*statement),
+                PrepareResult::SyntaxError(err) => Err(anyhow!(err)),
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            }
+        };
+        let ids = |table: &mut Table, input: &str| {
+            let ExecuteResult::ResultSet(result_set) = run(table, input).unwrap() else {
+                panic!("Expected a result set");
+            };
+            let ids: Vec<Value> = result_set.rows.into_iter().flatten().collect();
+            ids
+        };
+        let rows_per_page = Page::max_rows_per_page(Table::TABLE_PAGE_SIZE);
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        for id in 0..3 * rows_per_page {
+            run(&mut table, &format!("insert {} user{} user@example.com", id, id)).unwrap();
+        }
+        run(&mut table, "create unique index by_id on users (id)").unwrap();
+        let condition = format!("id % 2 = 0 and id >= {}", rows_per_page);
+        run(&mut table, &format!("delete from users where {}", condition)).unwrap();
+        let expected: Vec<Value> = (0..3 * rows_per_page as i64)
+            .filter(|id| *id < rows_per_page as i64 || id % 2 == 1)
+            .map(Value::Integer)
+            .collect();
+        assert_eq!(table.num_rows, expected.len());
+        assert_eq!(ids(&mut table, "select id from users"), expected);
+        assert_eq!(table.pager.num_pages(), 3);
+        assert!(table.pager.is_free(2));
+        assert!(table.integrity_check().is_empty());
+        let last = expected.last().unwrap().clone();
+        let input = format!("select id from users where id = {}", last);
+        assert_eq!(ids(&mut table, &input), vec![last]);
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert_eq!(ids(&mut table, "select id from users"), expected);
+        run(&mut table, "delete from users").unwrap();
+        assert_eq!(table.num_rows, 0);
+        assert!(ids(&mut table, "select id from users").is_empty());
+        assert!(table.integrity_check().is_empty());
+        run(&mut table, "insert 1 alice alice@example.com").unwrap();
+        assert_eq!(ids(&mut table, "select id from users"), vec![Value::Integer(1)]);
+    }
+
+    #[test]
+    fn test_execute_views() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+        let run = |table: &mut Table, input: &str| -> Result<String> {
+            let statement = match Statement::prepare(
+                input,
+                &table.definition,
+                &table.views,
+                &table.defaults,
+            ) {
+                PrepareResult::Success(statement) => statement,
+                PrepareResult::SyntaxError(err) => return Err(anyhow!(err)),
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            };
+            match table.execute(*statement)? {
+                ExecuteResult::ResultSet(result_set) => Ok(result_set.to_string()),
+                ExecuteResult::Success(rows) => Ok(format!("{} rows", rows.len())),
+                result => panic!("Expected a result set, got {:?}", result),
+            }
+        };
+        let create = "create view counts (name, n) as \
+                      select username, count(*) from users group by username";
+        assert_eq!(run(&mut table, create).unwrap(), "0 rows");
+        let select = "select name, n from counts where n > 1";
+        assert_eq!(run(&mut table, select).unwrap(), "name|n\nalice|2");
+
+        // A view can be joined like a table, and read by another view.
+        let joined = "select u.id, c.n from users u join counts c on c.name = u.username \
+                      order by u.id";
+        assert_eq!(
+            run(&mut table, joined).unwrap(),
+            "u.id|c.n\n1|1\n2|2\n3|1\n4|2"
+        );
+        let create = "create view popular as select name from counts where n > 1";
+        assert_eq!(run(&mut table, create).unwrap(), "0 rows");
+        assert_eq!(run(&mut table, "select * from popular").unwrap(), "name\nalice");
+        assert_eq!(
+            run(&mut table, "explain query plan select * from popular").unwrap(),
+            "id|parent|detail\n\
+             1|0|MATERIALIZE popular\n\
+             2|1|MATERIALIZE counts\n\
+             3|2|SCAN users (~4 rows)\n\
+             4|2|HASH AGGREGATE BY username\n\
+             5|1|SCAN counts (~4 rows)\n\
+             6|1|FILTER (~4 rows)\n\
+             7|0|SCAN popular (~4 rows)"
+        );
+
+        let errors = [
+            ("create view counts as select 1", "View counts already exists"),
+            ("create view users as select 1", "Table users already exists"),
+            ("create view v (a, b) as select id", "Table v has 1 values for 2 columns"),
+            ("drop view missing", "No such view: missing"),
+        ];
+        for (input, expected) in errors {
+            let err = run(&mut table, input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+
+        // The views built on a dropped view keep their copy of its select.
+        assert_eq!(run(&mut table, "drop view counts").unwrap(), "0 rows");
+        let err = run(&mut table, "select * from counts").unwrap_err();
+        assert_eq!(err.to_string(), "No such table: counts");
+        assert_eq!(run(&mut table, "select * from popular").unwrap(), "name\nalice");
+    }
+
+    #[test]
+    fn test_execute_select_window_functions() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice"), (5, "bob")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = |table: &mut Table, input: &str| -> String {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let cases = [
+            (
+                "select id, row_number() over (order by id desc) as n order by id",
+                "id|n\n1|5\n2|4\n3|3\n4|2\n5|1",
+            ),
+            (
+                "select username, rank() over (order by username) as r, \
+                 dense_rank() over (order by username) as d order by id",
+                "username|r|d\ncarol|5|3\nalice|1|1\nbob|3|2\nalice|1|1\nbob|3|2",
+            ),
+            (
+                "select id, lag(id) over (partition by username order by id) as previous, \
+                 lead(id, 1, 0) over (partition by username order by id) as next order by id",
+                "id|previous|next\n1|NULL|0\n2|NULL|4\n3|NULL|5\n4|2|0\n5|3|0",
+            ),
+            // With ORDER BY the frame ends at the last row sorting equal to the current one.
+            (
+                "select id, sum(id) over (order by username) as s, \
+                 count(*) over (partition by username) as c order by id",
+                "id|s|c\n1|15|1\n2|6|2\n3|14|2\n4|6|2\n5|14|2",
+            ),
+            (
+                "select id, sum(id) over (order by id rows between 1 preceding and 1 following) \
+                 as s, max(id) over (order by id rows 2 preceding) as m order by id",
+                "id|s|m\n1|3|1\n2|6|2\n3|9|3\n4|12|4\n5|9|5",
+            ),
+            // Window functions see the groups left by GROUP BY and HAVING.
+            (
+                "select username, count(*), sum(count(*)) over () as total, \
+                 rank() over (order by min(id) desc) as r \
+                 from users group by username having count(*) > 1 order by username",
+                "username|count(*)|total|r\nalice|2|4|2\nbob|2|4|1",
+            ),
+            // The window functions see every row, LIMIT applies after them.
+            (
+                "select id, count(*) over () as c where id > 1 order by id limit 2",
+                "id|c\n2|4\n3|4",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(select(&mut table, input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_execute_select_subqueries() {
+        let mut table = Table::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice")] {
+            let row = Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                ..dummy_row()
+            };
+            table.execute(insert_statement(Some(row))).unwrap();
+        }
+
+        let select = |table: &mut Table, input: &str| -> String {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let cases = [
+            (
+                "select id where id in (select id + 2 from users where username = 'alice')",
+                "id\n4",
+            ),
+            (
+                "select id where id not in (select id from users where id > 2)",
+                "id\n1\n2",
+            ),
+            // With a NULL among the values, NOT IN is unknown for every value not found.
+            (
+                "select id, id not in (select b.id from users a left join users b \
+                 on b.id = a.id + 1) as missing",
+                "id|missing\n1|NULL\n2|0\n3|0\n4|0",
+            ),
+            (
+                "select a.id from users a where exists \
+                 (select from users b where b.username = a.username and b.id != a.id)",
+                "a.id\n2\n4",
+            ),
+            (
+                "select id from users a where not exists (select from users where id > a.id)",
+                "id\n4",
+            ),
+            (
+                "select id, (select count(*) from users b where b.id < a.id) as before \
+                 from users a where id < 3",
+                "id|before\n1|0\n2|1",
+            ),
+            (
+                "select username, (select max(id) from users) from users limit 1",
+                "username|(SELECT max(id) FROM users)\ncarol|4",
+            ),
+            (
+                "select (select id where id > 4) limit 1",
+                "(SELECT id FROM users WHERE id > 4)\nNULL",
+            ),
+            (
+                "select username, count(*) from users a group by username \
+                 having count(*) > \
+                 (select count(*) from users b where b.username = a.username) - 1 \
+                 order by username",
+                "username|count(*)\nalice|2\nbob|1\ncarol|1",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(select(&mut table, input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_execute_select_join_algorithms() {
+        let mut table = Table::new();
+        for id in (0..200).rev() {
+            table.execute(insert_statement(Some(Row { id, ..dummy_row() }))).unwrap();
+        }
+        table.memory_budget = 1024;
+
+        let select = |table: &mut Table, input: &str| -> Vec<Vec<Value>> {
+            let select =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            match table.execute(query_statement(Some(select))).unwrap() {
+                ExecuteResult::ResultSet(result_set) => result_set.rows,
+                result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+            }
+        };
+        let ids = |ids: &[i64]| -> Vec<Vec<Value>> {
+            ids.iter().map(|id| vec![Value::Integer(*id)]).collect()
+        };
+        // 200 * 200 row pairs make this a hash join, spilling with the small budget.
+        let rows = select(
+            &mut table,
+            "select count(*), sum(b.id) from users a join users b on b.id = a.id % 10",
+        );
+        assert_eq!(rows, vec![vec![Value::Integer(200), Value::Integer(900)]]);
+
+        // Ordering by the join key makes this a merge join whose output needs no sort.
+        let rows = select(
+            &mut table,
+            "select a.id from users a join users b on a.id = b.id + 100 order by a.id limit 3",
+        );
+        assert_eq!(rows, ids(&[100, 101, 102]));
+
+        // With an index on the key of b, each row of a searches it instead, both through
+        // the executor and through the compiled loops.
+        table.create_index("by_id", &["id".to_string()], true).unwrap();
+        let rows = select(
+            &mut table,
+            "select count(*), sum(b.id) from users a join users b on b.id = a.id % 10",
+        );
+        assert_eq!(rows, vec![vec![Value::Integer(200), Value::Integer(900)]]);
+        let rows = select(
+            &mut table,
+            "select b.id from users a left join users b on b.id = a.id + 197 where a.id < 5",
+        );
+        let null = vec![Value::Null];
+        assert_eq!(rows, [vec![null.clone(), null], ids(&[199, 198, 197])].concat());
+        let join = "select b.id from users a join users b on b.id = a.id % 10";
+        let select = Statement::parse_select_input(join, &table.definition, &[]).unwrap();
+        let steps = planner::explain(&select, &table);
+        assert_eq!(
+            steps[1].detail,
+            "NESTED LOOP JOIN users AS b USING KEY by_id (id=?) (~200 rows)"
+        );
+    }
+
+    #[test]
+    fn test_execute_select_count_star_uses_row_count() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions {
+            key: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let num_rows = Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1;
+        for _ in 0..num_rows {
+            table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        }
+        table.close().unwrap();
+
+        // Corrupt the last page: counting the rows does not read it.
+        let mut contents = std::fs::read(file.path()).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 1;
+        std::fs::write(file.path(), contents).unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        let select = Statement::parse_select_input(
+            "select count(*) as total",
+            &TableDefinition::default(),
+            &[],
+        ).unwrap();
+        match table.execute(query_statement(Some(select))).unwrap() {
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(result_set.columns, vec!["total"]);
+                assert_eq!(result_set.rows, vec![vec![Value::Integer(num_rows as i64)]]);
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+        let select = Statement::parse_select_input(
+            "select count(*) where id = 1",
+            &TableDefinition::default(),
+            &[],
+        ).unwrap();
+        assert!(table.execute(query_statement(Some(select))).is_err());
+    }
+
+    #[test]
+    fn test_execute_insert_table_full() {
+        let mut table = Table::new();
+        let row = dummy_row();
+        for _ in 0..Table::max_rows() {
+            let result = table.execute(insert_statement(Some(row.clone()))).unwrap();
+            assert!(matches!(result, ExecuteResult::Success(_)));
+        }
+        assert_eq!(table.num_rows, Table::max_rows());
+        assert_eq!(table.pager.num_pages(), Table::TABLE_MAX_PAGES);
+
+        let result = table.execute(insert_statement(Some(row))).unwrap();
+        assert!(matches!(
+            result,
+            ExecuteResult::ExecutionFailure(ExecutionFailure::TableFull)
+        ));
+    }
+
+    #[test]
+    fn test_integrity_check_consistent_table() {
+        let mut table = Table::new();
+        assert!(table.integrity_check().is_empty());
+
+        for _ in 0..2 * Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1 {
+            table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        }
+        assert_eq!(table.pager.num_pages(), 3);
+        assert!(table.integrity_check().is_empty());
+
+        match table.execute(integrity_check_statement()).unwrap() {
+            ExecuteResult::IntegrityCheck(problems) => assert!(problems.is_empty()),
+            result => panic!("Expected ExecuteResult::IntegrityCheck, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_integrity_check_reports_every_problem() {
+        let mut table = Table::new();
+        for _ in 0..Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1 {
+            table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        }
+
+        // Corrupt the table: a partially filled page in the middle, an empty page at the
+        // end and a row count that no longer matches the pages.
+        table.pager.get_page_mut(0).unwrap().rows.pop();
+        table.pager.allocate_page();
+
+        let problems = table.integrity_check();
+        let max_rows = Page::max_rows_per_page(Table::TABLE_PAGE_SIZE);
+        assert_eq!(
+            problems,
+            vec![
+                format!(
+                    "Page 0: holds {} of {} rows but only the last page may be partially filled",
+                    max_rows - 1,
+                    max_rows
+                ),
+                format!(
+                    "Page 1: holds 1 of {} rows but only the last page may be partially filled",
+                    max_rows
+                ),
+                "Page 2: page is allocated but holds no rows".to_string(),
+                format!(
+                    "Table row count is {} but its pages hold {} rows",
+                    max_rows + 1,
+                    max_rows
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_close_reopen() {
+        let file = NamedTempFile::new().unwrap();
+        let row = dummy_row();
+
+        let mut table = Table::open(file.path(), &DatabaseOptions::default()).unwrap();
+        for _ in 0..Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1 {
+            table.execute(insert_statement(Some(row.clone()))).unwrap();
+        }
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &DatabaseOptions::default()).unwrap();
+        assert_eq!(
+            table.num_rows,
+            Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1
+        );
+        assert!(table.integrity_check().is_empty());
+        match table.execute(select_statement()).unwrap() {
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(result_set.rows.len(), table.num_rows);
+                assert!(result_set.rows.iter().all(|r| *r == row_values(&row)));
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_catalog_survives_reopen() {
+        let file = NamedTempFile::new().unwrap();
+        let run = |table: &mut Table, input: &str| {
+            let views = table.views.clone();
+            match Statement::prepare(input, &TableDefinition::default(), &views, &[]) {
+                PrepareResult::Success(statement) => match table.execute(*statement).unwrap() {
+                    ExecuteResult::ResultSet(result_set) => result_set.to_string(),
+                    ExecuteResult::Success(rows) => format!("{} rows", rows.len()),
+                    result => panic!("Expected a result set, got {:?}", result),
+                },
+                result => panic!("Expected PrepareResult::Success, got {:?}", result),
+            }
+        };
+        let mut table = Table::open(file.path(), &DatabaseOptions::default()).unwrap();
+        for id in 1..=3 {
+            table.execute(insert_statement(Some(Row { id, ..dummy_row() }))).unwrap();
+        }
+        run(&mut table, "create index by_id on users (id)");
+        let views = [
+            "create view names as select username, count(*) from users group by username",
+            "create view top as select * from names where \"count(*)\" > 1",
+            "create view seq(n) as with recursive c(n) as \
+             (select 1 union all select n + 1 from c where n < 3) select n from c",
+            "create view quoted as select 'it''s' as s, id, \
+             row_number() over (order by id desc) as r from users u \
+             where u.id in (select id from users where id > 1) order by 2 limit 1",
+            "drop view names",
+        ];
+        for view in views {
+            run(&mut table, view);
+        }
+        let queries = ["select * from top", "select * from seq", "select * from quoted"];
+        let results: Vec<String> = queries.iter().map(|q| run(&mut table, q)).collect();
+        assert_eq!(results[0], "username|count(*)\nalice|3");
+        assert_eq!(results[2], "s|id|r\nit's|2|2");
+        table.close().unwrap();
+
+        let mut table = Table::open(file.path(), &DatabaseOptions::default()).unwrap();
+        let names: Vec<&str> = table.views.iter().map(|view| view.name.as_str()).collect();
+        assert_eq!(names, vec!["top", "seq", "quoted"]);
+        for (query, expected) in queries.iter().zip(&results) {
+            assert_eq!(run(&mut table, query), *expected, "{}", query);
+        }
+        assert_eq!(
+            run(&mut table, "explain query plan select id where id = 2"),
+            "id|parent|detail\n\
+             1|0|SEARCH users USING INDEX by_id (id=?) (~1 rows)\n\
+             2|0|FILTER (~1 rows)"
+        );
+        assert_eq!(run(&mut table, "select id where id = 2"), "id\n2");
+        assert!(table.integrity_check().is_empty());
+
+        run(&mut table, "drop index by_id");
+        run(&mut table, "analyze");
+        let statistics = run(&mut table, "select * from zero_stat");
+        table.close().unwrap();
+        let mut table = Table::open(file.path(), &DatabaseOptions::default()).unwrap();
+        assert!(table.indexes.is_empty());
+        assert_eq!(run(&mut table, "select * from zero_stat"), statistics);
+        assert!(table.integrity_check().is_empty());
+    }
+
+    #[test]
+    fn test_integrity_check_reports_unreadable_pages() {
+        let file = NamedTempFile::new().unwrap();
+        let options = DatabaseOptions {
+            key: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut table = Table::open(file.path(), &options).unwrap();
+        table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        table.close().unwrap();
+
+        let mut contents = std::fs::read(file.path()).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 1;
+        std::fs::write(file.path(), contents).unwrap();
+
+        let mut table = Table::open(file.path(), &options).unwrap();
+        assert_eq!(
+            table.integrity_check(),
+            vec![
+                "Page 0: Page 0 failed authentication: Decryption failed: wrong key or corrupted data"
+                    .to_string(),
+                "Table row count is 1 but its pages hold 0 rows".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_memory_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("saved.db");
+        let memory = Path::new(Pager::MEMORY);
+
+        let mut table = Table::open(memory, &DatabaseOptions::default()).unwrap();
+        for _ in 0..Page::max_rows_per_page(Table::TABLE_PAGE_SIZE) + 1 {
+            table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        }
+        table.save(&path, &DatabaseOptions::default()).unwrap();
+
+        let mut saved = Table::open(&path, &DatabaseOptions::default()).unwrap();
+        assert_eq!(saved.num_rows, table.num_rows);
+        assert!(saved.integrity_check().is_empty());
+        match saved.execute(select_statement()).unwrap() {
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(result_set.rows.len(), table.num_rows)
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+    }
+
+    // Benchmark of cold selects, each iteration reopens the table so that every page is read
+    // from the file. Run with `cargo test --release -- --ignored --nocapture bench_`.
+    #[test]
+    #[ignore]
+    fn bench_select_throughput_mmap() {
+        let file = NamedTempFile::new().unwrap();
+        let mut table = Table::open(file.path(), &DatabaseOptions::default()).unwrap();
+        for _ in 0..Table::max_rows() {
+            table.execute(insert_statement(Some(dummy_row()))).unwrap();
+        }
+        table.close().unwrap();
+
+        for mmap_size in [0, 1 << 30] {
+            let options = DatabaseOptions {
+                mmap_size,
+                ..Default::default()
+            };
+            let iterations = 1_000;
+            let time_start = Instant::now();
+            for _ in 0..iterations {
+                let mut table = Table::open(file.path(), &options).unwrap();
+                match table.execute(select_statement()).unwrap() {
+                    ExecuteResult::ResultSet(result_set) => {
+                        assert_eq!(result_set.rows.len(), Table::max_rows())
+                    }
+                    result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+                }
             }
-            _ => panic!("Expected ExecuteResult::Success, got {:?}", select_result),
+            let seconds = time_start.elapsed().as_secs_f64();
+            println!(
+                "mmap_size {}: {:.0} rows/second",
+                mmap_size,
+                (iterations * Table::max_rows()) as f64 / seconds
+            );
         }
     }
 
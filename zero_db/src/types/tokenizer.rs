@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Result};
+
+/// Words with a meaning in the grammar. They are matched case-insensitively and cannot be
+/// used as column names. Words only meaningful in a statement that does not contain a query,
+/// like those of ALTER TABLE, are identifiers the parser matches, so that adding a statement
+/// does not reserve its words.
+const KEYWORDS: &[&str] = &[
+    "ALL",
+    "AND",
+    "AS",
+    "ASC",
+    "BETWEEN",
+    "BY",
+    "CREATE",
+    "CROSS",
+    "CURRENT",
+    "DESC",
+    "DROP",
+    "EXISTS",
+    "FOLLOWING",
+    "FROM",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INDEX",
+    "INNER",
+    "JOIN",
+    "LEFT",
+    "LIMIT",
+    "NOT",
+    "NULL",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PRECEDING",
+    "RECURSIVE",
+    "ROW",
+    "ROWS",
+    "SELECT",
+    "UNBOUNDED",
+    "UNION",
+    "UNIQUE",
+    "VIEW",
+    "WHERE",
+    "WITH",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// A keyword, stored in upper case.
+    Keyword(String),
+    Identifier(String),
+    Integer(i64),
+    Real(f64),
+    String(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    Dot,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(c);
+                chars.next();
+            }
+            if chars.next_if_eq(&'.').is_some() {
+                digits.push('.');
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                    chars.next();
+                }
+                let value = digits
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid real literal: {}", digits))?;
+                tokens.push(Token::Real(value));
+                continue;
+            }
+            let value = digits
+                .parse()
+                .map_err(|_| anyhow!("Integer literal out of range: {}", digits))?;
+            tokens.push(Token::Integer(value));
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                word.push(c);
+                chars.next();
+            }
+            let upper = word.to_ascii_uppercase();
+            if KEYWORDS.contains(&upper.as_str()) {
+                tokens.push(Token::Keyword(upper));
+            } else {
+                tokens.push(Token::Identifier(word));
+            }
+            continue;
+        }
+
+        chars.next();
+        let token = match c {
+            '\'' => {
+                // A quote inside a string literal is written as two quotes.
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            value.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(anyhow!("Unterminated string literal")),
+                    }
+                }
+                Token::String(value)
+            }
+            '"' => {
+                // A double-quoted identifier can be any name, even a keyword.
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            name.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(anyhow!("Unterminated quoted identifier")),
+                    }
+                }
+                Token::Identifier(name)
+            }
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Eq
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::NotEq,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::LtEq,
+            '<' if chars.next_if_eq(&'>').is_some() => Token::NotEq,
+            '<' => Token::Lt,
+            '>' if chars.next_if_eq(&'=').is_some() => Token::GtEq,
+            '>' => Token::Gt,
+            _ => return Err(anyhow!("Unexpected character: {}", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// `name` as written in SQL: unchanged when it tokenizes as an identifier, otherwise in
+/// double quotes, like a view column named after the expression `count(*)`.
+pub fn quote_identifier(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name.to_ascii_uppercase().as_str());
+    match plain {
+        true => name.to_string(),
+        false => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("select * WHERE id>=10 and name <> 'it''s'").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword("SELECT".to_string()),
+                Token::Star,
+                Token::Keyword("WHERE".to_string()),
+                Token::Identifier("id".to_string()),
+                Token::GtEq,
+                Token::Integer(10),
+                Token::Keyword("AND".to_string()),
+                Token::Identifier("name".to_string()),
+                Token::NotEq,
+                Token::String("it's".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        let tokens = tokenize("( ) , . + - / % = == != < <= > ").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftParen,
+                Token::RightParen,
+                Token::Comma,
+                Token::Dot,
+                Token::Plus,
+                Token::Minus,
+                Token::Slash,
+                Token::Percent,
+                Token::Eq,
+                Token::Eq,
+                Token::NotEq,
+                Token::Lt,
+                Token::LtEq,
+                Token::Gt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        for name in ["id", "count(*)", "order", "say \"hi\"", "2nd"] {
+            let tokens = tokenize(&quote_identifier(name)).unwrap();
+            assert_eq!(
+                tokens,
+                vec![Token::Identifier(name.to_string())],
+                "{}",
+                name
+            );
+        }
+        assert_eq!(quote_identifier("user_2"), "user_2");
+        assert_eq!(quote_identifier("count(*)"), "\"count(*)\"");
+    }
+
+    #[test]
+    fn test_tokenize_real() {
+        let tokens = tokenize("2.5 10. 3").unwrap();
+        assert_eq!(
+            tokens,
+            vec![Token::Real(2.5), Token::Real(10.0), Token::Integer(3)]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        let err = tokenize("'open").unwrap_err();
+        assert_eq!(err.to_string(), "Unterminated string literal");
+
+        let err = tokenize("\"open").unwrap_err();
+        assert_eq!(err.to_string(), "Unterminated quoted identifier");
+
+        let err = tokenize("id ? 1").unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected character: ?");
+
+        let err = tokenize("99999999999999999999").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Integer literal out of range: 99999999999999999999"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::Read;
+
+/// A typed value produced by evaluating an expression. Comparisons yield integers, 1 for
+/// true and 0 for false, or NULL when either side is NULL.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+impl Value {
+    pub fn from_bool(value: bool) -> Value {
+        Value::Integer(value as i64)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "NULL",
+            Value::Integer(_) => "INTEGER",
+            Value::Real(_) => "REAL",
+            Value::Text(_) => "TEXT",
+        }
+    }
+
+    /// Interprets the value as a condition, where NULL is unknown rather than true or false.
+    pub fn as_bool(&self) -> Result<Option<bool>> {
+        match self {
+            Value::Null => Ok(None),
+            Value::Integer(value) => Ok(Some(*value != 0)),
+            Value::Real(value) => Ok(Some(*value != 0.0)),
+            Value::Text(_) => Err(anyhow!("Expected a boolean condition but got TEXT")),
+        }
+    }
+
+    /// Whether a row passes a condition with this value. NULL does not.
+    pub fn is_truthy(&self) -> Result<bool> {
+        Ok(self.as_bool()?.unwrap_or(false))
+    }
+
+    /// Appends the value to `buf` as a type tag followed by the little-endian number, or by
+    /// a u32 length and the UTF-8 bytes of the text.
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::Integer(value) => {
+                buf.push(0);
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+            Value::Text(value) => {
+                buf.push(1);
+                buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                buf.extend_from_slice(value.as_bytes());
+            }
+            Value::Null => buf.push(2),
+            Value::Real(value) => {
+                buf.push(3);
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    pub fn deserialize(reader: &mut impl Read) -> Result<Value> {
+        let mut tag = [0; 1];
+        reader.read_exact(&mut tag)?;
+        match tag[0] {
+            0 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+                Ok(Value::Integer(i64::from_le_bytes(bytes)))
+            }
+            1 => {
+                let mut len = [0; 4];
+                reader.read_exact(&mut len)?;
+                let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+                reader.read_exact(&mut bytes)?;
+                Ok(Value::Text(String::from_utf8(bytes)?))
+            }
+            2 => Ok(Value::Null),
+            3 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+                Ok(Value::Real(f64::from_le_bytes(bytes)))
+            }
+            tag => Err(anyhow!("Unknown value type tag: {}", tag)),
+        }
+    }
+
+    /// Approximate number of bytes the value occupies in memory.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Value::Text(value) => size_of::<Value>() + value.len(),
+            _ => size_of::<Value>(),
+        }
+    }
+
+    /// Total order over all values: NULL sorts first, then numbers compared by value, then
+    /// text compared bytewise.
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Integer(left), Value::Integer(right)) => left.cmp(right),
+            (Value::Text(left), Value::Text(right)) => left.cmp(right),
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Less,
+            (_, Value::Null) => Ordering::Greater,
+            (Value::Text(_), _) => Ordering::Greater,
+            (_, Value::Text(_)) => Ordering::Less,
+            (Value::Real(left), Value::Real(right)) => left.total_cmp(right),
+            (Value::Integer(left), Value::Real(right)) => (*left as f64).total_cmp(right),
+            (Value::Real(left), Value::Integer(right)) => left.total_cmp(&(*right as f64)),
+        }
+    }
+
+    /// The value as a floating point number, for INTEGER and REAL values.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(value) => Some(*value as f64),
+            Value::Real(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Real(value) => write!(f, "{:?}", value),
+            Value::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_truthy() {
+        assert!(Value::Integer(2).is_truthy().unwrap());
+        assert!(!Value::Integer(0).is_truthy().unwrap());
+        assert!(Value::Real(0.5).is_truthy().unwrap());
+        assert!(!Value::Null.is_truthy().unwrap());
+        assert_eq!(Value::Null.as_bool().unwrap(), None);
+        let err = Value::Text("yes".to_string()).is_truthy().unwrap_err();
+        assert_eq!(err.to_string(), "Expected a boolean condition but got TEXT");
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let values = [
+            Value::Integer(-42),
+            Value::Text("it's".to_string()),
+            Value::Text(String::new()),
+            Value::Null,
+            Value::Real(2.5),
+        ];
+        let mut buf = Vec::new();
+        for value in &values {
+            value.serialize(&mut buf);
+        }
+        let mut reader = buf.as_slice();
+        for value in values {
+            assert_eq!(Value::deserialize(&mut reader).unwrap(), value);
+        }
+        assert!(reader.is_empty());
+
+        let err = Value::deserialize(&mut [7u8].as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown value type tag: 7");
+    }
+
+    #[test]
+    fn test_compare() {
+        let text = |value: &str| Value::Text(value.to_string());
+        assert_eq!(
+            Value::Integer(1).compare(&Value::Integer(2)),
+            Ordering::Less
+        );
+        assert_eq!(text("bob").compare(&text("alice")), Ordering::Greater);
+        assert_eq!(text("bob").compare(&text("bob")), Ordering::Equal);
+        assert_eq!(Value::Integer(100).compare(&text("1")), Ordering::Less);
+        assert_eq!(
+            Value::Integer(2).compare(&Value::Real(1.5)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Value::Real(2.0).compare(&Value::Integer(2)),
+            Ordering::Equal
+        );
+        assert_eq!(
+            Value::Null.compare(&Value::Integer(i64::MIN)),
+            Ordering::Less
+        );
+        assert_eq!(Value::Null.compare(&Value::Null), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Real(2.0).to_string(), "2.0");
+        assert_eq!(Value::Real(0.25).to_string(), "0.25");
+        assert_eq!(Value::Null.to_string(), "NULL");
+    }
+}
@@ -1,6 +1,15 @@
-use crate::types::{ExecuteResult, Statement, Table};
-use anyhow::Result;
+use crate::types::aggregate::Accumulator;
+use crate::types::expression::Expression;
+use crate::types::join::RowSource;
+use crate::types::{
+    Cursor, DatabaseOptions, ExecuteResult, ExecutionFailure, ForeignKey, IndexCursor, Instruction,
+    PrepareResult, Program, ResultSet, Row, SortedRows, Sorter, Statement, Table, Value,
+};
+use anyhow::{anyhow, Result};
+use std::path::Path;
 
+/// Runs statements against the open database. Each is compiled into a `Program`, whose
+/// instructions `run` executes one after another against a set of registers and cursors.
 pub struct VirtualMachine {
     table: Table,
 }
@@ -11,7 +20,539 @@ impl VirtualMachine {
         }
     }
 
+    /// Prepares `input` against the table and column names of the open database. Its
+    /// queries can read the views and its inserts can leave out the columns it has a default
+    /// value for.
+    pub fn prepare(&self, input: &str) -> PrepareResult {
+        let table = &self.table;
+        Statement::prepare(input, &table.definition, &table.views, &table.defaults)
+    }
+
+    /// Compiles `statement` and runs the program against the open database.
     pub fn execute(&mut self, statement: Statement) -> Result<ExecuteResult> {
-        Ok(self.table.execute(statement)?)
+        self.table.execute(statement)
+    }
+
+    /// Switches to the database file at `path`, saving the current one first. The current
+    /// database stays open if the new one cannot be opened.
+    pub fn open(&mut self, path: &Path, options: &DatabaseOptions) -> Result<()> {
+        self.table.close()?;
+        self.table = Table::open(path, options)?;
+        Ok(())
+    }
+
+    /// Writes the current database to a new file, typically to persist a `:memory:` one.
+    pub fn save(&mut self, path: &Path, options: &DatabaseOptions) -> Result<()> {
+        self.table.save(path, options)
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        self.table.close()
+    }
+}
+
+/// Executes `program` from its first instruction until it halts. The rows it outputs are
+/// returned as a result set, and the rows it inserts as `ExecuteResult::Success`.
+pub fn run(program: &Program, table: &mut Table) -> Result<ExecuteResult> {
+    let mut registers = vec![Value::Null; program.registers];
+    // The accumulators of `AggStep`, kept apart from the values of their registers.
+    let mut accumulators: Vec<Option<Accumulator>> = vec![None; program.registers];
+    let mut cursors: Vec<Option<OpenCursor>> = Vec::new();
+    let mut result = match &program.columns {
+        Some(columns) => ExecuteResult::ResultSet(ResultSet::new(columns.clone())),
+        None => ExecuteResult::Success(Vec::new()),
+    };
+    let mut address = 0;
+    loop {
+        let instruction = program
+            .instructions
+            .get(address)
+            .ok_or_else(|| anyhow!("Jump to {} is past the end of the program", address))?;
+        address += 1;
+        match instruction {
+            Instruction::Value { value, register } => {
+                registers[*register] = value.clone();
+            }
+            Instruction::OpenRead {
+                cursor,
+                table: name,
+            } => {
+                if *name != table.definition.name {
+                    return Err(anyhow!("No such table: {}", name));
+                }
+                open(&mut cursors, *cursor, Source::Table(Cursor::table_start()));
+            }
+            Instruction::Count {
+                table: name,
+                register,
+            } => {
+                if *name != table.definition.name {
+                    return Err(anyhow!("No such table: {}", name));
+                }
+                table.check_exists()?;
+                registers[*register] = Value::Integer(table.num_rows as i64);
+            }
+            Instruction::OpenIndex { cursor, lookup } => {
+                let index = IndexCursor::new(lookup.clone());
+                open(&mut cursors, *cursor, Source::Index(index));
+            }
+            Instruction::SeekIndex {
+                cursor,
+                probe,
+                start,
+            } => {
+                let values = registers[*start..*start + probe.keys.len()].to_vec();
+                let index = IndexCursor::new(probe.lookup(values));
+                open(&mut cursors, *cursor, Source::Index(index));
+            }
+            Instruction::OpenQuery { cursor, select } => {
+                let rows = table.run_select(select, None)?.rows;
+                open(&mut cursors, *cursor, Source::Rows { rows, next: 0 });
+            }
+            Instruction::SorterOpen { cursor, descending } => {
+                let sorter = Sorter::new(descending.clone(), table.memory_budget);
+                open(&mut cursors, *cursor, Source::Sorter(sorter));
+            }
+            Instruction::SorterInsert {
+                cursor,
+                start,
+                keys,
+                count,
+            } => {
+                let Source::Sorter(sorter) = &mut get(&mut cursors, *cursor)?.source else {
+                    return Err(anyhow!("Cursor {} is not an unsorted sorter", cursor));
+                };
+                let values = &registers[*start..*start + *count];
+                sorter.push(values[..*keys].to_vec(), values[*keys..].to_vec())?;
+            }
+            Instruction::SorterSort { cursor, target } => {
+                let open = get(&mut cursors, *cursor)?;
+                let source = std::mem::replace(&mut open.source, Source::Empty);
+                let Source::Sorter(sorter) = source else {
+                    return Err(anyhow!("Cursor {} is not an unsorted sorter", cursor));
+                };
+                open.source = Source::Sorted(sorter.finish()?);
+                if !open.advance(table)? {
+                    address = *target;
+                }
+            }
+            Instruction::NullRow { cursor } => {
+                get(&mut cursors, *cursor)?.row = Some(Vec::new());
+            }
+            Instruction::Rewind { cursor, target } => {
+                if !get(&mut cursors, *cursor)?.rewind(table)? {
+                    address = *target;
+                }
+            }
+            Instruction::Next { cursor, target } => {
+                if get(&mut cursors, *cursor)?.advance(table)? {
+                    address = *target;
+                }
+            }
+            Instruction::Column {
+                cursor,
+                column,
+                register,
+            } => {
+                let row = get(&mut cursors, *cursor)?
+                    .row
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Cursor {} has no current row", cursor))?;
+                registers[*register] = row.get(*column).cloned().unwrap_or(Value::Null);
+            }
+            Instruction::Unary {
+                operator,
+                operand,
+                register,
+            } => {
+                let value = registers[*operand].clone();
+                registers[*register] = Expression::apply_unary(*operator, value)?;
+            }
+            Instruction::Binary {
+                operator,
+                left,
+                right,
+                register,
+            } => {
+                let (left, right) = (registers[*left].clone(), registers[*right].clone());
+                registers[*register] = Expression::apply_binary(*operator, left, right)?;
+            }
+            Instruction::Copy { source, register } => {
+                registers[*register] = registers[*source].clone();
+            }
+            Instruction::AggStep {
+                function,
+                argument,
+                register,
+            } => {
+                let argument = argument.map(|argument| registers[argument].clone());
+                accumulators[*register]
+                    .get_or_insert_with(|| Accumulator::new(*function))
+                    .update(argument)?;
+            }
+            Instruction::AggFinal { function, register } => {
+                let accumulator = accumulators[*register].take();
+                registers[*register] = accumulator
+                    .unwrap_or_else(|| Accumulator::new(*function))
+                    .finish();
+            }
+            Instruction::If { register, target } => {
+                if registers[*register].as_bool()? == Some(true) {
+                    address = *target;
+                }
+            }
+            Instruction::IfNot {
+                register,
+                target,
+                null,
+            } => {
+                if registers[*register]
+                    .as_bool()?
+                    .map_or(*null, |value| !value)
+                {
+                    address = *target;
+                }
+            }
+            Instruction::IfDistinct {
+                left,
+                right,
+                count,
+                target,
+            } => {
+                let left = &registers[*left..*left + *count];
+                let right = &registers[*right..*right + *count];
+                if left
+                    .iter()
+                    .zip(right)
+                    .any(|(left, right)| left.compare(right).is_ne())
+                {
+                    address = *target;
+                }
+            }
+            Instruction::IfPositive { register, target } => {
+                let value = integer(&mut registers[*register])?;
+                if *value > 0 {
+                    *value -= 1;
+                    address = *target;
+                }
+            }
+            Instruction::DecrementJumpZero { register, target } => {
+                let value = integer(&mut registers[*register])?;
+                *value -= 1;
+                if *value == 0 {
+                    address = *target;
+                }
+            }
+            Instruction::Goto { target } => address = *target,
+            Instruction::ResultRow { start, count } => {
+                if let ExecuteResult::ResultSet(result_set) = &mut result {
+                    result_set
+                        .rows
+                        .push(registers[*start..*start + *count].to_vec());
+                }
+            }
+            Instruction::Insert { start } => {
+                let row = Row::from_values(&registers[*start..*start + Row::COLUMNS.len()])?;
+                if let Some(failure) = table.check_violation(&row)? {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+                if let Some(failure) = table.unique_violation(&row) {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+                if let Some(failure) = table.foreign_key_violation(&row) {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+                if !table.insert(&row)? {
+                    let failure = ExecutionFailure::TableFull;
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+                if let ExecuteResult::Success(rows) = &mut result {
+                    rows.push(row);
+                }
+            }
+            Instruction::Delete { condition } => {
+                if let Some(failure) = table.delete(condition.as_ref())? {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+            }
+            Instruction::IntegrityCheck => {
+                result = ExecuteResult::IntegrityCheck(table.integrity_check());
+            }
+            Instruction::CompressionStats => {
+                result = ExecuteResult::CompressionStats(table.pager.compression_stats());
+            }
+            Instruction::Analyze => table.analyze()?,
+            Instruction::CreateTable { name, columns } => table.create_table(name, columns)?,
+            Instruction::DropTable { name } => table.drop_table(name)?,
+            Instruction::RenameTable { name, new_name } => table.rename_table(name, new_name)?,
+            Instruction::RenameColumn { column, new_name } => {
+                table.rename_column(column, new_name)?
+            }
+            Instruction::CreateIndex {
+                name,
+                columns,
+                unique,
+            } => {
+                if let Some(failure) = table.create_index(name, columns, *unique)? {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+            }
+            Instruction::DropIndex { name } => table.drop_index(name)?,
+            Instruction::CreateView { view } => table.create_view(*view.clone())?,
+            Instruction::DropView { name } => table.drop_view(name)?,
+            Instruction::AddConstraint { name, check } => {
+                if let Some(failure) = table.add_constraint(name, check.clone())? {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+            }
+            Instruction::AddForeignKey {
+                name,
+                column,
+                parent_column,
+                on_delete,
+            } => {
+                let foreign_key =
+                    ForeignKey::new(name, column, parent_column, *on_delete, &table.definition)?;
+                if let Some(failure) = table.add_foreign_key(foreign_key)? {
+                    return Ok(ExecuteResult::ExecutionFailure(failure));
+                }
+            }
+            Instruction::DropConstraint { name } => table.drop_constraint(name)?,
+            Instruction::SetDefault { column, default } => {
+                table.set_default(column, default.as_ref())?
+            }
+            Instruction::RecursionLimit { register } => {
+                registers[*register] = Value::Integer(table.recursion_limit as i64);
+            }
+            Instruction::SetRecursionLimit { limit } => {
+                table.recursion_limit = *limit;
+            }
+            Instruction::ForeignKeys { register } => {
+                registers[*register] = Value::Integer(table.foreign_keys_enabled as i64);
+            }
+            Instruction::SetForeignKeys { enabled } => {
+                table.foreign_keys_enabled = *enabled;
+            }
+            Instruction::Halt => return Ok(result),
+        }
+    }
+}
+
+enum Source {
+    Table(Cursor),
+    /// Rows found by an index.
+    Index(IndexCursor),
+    /// Rows computed up front, with the index of the next one.
+    Rows {
+        rows: Vec<Vec<Value>>,
+        next: usize,
+    },
+    /// Rows being added to a sorter, which cannot be read until they are sorted.
+    Sorter(Sorter),
+    Sorted(SortedRows),
+    /// What a sorter holds while it sorts.
+    Empty,
+}
+
+// A cursor opened by a program, positioned on `row` until it moves past the last one.
+struct OpenCursor {
+    source: Source,
+    row: Option<Vec<Value>>,
+}
+impl OpenCursor {
+    fn rewind(&mut self, table: &mut Table) -> Result<bool> {
+        match &mut self.source {
+            Source::Table(cursor) => *cursor = Cursor::table_start(),
+            Source::Index(cursor) => cursor.rewind(),
+            Source::Rows { next, .. } => *next = 0,
+            Source::Sorter(_) | Source::Sorted(_) | Source::Empty => {
+                return Err(anyhow!("A sorter cannot be rewound"))
+            }
+        }
+        self.advance(table)
+    }
+
+    fn advance(&mut self, table: &mut Table) -> Result<bool> {
+        self.row = match &mut self.source {
+            Source::Table(cursor) => cursor.next_row(table)?,
+            Source::Index(cursor) => cursor.next_row(table)?,
+            Source::Rows { rows, next } => {
+                *next += 1;
+                rows.get(*next - 1).cloned()
+            }
+            Source::Sorted(rows) => rows.next().transpose()?,
+            Source::Sorter(_) | Source::Empty => {
+                return Err(anyhow!("A sorter is read before it is sorted"))
+            }
+        };
+        Ok(self.row.is_some())
+    }
+}
+
+fn open(cursors: &mut Vec<Option<OpenCursor>>, cursor: usize, source: Source) {
+    if cursors.len() <= cursor {
+        cursors.resize_with(cursor + 1, || None);
+    }
+    cursors[cursor] = Some(OpenCursor { source, row: None });
+}
+
+fn get(cursors: &mut [Option<OpenCursor>], cursor: usize) -> Result<&mut OpenCursor> {
+    cursors
+        .get_mut(cursor)
+        .and_then(Option::as_mut)
+        .ok_or_else(|| anyhow!("Cursor {} is not open", cursor))
+}
+
+fn integer(value: &mut Value) -> Result<&mut i64> {
+    match value {
+        Value::Integer(value) => Ok(value),
+        _ => Err(anyhow!(
+            "Expected an INTEGER counter but got {}",
+            value.type_name()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{StatementType, TableDefinition};
+    use heapless::String as HeapLessString;
+
+    fn insert(vm: &mut VirtualMachine, id: i32, username: &str) {
+        let statement = Statement {
+            statement_type: StatementType::Insert,
+            row_to_insert: Some(Row {
+                id,
+                username: HeapLessString::try_from(username).unwrap(),
+                email: HeapLessString::try_from("user@example.com").unwrap(),
+            }),
+            select: None,
+        };
+        match vm.execute(statement).unwrap() {
+            ExecuteResult::Success(rows) => assert_eq!(rows.len(), 1),
+            result => panic!("Expected ExecuteResult::Success, got {:?}", result),
+        }
+    }
+
+    fn select(vm: &mut VirtualMachine, input: &str) -> Result<String> {
+        let statement = Statement {
+            statement_type: StatementType::Select,
+            row_to_insert: None,
+            select: Some(Statement::parse_select_input(
+                input,
+                &TableDefinition::default(),
+                &[],
+            )?),
+        };
+        match vm.execute(statement)? {
+            ExecuteResult::ResultSet(result_set) => Ok(result_set.to_string()),
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_execute_programs() {
+        let mut vm = VirtualMachine::new();
+        for (id, username) in [(1, "carol"), (2, "alice"), (3, "bob"), (4, "alice")] {
+            insert(&mut vm, id, username);
+        }
+
+        // Compiled scans return what the query executor does.
+        let cases = [
+            "select * where username = 'alice'",
+            "select id, -id * 2 as double where id > 1 limit 2 offset 1",
+            "select id where id > 5 or username = 'bob'",
+            "select id where id = 1 and (username = 'carol' or 1 / 0)",
+            "select id where id > 1 and 10 / (id - 1) > 4",
+            "select id limit 0",
+            "select username, count(*) group by username order by username",
+            "select id where id in (select id + 1 from users) order by id desc",
+            "with t(n) as (select id * 10 from users) select n from t where n > 20",
+            "select username, id order by username desc, id limit 2 offset 1",
+            "select id order by id limit 0",
+            "select a.id, b.id from users a join users b on b.id = a.id + 1 where a.id > 1",
+            "select a.id, b.id from users a left join users b on b.id > a.id \
+             order by b.id desc, a.id limit 4",
+            "select * from users a cross join users b join users c on c.id = a.id + 2 \
+             left join users d on d.id = b.id * 2 where b.id < 3",
+        ];
+        for input in cases {
+            let select_statement =
+                Statement::parse_select_input(input, &TableDefinition::default(), &[]).unwrap();
+            let expected = vm.table.run_select(&select_statement, None).unwrap();
+            let expected = expected.to_string();
+            assert_eq!(select(&mut vm, input).unwrap(), expected, "{}", input);
+        }
+        assert_eq!(
+            select(&mut vm, "select id where id > 1 and 10 / (id - 1) > 4").unwrap(),
+            "id\n2\n3"
+        );
+
+        let err = select(&mut vm, "select id where username + 1 > 0").unwrap_err();
+        assert_eq!(err.to_string(), "Cannot apply Add to TEXT and INTEGER");
+    }
+
+    #[test]
+    fn test_run_pragmas() {
+        let mut vm = VirtualMachine::new();
+        let pragma = |vm: &mut VirtualMachine, statement_type| {
+            let statement = Statement {
+                statement_type,
+                row_to_insert: None,
+                select: None,
+            };
+            vm.execute(statement).unwrap()
+        };
+        pragma(&mut vm, StatementType::RecursionLimit(Some(7)));
+        match pragma(&mut vm, StatementType::RecursionLimit(None)) {
+            ExecuteResult::ResultSet(result_set) => {
+                assert_eq!(result_set.to_string(), "recursion_limit\n7")
+            }
+            result => panic!("Expected ExecuteResult::ResultSet, got {:?}", result),
+        }
+        match pragma(&mut vm, StatementType::IntegrityCheck) {
+            ExecuteResult::IntegrityCheck(problems) => assert!(problems.is_empty()),
+            result => panic!("Expected ExecuteResult::IntegrityCheck, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_run_invalid_programs() {
+        let mut table = Table::new();
+        let program = |instructions: Vec<Instruction>| Program {
+            instructions,
+            registers: 1,
+            columns: None,
+        };
+        let err = run(&program(vec![Instruction::Goto { target: 5 }]), &mut table).unwrap_err();
+        assert_eq!(err.to_string(), "Jump to 5 is past the end of the program");
+
+        let err = run(
+            &program(vec![Instruction::Next {
+                cursor: 2,
+                target: 0,
+            }]),
+            &mut table,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Cursor 2 is not open");
+
+        let err = run(
+            &program(vec![
+                Instruction::OpenRead {
+                    cursor: 0,
+                    table: Table::NAME.to_string(),
+                },
+                Instruction::Column {
+                    cursor: 0,
+                    column: 0,
+                    register: 0,
+                },
+            ]),
+            &mut table,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Cursor 0 has no current row");
     }
 }
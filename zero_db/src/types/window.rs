@@ -0,0 +1,435 @@
+use crate::types::aggregate::Accumulator;
+use crate::types::expression::{Scope, SubqueryRows};
+use crate::types::{AggregateFunction, Expression, OrderingTerm, Select, Value};
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowFunction {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Lag,
+    Lead,
+    /// An aggregate function computed over the frame of each row.
+    Aggregate(AggregateFunction),
+}
+impl WindowFunction {
+    /// Looks up a function that can only be used as a window function by its
+    /// case-insensitive name. Aggregate functions become window functions with OVER.
+    pub fn from_name(name: &str) -> Option<WindowFunction> {
+        match name.to_ascii_lowercase().as_str() {
+            "row_number" => Some(WindowFunction::RowNumber),
+            "rank" => Some(WindowFunction::Rank),
+            "dense_rank" => Some(WindowFunction::DenseRank),
+            "lag" => Some(WindowFunction::Lag),
+            "lead" => Some(WindowFunction::Lead),
+            _ => None,
+        }
+    }
+
+    /// The smallest and largest number of arguments the function takes. `LAG` and `LEAD`
+    /// take the value, the offset of the row to read it from and a default.
+    pub fn arity(self) -> (usize, usize) {
+        match self {
+            WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => (0, 0),
+            WindowFunction::Lag | WindowFunction::Lead => (1, 3),
+            // No argument stands for `COUNT(*)`.
+            WindowFunction::Aggregate(AggregateFunction::Count) => (0, 1),
+            WindowFunction::Aggregate(_) => (1, 1),
+        }
+    }
+}
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowFunction::RowNumber => write!(f, "row_number"),
+            WindowFunction::Rank => write!(f, "rank"),
+            WindowFunction::DenseRank => write!(f, "dense_rank"),
+            WindowFunction::Lag => write!(f, "lag"),
+            WindowFunction::Lead => write!(f, "lead"),
+            WindowFunction::Aggregate(function) => write!(f, "{}", function),
+        }
+    }
+}
+
+/// One end of a `ROWS BETWEEN start AND end` frame, relative to the current row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(usize),
+    CurrentRow,
+    Following(usize),
+    UnboundedFollowing,
+}
+impl FrameBound {
+    // Position relative to the current row, with the unbounded ends beyond every row.
+    fn offset(self) -> i128 {
+        match self {
+            FrameBound::UnboundedPreceding => i128::MIN,
+            FrameBound::Preceding(rows) => -(rows as i128),
+            FrameBound::CurrentRow => 0,
+            FrameBound::Following(rows) => rows as i128,
+            FrameBound::UnboundedFollowing => i128::MAX,
+        }
+    }
+
+    // Index of the bound in a partition of `len` rows for the row at `position`, clamped to
+    // one before the first row and one past the last.
+    fn index(self, position: usize, len: usize) -> i128 {
+        (position as i128)
+            .saturating_add(self.offset())
+            .clamp(-1, len as i128)
+    }
+}
+impl fmt::Display for FrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            FrameBound::Preceding(rows) => write!(f, "{} PRECEDING", rows),
+            FrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            FrameBound::Following(rows) => write!(f, "{} FOLLOWING", rows),
+            FrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+/// The rows of its partition an aggregate window function is computed over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+impl Frame {
+    pub fn new(start: FrameBound, end: FrameBound) -> Result<Frame> {
+        if start == FrameBound::UnboundedFollowing
+            || end == FrameBound::UnboundedPreceding
+            || start.offset() > end.offset()
+        {
+            return Err(anyhow!("Invalid window frame: {} AND {}", start, end));
+        }
+        Ok(Frame { start, end })
+    }
+}
+
+/// `OVER ([PARTITION BY expression, ...] [ORDER BY term, ...] [ROWS frame])`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    pub partition_by: Vec<Expression>,
+    pub order_by: Vec<OrderingTerm>,
+    /// Without a frame, an aggregate covers the whole partition, or with ORDER BY the rows
+    /// up to the last one that sorts equal to the current row.
+    pub frame: Option<Frame>,
+}
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut clauses = Vec::new();
+        if !self.partition_by.is_empty() {
+            let terms: Vec<String> = self.partition_by.iter().map(|e| e.to_string()).collect();
+            clauses.push(format!("PARTITION BY {}", terms.join(", ")));
+        }
+        if !self.order_by.is_empty() {
+            let terms: Vec<String> = self
+                .order_by
+                .iter()
+                .map(|term| match term.descending {
+                    true => format!("{} DESC", term.expression),
+                    false => term.expression.to_string(),
+                })
+                .collect();
+            clauses.push(format!("ORDER BY {}", terms.join(", ")));
+        }
+        if let Some(frame) = &self.frame {
+            clauses.push(format!("ROWS BETWEEN {} AND {}", frame.start, frame.end));
+        }
+        write!(f, "{}", clauses.join(" "))
+    }
+}
+
+/// A row or group of a query with window functions, with the values they computed for it.
+pub struct WindowScope<'a> {
+    pub row: &'a dyn Scope,
+    pub windows: &'a [Expression],
+    pub values: &'a [Value],
+}
+impl Scope for WindowScope<'_> {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<Value> {
+        self.row.column(table, name)
+    }
+
+    fn aggregate(&self, expression: &Expression) -> Option<Value> {
+        self.row.aggregate(expression)
+    }
+
+    fn subquery(&self, select: &Select) -> Result<SubqueryRows> {
+        self.row.subquery(select)
+    }
+
+    fn window(&self, expression: &Expression) -> Option<Value> {
+        let i = self.windows.iter().position(|w| w == expression)?;
+        self.values.get(i).cloned()
+    }
+}
+
+/// Computes the window functions `windows` for every row of a query, after WHERE, GROUP BY
+/// and HAVING. Returns the values of each row in the order of `windows`.
+///
+/// Each window splits the rows into partitions by the PARTITION BY values and sorts every
+/// partition by its ORDER BY terms, keeping the query order between rows that sort equal.
+pub fn compute(windows: &[Expression], rows: &[&dyn Scope]) -> Result<Vec<Vec<Value>>> {
+    let mut values = vec![Vec::with_capacity(windows.len()); rows.len()];
+    for expression in windows {
+        let Expression::Window {
+            function,
+            arguments,
+            window,
+        } = expression
+        else {
+            return Err(anyhow!("Not a window function: {}", expression));
+        };
+        let mut partitions: Vec<Vec<usize>> = Vec::new();
+        let mut index = HashMap::new();
+        let mut sort_keys = Vec::with_capacity(rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            let mut key = Vec::new();
+            for expression in &window.partition_by {
+                expression.evaluate(*row)?.serialize(&mut key);
+            }
+            let partition = *index.entry(key).or_insert_with(|| {
+                partitions.push(Vec::new());
+                partitions.len() - 1
+            });
+            partitions[partition].push(i);
+            sort_keys.push(
+                window
+                    .order_by
+                    .iter()
+                    .map(|term| term.expression.evaluate(*row))
+                    .collect::<Result<Vec<_>>>()?,
+            );
+        }
+        let compare = |a: &usize, b: &usize| {
+            let terms = window
+                .order_by
+                .iter()
+                .zip(&sort_keys[*a])
+                .zip(&sort_keys[*b]);
+            for ((term, a), b) in terms {
+                let ordering = a.compare(b);
+                if ordering != Ordering::Equal {
+                    return if term.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    };
+                }
+            }
+            Ordering::Equal
+        };
+        for partition in &mut partitions {
+            partition.sort_by(compare);
+            let peers = |a: usize, b: usize| compare(&partition[a], &partition[b]).is_eq();
+            let results = match function {
+                WindowFunction::RowNumber => {
+                    (1..=partition.len() as i64).map(Value::Integer).collect()
+                }
+                WindowFunction::Rank | WindowFunction::DenseRank => {
+                    let mut results = Vec::with_capacity(partition.len());
+                    let mut rank = 0;
+                    for position in 0..partition.len() {
+                        if position == 0 || !peers(position - 1, position) {
+                            rank = match function {
+                                WindowFunction::Rank => position as i64 + 1,
+                                _ => rank + 1,
+                            };
+                        }
+                        results.push(Value::Integer(rank));
+                    }
+                    results
+                }
+                WindowFunction::Lag | WindowFunction::Lead => shifted(
+                    *function == WindowFunction::Lead,
+                    arguments,
+                    partition,
+                    rows,
+                )?,
+                WindowFunction::Aggregate(function) => {
+                    let arguments = partition
+                        .iter()
+                        .map(|&i| match arguments.first() {
+                            Some(argument) => argument.evaluate(rows[i]).map(Some),
+                            None => Ok(None),
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    let frames = (0..partition.len())
+                        .map(|position| match &window.frame {
+                            Some(frame) => (
+                                frame.start.index(position, partition.len()),
+                                frame.end.index(position, partition.len()),
+                            ),
+                            None if window.order_by.is_empty() => (0, partition.len() as i128 - 1),
+                            None => {
+                                let mut end = position;
+                                while end + 1 < partition.len() && peers(end, end + 1) {
+                                    end += 1;
+                                }
+                                (0, end as i128)
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    aggregate(*function, &arguments, &frames)?
+                }
+            };
+            for (&i, result) in partition.iter().zip(results) {
+                values[i].push(result);
+            }
+        }
+    }
+    Ok(values)
+}
+
+// `LAG(value, offset, default)` reads `value` from the row `offset` rows before the current
+// one in its partition, and `LEAD` from the row after. Past either end it is `default`.
+fn shifted(
+    lead: bool,
+    arguments: &[Expression],
+    partition: &[usize],
+    rows: &[&dyn Scope],
+) -> Result<Vec<Value>> {
+    let mut results = Vec::with_capacity(partition.len());
+    for (position, &i) in partition.iter().enumerate() {
+        let offset = match arguments.get(1) {
+            Some(offset) => match offset.evaluate(rows[i])? {
+                Value::Integer(offset) if offset >= 0 => offset as usize,
+                value => {
+                    return Err(anyhow!(
+                        "The offset of {}() must be a non-negative integer, not {}",
+                        if lead { "lead" } else { "lag" },
+                        value
+                    ))
+                }
+            },
+            None => 1,
+        };
+        let source = match lead {
+            true => position.checked_add(offset),
+            false => position.checked_sub(offset),
+        };
+        results.push(match source.and_then(|source| partition.get(source)) {
+            Some(&source) => arguments[0].evaluate(rows[source])?,
+            None => match arguments.get(2) {
+                Some(default) => default.evaluate(rows[i])?,
+                None => Value::Null,
+            },
+        });
+    }
+    Ok(results)
+}
+
+// The aggregate of the arguments within each frame, given as the inclusive indexes of its
+// first and last row. Frames starting at the first row only grow, so one accumulator runs
+// along the partition; other frames are aggregated one by one.
+fn aggregate(
+    function: AggregateFunction,
+    arguments: &[Option<Value>],
+    frames: &[(i128, i128)],
+) -> Result<Vec<Value>> {
+    let mut results = Vec::with_capacity(frames.len());
+    let mut running = Accumulator::new(function);
+    let mut next = 0;
+    for &(start, end) in frames {
+        let end = end.min(arguments.len() as i128 - 1);
+        if start <= 0 && end + 1 >= next as i128 {
+            while (next as i128) <= end {
+                running.update(arguments[next].clone())?;
+                next += 1;
+            }
+            results.push(running.clone().finish());
+            continue;
+        }
+        let (first, last) = (start.max(0) as usize, (end + 1).max(0) as usize);
+        let mut accumulator = Accumulator::new(function);
+        for argument in arguments.get(first..last).unwrap_or_default() {
+            accumulator.update(argument.clone())?;
+        }
+        results.push(accumulator.finish());
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Parser, Schema, Tuple};
+
+    fn compute_over(input: &str, values: &[Vec<Value>]) -> Result<Vec<Value>> {
+        let window = Parser::new(input).unwrap().parse_expression().unwrap();
+        let schema = Schema::with_columns("t", &["k", "v"]);
+        let tuples: Vec<Tuple> = values
+            .iter()
+            .map(|values| Tuple {
+                schema: &schema,
+                values,
+            })
+            .collect();
+        let rows: Vec<&dyn Scope> = tuples.iter().map(|tuple| tuple as &dyn Scope).collect();
+        let values = compute(&[window], &rows)?;
+        Ok(values
+            .into_iter()
+            .map(|mut values| values.remove(0))
+            .collect())
+    }
+
+    #[test]
+    fn test_compute() {
+        let rows = [
+            vec![Value::Integer(1), Value::Integer(10)],
+            vec![Value::Integer(2), Value::Null],
+            vec![Value::Integer(1), Value::Integer(30)],
+            vec![Value::Integer(2), Value::Integer(40)],
+            vec![Value::Integer(1), Value::Integer(50)],
+        ];
+        let integers = |values: &[i64]| values.iter().map(|&v| Value::Integer(v)).collect();
+        let cases: [(&str, Vec<Value>); 6] = [
+            (
+                "row_number() over (partition by k)",
+                integers(&[1, 1, 2, 2, 3]),
+            ),
+            ("count(v) over (partition by k)", integers(&[3, 1, 3, 1, 3])),
+            (
+                "sum(v) over (order by v rows between 1 following and 2 following)",
+                vec![
+                    Value::Integer(70),
+                    Value::Integer(40),
+                    Value::Integer(90),
+                    Value::Integer(50),
+                    Value::Null,
+                ],
+            ),
+            (
+                "count(*) over (rows between 3 preceding and 2 preceding)",
+                integers(&[0, 0, 1, 2, 2]),
+            ),
+            (
+                "lead(v, 2, -1) over (partition by k order by v desc)",
+                integers(&[-1, -1, -1, -1, 10]),
+            ),
+            (
+                "lag(v, 0) over ()",
+                rows.iter().map(|row| row[1].clone()).collect(),
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(compute_over(input, &rows).unwrap(), expected, "{}", input);
+        }
+
+        let err = compute_over("lag(v, k - 2) over ()", &rows).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The offset of lag() must be a non-negative integer, not -1"
+        );
+        assert!(compute_over("rank() over ()", &[]).unwrap().is_empty());
+    }
+}